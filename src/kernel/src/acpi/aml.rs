@@ -0,0 +1,162 @@
+//======---------------------------------------------------------------======//
+//                                                                           //
+// Copyright 2022-2023 Evan Cox <evanacox00@gmail.com>. All rights reserved. //
+//                                                                           //
+// Use of this source code is governed by a BSD-style license that can be    //
+// found in the LICENSE.txt file at the root of this project, or at the      //
+// following link: https://opensource.org/licenses/BSD-3-Clause              //
+//                                                                           //
+//======---------------------------------------------------------------======//
+
+//! Just enough AML to get the sleep type values out of `\_Sx` objects.
+//!
+//! A real AML interpreter is a lot of work, and sleep states only need a
+//! `Name(\_S5, Package() { ... })` to be found. Firmware basically always
+//! defines those at the top level of the DSDT as a constant package, so a
+//! byte scan for the name finds them.
+
+use crate::acpi::{self, Fadt, SdtHeader};
+use crate::mm::PhysAddr;
+use core::{mem, slice};
+
+const NAME_OP: u8 = 0x08;
+const PACKAGE_OP: u8 = 0x12;
+const ROOT_CHAR: u8 = b'\\';
+
+const ZERO_OP: u8 = 0x00;
+const ONE_OP: u8 = 0x01;
+const BYTE_PREFIX: u8 = 0x0A;
+const WORD_PREFIX: u8 = 0x0B;
+const DWORD_PREFIX: u8 = 0x0C;
+const QWORD_PREFIX: u8 = 0x0E;
+const ONES_OP: u8 = 0xFF;
+
+/// The values to write into `SLP_TYPx` to enter a sleep state.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct SleepType {
+    /// The value for the `PM1a` control block.
+    pub a: u8,
+    /// The value for the `PM1b` control block.
+    pub b: u8,
+}
+
+// decodes a PkgLength, returning the length and the number of bytes it took up
+fn package_length(aml: &[u8]) -> Option<(usize, usize)> {
+    let lead = *aml.first()?;
+    let follow = usize::from(lead >> 6);
+
+    if follow == 0 {
+        return Some((usize::from(lead & 0x3F), 1));
+    }
+
+    let mut length = usize::from(lead & 0x0F);
+
+    for (i, byte) in aml.get(1..=follow)?.iter().enumerate() {
+        length |= usize::from(*byte) << (4 + i * 8);
+    }
+
+    Some((length, follow + 1))
+}
+
+// decodes a constant integer, returning it and the number of bytes it took up
+fn integer(aml: &[u8]) -> Option<(u64, usize)> {
+    let read = |bytes: usize| -> Option<u64> {
+        let data = aml.get(1..=bytes)?;
+
+        Some(
+            data.iter()
+                .rev()
+                .fold(0, |value, byte| (value << 8) | u64::from(*byte)),
+        )
+    };
+
+    match *aml.first()? {
+        ZERO_OP => Some((0, 1)),
+        ONE_OP => Some((1, 1)),
+        ONES_OP => Some((u64::MAX, 1)),
+        BYTE_PREFIX => Some((read(1)?, 2)),
+        WORD_PREFIX => Some((read(2)?, 3)),
+        DWORD_PREFIX => Some((read(4)?, 5)),
+        QWORD_PREFIX => Some((read(8)?, 9)),
+        _ => None,
+    }
+}
+
+// parses the package that comes right after the name in `Name(_Sx, ...)`
+#[allow(clippy::cast_possible_truncation)]
+fn sleep_package(aml: &[u8]) -> Option<SleepType> {
+    if *aml.first()? != PACKAGE_OP {
+        return None;
+    }
+
+    let (_, length_size) = package_length(&aml[1..])?;
+    let elements = aml.get(1 + length_size..)?;
+
+    // NumElements, then the elements. only the first two matter, some
+    // firmware only provides the first and expects the second to be 0
+    let count = *elements.first()?;
+    let (a, used) = integer(elements.get(1..)?)?;
+    let b = if count >= 2 {
+        integer(elements.get(1 + used..)?).map_or(0, |(b, _)| b)
+    } else {
+        0
+    };
+
+    // SLP_TYPx is a 3-bit field
+    Some(SleepType {
+        a: (a & 0x7) as u8,
+        b: (b & 0x7) as u8,
+    })
+}
+
+/// Finds the sleep type values for `\_Sx` in a block of AML.
+pub fn find_sleep_type(aml: &[u8], state: u8) -> Option<SleepType> {
+    let name = [b'_', b'S', b'0' + state, b'_'];
+
+    aml.windows(4).enumerate().find_map(|(i, window)| {
+        if window != name || i == 0 {
+            return None;
+        }
+
+        // the name has to actually be the subject of a `Name`, optionally
+        // with a root prefix. anything else is a reference to it
+        let is_name =
+            aml[i - 1] == NAME_OP || (aml[i - 1] == ROOT_CHAR && i >= 2 && aml[i - 2] == NAME_OP);
+
+        if is_name {
+            sleep_package(&aml[i + 4..])
+        } else {
+            None
+        }
+    })
+}
+
+// the AML in a definition block, i.e. everything after the header
+fn definition_block(table: PhysAddr) -> &'static [u8] {
+    let length = u64::from(acpi::header(table).length);
+    let header = mem::size_of::<SdtHeader>() as u64;
+    let start = table.add(header).to_virt();
+
+    // the HHDM maps all of physical memory for as long as the kernel runs
+    #[allow(clippy::cast_possible_truncation)]
+    unsafe {
+        slice::from_raw_parts(
+            start.as_mut_ptr::<u8>(),
+            length.saturating_sub(header) as usize,
+        )
+    }
+}
+
+/// Finds the sleep type values for sleep state `Sx`, looking through the
+/// DSDT and then every SSDT.
+pub fn sleep_type(state: u8) -> Option<SleepType> {
+    let dsdt = Fadt::get()?.dsdt.filter(|&dsdt| acpi::is_valid(dsdt));
+
+    dsdt.into_iter()
+        .chain(
+            acpi::tables().filter(|&table| {
+                acpi::header(table).signature == *b"SSDT" && acpi::is_valid(table)
+            }),
+        )
+        .find_map(|table| find_sleep_type(definition_block(table), state))
+}
@@ -0,0 +1,189 @@
+//======---------------------------------------------------------------======//
+//                                                                           //
+// Copyright 2022-2023 Evan Cox <evanacox00@gmail.com>. All rights reserved. //
+//                                                                           //
+// Use of this source code is governed by a BSD-style license that can be    //
+// found in the LICENSE.txt file at the root of this project, or at the      //
+// following link: https://opensource.org/licenses/BSD-3-Clause              //
+//                                                                           //
+//======---------------------------------------------------------------======//
+
+// the whole table is parsed, power management only needs some of it
+#![allow(dead_code)]
+
+use crate::acpi::{self, GenericAddress};
+use crate::mm::PhysAddr;
+
+/// The FADT flag for the reset register being supported.
+pub const FADT_RESET_REG_SUPPORTED: u32 = 1 << 10;
+
+/// The FADT flag for the platform being "hardware-reduced", i.e. having no
+/// fixed ACPI hardware (PM1 blocks, the SCI, etc.) at all.
+pub const FADT_HW_REDUCED_ACPI: u32 = 1 << 20;
+
+/// The fixed ACPI description table, i.e. where all the fixed ACPI hardware
+/// (power management blocks, the reset register, etc.) lives.
+///
+/// Every register is given as a [`GenericAddress`]. Old tables that only
+/// have 32-bit I/O port fields get those converted.
+#[derive(Copy, Clone, Debug)]
+pub struct Fadt {
+    /// The physical address of the FACS.
+    pub facs: Option<PhysAddr>,
+    /// The physical address of the DSDT.
+    pub dsdt: Option<PhysAddr>,
+    /// The preferred power management profile (desktop, mobile, server...).
+    pub preferred_pm_profile: u8,
+    /// The interrupt that the SCI is wired to, as an ISA IRQ.
+    pub sci_interrupt: u16,
+    /// The I/O port to write `acpi_enable` to to switch into ACPI mode.
+    pub smi_command_port: u32,
+    /// The value that enables ACPI mode.
+    pub acpi_enable: u8,
+    /// The value that disables ACPI mode.
+    pub acpi_disable: u8,
+    /// The `PM1a` event register block.
+    pub pm1a_event_block: GenericAddress,
+    /// The `PM1b` event register block.
+    pub pm1b_event_block: GenericAddress,
+    /// The `PM1a` control register block.
+    pub pm1a_control_block: GenericAddress,
+    /// The `PM1b` control register block.
+    pub pm1b_control_block: GenericAddress,
+    /// The power management timer.
+    pub pm_timer_block: GenericAddress,
+    /// The RTC CMOS index of the century, or `0` if there isn't one.
+    pub century: u8,
+    /// The IA-PC boot architecture flags.
+    pub iapc_boot_arch: u16,
+    /// The ARM boot architecture flags.
+    pub arm_boot_arch: u16,
+    /// The fixed feature flags, see [`FADT_RESET_REG_SUPPORTED`].
+    pub flags: u32,
+    /// The reset register.
+    pub reset_register: GenericAddress,
+    /// The value to write to the reset register to reset the system.
+    pub reset_value: u8,
+    /// The sleep control register, only used on hardware-reduced platforms.
+    pub sleep_control_register: GenericAddress,
+    /// The sleep status register, only used on hardware-reduced platforms.
+    pub sleep_status_register: GenericAddress,
+}
+
+// offsets of the fields that we care about, from the start of the table
+const FIRMWARE_CTRL: u64 = 36;
+const DSDT: u64 = 40;
+const PREFERRED_PM_PROFILE: u64 = 45;
+const SCI_INT: u64 = 46;
+const SMI_CMD: u64 = 48;
+const ACPI_ENABLE: u64 = 52;
+const ACPI_DISABLE: u64 = 53;
+const PM1A_EVT_BLK: u64 = 56;
+const PM1B_EVT_BLK: u64 = 60;
+const PM1A_CNT_BLK: u64 = 64;
+const PM1B_CNT_BLK: u64 = 68;
+const PM_TMR_BLK: u64 = 76;
+const PM1_EVT_LEN: u64 = 88;
+const PM1_CNT_LEN: u64 = 89;
+const PM_TMR_LEN: u64 = 91;
+const CENTURY: u64 = 108;
+const IAPC_BOOT_ARCH: u64 = 109;
+const FLAGS: u64 = 112;
+const RESET_REG: u64 = 116;
+const RESET_VALUE: u64 = 128;
+const ARM_BOOT_ARCH: u64 = 129;
+const X_FIRMWARE_CTRL: u64 = 132;
+const X_DSDT: u64 = 140;
+const X_PM1A_EVT_BLK: u64 = 148;
+const X_PM1B_EVT_BLK: u64 = 160;
+const X_PM1A_CNT_BLK: u64 = 172;
+const X_PM1B_CNT_BLK: u64 = 184;
+const X_PM_TMR_BLK: u64 = 208;
+const SLEEP_CONTROL_REG: u64 = 244;
+const SLEEP_STATUS_REG: u64 = 256;
+
+// prefers the 64-bit address if it's there, otherwise falls back to the
+// 32-bit one (which is always 0 or an I/O port)
+fn address(wide: u64, narrow: u32) -> Option<PhysAddr> {
+    match (wide, narrow) {
+        (0, 0) => None,
+        (0, narrow) => Some(PhysAddr::new(u64::from(narrow))),
+        (wide, _) => Some(PhysAddr::new(wide)),
+    }
+}
+
+fn register(wide: GenericAddress, port: u32, length: u8) -> GenericAddress {
+    if wide.is_present() {
+        wide
+    } else {
+        GenericAddress {
+            address_space: 1,
+            bit_width: length.saturating_mul(8),
+            bit_offset: 0,
+            access_size: 0,
+            address: u64::from(port),
+        }
+    }
+}
+
+impl Fadt {
+    /// Finds the FADT, if there is one.
+    pub fn get() -> Option<Self> {
+        let table = acpi::find_table(*b"FACP")?;
+        let length = u64::from(acpi::header(table).length);
+        let field = |offset| unsafe { acpi::read_field::<u8>(table, length, offset) };
+        let field16 = |offset| unsafe { acpi::read_field::<u16>(table, length, offset) };
+        let field32 = |offset| unsafe { acpi::read_field::<u32>(table, length, offset) };
+        let field64 = |offset| unsafe { acpi::read_field::<u64>(table, length, offset) };
+        let gas = |offset| unsafe { acpi::read_field::<GenericAddress>(table, length, offset) };
+
+        Some(Self {
+            facs: address(field64(X_FIRMWARE_CTRL), field32(FIRMWARE_CTRL)),
+            dsdt: address(field64(X_DSDT), field32(DSDT)),
+            preferred_pm_profile: field(PREFERRED_PM_PROFILE),
+            sci_interrupt: field16(SCI_INT),
+            smi_command_port: field32(SMI_CMD),
+            acpi_enable: field(ACPI_ENABLE),
+            acpi_disable: field(ACPI_DISABLE),
+            pm1a_event_block: register(
+                gas(X_PM1A_EVT_BLK),
+                field32(PM1A_EVT_BLK),
+                field(PM1_EVT_LEN),
+            ),
+            pm1b_event_block: register(
+                gas(X_PM1B_EVT_BLK),
+                field32(PM1B_EVT_BLK),
+                field(PM1_EVT_LEN),
+            ),
+            pm1a_control_block: register(
+                gas(X_PM1A_CNT_BLK),
+                field32(PM1A_CNT_BLK),
+                field(PM1_CNT_LEN),
+            ),
+            pm1b_control_block: register(
+                gas(X_PM1B_CNT_BLK),
+                field32(PM1B_CNT_BLK),
+                field(PM1_CNT_LEN),
+            ),
+            pm_timer_block: register(gas(X_PM_TMR_BLK), field32(PM_TMR_BLK), field(PM_TMR_LEN)),
+            century: field(CENTURY),
+            iapc_boot_arch: field16(IAPC_BOOT_ARCH),
+            arm_boot_arch: field16(ARM_BOOT_ARCH),
+            flags: field32(FLAGS),
+            reset_register: gas(RESET_REG),
+            reset_value: field(RESET_VALUE),
+            sleep_control_register: gas(SLEEP_CONTROL_REG),
+            sleep_status_register: gas(SLEEP_STATUS_REG),
+        })
+    }
+
+    /// Whether the platform is hardware-reduced, see [`FADT_HW_REDUCED_ACPI`].
+    pub const fn is_hardware_reduced(&self) -> bool {
+        self.flags & FADT_HW_REDUCED_ACPI != 0
+    }
+
+    /// Whether [`Fadt::reset_register`] can be used.
+    pub const fn supports_reset_register(&self) -> bool {
+        self.flags & FADT_RESET_REG_SUPPORTED != 0 && self.reset_register.is_present()
+    }
+}
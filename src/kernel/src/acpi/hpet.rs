@@ -0,0 +1,63 @@
+//======---------------------------------------------------------------======//
+//                                                                           //
+// Copyright 2022-2023 Evan Cox <evanacox00@gmail.com>. All rights reserved. //
+//                                                                           //
+// Use of this source code is governed by a BSD-style license that can be    //
+// found in the LICENSE.txt file at the root of this project, or at the      //
+// following link: https://opensource.org/licenses/BSD-3-Clause              //
+//                                                                           //
+//======---------------------------------------------------------------======//
+
+use crate::acpi::{self, AddressSpace, GenericAddress, SdtHeader};
+use core::mem;
+
+/// The HPET description table, i.e. where the high precision event
+/// timer's registers are.
+#[derive(Copy, Clone, Debug)]
+pub struct Hpet {
+    /// The hardware revision, comparator count, counter size, and PCI
+    /// vendor ID of the timer block.
+    pub event_timer_block_id: u32,
+    /// The location of the timer's registers, always in system memory.
+    pub address: GenericAddress,
+    /// Which HPET this is, starting from `0`.
+    pub number: u8,
+    /// The minimum number of ticks that periodic mode can be set to without
+    /// losing interrupts.
+    pub minimum_tick: u16,
+}
+
+impl Hpet {
+    /// Finds the HPET table, if there is one.
+    ///
+    /// Tables that claim the registers are anywhere but system memory are
+    /// treated as missing.
+    pub fn get() -> Option<Self> {
+        let fields = acpi::find_table(*b"HPET")?.add(mem::size_of::<SdtHeader>() as u64);
+        let hpet = unsafe {
+            Self {
+                event_timer_block_id: acpi::read(fields),
+                address: acpi::read(fields.add(4)),
+                number: acpi::read(fields.add(16)),
+                minimum_tick: acpi::read(fields.add(17)),
+            }
+        };
+
+        (hpet.address.space() == AddressSpace::SystemMemory).then_some(hpet)
+    }
+
+    /// The number of comparators (i.e. individual timers) in the block.
+    pub const fn comparators(&self) -> u32 {
+        ((self.event_timer_block_id >> 8) & 0x1F) + 1
+    }
+
+    /// Whether the main counter is 64 bits wide (rather than 32).
+    pub const fn is_64_bit(&self) -> bool {
+        self.event_timer_block_id & (1 << 13) != 0
+    }
+
+    /// The PCI vendor ID of the timer block.
+    pub const fn vendor_id(&self) -> u16 {
+        (self.event_timer_block_id >> 16) as u16
+    }
+}
@@ -0,0 +1,165 @@
+//======---------------------------------------------------------------======//
+//                                                                           //
+// Copyright 2022-2023 Evan Cox <evanacox00@gmail.com>. All rights reserved. //
+//                                                                           //
+// Use of this source code is governed by a BSD-style license that can be    //
+// found in the LICENSE.txt file at the root of this project, or at the      //
+// following link: https://opensource.org/licenses/BSD-3-Clause              //
+//                                                                           //
+//======---------------------------------------------------------------======//
+
+use crate::acpi::{self, SdtHeader, Subtables};
+use crate::mm::PhysAddr;
+use core::mem;
+
+/// A single entry in the MADT's list of interrupt controllers.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum MadtEntry {
+    /// A CPU with an xAPIC.
+    LocalApic {
+        /// The ACPI processor UID.
+        processor_id: u8,
+        /// The CPU's APIC ID.
+        apic_id: u8,
+        /// Bit 0 is "enabled", bit 1 is "online capable".
+        flags: u32,
+    },
+    /// An I/O APIC.
+    IoApic {
+        /// The I/O APIC's ID.
+        id: u8,
+        /// The physical address of the I/O APIC's registers.
+        address: u32,
+        /// The first GSI that the I/O APIC handles.
+        gsi_base: u32,
+    },
+    /// An ISA IRQ that's connected to a different GSI than its IRQ number,
+    /// or that has a non-standard polarity or trigger mode.
+    InterruptSourceOverride {
+        /// The bus, always `0` (ISA).
+        bus: u8,
+        /// The ISA IRQ.
+        source: u8,
+        /// The GSI that the IRQ is connected to.
+        gsi: u32,
+        /// MPS INTI flags, i.e. polarity in bits 0-1 and trigger mode in bits 2-3.
+        flags: u16,
+    },
+    /// A local APIC LINT pin that's connected to NMI.
+    LocalApicNmi {
+        /// The ACPI processor UID, or `0xFF` for every processor.
+        processor_id: u8,
+        /// MPS INTI flags.
+        flags: u16,
+        /// Which LINT pin, `0` or `1`.
+        lint: u8,
+    },
+    /// A 64-bit address for the local APICs that overrides the one in the MADT header.
+    LocalApicAddressOverride {
+        /// The physical address of the local APIC registers.
+        address: u64,
+    },
+    /// A CPU with an x2APIC.
+    LocalX2Apic {
+        /// The CPU's x2APIC ID.
+        x2apic_id: u32,
+        /// Bit 0 is "enabled", bit 1 is "online capable".
+        flags: u32,
+        /// The ACPI processor UID.
+        processor_uid: u32,
+    },
+    /// Any entry type that isn't parsed.
+    Unknown {
+        /// The entry type.
+        kind: u8,
+    },
+}
+
+/// The multiple APIC description table, i.e. the list of interrupt
+/// controllers in the system.
+#[derive(Copy, Clone, Debug)]
+pub struct Madt {
+    address: PhysAddr,
+    length: u64,
+    /// The physical address of the local APIC registers.
+    pub local_apic_address: u32,
+    /// Bit 0 is set if the system also has legacy 8259 PICs.
+    pub flags: u32,
+}
+
+impl Madt {
+    /// Finds the MADT, if there is one.
+    pub fn get() -> Option<Self> {
+        let address = acpi::find_table(*b"APIC")?;
+        let fields = address.add(mem::size_of::<SdtHeader>() as u64);
+
+        Some(Self {
+            address,
+            length: u64::from(acpi::header(address).length),
+            local_apic_address: unsafe { acpi::read::<u32>(fields) },
+            flags: unsafe { acpi::read::<u32>(fields.add(4)) },
+        })
+    }
+
+    /// Iterates over every entry in the table.
+    pub const fn entries(&self) -> MadtEntries {
+        MadtEntries {
+            // the entries start after the header and the two fields
+            inner: Subtables::new(
+                self.address,
+                self.length,
+                mem::size_of::<SdtHeader>() as u64 + 8,
+            ),
+        }
+    }
+}
+
+/// An iterator over the entries in a [`Madt`].
+pub struct MadtEntries {
+    inner: Subtables,
+}
+
+impl Iterator for MadtEntries {
+    type Item = MadtEntry;
+
+    fn next(&mut self) -> Option<MadtEntry> {
+        let (kind, at) = self.inner.next()?;
+
+        let entry = unsafe {
+            match kind {
+                0 => MadtEntry::LocalApic {
+                    processor_id: acpi::read(at.add(2)),
+                    apic_id: acpi::read(at.add(3)),
+                    flags: acpi::read(at.add(4)),
+                },
+                1 => MadtEntry::IoApic {
+                    id: acpi::read(at.add(2)),
+                    address: acpi::read(at.add(4)),
+                    gsi_base: acpi::read(at.add(8)),
+                },
+                2 => MadtEntry::InterruptSourceOverride {
+                    bus: acpi::read(at.add(2)),
+                    source: acpi::read(at.add(3)),
+                    gsi: acpi::read(at.add(4)),
+                    flags: acpi::read(at.add(8)),
+                },
+                4 => MadtEntry::LocalApicNmi {
+                    processor_id: acpi::read(at.add(2)),
+                    flags: acpi::read(at.add(3)),
+                    lint: acpi::read(at.add(5)),
+                },
+                5 => MadtEntry::LocalApicAddressOverride {
+                    address: acpi::read(at.add(4)),
+                },
+                9 => MadtEntry::LocalX2Apic {
+                    x2apic_id: acpi::read(at.add(4)),
+                    flags: acpi::read(at.add(8)),
+                    processor_uid: acpi::read(at.add(12)),
+                },
+                kind => MadtEntry::Unknown { kind },
+            }
+        };
+
+        Some(entry)
+    }
+}
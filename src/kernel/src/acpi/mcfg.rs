@@ -0,0 +1,87 @@
+//======---------------------------------------------------------------======//
+//                                                                           //
+// Copyright 2022-2023 Evan Cox <evanacox00@gmail.com>. All rights reserved. //
+//                                                                           //
+// Use of this source code is governed by a BSD-style license that can be    //
+// found in the LICENSE.txt file at the root of this project, or at the      //
+// following link: https://opensource.org/licenses/BSD-3-Clause              //
+//                                                                           //
+//======---------------------------------------------------------------======//
+
+// there's no PCI driver to find functions in these regions yet
+#![allow(dead_code)]
+
+use crate::acpi::{self, SdtHeader};
+use crate::mm::PhysAddr;
+use core::mem;
+
+// the allocations start after the header and 8 reserved bytes
+const ALLOCATIONS_OFFSET: u64 = mem::size_of::<SdtHeader>() as u64 + 8;
+const ALLOCATION_SIZE: u64 = 16;
+
+/// One PCI segment group's memory-mapped configuration space (ECAM).
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct PciSegment {
+    /// The physical address of the configuration space of bus `0`, even
+    /// if `start_bus` isn't `0`.
+    pub base: PhysAddr,
+    /// The PCI segment group number.
+    pub segment: u16,
+    /// The first bus decoded by this region.
+    pub start_bus: u8,
+    /// The last bus decoded by this region.
+    pub end_bus: u8,
+}
+
+impl PciSegment {
+    /// Gets the address of the configuration space for a single function,
+    /// if it's inside of this region.
+    pub const fn config_address(&self, bus: u8, device: u8, function: u8) -> Option<PhysAddr> {
+        if bus < self.start_bus || bus > self.end_bus || device >= 32 || function >= 8 {
+            return None;
+        }
+
+        let offset = ((bus as u64) << 20) | ((device as u64) << 15) | ((function as u64) << 12);
+
+        Some(self.base.add(offset))
+    }
+}
+
+/// The PCI memory-mapped configuration table, i.e. where the PCI Express
+/// configuration space for every segment group is.
+#[derive(Copy, Clone, Debug)]
+pub struct Mcfg {
+    address: PhysAddr,
+    count: u64,
+}
+
+impl Mcfg {
+    /// Finds the MCFG, if there is one.
+    pub fn get() -> Option<Self> {
+        let address = acpi::find_table(*b"MCFG")?;
+        let length = u64::from(acpi::header(address).length);
+
+        Some(Self {
+            address,
+            count: length.saturating_sub(ALLOCATIONS_OFFSET) / ALLOCATION_SIZE,
+        })
+    }
+
+    /// Iterates over every segment group in the table.
+    pub fn segments(&self) -> impl Iterator<Item = PciSegment> {
+        let address = self.address;
+
+        (0..self.count).map(move |i| {
+            let at = address.add(ALLOCATIONS_OFFSET + i * ALLOCATION_SIZE);
+
+            unsafe {
+                PciSegment {
+                    base: PhysAddr::new(acpi::read(at)),
+                    segment: acpi::read(at.add(8)),
+                    start_bus: acpi::read(at.add(10)),
+                    end_bus: acpi::read(at.add(11)),
+                }
+            }
+        })
+    }
+}
@@ -0,0 +1,395 @@
+//======---------------------------------------------------------------======//
+//                                                                           //
+// Copyright 2022-2023 Evan Cox <evanacox00@gmail.com>. All rights reserved. //
+//                                                                           //
+// Use of this source code is governed by a BSD-style license that can be    //
+// found in the LICENSE.txt file at the root of this project, or at the      //
+// following link: https://opensource.org/licenses/BSD-3-Clause              //
+//                                                                           //
+//======---------------------------------------------------------------======//
+
+//! Discovery and parsing of ACPI tables.
+//!
+//! Tables are never copied, they're read in place through the HHDM
+//! whenever they're needed. Every table read is unaligned, since nothing
+//! guarantees that firmware aligns any of them.
+//!
+//! Tables with a bad checksum show up in the inventory that's logged at
+//! boot, but are otherwise treated as if they don't exist.
+
+mod aml;
+mod fadt;
+mod hpet;
+mod madt;
+mod mcfg;
+mod srat;
+
+pub use aml::*;
+pub use fadt::*;
+pub use hpet::*;
+pub use madt::*;
+pub use mcfg::*;
+pub use srat::*;
+
+use crate::mm::PhysAddr;
+use crate::utility::KSpinOnceCell;
+use core::{mem, str};
+use log::{info, trace, warn};
+
+/// The header that every system description table starts with.
+#[repr(C, packed)]
+#[derive(Copy, Clone, Debug)]
+pub struct SdtHeader {
+    /// The 4-byte signature of the table, e.g. `APIC` for the MADT.
+    pub signature: [u8; 4],
+    /// The length of the entire table, including this header.
+    pub length: u32,
+    /// The revision of the table's structure.
+    pub revision: u8,
+    /// Makes every byte of the table sum to zero.
+    pub checksum: u8,
+    /// Identifies the OEM.
+    pub oem_id: [u8; 6],
+    /// Identifies this table, according to the OEM.
+    pub oem_table_id: [u8; 8],
+    /// The revision of this table, according to the OEM.
+    pub oem_revision: u32,
+    /// Identifies whatever created the table.
+    pub creator_id: u32,
+    /// The revision of whatever created the table.
+    pub creator_revision: u32,
+}
+
+impl SdtHeader {
+    /// The signature of the table as a string.
+    pub fn signature(&self) -> &str {
+        str::from_utf8(&self.signature).unwrap_or("????")
+    }
+
+    /// The OEM ID as a string, without any padding.
+    pub fn oem_id(&self) -> &str {
+        str::from_utf8(&self.oem_id).map_or("??????", |id| id.trim_end_matches([' ', '\0']))
+    }
+}
+
+/// The address space that a [`GenericAddress`] is in.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum AddressSpace {
+    /// Physical memory, i.e. MMIO.
+    SystemMemory,
+    /// x86 I/O ports.
+    SystemIo,
+    /// PCI configuration space.
+    PciConfig,
+    /// Anything else, with the raw address space ID.
+    Other(u8),
+}
+
+/// A register location, which can be in one of several address spaces.
+#[repr(C, packed)]
+#[derive(Copy, Clone, Debug, Default)]
+pub struct GenericAddress {
+    /// The raw address space ID, see [`GenericAddress::space`].
+    pub address_space: u8,
+    /// The width of the register in bits.
+    pub bit_width: u8,
+    /// The offset of the register in bits.
+    pub bit_offset: u8,
+    /// The access size (1 = byte, 2 = word, 3 = dword, 4 = qword).
+    pub access_size: u8,
+    /// The address of the register in the address space.
+    pub address: u64,
+}
+
+impl GenericAddress {
+    /// The address space that the register is in.
+    pub const fn space(&self) -> AddressSpace {
+        match self.address_space {
+            0 => AddressSpace::SystemMemory,
+            1 => AddressSpace::SystemIo,
+            2 => AddressSpace::PciConfig,
+            other => AddressSpace::Other(other),
+        }
+    }
+
+    /// Whether the register exists at all, an address of `0` means it doesn't.
+    pub const fn is_present(&self) -> bool {
+        self.address != 0
+    }
+}
+
+#[repr(C, packed)]
+#[derive(Copy, Clone)]
+struct Rsdp {
+    signature: [u8; 8],
+    checksum: u8,
+    oem_id: [u8; 6],
+    revision: u8,
+    rsdt_address: u32,
+}
+
+// the revision 1 RSDP is 20 bytes, revision 2 extends it with a length
+// at this offset and the XSDT address after that
+const RSDP_V1_LENGTH: u64 = 20;
+const RSDP_LENGTH_OFFSET: u64 = 20;
+const RSDP_XSDT_OFFSET: u64 = 24;
+
+#[derive(Copy, Clone, Debug)]
+struct RootTable {
+    address: PhysAddr,
+    // 4 for the RSDT, 8 for the XSDT
+    entry_size: u64,
+}
+
+static ROOT_TABLE: KSpinOnceCell<Option<RootTable>> = KSpinOnceCell::uninit();
+
+/// Reads a `T` out of physical memory, through the HHDM.
+///
+/// # Safety
+/// `[addr, addr + size_of::<T>())` must be mapped by the HHDM, and must
+/// contain a valid `T`.
+pub unsafe fn read<T: Copy>(addr: PhysAddr) -> T {
+    addr.to_virt().as_mut_ptr::<T>().read_unaligned()
+}
+
+// reads a field at `offset` in a table, giving back the default value if the
+// table is too short to have it. older revisions of tables are shorter,
+// and the fields they don't have are meant to be treated as zero
+unsafe fn read_field<T: Copy + Default>(table: PhysAddr, length: u64, offset: u64) -> T {
+    if offset + mem::size_of::<T>() as u64 <= length {
+        read(table.add(offset))
+    } else {
+        T::default()
+    }
+}
+
+// checks that every byte in `[addr, addr + length)` sums to zero
+fn checksum_ok(addr: PhysAddr, length: u64) -> bool {
+    (0..length).fold(0u8, |sum, i| {
+        sum.wrapping_add(unsafe { read::<u8>(addr.add(i)) })
+    }) == 0
+}
+
+fn rsdp_ok(addr: PhysAddr, rsdp: &Rsdp) -> bool {
+    if !checksum_ok(addr, RSDP_V1_LENGTH) {
+        return false;
+    }
+
+    rsdp.revision < 2
+        || checksum_ok(
+            addr,
+            u64::from(unsafe { read::<u32>(addr.add(RSDP_LENGTH_OFFSET)) }),
+        )
+}
+
+/// Reads the header of the table at `table`.
+pub fn header(table: PhysAddr) -> SdtHeader {
+    unsafe { read::<SdtHeader>(table) }
+}
+
+/// Checks whether the table at `table` has a valid checksum.
+pub fn is_valid(table: PhysAddr) -> bool {
+    checksum_ok(table, u64::from(header(table).length))
+}
+
+/// Finds the root table from the RSDP at `rsdp`, if there is one.
+///
+/// If there is no RSDP every table lookup fails, which is how everything
+/// that uses ACPI finds out that it's not available.
+pub fn init(rsdp: Option<PhysAddr>) {
+    let root = rsdp.and_then(|addr| {
+        let rsdp = unsafe { read::<Rsdp>(addr) };
+
+        if &rsdp.signature != b"RSD PTR " {
+            warn!("rsdp at {addr:?} has a bad signature, ignoring acpi");
+
+            return None;
+        }
+
+        if !rsdp_ok(addr, &rsdp) {
+            warn!("rsdp at {addr:?} has a bad checksum, ignoring acpi");
+
+            return None;
+        }
+
+        let xsdt = if rsdp.revision >= 2 {
+            unsafe { read::<u64>(addr.add(RSDP_XSDT_OFFSET)) }
+        } else {
+            0
+        };
+
+        let root = if xsdt != 0 {
+            RootTable {
+                address: PhysAddr::new(xsdt),
+                entry_size: 8,
+            }
+        } else {
+            RootTable {
+                address: PhysAddr::new(u64::from(rsdp.rsdt_address)),
+                entry_size: 4,
+            }
+        };
+
+        if !is_valid(root.address) {
+            warn!(
+                "root table at {:?} has a bad checksum, ignoring acpi",
+                root.address
+            );
+
+            return None;
+        }
+
+        Some(root)
+    });
+
+    if root.is_none() {
+        warn!("no acpi tables available");
+    }
+
+    let _ = ROOT_TABLE.set(root);
+
+    log_inventory();
+}
+
+fn log_table(table: PhysAddr) {
+    let header = header(table);
+    let (length, revision) = (header.length, header.revision);
+
+    info!(
+        "acpi table '{}' at {table:?}: {length} bytes, revision {revision}, oem '{}'{}",
+        header.signature(),
+        header.oem_id(),
+        if is_valid(table) {
+            ""
+        } else {
+            " (bad checksum, ignored)"
+        }
+    );
+}
+
+fn log_inventory() {
+    if let Some(root) = ROOT_TABLE.get() {
+        log_table(root.address);
+    }
+
+    for table in tables() {
+        log_table(table);
+    }
+
+    // the DSDT isn't in the root table, it's only reachable through the FADT
+    if let Some(dsdt) = Fadt::get().and_then(|fadt| fadt.dsdt) {
+        log_table(dsdt);
+    }
+
+    if let Some(madt) = Madt::get() {
+        trace!(
+            "madt: local apic at {:#x}, {} entries, flags = {:#x}",
+            madt.local_apic_address,
+            madt.entries().count(),
+            madt.flags
+        );
+    }
+
+    if let Some(hpet) = Hpet::get() {
+        trace!(
+            "hpet {}: {} {}-bit comparators at {:#x}, minimum tick {}, vendor {:#06x}",
+            hpet.number,
+            hpet.comparators(),
+            if hpet.is_64_bit() { 64 } else { 32 },
+            { hpet.address.address },
+            hpet.minimum_tick,
+            hpet.vendor_id()
+        );
+    }
+
+    if let Some(mcfg) = Mcfg::get() {
+        for segment in mcfg.segments() {
+            trace!(
+                "mcfg: segment {} buses {}..={} at {:?}",
+                segment.segment,
+                segment.start_bus,
+                segment.end_bus,
+                segment.base
+            );
+        }
+    }
+
+    if let Some(srat) = Srat::get() {
+        trace!(
+            "srat: {} entries, {} enabled, {} hot-pluggable memory ranges",
+            srat.entries().count(),
+            srat.entries().filter(SratEntry::is_enabled).count(),
+            srat.entries().filter(SratEntry::is_hot_pluggable).count()
+        );
+    }
+}
+
+/// Gets the physical address of every table listed in the root table.
+///
+/// This includes tables with bad checksums, see [`is_valid`].
+pub fn tables() -> impl Iterator<Item = PhysAddr> {
+    let root = *ROOT_TABLE.get();
+    let (address, entry_size, count) = root.map_or((PhysAddr::new(0), 0, 0), |root| {
+        let entries = u64::from(header(root.address).length)
+            .saturating_sub(mem::size_of::<SdtHeader>() as u64);
+
+        (root.address, root.entry_size, entries / root.entry_size)
+    });
+
+    (0..count).map(move |i| {
+        let entry = address.add(mem::size_of::<SdtHeader>() as u64 + i * entry_size);
+
+        PhysAddr::new(unsafe {
+            if entry_size == 8 {
+                read::<u64>(entry)
+            } else {
+                u64::from(read::<u32>(entry))
+            }
+        })
+    })
+}
+
+/// Finds the first table with a given signature and a valid checksum.
+pub fn find_table(signature: [u8; 4]) -> Option<PhysAddr> {
+    tables().find(|&table| header(table).signature == signature && is_valid(table))
+}
+
+// walks the variable-length entries that the MADT and SRAT are made of,
+// every entry starts with a type byte and a length byte
+struct Subtables {
+    current: PhysAddr,
+    end: PhysAddr,
+}
+
+impl Subtables {
+    const fn new(table: PhysAddr, length: u64, offset: u64) -> Self {
+        Self {
+            current: table.add(offset),
+            end: table.add(length),
+        }
+    }
+}
+
+impl Iterator for Subtables {
+    // the type, and the address of the entry
+    type Item = (u8, PhysAddr);
+
+    fn next(&mut self) -> Option<(u8, PhysAddr)> {
+        if self.current.as_u64() + 2 > self.end.as_u64() {
+            return None;
+        }
+
+        let at = self.current;
+        let kind = unsafe { read::<u8>(at) };
+        let length = unsafe { read::<u8>(at.add(1)) };
+
+        // a zero length would loop forever
+        if length < 2 || at.as_u64() + u64::from(length) > self.end.as_u64() {
+            return None;
+        }
+
+        self.current = at.add(u64::from(length));
+
+        Some((kind, at))
+    }
+}
@@ -0,0 +1,176 @@
+//======---------------------------------------------------------------======//
+//                                                                           //
+// Copyright 2022-2023 Evan Cox <evanacox00@gmail.com>. All rights reserved. //
+//                                                                           //
+// Use of this source code is governed by a BSD-style license that can be    //
+// found in the LICENSE.txt file at the root of this project, or at the      //
+// following link: https://opensource.org/licenses/BSD-3-Clause              //
+//                                                                           //
+//======---------------------------------------------------------------======//
+
+use crate::acpi::{self, SdtHeader, Subtables};
+use crate::mm::PhysAddr;
+use core::mem;
+
+/// The flag for an SRAT entry being enabled, entries without it set
+/// should be ignored.
+pub const SRAT_ENABLED: u32 = 1 << 0;
+
+/// The flag for a memory range being hot-pluggable.
+pub const SRAT_HOT_PLUGGABLE: u32 = 1 << 1;
+
+/// A single entry in the SRAT.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum SratEntry {
+    /// A CPU with an xAPIC.
+    ProcessorAffinity {
+        /// The NUMA node that the CPU is in.
+        proximity_domain: u32,
+        /// The CPU's APIC ID.
+        apic_id: u8,
+        /// See [`SRAT_ENABLED`].
+        flags: u32,
+    },
+    /// A range of physical memory.
+    MemoryAffinity {
+        /// The NUMA node that the memory is in.
+        proximity_domain: u32,
+        /// The start of the range.
+        base: PhysAddr,
+        /// The length of the range in bytes.
+        length: u64,
+        /// See [`SRAT_ENABLED`] and [`SRAT_HOT_PLUGGABLE`].
+        flags: u32,
+    },
+    /// A CPU with an x2APIC.
+    X2ApicAffinity {
+        /// The NUMA node that the CPU is in.
+        proximity_domain: u32,
+        /// The CPU's x2APIC ID.
+        x2apic_id: u32,
+        /// See [`SRAT_ENABLED`].
+        flags: u32,
+    },
+    /// A CPU with a GIC CPU interface.
+    GiccAffinity {
+        /// The NUMA node that the CPU is in.
+        proximity_domain: u32,
+        /// The ACPI processor UID.
+        processor_uid: u32,
+        /// See [`SRAT_ENABLED`].
+        flags: u32,
+    },
+    /// Any entry type that isn't parsed.
+    Unknown {
+        /// The entry type.
+        kind: u8,
+    },
+}
+
+impl SratEntry {
+    /// The entry's flags, or `0` for entries that aren't parsed.
+    pub const fn flags(&self) -> u32 {
+        match *self {
+            Self::ProcessorAffinity { flags, .. }
+            | Self::MemoryAffinity { flags, .. }
+            | Self::X2ApicAffinity { flags, .. }
+            | Self::GiccAffinity { flags, .. } => flags,
+            Self::Unknown { .. } => 0,
+        }
+    }
+
+    /// Whether the entry should be used, see [`SRAT_ENABLED`].
+    pub const fn is_enabled(&self) -> bool {
+        self.flags() & SRAT_ENABLED != 0
+    }
+
+    /// Whether the entry is a memory range that can be hot-plugged.
+    pub const fn is_hot_pluggable(&self) -> bool {
+        matches!(self, Self::MemoryAffinity { .. }) && self.flags() & SRAT_HOT_PLUGGABLE != 0
+    }
+}
+
+/// The system resource affinity table, i.e. which NUMA node every CPU
+/// and memory range belongs to.
+#[derive(Copy, Clone, Debug)]
+pub struct Srat {
+    address: PhysAddr,
+    length: u64,
+}
+
+impl Srat {
+    /// Finds the SRAT, if there is one.
+    pub fn get() -> Option<Self> {
+        let address = acpi::find_table(*b"SRAT")?;
+
+        Some(Self {
+            address,
+            length: u64::from(acpi::header(address).length),
+        })
+    }
+
+    /// Iterates over every entry in the table.
+    pub const fn entries(&self) -> SratEntries {
+        SratEntries {
+            // the entries start after the header and 12 reserved bytes
+            inner: Subtables::new(
+                self.address,
+                self.length,
+                mem::size_of::<SdtHeader>() as u64 + 12,
+            ),
+        }
+    }
+}
+
+/// An iterator over the entries in an [`Srat`].
+pub struct SratEntries {
+    inner: Subtables,
+}
+
+impl Iterator for SratEntries {
+    type Item = SratEntry;
+
+    fn next(&mut self) -> Option<SratEntry> {
+        let (kind, at) = self.inner.next()?;
+
+        let entry = unsafe {
+            match kind {
+                0 => {
+                    // the proximity domain is split, the low byte comes first
+                    // and the upper three bytes are after the SAPIC EID
+                    let low = acpi::read::<u8>(at.add(2));
+                    let high = [
+                        acpi::read::<u8>(at.add(9)),
+                        acpi::read::<u8>(at.add(10)),
+                        acpi::read::<u8>(at.add(11)),
+                    ];
+
+                    SratEntry::ProcessorAffinity {
+                        proximity_domain: u32::from_le_bytes([low, high[0], high[1], high[2]]),
+                        apic_id: acpi::read(at.add(3)),
+                        flags: acpi::read(at.add(4)),
+                    }
+                }
+                1 => SratEntry::MemoryAffinity {
+                    proximity_domain: acpi::read(at.add(2)),
+                    base: PhysAddr::new(acpi::read(at.add(8))),
+                    length: acpi::read(at.add(16)),
+                    flags: acpi::read(at.add(28)),
+                },
+                2 => SratEntry::X2ApicAffinity {
+                    proximity_domain: acpi::read(at.add(4)),
+                    x2apic_id: acpi::read(at.add(8)),
+                    flags: acpi::read(at.add(12)),
+                },
+                3 => SratEntry::GiccAffinity {
+                    proximity_domain: acpi::read(at.add(2)),
+                    processor_uid: acpi::read(at.add(6)),
+                    flags: acpi::read(at.add(10)),
+                },
+                kind => SratEntry::Unknown { kind },
+            }
+        };
+
+        Some(entry)
+    }
+}
@@ -0,0 +1,85 @@
+//======---------------------------------------------------------------======//
+//                                                                           //
+// Copyright 2022-2023 Evan Cox <evanacox00@gmail.com>. All rights reserved. //
+//                                                                           //
+// Use of this source code is governed by a BSD-style license that can be    //
+// found in the LICENSE.txt file at the root of this project, or at the      //
+// following link: https://opensource.org/licenses/BSD-3-Clause              //
+//                                                                           //
+//======---------------------------------------------------------------======//
+
+use crate::utility::KSpinLazy;
+use core::arch::asm;
+
+static HAS_RNDR: KSpinLazy<bool> = KSpinLazy::new(has_rndr);
+
+// how many times a read is retried if the hardware doesn't have anything
+const RETRIES: usize = 10;
+
+// the RNDR field of `ID_AA64ISAR0_EL1`
+fn has_rndr() -> bool {
+    let features: u64;
+
+    unsafe {
+        asm!("mrs {}, id_aa64isar0_el1", out(reg) features, options(nomem, nostack, preserves_flags));
+    }
+
+    (features >> 60) & 0xF != 0
+}
+
+// `RNDR` and `RNDRRS` set `NZCV` to `0b0100` when they fail
+macro_rules! read_random_register {
+    ($register:literal) => {{
+        let (value, failed): (u64, u64);
+
+        unsafe {
+            asm!(
+                concat!("mrs {}, ", $register),
+                "cset {}, eq",
+                out(reg) value,
+                out(reg) failed,
+                options(nomem, nostack),
+            );
+        }
+
+        (failed == 0).then_some(value)
+    }};
+}
+
+/// Reads 64 bits from the CPU's random number generator after making it
+/// reseed itself from its entropy source (`RNDRRS`), if there is one.
+pub fn hardware_seed() -> Option<u64> {
+    if !*HAS_RNDR {
+        return None;
+    }
+
+    // `s3_3_c2_c4_1` is `RNDRRS`, older assemblers don't know the name
+    (0..RETRIES).find_map(|_| read_random_register!("s3_3_c2_c4_1"))
+}
+
+/// Reads 64 bits from the CPU's random number generator (`RNDR`), if
+/// there is one.
+///
+/// This is a DRBG that the hardware reseeds from its entropy source
+/// every so often, so its output isn't all entropy.
+pub fn hardware_random() -> Option<u64> {
+    if !*HAS_RNDR {
+        return None;
+    }
+
+    // `s3_3_c2_c4_0` is `RNDR`
+    (0..RETRIES).find_map(|_| read_random_register!("s3_3_c2_c4_0"))
+}
+
+/// A fast, high-resolution counter that timing jitter can be measured
+/// with. It doesn't have any particular unit.
+#[inline]
+pub fn cycle_counter() -> u64 {
+    let count: u64;
+
+    unsafe {
+        asm!("mrs {}, cntvct_el0", out(reg) count, options(nomem, nostack, preserves_flags));
+    }
+
+    count
+}
@@ -0,0 +1,56 @@
+//======---------------------------------------------------------------======//
+//                                                                           //
+// Copyright 2022-2023 Evan Cox <evanacox00@gmail.com>. All rights reserved. //
+//                                                                           //
+// Use of this source code is governed by a BSD-style license that can be    //
+// found in the LICENSE.txt file at the root of this project, or at the      //
+// following link: https://opensource.org/licenses/BSD-3-Clause              //
+//                                                                           //
+//======---------------------------------------------------------------======//
+
+use core::arch::asm;
+
+// the IRQ mask bit in DAIF
+const DAIF_I: u64 = 1 << 7;
+
+/// Disables interrupts on the current CPU, returning whether they were
+/// enabled beforehand.
+///
+/// Calls nest as long as every one is paired with an [`interrupts_restore`]
+/// in reverse order.
+#[inline]
+pub fn interrupts_disable() -> bool {
+    let enabled = interrupts_enabled();
+
+    unsafe {
+        asm!("msr daifset, #2", options(nomem, nostack, preserves_flags));
+    }
+
+    enabled
+}
+
+/// Restores the state from a call to [`interrupts_disable`].
+///
+/// Interrupts are only enabled if they were enabled before that call.
+///
+/// # Safety
+/// Every interrupt-disabled section that was entered after the matching
+/// [`interrupts_disable`] must have been left.
+#[inline]
+pub unsafe fn interrupts_restore(were_enabled: bool) {
+    if were_enabled {
+        asm!("msr daifclr, #2", options(nomem, nostack, preserves_flags));
+    }
+}
+
+/// Checks whether interrupts are enabled on the current CPU.
+#[inline]
+pub fn interrupts_enabled() -> bool {
+    let daif: u64;
+
+    unsafe {
+        asm!("mrs {}, daif", out(reg) daif, options(nomem, nostack, preserves_flags));
+    }
+
+    daif & DAIF_I == 0
+}
@@ -10,8 +10,22 @@
 
 //! aarch64 implementations of Beryl's HAL.
 
+mod entropy;
+mod interrupts;
+mod percpu;
+mod platform;
+mod power;
 mod serial;
 mod spin;
+mod time;
+mod wait;
 
+pub use entropy::*;
+pub use interrupts::*;
+pub use percpu::*;
+pub use platform::*;
+pub use power::*;
 pub use serial::*;
 pub use spin::*;
+pub use time::*;
+pub use wait::*;
@@ -0,0 +1,32 @@
+//======---------------------------------------------------------------======//
+//                                                                           //
+// Copyright 2022-2023 Evan Cox <evanacox00@gmail.com>. All rights reserved. //
+//                                                                           //
+// Use of this source code is governed by a BSD-style license that can be    //
+// found in the LICENSE.txt file at the root of this project, or at the      //
+// following link: https://opensource.org/licenses/BSD-3-Clause              //
+//                                                                           //
+//======---------------------------------------------------------------======//
+
+use crate::mm::VirtAddr;
+use core::arch::asm;
+
+/// Points the current CPU's per-CPU base register at `base`.
+///
+/// # Safety
+/// `base` must stay valid forever.
+pub unsafe fn set_percpu_base(base: VirtAddr) {
+    asm!("msr tpidr_el1, {}", in(reg) base.as_u64(), options(nomem, nostack, preserves_flags));
+}
+
+/// Gets the current CPU's per-CPU base.
+#[inline]
+pub fn percpu_base() -> VirtAddr {
+    let base: u64;
+
+    unsafe {
+        asm!("mrs {}, tpidr_el1", out(reg) base, options(nomem, nostack, preserves_flags));
+    }
+
+    VirtAddr::new(base)
+}
@@ -0,0 +1,14 @@
+//======---------------------------------------------------------------======//
+//                                                                           //
+// Copyright 2022-2023 Evan Cox <evanacox00@gmail.com>. All rights reserved. //
+//                                                                           //
+// Use of this source code is governed by a BSD-style license that can be    //
+// found in the LICENSE.txt file at the root of this project, or at the      //
+// following link: https://opensource.org/licenses/BSD-3-Clause              //
+//                                                                           //
+//======---------------------------------------------------------------======//
+/// Initializes the parts of the platform that need memory management to
+/// be up, i.e. the interrupt controllers and timers.
+///
+/// Nothing needs to be done here yet.
+pub fn init() {}
@@ -0,0 +1,76 @@
+//======---------------------------------------------------------------======//
+//                                                                           //
+// Copyright 2022-2023 Evan Cox <evanacox00@gmail.com>. All rights reserved. //
+//                                                                           //
+// Use of this source code is governed by a BSD-style license that can be    //
+// found in the LICENSE.txt file at the root of this project, or at the      //
+// following link: https://opensource.org/licenses/BSD-3-Clause              //
+//                                                                           //
+//======---------------------------------------------------------------======//
+
+use crate::acpi::Fadt;
+use crate::arch::aarch64::hal;
+use core::arch::asm;
+use log::{error, info};
+
+const PSCI_SYSTEM_OFF: u64 = 0x8400_0008;
+const PSCI_SYSTEM_RESET: u64 = 0x8400_0009;
+
+// the FADT's ARM boot architecture flag for PSCI calls going through
+// `hvc` instead of `smc`
+const ARM_BOOT_PSCI_USE_HVC: u16 = 1 << 1;
+
+// makes a PSCI call that doesn't return if it succeeds
+fn psci_call(function: u64) {
+    let use_hvc = Fadt::get().is_some_and(|fadt| fadt.arm_boot_arch & ARM_BOOT_PSCI_USE_HVC != 0);
+
+    unsafe {
+        if use_hvc {
+            asm!(
+                "hvc #0",
+                inout("x0") function => _,
+                out("x1") _, out("x2") _, out("x3") _, out("x4") _, out("x5") _,
+                out("x6") _, out("x7") _, out("x8") _, out("x9") _, out("x10") _,
+                out("x11") _, out("x12") _, out("x13") _, out("x14") _, out("x15") _,
+                out("x16") _, out("x17") _,
+                options(nomem, nostack)
+            );
+        } else {
+            asm!(
+                "smc #0",
+                inout("x0") function => _,
+                out("x1") _, out("x2") _, out("x3") _, out("x4") _, out("x5") _,
+                out("x6") _, out("x7") _, out("x8") _, out("x9") _, out("x10") _,
+                out("x11") _, out("x12") _, out("x13") _, out("x14") _, out("x15") _,
+                out("x16") _, out("x17") _,
+                options(nomem, nostack)
+            );
+        }
+    }
+}
+
+/// Powers off the machine through PSCI.
+///
+/// If that doesn't work, this halts the current CPU instead.
+pub fn shutdown() -> ! {
+    info!("shutting down");
+
+    psci_call(PSCI_SYSTEM_OFF);
+
+    error!("psci SYSTEM_OFF returned, halting instead");
+
+    unsafe { hal::privileged_halt_thread() }
+}
+
+/// Resets the machine through PSCI.
+///
+/// If that doesn't work, this halts the current CPU instead.
+pub fn reboot() -> ! {
+    info!("rebooting");
+
+    psci_call(PSCI_SYSTEM_RESET);
+
+    error!("psci SYSTEM_RESET returned, halting instead");
+
+    unsafe { hal::privileged_halt_thread() }
+}
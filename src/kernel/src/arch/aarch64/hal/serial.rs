@@ -26,7 +26,7 @@ impl SerialBackend for SerialPort {
         todo!()
     }
 
-    fn recv(&mut self) -> u8 {
+    fn recv(&mut self) -> Option<u8> {
         todo!()
     }
 }
@@ -0,0 +1,24 @@
+//======---------------------------------------------------------------======//
+//                                                                           //
+// Copyright 2022-2023 Evan Cox <evanacox00@gmail.com>. All rights reserved. //
+//                                                                           //
+// Use of this source code is governed by a BSD-style license that can be    //
+// found in the LICENSE.txt file at the root of this project, or at the      //
+// following link: https://opensource.org/licenses/BSD-3-Clause              //
+//                                                                           //
+//======---------------------------------------------------------------======//
+use core::arch::asm;
+
+/// The number of nanoseconds since the system booted, according to
+/// the generic timer's virtual counter.
+#[allow(clippy::cast_possible_truncation)]
+pub fn nanos_since_boot() -> u64 {
+    let (count, frequency): (u64, u64);
+
+    unsafe {
+        asm!("mrs {}, cntvct_el0", out(reg) count, options(nomem, nostack, preserves_flags));
+        asm!("mrs {}, cntfrq_el0", out(reg) frequency, options(nomem, nostack, preserves_flags));
+    }
+
+    (u128::from(count) * 1_000_000_000 / u128::from(frequency.max(1))) as u64
+}
@@ -0,0 +1,35 @@
+//======---------------------------------------------------------------======//
+//                                                                           //
+// Copyright 2022-2023 Evan Cox <evanacox00@gmail.com>. All rights reserved. //
+//                                                                           //
+// Use of this source code is governed by a BSD-style license that can be    //
+// found in the LICENSE.txt file at the root of this project, or at the      //
+// following link: https://opensource.org/licenses/BSD-3-Clause              //
+//                                                                           //
+//======---------------------------------------------------------------======//
+
+use core::arch::asm;
+
+/// Sleeps the current CPU until it's woken up by [`wake_cpu`] or an
+/// interrupt arrives. This can also return early for no reason.
+///
+/// There's no timer driver yet, so waits with a deadline return
+/// immediately and the caller ends up polling.
+///
+/// # Safety
+/// Interrupts must be disabled when this is called.
+pub unsafe fn wait_for_wake(deadline: Option<u64>) {
+    if deadline.is_none() {
+        asm!("wfe", options(nomem, nostack, preserves_flags));
+    }
+}
+
+/// Wakes up CPU `index` if it's sleeping in [`wait_for_wake`].
+///
+/// This wakes up every CPU, they just go back to sleep if they had
+/// nothing to wake up for.
+pub fn wake_cpu(_index: usize) {
+    unsafe {
+        asm!("sev", options(nomem, nostack, preserves_flags));
+    }
+}
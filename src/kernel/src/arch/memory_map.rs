@@ -0,0 +1,198 @@
+//======---------------------------------------------------------------======//
+//                                                                           //
+// Copyright 2022-2023 Evan Cox <evanacox00@gmail.com>. All rights reserved. //
+//                                                                           //
+// Use of this source code is governed by a BSD-style license that can be    //
+// found in the LICENSE.txt file at the root of this project, or at the      //
+// following link: https://opensource.org/licenses/BSD-3-Clause              //
+//                                                                           //
+//======---------------------------------------------------------------======//
+
+//! A platform-neutral model of the physical memory map.
+//!
+//! Boot code is responsible for translating whatever the bootloader or the
+//! firmware gives it into a [`MemoryMap`], everything after that point only
+//! deals with the types in here.
+
+use core::fmt;
+
+/// The maximum number of regions that a [`MemoryMap`] is able to hold.
+///
+/// Real machines (and QEMU) tend to report a few dozen at most, this is
+/// just a fixed upper bound so that the map can live on the stack.
+pub const MAX_MEMORY_REGIONS: usize = 128;
+
+/// What a given region of physical memory is being used for.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum MemoryRegionKind {
+    /// Free RAM that the kernel is able to use however it wants.
+    Usable,
+    /// RAM that the bootloader is still using (page tables, boot-time
+    /// structures, etc.), but that can be reclaimed once the kernel no
+    /// longer needs anything the bootloader gave it.
+    BootloaderReclaimable,
+    /// RAM holding ACPI tables, reclaimable once the tables have been parsed.
+    AcpiReclaimable,
+    /// RAM that the firmware needs preserved across sleep states.
+    AcpiNvs,
+    /// RAM that the kernel image and any boot modules were loaded into.
+    KernelAndModules,
+    /// Memory backing a framebuffer handed to us by the bootloader.
+    Framebuffer,
+    /// Memory that cannot be used for anything, e.g. firmware-reserved
+    /// memory or memory-mapped I/O holes.
+    Reserved,
+    /// RAM that the firmware reported as defective.
+    Bad,
+}
+
+impl MemoryRegionKind {
+    /// Every region kind, in the order they should be reported in.
+    pub const ALL: [Self; 8] = [
+        Self::Usable,
+        Self::BootloaderReclaimable,
+        Self::AcpiReclaimable,
+        Self::AcpiNvs,
+        Self::KernelAndModules,
+        Self::Framebuffer,
+        Self::Reserved,
+        Self::Bad,
+    ];
+
+    /// Whether this region is actually backed by RAM in the system, as opposed
+    /// to being a device or reserved hole in the physical address space.
+    #[inline]
+    pub const fn is_ram(self) -> bool {
+        matches!(
+            self,
+            Self::Usable
+                | Self::BootloaderReclaimable
+                | Self::AcpiReclaimable
+                | Self::AcpiNvs
+                | Self::KernelAndModules
+        )
+    }
+
+    /// A short human-readable name for the kind, used for logging.
+    #[inline]
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::Usable => "usable",
+            Self::BootloaderReclaimable => "bootloader reclaimable",
+            Self::AcpiReclaimable => "acpi reclaimable",
+            Self::AcpiNvs => "acpi nvs",
+            Self::KernelAndModules => "kernel/modules",
+            Self::Framebuffer => "framebuffer",
+            Self::Reserved => "reserved",
+            Self::Bad => "bad",
+        }
+    }
+}
+
+/// A single contiguous region of physical memory.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct MemoryRegion {
+    /// The physical address that the region starts at.
+    pub base: u64,
+    /// The length of the region, in bytes.
+    pub length: u64,
+    /// What the region is being used for.
+    pub kind: MemoryRegionKind,
+}
+
+impl MemoryRegion {
+    /// Creates a region describing `[base, base + length)`.
+    #[inline]
+    pub const fn new(base: u64, length: u64, kind: MemoryRegionKind) -> Self {
+        Self { base, length, kind }
+    }
+
+    /// The first physical address after the end of the region.
+    #[inline]
+    pub const fn end(&self) -> u64 {
+        self.base + self.length
+    }
+}
+
+/// The physical memory map of the system.
+///
+/// This is a fixed-capacity list of [`MemoryRegion`]s in the order that the
+/// bootloader reported them (which is normally sorted by base address).
+#[derive(Copy, Clone)]
+pub struct MemoryMap {
+    regions: [MemoryRegion; MAX_MEMORY_REGIONS],
+    len: usize,
+}
+
+impl MemoryMap {
+    /// Creates an empty memory map.
+    pub const fn new() -> Self {
+        Self {
+            regions: [MemoryRegion::new(0, 0, MemoryRegionKind::Reserved); MAX_MEMORY_REGIONS],
+            len: 0,
+        }
+    }
+
+    /// Adds a region to the end of the map.
+    ///
+    /// # Errors
+    /// If the map already holds [`MAX_MEMORY_REGIONS`] regions, the region
+    /// is given back in `Err(region)`.
+    pub const fn push(&mut self, region: MemoryRegion) -> Result<(), MemoryRegion> {
+        if self.len == MAX_MEMORY_REGIONS {
+            return Err(region);
+        }
+
+        self.regions[self.len] = region;
+        self.len += 1;
+
+        Ok(())
+    }
+
+    /// Every region in the map.
+    #[inline]
+    pub fn regions(&self) -> &[MemoryRegion] {
+        &self.regions[..self.len]
+    }
+
+    /// Every region in the map of a given kind.
+    #[inline]
+    pub fn regions_of(&self, kind: MemoryRegionKind) -> impl Iterator<Item = &MemoryRegion> {
+        self.regions()
+            .iter()
+            .filter(move |region| region.kind == kind)
+    }
+
+    /// The total number of bytes in every region of a given kind.
+    pub fn total_of(&self, kind: MemoryRegionKind) -> u64 {
+        self.regions_of(kind).map(|region| region.length).sum()
+    }
+
+    /// The total amount of RAM (in bytes) in the system, i.e. the sum of
+    /// every region that [`MemoryRegionKind::is_ram`].
+    pub fn total(&self) -> u64 {
+        self.regions()
+            .iter()
+            .filter(|region| region.kind.is_ram())
+            .map(|region| region.length)
+            .sum()
+    }
+
+    /// The number of bytes that are immediately usable by the kernel.
+    #[inline]
+    pub fn usable(&self) -> u64 {
+        self.total_of(MemoryRegionKind::Usable)
+    }
+}
+
+impl Default for MemoryMap {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Debug for MemoryMap {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.regions()).finish()
+    }
+}
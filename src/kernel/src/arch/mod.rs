@@ -16,12 +16,40 @@
 //!
 //! This module also provides `hal`
 
+mod memory_map;
+mod paging;
+
+use core::sync::atomic::AtomicUsize;
+
+pub use memory_map::*;
+pub use paging::*;
+
+/// The maximum number of CPUs that the kernel will ever use.
+pub const MAX_CPUS: usize = 64;
+
 #[derive(Copy, Clone, Debug)]
 pub struct SystemInfo {
     /// The amount of memory (in bytes) that the host system has
     /// available to it **in total**. This includes memory that
     /// the kernel is currently occupying.
     pub memory: usize,
+    /// The physical memory map of the system, as reported by the
+    /// bootloader.
+    pub memory_map: MemoryMap,
+    /// The virtual address that the bootloader mapped physical
+    /// address `0` to, all of physical memory is mapped linearly
+    /// starting at that address.
+    pub hhdm_offset: u64,
+    /// The physical address of the ACPI RSDP, if the firmware
+    /// provided one.
+    pub rsdp_address: Option<u64>,
+    /// The number of CPUs that were registered in the CPU table, including
+    /// the bootstrap processor.
+    pub cpus_registered: usize,
+    /// The number of CPUs that are online, including the bootstrap processor.
+    /// Only the bootstrap processor is online at first, this goes up as each
+    /// of the others is brought up by `hal::init` and parks.
+    pub online_cpus: &'static AtomicUsize,
 }
 
 #[cfg(target_arch = "x86_64")]
@@ -0,0 +1,202 @@
+//======---------------------------------------------------------------======//
+//                                                                           //
+// Copyright 2022-2023 Evan Cox <evanacox00@gmail.com>. All rights reserved. //
+//                                                                           //
+// Use of this source code is governed by a BSD-style license that can be    //
+// found in the LICENSE.txt file at the root of this project, or at the      //
+// following link: https://opensource.org/licenses/BSD-3-Clause              //
+//                                                                           //
+//======---------------------------------------------------------------======//
+
+//! The platform-neutral interface for editing page tables.
+//!
+//! Each architecture provides a `hal::PageTables` type that implements
+//! [`PageMapper`], platform-independent code only ever goes through the trait.
+
+// nothing translates, tears down or changes mappings yet, MMIO mappings live
+// forever and there are no user address spaces
+#![allow(dead_code)]
+
+use crate::mm::{PhysAddr, VirtAddr};
+use core::ops::{BitOr, BitOrAssign};
+
+/// Permissions and caching behavior for a mapped page.
+///
+/// Pages are always readable, everything else needs to be asked for.
+#[repr(transparent)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, Default)]
+pub struct PageFlags(u32);
+
+impl PageFlags {
+    /// A read-only, non-executable, kernel-only page.
+    pub const NONE: Self = Self(0);
+
+    /// The page can be written to.
+    pub const WRITABLE: Self = Self(1 << 0);
+
+    /// The page can be accessed from user mode.
+    pub const USER: Self = Self(1 << 1);
+
+    /// Code can be executed from the page.
+    pub const EXECUTABLE: Self = Self(1 << 2);
+
+    /// Accesses to the page bypass the cache, e.g. for MMIO.
+    pub const NO_CACHE: Self = Self(1 << 3);
+
+    /// Writes to the page go straight through the cache.
+    pub const WRITE_THROUGH: Self = Self(1 << 4);
+
+    /// The mapping is shared between every address space, and doesn't
+    /// need to be flushed when switching between them.
+    pub const GLOBAL: Self = Self(1 << 5);
+
+    /// Checks if every flag in `other` is also set in `self`.
+    #[inline]
+    pub const fn contains(self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+}
+
+impl BitOr for PageFlags {
+    type Output = Self;
+
+    #[inline]
+    fn bitor(self, rhs: Self) -> Self::Output {
+        Self(self.0 | rhs.0)
+    }
+}
+
+impl BitOrAssign for PageFlags {
+    #[inline]
+    fn bitor_assign(&mut self, rhs: Self) {
+        self.0 |= rhs.0;
+    }
+}
+
+/// The sizes of page that can be mapped.
+///
+/// Not every architecture supports every size, in which case mapping
+/// with that size fails with [`MapError::Unsupported`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum PageSize {
+    /// A normal 4 KiB page.
+    Size4KiB,
+    /// A 2 MiB "huge" page.
+    Size2MiB,
+    /// A 1 GiB "huge" page.
+    Size1GiB,
+}
+
+impl PageSize {
+    /// The size of the page, in bytes.
+    #[inline]
+    pub const fn bytes(self) -> u64 {
+        match self {
+            Self::Size4KiB => 4 * 1024,
+            Self::Size2MiB => 2 * 1024 * 1024,
+            Self::Size1GiB => 1024 * 1024 * 1024,
+        }
+    }
+}
+
+/// The ways that editing page tables can fail.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum MapError {
+    /// Something is already mapped at the virtual address.
+    AlreadyMapped,
+    /// Nothing is mapped at the virtual address.
+    NotMapped,
+    /// The range is already covered by a bigger page than the one
+    /// that the operation wanted to work with.
+    HugePageConflict,
+    /// An address wasn't aligned to the page size.
+    Misaligned,
+    /// The virtual address is not a valid (e.g. canonical) address.
+    InvalidAddress,
+    /// The page size isn't supported by the hardware.
+    Unsupported,
+    /// A frame was needed for a new page table, but none were available.
+    OutOfMemory,
+}
+
+/// Where a virtual address is mapped to, and how.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Translation {
+    /// The physical address that the virtual address maps to.
+    pub phys: PhysAddr,
+    /// The size of the page that the address is inside of.
+    pub size: PageSize,
+    /// The flags that the page is mapped with.
+    pub flags: PageFlags,
+}
+
+/// Something that can edit a set of page tables.
+///
+/// Every operation flushes the TLB entries that it invalidates on the current
+/// CPU, but does **not** do any sort of shootdown for other CPUs.
+pub trait PageMapper {
+    /// Maps a page of size `size` at `virt` to `phys`.
+    ///
+    /// Any intermediate tables needed are allocated from the physical memory
+    /// manager. Both `virt` and `phys` need to be aligned to `size`.
+    ///
+    /// # Errors
+    /// Fails if anything is already mapped in the range, if the addresses are
+    /// invalid, or if the intermediate tables can't be allocated.
+    ///
+    /// # Safety
+    /// The mapping must not break any existing references, e.g. by mapping
+    /// memory in use by something else as writable in another place.
+    unsafe fn map_sized(
+        &mut self,
+        virt: VirtAddr,
+        phys: PhysAddr,
+        size: PageSize,
+        flags: PageFlags,
+    ) -> Result<(), MapError>;
+
+    /// Removes the mapping for the page containing `virt`, and returns
+    /// what it used to map to.
+    ///
+    /// Intermediate tables are not freed, even if they become empty.
+    ///
+    /// # Errors
+    /// Fails if nothing is mapped at `virt`.
+    ///
+    /// # Safety
+    /// Nothing may reference memory in the page after it's unmapped.
+    unsafe fn unmap(&mut self, virt: VirtAddr) -> Result<Translation, MapError>;
+
+    /// Changes the flags of the page containing `virt`.
+    ///
+    /// # Errors
+    /// Fails if nothing is mapped at `virt`.
+    ///
+    /// # Safety
+    /// Any existing references into the page must remain valid with the
+    /// new permissions.
+    unsafe fn protect(&mut self, virt: VirtAddr, flags: PageFlags) -> Result<(), MapError>;
+
+    /// Finds out where `virt` is mapped to, if anywhere.
+    ///
+    /// The returned physical address includes the offset of `virt`
+    /// inside of its page.
+    fn translate(&self, virt: VirtAddr) -> Option<Translation>;
+
+    /// Maps a single 4 KiB page at `virt` to `phys`.
+    ///
+    /// # Errors
+    /// See [`Self::map_sized`].
+    ///
+    /// # Safety
+    /// See [`Self::map_sized`].
+    #[inline]
+    unsafe fn map(
+        &mut self,
+        virt: VirtAddr,
+        phys: PhysAddr,
+        flags: PageFlags,
+    ) -> Result<(), MapError> {
+        self.map_sized(virt, phys, PageSize::Size4KiB, flags)
+    }
+}
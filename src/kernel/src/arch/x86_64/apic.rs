@@ -0,0 +1,415 @@
+//======---------------------------------------------------------------======//
+//                                                                           //
+// Copyright 2022-2023 Evan Cox <evanacox00@gmail.com>. All rights reserved. //
+//                                                                           //
+// Use of this source code is governed by a BSD-style license that can be    //
+// found in the LICENSE.txt file at the root of this project, or at the      //
+// following link: https://opensource.org/licenses/BSD-3-Clause              //
+//                                                                           //
+//======---------------------------------------------------------------======//
+
+//! The local APIC, in either xAPIC (MMIO) or x2APIC (MSR) mode.
+//!
+//! x2APIC is used whenever the CPU supports it. Every CPU has its own
+//! local APIC, but they're all accessed the same way, so only the access
+//! mode is global. The legacy 8259 PICs are remapped and masked when the
+//! APIC is brought up, since nothing uses them.
+//!
+//! The APIC timer is calibrated against channel 2 of the PIT, which runs
+//! at a known frequency. The TSC frequency is measured at the same time.
+
+// there's no scheduler yet to drive with the periodic timer or to send most
+// kinds of IPIs
+#![allow(dead_code)]
+
+use crate::arch::x86_64::cpu::{self, inb, outb};
+use crate::arch::x86_64::interrupts::{self, TrapFrame};
+use crate::arch::x86_64::tsc;
+use crate::mm::{mmio, PhysAddr, VirtAddr};
+use crate::utility::KSpinOnceCell;
+use core::hint;
+use core::sync::atomic::{self, AtomicU64, Ordering};
+use log::{error, info, warn};
+
+/// The vector that the APIC timer fires on.
+pub const TIMER_VECTOR: u8 = 0xF0;
+
+/// The vector that APIC errors are reported on.
+pub const ERROR_VECTOR: u8 = 0xFE;
+
+/// The vector that spurious interrupts are delivered on.
+pub const SPURIOUS_VECTOR: u8 = 0xFF;
+
+const IA32_APIC_BASE: u32 = 0x1B;
+const IA32_TSC_DEADLINE: u32 = 0x6E0;
+const X2APIC_MSR_BASE: u32 = 0x800;
+
+const APIC_BASE_ENABLE: u64 = 1 << 11;
+const APIC_BASE_X2APIC: u64 = 1 << 10;
+const APIC_BASE_ADDRESS: u64 = 0x000F_FFFF_FFFF_F000;
+
+const REG_ID: u32 = 0x20;
+const REG_TPR: u32 = 0x80;
+const REG_EOI: u32 = 0xB0;
+const REG_SVR: u32 = 0xF0;
+const REG_ESR: u32 = 0x280;
+const REG_ICR_LOW: u32 = 0x300;
+const REG_ICR_HIGH: u32 = 0x310;
+const REG_LVT_TIMER: u32 = 0x320;
+const REG_LVT_LINT0: u32 = 0x350;
+const REG_LVT_ERROR: u32 = 0x370;
+const REG_TIMER_INITIAL: u32 = 0x380;
+const REG_TIMER_CURRENT: u32 = 0x390;
+const REG_TIMER_DIVIDE: u32 = 0x3E0;
+
+const SVR_ENABLE: u32 = 1 << 8;
+const LVT_MASKED: u32 = 1 << 16;
+const TIMER_PERIODIC: u32 = 1 << 17;
+const TIMER_TSC_DEADLINE: u32 = 2 << 17;
+const TIMER_DIVIDE_BY_16: u32 = 0b0011;
+const ICR_DELIVERY_PENDING: u32 = 1 << 12;
+const ICR_LEVEL_ASSERT: u32 = 1 << 14;
+
+const PIT_FREQUENCY: u64 = 1_193_182;
+const PIT_CHANNEL2_DATA: u16 = 0x42;
+const PIT_COMMAND: u16 = 0x43;
+const PIT_CHANNEL2_GATE: u16 = 0x61;
+const CALIBRATION_MS: u64 = 10;
+
+#[derive(Copy, Clone, Debug)]
+enum Access {
+    XApic(VirtAddr),
+    X2Apic,
+}
+
+#[derive(Copy, Clone, Debug)]
+struct Timer {
+    // with the divider set to 16
+    ticks_per_ms: u64,
+    tsc_deadline: bool,
+}
+
+static ACCESS: KSpinOnceCell<Access> = KSpinOnceCell::uninit();
+
+static TIMER: KSpinOnceCell<Timer> = KSpinOnceCell::uninit();
+
+static TICKS: AtomicU64 = AtomicU64::new(0);
+
+/// Who an IPI should be delivered to.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum IpiTarget {
+    /// A single CPU, identified by its APIC ID.
+    Cpu(u32),
+    /// Only the current CPU.
+    Current,
+    /// Every CPU, including the current one.
+    All,
+    /// Every CPU except the current one.
+    Others,
+}
+
+impl IpiTarget {
+    const fn shorthand(self) -> u32 {
+        let bits = match self {
+            Self::Cpu(_) => 0b00,
+            Self::Current => 0b01,
+            Self::All => 0b10,
+            Self::Others => 0b11,
+        };
+
+        bits << 18
+    }
+}
+
+fn has_x2apic() -> bool {
+    cpu::cpuid(1, 0)[2] & (1 << 21) != 0
+}
+
+fn has_tsc_deadline() -> bool {
+    cpu::cpuid(1, 0)[2] & (1 << 24) != 0
+}
+
+fn read(reg: u32) -> u32 {
+    match *ACCESS.get() {
+        Access::XApic(base) => unsafe {
+            base.add(u64::from(reg)).as_mut_ptr::<u32>().read_volatile()
+        },
+        #[allow(clippy::cast_possible_truncation)]
+        Access::X2Apic => unsafe { cpu::rdmsr(X2APIC_MSR_BASE + (reg >> 4)) as u32 },
+    }
+}
+
+fn write(reg: u32, value: u32) {
+    match *ACCESS.get() {
+        Access::XApic(base) => unsafe {
+            base.add(u64::from(reg))
+                .as_mut_ptr::<u32>()
+                .write_volatile(value);
+        },
+        Access::X2Apic => unsafe { cpu::wrmsr(X2APIC_MSR_BASE + (reg >> 4), u64::from(value)) },
+    }
+}
+
+fn write_icr(destination: u32, low: u32) {
+    match *ACCESS.get() {
+        Access::XApic(_) => {
+            write(REG_ICR_HIGH, destination << 24);
+            write(REG_ICR_LOW, low);
+
+            while read(REG_ICR_LOW) & ICR_DELIVERY_PENDING != 0 {
+                hint::spin_loop();
+            }
+        }
+        // the ICR is a single 64-bit register in x2APIC mode
+        Access::X2Apic => unsafe {
+            cpu::wrmsr(
+                X2APIC_MSR_BASE + (REG_ICR_LOW >> 4),
+                (u64::from(destination) << 32) | u64::from(low),
+            );
+        },
+    }
+}
+
+// remaps the PICs out of the exception vectors, then masks every line
+fn disable_legacy_pic() {
+    const PIC1_COMMAND: u16 = 0x20;
+    const PIC1_DATA: u16 = 0x21;
+    const PIC2_COMMAND: u16 = 0xA0;
+    const PIC2_DATA: u16 = 0xA1;
+
+    unsafe {
+        outb(PIC1_COMMAND, 0x11);
+        outb(PIC2_COMMAND, 0x11);
+        outb(PIC1_DATA, interrupts::FIRST_DEVICE_VECTOR);
+        outb(PIC2_DATA, interrupts::FIRST_DEVICE_VECTOR + 8);
+        outb(PIC1_DATA, 0x04);
+        outb(PIC2_DATA, 0x02);
+        outb(PIC1_DATA, 0x01);
+        outb(PIC2_DATA, 0x01);
+        outb(PIC1_DATA, 0xFF);
+        outb(PIC2_DATA, 0xFF);
+    }
+}
+
+// enables the APIC of the current CPU in whatever mode was picked
+fn enable_current() {
+    unsafe {
+        let base = cpu::rdmsr(IA32_APIC_BASE) | APIC_BASE_ENABLE;
+
+        // going straight from disabled to x2APIC mode isn't a valid
+        // transition, the APIC has to be enabled in xAPIC mode first and
+        // then switched over with a second write
+        cpu::wrmsr(IA32_APIC_BASE, base);
+
+        if matches!(ACCESS.get(), Access::X2Apic) {
+            cpu::wrmsr(IA32_APIC_BASE, base | APIC_BASE_X2APIC);
+        }
+    }
+
+    write(REG_TPR, 0);
+    write(REG_LVT_TIMER, LVT_MASKED | u32::from(TIMER_VECTOR));
+    write(REG_LVT_LINT0, LVT_MASKED);
+    write(REG_LVT_ERROR, u32::from(ERROR_VECTOR));
+
+    // the ESR needs a write to latch the current errors before reading
+    write(REG_ESR, 0);
+    write(REG_ESR, 0);
+
+    write(REG_SVR, SVR_ENABLE | u32::from(SPURIOUS_VECTOR));
+    end_of_interrupt();
+}
+
+// measures the APIC timer and TSC against a fixed amount of PIT ticks,
+// returning `(apic ticks per ms, tsc hz)`
+fn calibrate() -> (u64, u64) {
+    #[allow(clippy::cast_possible_truncation)]
+    let count = (PIT_FREQUENCY * CALIBRATION_MS / 1000) as u16;
+    let [low, high] = count.to_le_bytes();
+
+    write(REG_TIMER_DIVIDE, TIMER_DIVIDE_BY_16);
+
+    unsafe {
+        // gate low and speaker off while programming, then channel 2 in
+        // mode 0 (interrupt on terminal count) with a 16-bit count
+        let gate = inb(PIT_CHANNEL2_GATE) & !0b11;
+
+        outb(PIT_CHANNEL2_GATE, gate);
+        outb(PIT_COMMAND, 0b1011_0000);
+        outb(PIT_CHANNEL2_DATA, low);
+        outb(PIT_CHANNEL2_DATA, high);
+
+        // raising the gate starts the count
+        outb(PIT_CHANNEL2_GATE, gate | 1);
+        write(REG_TIMER_INITIAL, u32::MAX);
+
+        let tsc_start = cpu::rdtsc();
+
+        // bit 5 is the output of channel 2, which goes high at zero
+        while inb(PIT_CHANNEL2_GATE) & (1 << 5) == 0 {
+            hint::spin_loop();
+        }
+
+        let apic_ticks = u64::from(u32::MAX - read(REG_TIMER_CURRENT));
+        let tsc_ticks = cpu::rdtsc() - tsc_start;
+
+        write(REG_TIMER_INITIAL, 0);
+        outb(PIT_CHANNEL2_GATE, gate);
+
+        (
+            apic_ticks / CALIBRATION_MS,
+            tsc_ticks * (1000 / CALIBRATION_MS),
+        )
+    }
+}
+
+fn timer_interrupt(_: &mut TrapFrame) {
+    TICKS.fetch_add(1, Ordering::Relaxed);
+}
+
+fn error_interrupt(_: &mut TrapFrame) {
+    write(REG_ESR, 0);
+
+    error!("local apic error! esr = {:#x}", read(REG_ESR));
+}
+
+const fn spurious_interrupt(_: &mut TrapFrame) {}
+
+/// Brings up the local APIC on the bootstrap processor, disables the
+/// legacy PICs and calibrates the APIC timer.
+///
+/// # Panics
+/// Panics if the APIC registers can't be mapped in xAPIC mode.
+pub fn init() {
+    let access = if has_x2apic() {
+        Access::X2Apic
+    } else {
+        let base = PhysAddr::new(unsafe { cpu::rdmsr(IA32_APIC_BASE) } & APIC_BASE_ADDRESS);
+
+        Access::XApic(mmio::map(base, 0x1000).expect("unable to map the local apic"))
+    };
+
+    let _ = ACCESS.set(access);
+
+    disable_legacy_pic();
+    enable_current();
+
+    for (vector, handler) in [
+        (
+            TIMER_VECTOR,
+            timer_interrupt as interrupts::InterruptHandler,
+        ),
+        (ERROR_VECTOR, error_interrupt),
+        (SPURIOUS_VECTOR, spurious_interrupt),
+    ] {
+        if let Err(e) = interrupts::register(vector, handler) {
+            warn!("unable to register apic vector {vector:#x}: {e:?}");
+        }
+    }
+
+    let (ticks_per_ms, tsc_hz) = calibrate();
+
+    tsc::set_frequency(tsc_hz);
+
+    if !tsc::is_invariant() {
+        warn!("tsc is not invariant, timekeeping may drift");
+    }
+
+    let _ = TIMER.set(Timer {
+        ticks_per_ms,
+        tsc_deadline: has_tsc_deadline(),
+    });
+
+    info!(
+        "local apic {} initialized in {} mode! timer: {ticks_per_ms} ticks/ms, tsc: {tsc_hz} Hz",
+        id(),
+        match access {
+            Access::XApic(_) => "xapic",
+            Access::X2Apic => "x2apic",
+        }
+    );
+}
+
+/// Brings up the local APIC on an application processor, using the mode
+/// and calibration that [`init`] picked.
+pub fn init_current() {
+    enable_current();
+}
+
+/// The APIC ID of the current CPU.
+pub fn id() -> u32 {
+    match ACCESS.get() {
+        Access::XApic(_) => read(REG_ID) >> 24,
+        Access::X2Apic => read(REG_ID),
+    }
+}
+
+/// Signals the end of an interrupt to the current CPU's APIC.
+#[inline]
+pub fn end_of_interrupt() {
+    write(REG_EOI, 0);
+}
+
+/// Sends an IPI on `vector` to `target`.
+pub fn send_ipi(target: IpiTarget, vector: u8) {
+    let destination = match target {
+        IpiTarget::Cpu(id) => id,
+        _ => 0,
+    };
+
+    write_icr(
+        destination,
+        target.shorthand() | ICR_LEVEL_ASSERT | u32::from(vector),
+    );
+}
+
+fn timer_count(nanos: u64) -> u32 {
+    let ticks = TIMER.get().ticks_per_ms.saturating_mul(nanos) / 1_000_000;
+
+    u32::try_from(ticks).unwrap_or(u32::MAX).max(1)
+}
+
+/// Fires the timer once, `nanos` nanoseconds from now.
+pub fn timer_oneshot(nanos: u64) {
+    write(REG_TIMER_DIVIDE, TIMER_DIVIDE_BY_16);
+    write(REG_LVT_TIMER, u32::from(TIMER_VECTOR));
+    write(REG_TIMER_INITIAL, timer_count(nanos));
+}
+
+/// Fires the timer every `nanos` nanoseconds until stopped.
+pub fn timer_periodic(nanos: u64) {
+    write(REG_TIMER_DIVIDE, TIMER_DIVIDE_BY_16);
+    write(REG_LVT_TIMER, TIMER_PERIODIC | u32::from(TIMER_VECTOR));
+    write(REG_TIMER_INITIAL, timer_count(nanos));
+}
+
+/// Fires the timer once the TSC reaches `deadline`. Falls back to a
+/// one-shot timer if the CPU doesn't support TSC-deadline mode.
+pub fn timer_deadline(deadline: u64) {
+    if !TIMER.get().tsc_deadline {
+        timer_oneshot(tsc::ticks_to_nanos(deadline.saturating_sub(cpu::rdtsc())));
+
+        return;
+    }
+
+    write(REG_LVT_TIMER, TIMER_TSC_DEADLINE | u32::from(TIMER_VECTOR));
+
+    // the LVT write has to be visible before the deadline is armed
+    atomic::fence(Ordering::SeqCst);
+
+    unsafe { cpu::wrmsr(IA32_TSC_DEADLINE, deadline) };
+}
+
+/// Stops the timer, whatever mode it's in.
+pub fn timer_stop() {
+    write(REG_LVT_TIMER, LVT_MASKED | u32::from(TIMER_VECTOR));
+    write(REG_TIMER_INITIAL, 0);
+
+    if TIMER.get().tsc_deadline {
+        unsafe { cpu::wrmsr(IA32_TSC_DEADLINE, 0) };
+    }
+}
+
+/// The number of timer interrupts that have been handled, across every CPU.
+pub fn ticks() -> u64 {
+    TICKS.load(Ordering::Relaxed)
+}
@@ -0,0 +1,323 @@
+//======---------------------------------------------------------------======//
+//                                                                           //
+// Copyright 2022-2023 Evan Cox <evanacox00@gmail.com>. All rights reserved. //
+//                                                                           //
+// Use of this source code is governed by a BSD-style license that can be    //
+// found in the LICENSE.txt file at the root of this project, or at the      //
+// following link: https://opensource.org/licenses/BSD-3-Clause              //
+//                                                                           //
+//======---------------------------------------------------------------======//
+
+//! Thin wrappers around privileged x86-64 instructions and registers.
+
+use core::arch::asm;
+use core::arch::x86_64::__cpuid_count;
+use core::ptr;
+
+/// Reads the `CR0` register.
+#[inline]
+pub fn read_cr0() -> u64 {
+    let value: u64;
+
+    unsafe {
+        asm!("mov {}, cr0", out(reg) value, options(nomem, nostack, preserves_flags));
+    }
+
+    value
+}
+
+/// Reads the `CR2` register (the address that caused the last page fault).
+#[inline]
+pub fn read_cr2() -> u64 {
+    let value: u64;
+
+    unsafe {
+        asm!("mov {}, cr2", out(reg) value, options(nomem, nostack, preserves_flags));
+    }
+
+    value
+}
+
+/// Reads the `CR3` register (the physical address of the top-level page table).
+#[inline]
+pub fn read_cr3() -> u64 {
+    let value: u64;
+
+    unsafe {
+        asm!("mov {}, cr3", out(reg) value, options(nomem, nostack, preserves_flags));
+    }
+
+    value
+}
+
+/// Reads the `CR4` register.
+#[inline]
+pub fn read_cr4() -> u64 {
+    let value: u64;
+
+    unsafe {
+        asm!("mov {}, cr4", out(reg) value, options(nomem, nostack, preserves_flags));
+    }
+
+    value
+}
+
+/// Invalidates the TLB entry for the page containing `addr` on the current CPU.
+#[inline]
+pub fn invlpg(addr: u64) {
+    unsafe {
+        asm!("invlpg [{}]", in(reg) addr, options(nostack, preserves_flags));
+    }
+}
+
+/// Executes `cpuid` with a given leaf and sub-leaf, returning `[eax, ebx, ecx, edx]`.
+#[inline]
+pub fn cpuid(leaf: u32, subleaf: u32) -> [u32; 4] {
+    let result = __cpuid_count(leaf, subleaf);
+
+    [result.eax, result.ebx, result.ecx, result.edx]
+}
+
+/// The highest extended `cpuid` leaf that the CPU supports.
+#[inline]
+pub fn max_extended_leaf() -> u32 {
+    cpuid(0x8000_0000, 0)[0]
+}
+
+/// Checks whether the CPU supports 1 GiB pages.
+#[inline]
+pub fn has_1gib_pages() -> bool {
+    max_extended_leaf() >= 0x8000_0001 && cpuid(0x8000_0001, 0)[3] & (1 << 26) != 0
+}
+
+/// Checks whether the CPU supports `rdrand`.
+#[inline]
+pub fn has_rdrand() -> bool {
+    cpuid(1, 0)[2] & (1 << 30) != 0
+}
+
+/// Checks whether the CPU supports `rdseed`.
+#[inline]
+pub fn has_rdseed() -> bool {
+    cpuid(0, 0)[0] >= 7 && cpuid(7, 0)[1] & (1 << 18) != 0
+}
+
+/// Executes `rdrand`, returning `None` if the CPU didn't have a value ready.
+///
+/// # Safety
+/// The CPU must support `rdrand`, see [`has_rdrand`].
+#[inline]
+pub unsafe fn rdrand() -> Option<u64> {
+    let (value, ok): (u64, u8);
+
+    asm!("rdrand {}", "setc {}", out(reg) value, out(reg_byte) ok, options(nomem, nostack));
+
+    (ok != 0).then_some(value)
+}
+
+/// Executes `rdseed`, returning `None` if the CPU didn't have a value ready.
+///
+/// # Safety
+/// The CPU must support `rdseed`, see [`has_rdseed`].
+#[inline]
+pub unsafe fn rdseed() -> Option<u64> {
+    let (value, ok): (u64, u8);
+
+    asm!("rdseed {}", "setc {}", out(reg) value, out(reg_byte) ok, options(nomem, nostack));
+
+    (ok != 0).then_some(value)
+}
+
+/// The operand of `lgdt`/`lidt` (and what `sgdt`/`sidt` store).
+#[repr(C, packed(2))]
+#[derive(Copy, Clone, Debug)]
+pub struct DescriptorTablePointer {
+    /// The size of the table in bytes, minus one.
+    pub limit: u16,
+    /// The virtual address of the table.
+    pub base: u64,
+}
+
+/// Loads a new GDT.
+///
+/// # Safety
+/// The table must be valid and must stay alive (and not move) while loaded.
+#[inline]
+pub unsafe fn lgdt(pointer: &DescriptorTablePointer) {
+    asm!("lgdt [{}]", in(reg) pointer, options(readonly, nostack, preserves_flags));
+}
+
+/// Loads a new IDT.
+///
+/// # Safety
+/// The table must be valid and must stay alive (and not move) while loaded.
+#[inline]
+pub unsafe fn lidt(pointer: &DescriptorTablePointer) {
+    asm!("lidt [{}]", in(reg) pointer, options(readonly, nostack, preserves_flags));
+}
+
+/// Gets the location of the currently loaded GDT.
+#[inline]
+pub fn sgdt() -> DescriptorTablePointer {
+    let mut pointer = DescriptorTablePointer { limit: 0, base: 0 };
+
+    unsafe {
+        asm!("sgdt [{}]", in(reg) ptr::addr_of_mut!(pointer), options(nostack, preserves_flags));
+    }
+
+    pointer
+}
+
+/// Loads the task register with a TSS selector.
+///
+/// # Safety
+/// The selector must refer to an available TSS descriptor in the current GDT.
+#[inline]
+pub unsafe fn ltr(selector: u16) {
+    asm!("ltr {:x}", in(reg) selector, options(nostack, preserves_flags));
+}
+
+/// Reads a byte from an I/O port.
+///
+/// # Safety
+/// Reading from the port must not violate memory safety.
+#[inline]
+pub unsafe fn inb(port: u16) -> u8 {
+    let mut value: u8;
+
+    asm!("in al, dx", out("al") value, in("dx") port, options(nomem, nostack, preserves_flags));
+
+    value
+}
+
+/// Writes a byte to an I/O port.
+///
+/// # Safety
+/// Writing to the port must not violate memory safety.
+#[inline]
+pub unsafe fn outb(port: u16, value: u8) {
+    asm!("out dx, al", in("dx") port, in("al") value, options(nomem, nostack, preserves_flags));
+}
+
+/// Reads a word from an I/O port.
+///
+/// # Safety
+/// Reading from the port must not violate memory safety.
+#[inline]
+pub unsafe fn inw(port: u16) -> u16 {
+    let mut value: u16;
+
+    asm!("in ax, dx", out("ax") value, in("dx") port, options(nomem, nostack, preserves_flags));
+
+    value
+}
+
+/// Writes a word to an I/O port.
+///
+/// # Safety
+/// Writing to the port must not violate memory safety.
+#[inline]
+pub unsafe fn outw(port: u16, value: u16) {
+    asm!("out dx, ax", in("dx") port, in("ax") value, options(nomem, nostack, preserves_flags));
+}
+
+/// Reads a doubleword from an I/O port.
+///
+/// # Safety
+/// Reading from the port must not violate memory safety.
+#[inline]
+pub unsafe fn inl(port: u16) -> u32 {
+    let mut value: u32;
+
+    asm!("in eax, dx", out("eax") value, in("dx") port, options(nomem, nostack, preserves_flags));
+
+    value
+}
+
+/// Writes a doubleword to an I/O port.
+///
+/// # Safety
+/// Writing to the port must not violate memory safety.
+#[inline]
+pub unsafe fn outl(port: u16, value: u32) {
+    asm!("out dx, eax", in("dx") port, in("eax") value, options(nomem, nostack, preserves_flags));
+}
+
+/// Reads a model-specific register.
+///
+/// # Safety
+/// The MSR must exist, otherwise this raises `#GP`.
+#[inline]
+pub unsafe fn rdmsr(msr: u32) -> u64 {
+    let (low, high): (u32, u32);
+
+    asm!("rdmsr", in("ecx") msr, out("eax") low, out("edx") high, options(nomem, nostack, preserves_flags));
+
+    (u64::from(high) << 32) | u64::from(low)
+}
+
+/// Writes a model-specific register.
+///
+/// # Safety
+/// The MSR must exist, and writing `value` to it must not violate memory safety.
+#[inline]
+pub unsafe fn wrmsr(msr: u32, value: u64) {
+    #[allow(clippy::cast_possible_truncation)]
+    let (low, high) = (value as u32, (value >> 32) as u32);
+
+    asm!("wrmsr", in("ecx") msr, in("eax") low, in("edx") high, options(nostack, preserves_flags));
+}
+
+/// Reads the time-stamp counter.
+#[inline]
+pub fn rdtsc() -> u64 {
+    let (low, high): (u32, u32);
+
+    unsafe {
+        asm!("rdtsc", out("eax") low, out("edx") high, options(nomem, nostack, preserves_flags));
+    }
+
+    (u64::from(high) << 32) | u64::from(low)
+}
+
+/// Enables interrupts on the current CPU.
+///
+/// # Safety
+/// The IDT must be loaded, and nothing that interrupt handlers touch may
+/// be in an inconsistent state.
+#[inline]
+pub unsafe fn enable_interrupts() {
+    asm!("sti", options(nomem, nostack));
+}
+
+/// Enables interrupts and halts until the next one arrives.
+///
+/// `sti` only takes effect after the next instruction, so an interrupt
+/// can't sneak in between the two and leave the CPU halted.
+///
+/// # Safety
+/// See [`enable_interrupts`].
+#[inline]
+pub unsafe fn enable_interrupts_and_halt() {
+    asm!("sti", "hlt", options(nomem, nostack));
+}
+
+/// Disables interrupts on the current CPU.
+#[inline]
+pub fn disable_interrupts() {
+    unsafe {
+        asm!("cli", options(nomem, nostack));
+    }
+}
+
+/// Checks whether interrupts are enabled on the current CPU.
+#[inline]
+pub fn interrupts_enabled() -> bool {
+    let flags: u64;
+
+    unsafe {
+        asm!("pushfq", "pop {}", out(reg) flags, options(nomem, preserves_flags));
+    }
+
+    flags & (1 << 9) != 0
+}
@@ -0,0 +1,289 @@
+//======---------------------------------------------------------------======//
+//                                                                           //
+// Copyright 2022-2023 Evan Cox <evanacox00@gmail.com>. All rights reserved. //
+//                                                                           //
+// Use of this source code is governed by a BSD-style license that can be    //
+// found in the LICENSE.txt file at the root of this project, or at the      //
+// following link: https://opensource.org/licenses/BSD-3-Clause              //
+//                                                                           //
+//======---------------------------------------------------------------======//
+
+//! The GDT and TSS.
+//!
+//! Every CPU gets its own [`CpuTables`], since every CPU needs its own TSS
+//! (and the TSS descriptor lives in the GDT). The segment layout is the same
+//! everywhere, and is ordered so that `syscall`/`sysret` can be used:
+//!
+//! | selector | segment            |
+//! |----------|--------------------|
+//! | `0x00`   | null               |
+//! | `0x08`   | kernel code        |
+//! | `0x10`   | kernel data        |
+//! | `0x18`   | user data          |
+//! | `0x20`   | user code          |
+//! | `0x28`   | TSS (two entries)  |
+
+// there's no user mode to enter yet, the user selectors and TSS stack are
+// only here so the GDT layout is final
+#![allow(dead_code)]
+
+use crate::arch::x86_64::cpu::{self, DescriptorTablePointer};
+use crate::mm::VirtAddr;
+use core::arch::asm;
+use core::cell::UnsafeCell;
+use core::mem;
+use core::ptr;
+use log::trace;
+
+/// The selector for kernel code.
+pub const KERNEL_CODE_SELECTOR: u16 = 0x08;
+
+/// The selector for kernel data (and the kernel stack segment).
+pub const KERNEL_DATA_SELECTOR: u16 = 0x10;
+
+/// The selector for user data, with RPL 3.
+pub const USER_DATA_SELECTOR: u16 = 0x18 | 3;
+
+/// The selector for user code, with RPL 3.
+pub const USER_CODE_SELECTOR: u16 = 0x20 | 3;
+
+/// The selector for the TSS.
+pub const TSS_SELECTOR: u16 = 0x28;
+
+/// The IST index (as used by IDT entries) of the double fault stack.
+pub const DOUBLE_FAULT_IST: u8 = 1;
+
+/// The IST index (as used by IDT entries) of the NMI stack.
+pub const NMI_IST: u8 = 2;
+
+/// The IST index (as used by IDT entries) of the machine check stack.
+pub const MACHINE_CHECK_IST: u8 = 3;
+
+/// The number of IST stacks that each CPU needs.
+pub const IST_STACKS: usize = 3;
+
+/// The size of each IST stack.
+pub const IST_STACK_SIZE: usize = 16 * 1024;
+
+const GDT_ENTRIES: usize = (GDT_LIMIT as usize + 1) / 8;
+const GDT_LIMIT: u16 = 7 * 8 - 1;
+const TSS_SIZE: u16 = 104;
+const TSS_INDEX: usize = (TSS_SELECTOR / 8) as usize;
+
+// 64-bit, present, code segments have the readable bit and data segments
+// the writable bit set. base and limit are ignored in long mode
+const KERNEL_CODE: u64 = 0x00AF_9A00_0000_FFFF;
+const KERNEL_DATA: u64 = 0x00CF_9200_0000_FFFF;
+const USER_DATA: u64 = 0x00CF_F200_0000_FFFF;
+const USER_CODE: u64 = 0x00AF_FA00_0000_FFFF;
+
+/// The 64-bit task state segment.
+///
+/// In long mode this doesn't hold any task state, it just holds the stacks
+/// that the CPU switches to when entering ring 0 or taking an interrupt
+/// that uses an IST entry.
+#[repr(C, packed(4))]
+#[derive(Copy, Clone)]
+pub struct TaskStateSegment {
+    reserved0: u32,
+    /// The stacks loaded when switching to rings 0, 1 and 2.
+    pub privilege_stacks: [u64; 3],
+    reserved1: u64,
+    /// The interrupt stack table, entry `n` here is IST index `n + 1`.
+    pub interrupt_stacks: [u64; 7],
+    reserved2: u64,
+    reserved3: u16,
+    /// The offset of the I/O permission bitmap from the start of the TSS.
+    pub iomap_base: u16,
+}
+
+impl TaskStateSegment {
+    /// Creates an empty TSS with no stacks and no I/O permission bitmap.
+    #[must_use]
+    pub const fn new() -> Self {
+        Self {
+            reserved0: 0,
+            privilege_stacks: [0; 3],
+            reserved1: 0,
+            interrupt_stacks: [0; 7],
+            reserved2: 0,
+            reserved3: 0,
+            iomap_base: TSS_SIZE,
+        }
+    }
+}
+
+impl Default for TaskStateSegment {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+const _: () = assert!(mem::size_of::<TaskStateSegment>() == TSS_SIZE as usize);
+
+#[repr(C, align(16))]
+struct Gdt([u64; GDT_ENTRIES]);
+
+/// The GDT and TSS for a single CPU.
+///
+/// These must live forever once installed, and are only ever touched by
+/// the CPU that they are installed on.
+pub struct CpuTables {
+    gdt: UnsafeCell<Gdt>,
+    tss: UnsafeCell<TaskStateSegment>,
+}
+
+impl CpuTables {
+    /// Creates a set of tables, nothing is filled in until they're installed.
+    #[must_use]
+    pub const fn new() -> Self {
+        Self {
+            gdt: UnsafeCell::new(Gdt([0; GDT_ENTRIES])),
+            tss: UnsafeCell::new(TaskStateSegment::new()),
+        }
+    }
+
+    /// Fills in the tables, loads them on the current CPU and reloads
+    /// every segment register to use them.
+    ///
+    /// `ist` contains the top of each IST stack, in IST index order.
+    ///
+    /// # Safety
+    /// This must be called at most once per set of tables, and the tables
+    /// must not be installed on any other CPU.
+    pub unsafe fn install(&'static self, ist: [VirtAddr; IST_STACKS]) {
+        let tss = self.tss.get();
+        let mut interrupt_stacks = [0; 7];
+
+        for (slot, top) in interrupt_stacks.iter_mut().zip(ist) {
+            *slot = top.as_u64();
+        }
+
+        (*tss).interrupt_stacks = interrupt_stacks;
+
+        let (low, high) = tss_descriptor(tss as u64);
+        let gdt = &mut *self.gdt.get();
+
+        gdt.0 = [0, KERNEL_CODE, KERNEL_DATA, USER_DATA, USER_CODE, low, high];
+
+        cpu::lgdt(&DescriptorTablePointer {
+            limit: GDT_LIMIT,
+            base: ptr::from_ref(gdt) as u64,
+        });
+
+        reload_segments();
+        cpu::ltr(TSS_SELECTOR);
+    }
+}
+
+impl Default for CpuTables {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// only the owning CPU ever touches the tables after installing them
+unsafe impl Sync for CpuTables {}
+
+/// A statically-allocated stack, for use before the heap exists.
+#[repr(C, align(16))]
+pub struct StaticStack<const N: usize>(UnsafeCell<[u8; N]>);
+
+impl<const N: usize> StaticStack<N> {
+    /// Creates a zeroed stack.
+    #[must_use]
+    pub const fn new() -> Self {
+        Self(UnsafeCell::new([0; N]))
+    }
+
+    /// Gets the address of the top of the stack, i.e. the initial stack pointer.
+    pub fn top(&self) -> VirtAddr {
+        VirtAddr::from_ptr(self.0.get()).add(N as u64)
+    }
+}
+
+impl<const N: usize> Default for StaticStack<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// the stack is only ever used by the CPU, never through the reference
+unsafe impl<const N: usize> Sync for StaticStack<N> {}
+
+static BSP_TABLES: CpuTables = CpuTables::new();
+
+static BSP_IST_STACKS: [StaticStack<IST_STACK_SIZE>; IST_STACKS] =
+    [const { StaticStack::new() }; IST_STACKS];
+
+/// Installs the GDT and TSS for the bootstrap processor.
+///
+/// The bootloader's GDT is not used after this.
+pub fn init() {
+    unsafe { BSP_TABLES.install(BSP_IST_STACKS.each_ref().map(StaticStack::top)) };
+
+    trace!("loaded gdt and tss for the bsp");
+}
+
+/// Sets the stack that the current CPU switches to when going from
+/// user mode into the kernel. This needs to be updated on every switch
+/// to a different thread.
+///
+/// # Safety
+/// `top` must be the top of a valid, mapped kernel stack that is not in use.
+pub unsafe fn set_kernel_stack(top: VirtAddr) {
+    ptr::addr_of_mut!((*current_tss()).privilege_stacks[0]).write_unaligned(top.as_u64());
+}
+
+/// Gets the stack that the current CPU will switch to when going from
+/// user mode into the kernel.
+pub fn kernel_stack() -> VirtAddr {
+    VirtAddr::new(unsafe { ptr::addr_of!((*current_tss()).privilege_stacks[0]).read_unaligned() })
+}
+
+// builds the two GDT entries for an available 64-bit TSS at `base`
+const fn tss_descriptor(base: u64) -> (u64, u64) {
+    let limit = TSS_SIZE as u64 - 1;
+    let present = 1 << 47;
+    let available_tss = 0x9 << 40;
+    let low = (limit & 0xFFFF)
+        | ((base & 0xFF_FFFF) << 16)
+        | available_tss
+        | present
+        | (((limit >> 16) & 0xF) << 48)
+        | (((base >> 24) & 0xFF) << 56);
+
+    (low, base >> 32)
+}
+
+// the TSS of the current CPU, found by decoding the TSS descriptor in
+// whatever GDT is currently loaded
+fn current_tss() -> *mut TaskStateSegment {
+    let gdt = cpu::sgdt().base as *const u64;
+
+    unsafe {
+        let low = gdt.add(TSS_INDEX).read();
+        let high = gdt.add(TSS_INDEX + 1).read();
+        let base = ((low >> 16) & 0xFF_FFFF) | (((low >> 56) & 0xFF) << 24) | (high << 32);
+
+        base as *mut TaskStateSegment
+    }
+}
+
+// reloads `cs` with a far return, and the data segments directly
+unsafe fn reload_segments() {
+    asm!(
+        "push {code}",
+        "lea {tmp}, [rip + 2f]",
+        "push {tmp}",
+        "retfq",
+        "2:",
+        "mov ds, {data:x}",
+        "mov es, {data:x}",
+        "mov ss, {data:x}",
+        code = in(reg) u64::from(KERNEL_CODE_SELECTOR),
+        data = in(reg) KERNEL_DATA_SELECTOR,
+        tmp = out(reg) _,
+        options(preserves_flags),
+    );
+}
@@ -0,0 +1,63 @@
+//======---------------------------------------------------------------======//
+//                                                                           //
+// Copyright 2022-2023 Evan Cox <evanacox00@gmail.com>. All rights reserved. //
+//                                                                           //
+// Use of this source code is governed by a BSD-style license that can be    //
+// found in the LICENSE.txt file at the root of this project, or at the      //
+// following link: https://opensource.org/licenses/BSD-3-Clause              //
+//                                                                           //
+//======---------------------------------------------------------------======//
+
+use crate::arch::x86_64::cpu;
+use crate::utility::KSpinLazy;
+use core::hint;
+
+static HAS_RDRAND: KSpinLazy<bool> = KSpinLazy::new(cpu::has_rdrand);
+
+static HAS_RDSEED: KSpinLazy<bool> = KSpinLazy::new(cpu::has_rdseed);
+
+// intel recommends retrying `rdrand` 10 times before giving up, `rdseed`
+// runs dry much more easily so it gets more chances
+const RDRAND_RETRIES: usize = 10;
+const RDSEED_RETRIES: usize = 100;
+
+/// Reads 64 bits straight from the CPU's entropy source (`rdseed`),
+/// if there is one and it has anything ready.
+///
+/// The output is conditioned but not stretched, so every bit of it is
+/// supposed to be a bit of entropy.
+pub fn hardware_seed() -> Option<u64> {
+    if !*HAS_RDSEED {
+        return None;
+    }
+
+    (0..RDSEED_RETRIES).find_map(|_| {
+        let value = unsafe { cpu::rdseed() };
+
+        if value.is_none() {
+            hint::spin_loop();
+        }
+
+        value
+    })
+}
+
+/// Reads 64 bits from the CPU's random number generator (`rdrand`), if
+/// there is one.
+///
+/// This is a DRBG that the hardware reseeds from its entropy source
+/// every so often, so its output isn't all entropy.
+pub fn hardware_random() -> Option<u64> {
+    if !*HAS_RDRAND {
+        return None;
+    }
+
+    (0..RDRAND_RETRIES).find_map(|_| unsafe { cpu::rdrand() })
+}
+
+/// A fast, high-resolution counter that timing jitter can be measured
+/// with. It doesn't have any particular unit.
+#[inline]
+pub fn cycle_counter() -> u64 {
+    cpu::rdtsc()
+}
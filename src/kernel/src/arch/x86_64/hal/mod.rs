@@ -13,8 +13,21 @@
 //! This provides the x86_64-specific implementation of various system
 //! functions that the kernel needs to be able to perform.
 
+mod entropy;
+mod interrupts;
+mod percpu;
+mod platform;
 mod serial;
 mod spin;
+mod wait;
 
+pub use crate::arch::x86_64::paging::PageTables;
+pub use crate::arch::x86_64::power::*;
+pub use crate::arch::x86_64::tsc::nanos_since_boot;
+pub use entropy::*;
+pub use interrupts::*;
+pub use percpu::*;
+pub use platform::*;
 pub use serial::*;
 pub use spin::*;
+pub use wait::*;
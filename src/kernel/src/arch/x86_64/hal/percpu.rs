@@ -0,0 +1,43 @@
+//======---------------------------------------------------------------======//
+//                                                                           //
+// Copyright 2022-2023 Evan Cox <evanacox00@gmail.com>. All rights reserved. //
+//                                                                           //
+// Use of this source code is governed by a BSD-style license that can be    //
+// found in the LICENSE.txt file at the root of this project, or at the      //
+// following link: https://opensource.org/licenses/BSD-3-Clause              //
+//                                                                           //
+//======---------------------------------------------------------------======//
+
+use crate::arch::x86_64::cpu;
+use crate::mm::VirtAddr;
+use core::arch::asm;
+
+const IA32_GS_BASE: u32 = 0xC000_0101;
+const IA32_KERNEL_GS_BASE: u32 = 0xC000_0102;
+
+/// Points the current CPU's per-CPU base register at `base`.
+///
+/// The first word at `base` must be `base` itself, that's what
+/// [`percpu_base`] actually reads.
+///
+/// # Safety
+/// `base` must stay valid forever.
+pub unsafe fn set_percpu_base(base: VirtAddr) {
+    cpu::wrmsr(IA32_GS_BASE, base.as_u64());
+
+    // the kernel's GS base gets swapped in here whenever user mode is
+    // entered, so user mode starts out with nothing
+    cpu::wrmsr(IA32_KERNEL_GS_BASE, 0);
+}
+
+/// Gets the current CPU's per-CPU base.
+#[inline]
+pub fn percpu_base() -> VirtAddr {
+    let base: u64;
+
+    unsafe {
+        asm!("mov {}, gs:[0]", out(reg) base, options(readonly, nostack, preserves_flags));
+    }
+
+    VirtAddr::new(base)
+}
@@ -0,0 +1,37 @@
+//======---------------------------------------------------------------======//
+//                                                                           //
+// Copyright 2022-2023 Evan Cox <evanacox00@gmail.com>. All rights reserved. //
+//                                                                           //
+// Use of this source code is governed by a BSD-style license that can be    //
+// found in the LICENSE.txt file at the root of this project, or at the      //
+// following link: https://opensource.org/licenses/BSD-3-Clause              //
+//                                                                           //
+//======---------------------------------------------------------------======//
+use crate::arch::x86_64::hal::SerialPort;
+use crate::arch::x86_64::{apic, cpu, ioapic, smp};
+use crate::drivers::kserial;
+use ksupport::sync::BasicMutex;
+use log::{trace, warn};
+
+/// Initializes the parts of the platform that need memory management to
+/// be up, i.e. the interrupt controllers and timers. This needs the ACPI
+/// tables to be available.
+///
+/// Interrupts are enabled on the current CPU once this returns.
+pub fn init() {
+    apic::init();
+    ioapic::init();
+
+    let rx = kserial::serial()
+        .lock()
+        .enable_rx_interrupt(SerialPort::COM1_IRQ);
+
+    match rx {
+        Ok(vector) => trace!("serial input is delivered on vector {vector:#x}"),
+        Err(e) => warn!("unable to route serial interrupts, falling back to polling: {e:?}"),
+    }
+
+    smp::start();
+
+    unsafe { cpu::enable_interrupts() };
+}
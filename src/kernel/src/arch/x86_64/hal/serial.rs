@@ -8,10 +8,37 @@
 //                                                                           //
 //======---------------------------------------------------------------======//
 
+use crate::arch::x86_64::cpu::{inb, outb};
+use crate::arch::x86_64::interrupts::TrapFrame;
+use crate::arch::x86_64::ioapic::{self, IoApicError};
 use crate::drivers::kserial::SerialBackend;
-use core::arch::asm;
 use core::fmt::Write;
+use core::sync::atomic::{AtomicU16, Ordering};
 use core::{fmt, hint};
+use ksupport::ring::SpscRing;
+
+const RX_BUFFER_SIZE: usize = 256;
+
+// bytes received by the interrupt handler, waiting to be `recv`ed. the
+// handler is the only producer, so it never has to take a lock
+static RX_BUFFER: SpscRing<u8, RX_BUFFER_SIZE> = SpscRing::new();
+
+// the port that receive interrupts are enabled for, or 0 if none are
+static RX_PORT: AtomicU16 = AtomicU16::new(0);
+
+fn rx_interrupt(_: &mut TrapFrame) {
+    let port = unsafe { SerialPort::with_port(RX_PORT.load(Ordering::Acquire)) };
+    let mut buffer = RX_BUFFER.producer();
+
+    // the data has to be read either way, if the buffer is full it's dropped
+    while port.is_data_ready() {
+        let byte = unsafe { inb(port.port_data()) };
+
+        if let Some(buffer) = &mut buffer {
+            let _ = buffer.push(byte);
+        }
+    }
+}
 
 /// Wraps a standard x86-64 serial port (using `inb` and `outb`).
 ///
@@ -23,20 +50,6 @@ pub struct SerialPort {
     port: u16,
 }
 
-#[inline(always)]
-unsafe fn inb(port: u16) -> u8 {
-    let mut value: u8;
-
-    asm!("in al, dx", out("al") value, in("dx") port);
-
-    value
-}
-
-#[inline(always)]
-unsafe fn outb(port: u16, value: u8) {
-    asm!("out dx, al", in("dx") port, in("al") value);
-}
-
 impl SerialPort {
     /// Creates a serial port with a given port number.
     ///
@@ -51,6 +64,24 @@ impl SerialPort {
         Self { port }
     }
 
+    /// The ISA IRQ that COM1 is connected to.
+    pub const COM1_IRQ: u8 = 4;
+
+    /// Makes received bytes get delivered through ISA IRQ `irq` instead
+    /// of being polled for, returning the vector that they arrive on.
+    ///
+    /// Only one port can receive through interrupts at a time.
+    ///
+    /// # Errors
+    /// Fails if the IRQ can't be routed through the I/O APIC.
+    pub fn enable_rx_interrupt(&self, irq: u8) -> Result<u8, IoApicError> {
+        RX_PORT.store(self.port, Ordering::Release);
+
+        ioapic::route_isa(irq, rx_interrupt).inspect_err(|_| {
+            RX_PORT.store(0, Ordering::Release);
+        })
+    }
+
     /// Creates a serial port with the default COM1 port (`0x3F8`).
     ///
     /// # Safety
@@ -133,12 +164,13 @@ impl SerialBackend for SerialPort {
         unsafe { outb(self.port_data(), byte) }
     }
 
-    fn recv(&mut self) -> u8 {
-        while !self.is_data_ready() {
-            hint::spin_loop();
+    fn recv(&mut self) -> Option<u8> {
+        if RX_PORT.load(Ordering::Acquire) == self.port {
+            return RX_BUFFER.consumer().and_then(|mut rx| rx.pop());
         }
 
-        unsafe { inb(self.port_data()) }
+        self.is_data_ready()
+            .then(|| unsafe { inb(self.port_data()) })
     }
 }
 
@@ -0,0 +1,51 @@
+//======---------------------------------------------------------------======//
+//                                                                           //
+// Copyright 2022-2023 Evan Cox <evanacox00@gmail.com>. All rights reserved. //
+//                                                                           //
+// Use of this source code is governed by a BSD-style license that can be    //
+// found in the LICENSE.txt file at the root of this project, or at the      //
+// following link: https://opensource.org/licenses/BSD-3-Clause              //
+//                                                                           //
+//======---------------------------------------------------------------======//
+
+use crate::arch::x86_64::{apic, cpu, smp, tsc};
+use core::hint;
+
+/// Sleeps the current CPU until it's woken up by [`wake_cpu`], an
+/// interrupt arrives, or [`nanos_since_boot`](super::nanos_since_boot)
+/// reaches `deadline`. This can also return early for no reason.
+///
+/// Interrupts are enabled while the CPU sleeps, and disabled again
+/// before this returns.
+///
+/// # Safety
+/// Interrupts must be disabled when this is called, and it must be
+/// safe for interrupt handlers to run.
+pub unsafe fn wait_for_wake(deadline: Option<u64>) {
+    let Some(deadline) = deadline else {
+        cpu::enable_interrupts_and_halt();
+        cpu::disable_interrupts();
+
+        return;
+    };
+
+    // the timer can't be programmed until the TSC has been measured, and
+    // halting without it could sleep forever. waits before that just poll
+    if tsc::frequency() == 0 {
+        hint::spin_loop();
+
+        return;
+    }
+
+    apic::timer_deadline(tsc::nanos_to_ticks(deadline));
+
+    cpu::enable_interrupts_and_halt();
+    cpu::disable_interrupts();
+
+    apic::timer_stop();
+}
+
+/// Wakes up CPU `index` if it's sleeping in [`wait_for_wake`].
+pub fn wake_cpu(index: usize) {
+    smp::wake(index);
+}
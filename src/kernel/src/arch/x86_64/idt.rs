@@ -0,0 +1,198 @@
+//======---------------------------------------------------------------======//
+//                                                                           //
+// Copyright 2022-2023 Evan Cox <evanacox00@gmail.com>. All rights reserved. //
+//                                                                           //
+// Use of this source code is governed by a BSD-style license that can be    //
+// found in the LICENSE.txt file at the root of this project, or at the      //
+// following link: https://opensource.org/licenses/BSD-3-Clause              //
+//                                                                           //
+//======---------------------------------------------------------------======//
+
+//! The IDT, and the entry stubs for every vector.
+//!
+//! Every one of the 256 vectors gets a tiny stub that pushes the vector
+//! number (and a fake error code for vectors where the CPU doesn't push one),
+//! and then jumps to a common stub. The common stub saves every register
+//! into a [`TrapFrame`] and calls into [`interrupts`].
+//!
+//! One IDT is shared by every CPU, it only needs to be built once.
+
+use crate::arch::x86_64::cpu::{self, DescriptorTablePointer};
+use crate::arch::x86_64::gdt::{self, DOUBLE_FAULT_IST, MACHINE_CHECK_IST, NMI_IST};
+use crate::arch::x86_64::interrupts::{self, TrapFrame};
+use crate::utility::KSpinOnceCell;
+use core::arch::global_asm;
+use core::{mem, ptr};
+use log::trace;
+
+const VECTORS: usize = 256;
+const STUB_SIZE: u64 = 16;
+const IDT_LIMIT: u16 = 256 * 16 - 1;
+
+// present, DPL 0, 64-bit interrupt gate
+const INTERRUPT_GATE: u8 = 0x8E;
+
+// present, DPL 3, 64-bit interrupt gate. lets `int3` work from user mode
+const USER_INTERRUPT_GATE: u8 = 0xEE;
+
+// the vectors where the CPU pushes an error code need to be kept in
+// sync with `interrupts::has_error_code`
+global_asm!(
+    ".section .text",
+    ".balign 16",
+    ".global beryl_trap_stubs",
+    "beryl_trap_stubs:",
+    ".set vector, 0",
+    ".rept 256",
+    "    .balign 16",
+    "    .if vector == 8 || (vector >= 10 && vector <= 14) || vector == 17 || vector == 21 || vector == 29 || vector == 30",
+    "    .else",
+    // push 0
+    "        .byte 0x6A, 0x00",
+    "    .endif",
+    // push imm32 `vector`
+    "    .byte 0x68",
+    "    .long vector",
+    "    jmp beryl_trap_common",
+    "    .set vector, vector + 1",
+    ".endr",
+    "",
+    "beryl_trap_common:",
+    // coming from user mode, the GS base is still the user's. the saved CS
+    // is after the vector, the error code and the saved RIP
+    "    test byte ptr [rsp + 24], 3",
+    "    jz 1f",
+    "    swapgs",
+    "1:",
+    "    push r15",
+    "    push r14",
+    "    push r13",
+    "    push r12",
+    "    push r11",
+    "    push r10",
+    "    push r9",
+    "    push r8",
+    "    push rbp",
+    "    push rdi",
+    "    push rsi",
+    "    push rdx",
+    "    push rcx",
+    "    push rbx",
+    "    push rax",
+    "    cld",
+    // the CPU aligns the stack before pushing its frame, and the whole
+    // trap frame is 22 quadwords, so the stack is still aligned here
+    "    mov rdi, rsp",
+    "    call {dispatch}",
+    "    pop rax",
+    "    pop rbx",
+    "    pop rcx",
+    "    pop rdx",
+    "    pop rsi",
+    "    pop rdi",
+    "    pop rbp",
+    "    pop r8",
+    "    pop r9",
+    "    pop r10",
+    "    pop r11",
+    "    pop r12",
+    "    pop r13",
+    "    pop r14",
+    "    pop r15",
+    // the vector and error code
+    "    add rsp, 16",
+    "    test byte ptr [rsp + 8], 3",
+    "    jz 1f",
+    "    swapgs",
+    "1:",
+    "    iretq",
+    dispatch = sym interrupts::trap_dispatch,
+);
+
+extern "C" {
+    static beryl_trap_stubs: u8;
+}
+
+const _: () = assert!(mem::size_of::<TrapFrame>() == 22 * 8);
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+struct Gate {
+    offset_low: u16,
+    selector: u16,
+    ist: u8,
+    attributes: u8,
+    offset_mid: u16,
+    offset_high: u32,
+    reserved: u32,
+}
+
+impl Gate {
+    // the handler's address is split up across three fields
+    #[allow(clippy::cast_possible_truncation)]
+    const fn new(handler: u64, ist: u8, attributes: u8) -> Self {
+        Self {
+            offset_low: handler as u16,
+            selector: gdt::KERNEL_CODE_SELECTOR,
+            ist,
+            attributes,
+            offset_mid: (handler >> 16) as u16,
+            offset_high: (handler >> 32) as u32,
+            reserved: 0,
+        }
+    }
+}
+
+#[repr(C, align(16))]
+struct Idt([Gate; VECTORS]);
+
+static IDT: KSpinOnceCell<Idt> = KSpinOnceCell::uninit();
+
+// NMIs, double faults and machine checks can happen with a bad stack,
+// so they always switch to a known-good one
+const fn ist_for(vector: usize) -> u8 {
+    match vector {
+        interrupts::NMI => NMI_IST,
+        interrupts::DOUBLE_FAULT => DOUBLE_FAULT_IST,
+        interrupts::MACHINE_CHECK => MACHINE_CHECK_IST,
+        _ => 0,
+    }
+}
+
+/// Builds the IDT and loads it on the current CPU.
+///
+/// This relies on the GDT from [`gdt::init`] being loaded, since some
+/// vectors use IST stacks.
+pub fn init() {
+    let stubs = ptr::addr_of!(beryl_trap_stubs) as u64;
+
+    let _ = IDT.set(Idt(core::array::from_fn(|vector| {
+        let attributes = if vector == interrupts::BREAKPOINT {
+            USER_INTERRUPT_GATE
+        } else {
+            INTERRUPT_GATE
+        };
+
+        Gate::new(
+            stubs + vector as u64 * STUB_SIZE,
+            ist_for(vector),
+            attributes,
+        )
+    })));
+
+    load();
+
+    trace!("loaded idt");
+}
+
+/// Loads the (already built) IDT on the current CPU.
+pub fn load() {
+    let idt = IDT.get();
+
+    unsafe {
+        cpu::lidt(&DescriptorTablePointer {
+            limit: IDT_LIMIT,
+            base: ptr::from_ref(idt) as u64,
+        });
+    }
+}
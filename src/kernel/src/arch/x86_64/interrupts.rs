@@ -0,0 +1,404 @@
+//======---------------------------------------------------------------======//
+//                                                                           //
+// Copyright 2022-2023 Evan Cox <evanacox00@gmail.com>. All rights reserved. //
+//                                                                           //
+// Use of this source code is governed by a BSD-style license that can be    //
+// found in the LICENSE.txt file at the root of this project, or at the      //
+// following link: https://opensource.org/licenses/BSD-3-Clause              //
+//                                                                           //
+//======---------------------------------------------------------------======//
+
+//! Interrupt and exception dispatch.
+//!
+//! Every vector ends up in [`trap_dispatch`] with a full [`TrapFrame`].
+//! CPU exceptions (vectors `0` through `31`) are fatal: the state of the
+//! CPU is dumped through `klog` and the CPU is halted. Everything else
+//! goes to whatever handler was registered for the vector.
+
+use crate::arch::x86_64::hal;
+use crate::arch::x86_64::{apic, cpu};
+use crate::percpu::this_cpu;
+use crate::random;
+use core::sync::atomic::{AtomicPtr, Ordering};
+use core::{fmt, mem, ptr};
+use log::{error, warn};
+
+/// The vector of non-maskable interrupts.
+pub const NMI: usize = 2;
+
+/// The vector of `#BP`, i.e. what `int3` raises.
+pub const BREAKPOINT: usize = 3;
+
+/// The vector of `#DF`.
+pub const DOUBLE_FAULT: usize = 8;
+
+/// The vector of `#PF`.
+pub const PAGE_FAULT: usize = 14;
+
+/// The vector of `#MC`.
+pub const MACHINE_CHECK: usize = 18;
+
+/// The vector of `#CP`.
+pub const CONTROL_PROTECTION: usize = 21;
+
+/// The first vector that isn't reserved for CPU exceptions.
+pub const FIRST_DEVICE_VECTOR: u8 = 32;
+
+/// The first vector reserved for the kernel's own use (timers, IPIs, etc).
+/// Vectors from here up are never handed out by [`allocate_vector`].
+pub const FIRST_SYSTEM_VECTOR: u8 = 0xF0;
+
+const EXCEPTION_NAMES: [&str; 32] = [
+    "divide error",
+    "debug",
+    "non-maskable interrupt",
+    "breakpoint",
+    "overflow",
+    "bound range exceeded",
+    "invalid opcode",
+    "device not available",
+    "double fault",
+    "coprocessor segment overrun",
+    "invalid tss",
+    "segment not present",
+    "stack-segment fault",
+    "general protection fault",
+    "page fault",
+    "reserved",
+    "x87 floating-point exception",
+    "alignment check",
+    "machine check",
+    "simd floating-point exception",
+    "virtualization exception",
+    "control protection exception",
+    "reserved",
+    "reserved",
+    "reserved",
+    "reserved",
+    "reserved",
+    "reserved",
+    "hypervisor injection exception",
+    "vmm communication exception",
+    "security exception",
+    "reserved",
+];
+
+/// The state of the interrupted code, as saved by the entry stubs.
+///
+/// Handlers can modify this, whatever is in here when the handler
+/// returns is what gets restored.
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+#[allow(missing_docs)]
+pub struct TrapFrame {
+    pub rax: u64,
+    pub rbx: u64,
+    pub rcx: u64,
+    pub rdx: u64,
+    pub rsi: u64,
+    pub rdi: u64,
+    pub rbp: u64,
+    pub r8: u64,
+    pub r9: u64,
+    pub r10: u64,
+    pub r11: u64,
+    pub r12: u64,
+    pub r13: u64,
+    pub r14: u64,
+    pub r15: u64,
+    /// The vector that was raised.
+    pub vector: u64,
+    /// The error code pushed by the CPU, or `0` for vectors without one.
+    pub error_code: u64,
+    pub rip: u64,
+    pub cs: u64,
+    pub rflags: u64,
+    pub rsp: u64,
+    pub ss: u64,
+}
+
+impl TrapFrame {
+    /// Checks whether the interrupted code was running in user mode.
+    #[must_use]
+    pub const fn is_user(&self) -> bool {
+        self.cs & 3 != 0
+    }
+}
+
+/// A handler for a device vector.
+pub type InterruptHandler = fn(&mut TrapFrame);
+
+/// The ways that registering an interrupt handler can fail.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum InterruptError {
+    /// The vector is reserved for CPU exceptions.
+    ReservedVector,
+    /// The vector already has a handler.
+    InUse,
+    /// Every vector that can be allocated already has a handler.
+    NoFreeVectors,
+}
+
+const DEVICE_VECTORS: usize = 256 - FIRST_DEVICE_VECTOR as usize;
+
+// handlers are read from interrupt context, so they can't be behind a lock
+static HANDLERS: [AtomicPtr<()>; DEVICE_VECTORS] =
+    [const { AtomicPtr::new(ptr::null_mut()) }; DEVICE_VECTORS];
+
+fn slot(vector: u8) -> Result<&'static AtomicPtr<()>, InterruptError> {
+    vector
+        .checked_sub(FIRST_DEVICE_VECTOR)
+        .map(|index| &HANDLERS[index as usize])
+        .ok_or(InterruptError::ReservedVector)
+}
+
+/// Registers `handler` to be called whenever `vector` is raised.
+///
+/// # Errors
+/// Fails if `vector` is a CPU exception vector or if it already has a handler.
+pub fn register(vector: u8, handler: InterruptHandler) -> Result<(), InterruptError> {
+    slot(vector)?
+        .compare_exchange(
+            ptr::null_mut(),
+            handler as *mut (),
+            Ordering::AcqRel,
+            Ordering::Acquire,
+        )
+        .map(|_| ())
+        .map_err(|_| InterruptError::InUse)
+}
+
+/// Removes the handler for `vector`, returning it if there was one.
+pub fn unregister(vector: u8) -> Option<InterruptHandler> {
+    let old = slot(vector).ok()?.swap(ptr::null_mut(), Ordering::AcqRel);
+
+    // the only non-null values ever stored are `InterruptHandler`s
+    (!old.is_null()).then(|| unsafe { mem::transmute::<*mut (), InterruptHandler>(old) })
+}
+
+/// Finds a free vector below [`FIRST_SYSTEM_VECTOR`] and registers
+/// `handler` for it, returning the vector.
+///
+/// # Errors
+/// Fails if every allocatable vector already has a handler.
+pub fn allocate_vector(handler: InterruptHandler) -> Result<u8, InterruptError> {
+    (FIRST_DEVICE_VECTOR..FIRST_SYSTEM_VECTOR)
+        .find(|&vector| register(vector, handler).is_ok())
+        .ok_or(InterruptError::NoFreeVectors)
+}
+
+fn handler(vector: u8) -> Option<InterruptHandler> {
+    let raw = slot(vector).ok()?.load(Ordering::Acquire);
+
+    // see `unregister`
+    (!raw.is_null()).then(|| unsafe { mem::transmute::<*mut (), InterruptHandler>(raw) })
+}
+
+/// Whether the CPU pushes an error code for a given vector. This needs
+/// to be kept in sync with the entry stubs.
+#[must_use]
+pub const fn has_error_code(vector: usize) -> bool {
+    matches!(vector, 8 | 10..=14 | 17 | 21 | 29 | 30)
+}
+
+/// Called by the entry stubs for every vector.
+pub extern "C" fn trap_dispatch(frame: &mut TrapFrame) {
+    let Ok(vector) = u8::try_from(frame.vector) else {
+        unreachable!("entry stubs only exist for 256 vectors");
+    };
+
+    if vector < FIRST_DEVICE_VECTOR {
+        exception(frame, usize::from(vector));
+
+        return;
+    }
+
+    let cpu = this_cpu();
+
+    cpu.interrupt_entered();
+
+    // when interrupts arrive is a little unpredictable
+    random::add_interrupt_timing(vector);
+
+    let (locks, interrupts_off) = (cpu.lock_depth(), cpu.interrupts_off_depth());
+
+    if let Some(handler) = handler(vector) {
+        handler(frame);
+    } else {
+        warn!("unhandled interrupt on vector {vector:#x}");
+    }
+
+    debug_assert!(
+        cpu.lock_depth() == locks && cpu.interrupts_off_depth() == interrupts_off,
+        "the handler for vector {vector:#x} didn't release everything it took"
+    );
+
+    cpu.interrupt_exited();
+
+    // spurious interrupts aren't in service, so they must not be acknowledged
+    if vector != apic::SPURIOUS_VECTOR {
+        apic::end_of_interrupt();
+    }
+}
+
+fn exception(frame: &TrapFrame, vector: usize) {
+    if vector == BREAKPOINT {
+        warn!("breakpoint hit at {:#x}", frame.rip);
+
+        return;
+    }
+
+    error!(
+        "cpu exception! vector {vector} ({}) in {} mode",
+        EXCEPTION_NAMES[vector],
+        if frame.is_user() { "user" } else { "kernel" }
+    );
+
+    if has_error_code(vector) {
+        error!(
+            "error code = {:#x} ({})",
+            frame.error_code,
+            ErrorCode {
+                vector,
+                code: frame.error_code
+            }
+        );
+    }
+
+    if vector == PAGE_FAULT {
+        error!("faulting address (cr2) = {:#018x}", cpu::read_cr2());
+    }
+
+    dump_registers(frame);
+
+    unsafe { hal::privileged_halt_thread() }
+}
+
+fn dump_registers(frame: &TrapFrame) {
+    error!(
+        "rax = {:#018x}  rbx = {:#018x}  rcx = {:#018x}  rdx = {:#018x}",
+        frame.rax, frame.rbx, frame.rcx, frame.rdx
+    );
+    error!(
+        "rsi = {:#018x}  rdi = {:#018x}  rbp = {:#018x}  rsp = {:#018x}",
+        frame.rsi, frame.rdi, frame.rbp, frame.rsp
+    );
+    error!(
+        "r8  = {:#018x}  r9  = {:#018x}  r10 = {:#018x}  r11 = {:#018x}",
+        frame.r8, frame.r9, frame.r10, frame.r11
+    );
+    error!(
+        "r12 = {:#018x}  r13 = {:#018x}  r14 = {:#018x}  r15 = {:#018x}",
+        frame.r12, frame.r13, frame.r14, frame.r15
+    );
+    error!(
+        "rip = {:#018x}  cs  = {:#06x}  rflags = {:#010x}  ss  = {:#06x}",
+        frame.rip, frame.cs, frame.rflags, frame.ss
+    );
+    error!(
+        "cr0 = {:#018x}  cr2 = {:#018x}  cr3 = {:#018x}  cr4 = {:#018x}",
+        cpu::read_cr0(),
+        cpu::read_cr2(),
+        cpu::read_cr3(),
+        cpu::read_cr4()
+    );
+}
+
+// formats an error code based on what the vector says it means
+struct ErrorCode {
+    vector: usize,
+    code: u64,
+}
+
+impl ErrorCode {
+    fn fmt_page_fault(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let code = self.code;
+
+        write!(
+            f,
+            "{} {} in {} mode",
+            if code & (1 << 0) != 0 {
+                "protection violation on"
+            } else {
+                "non-present page on"
+            },
+            if code & (1 << 4) != 0 {
+                "instruction fetch"
+            } else if code & (1 << 1) != 0 {
+                "write"
+            } else {
+                "read"
+            },
+            if code & (1 << 2) != 0 {
+                "user"
+            } else {
+                "kernel"
+            }
+        )?;
+
+        for (bit, name) in [
+            (3, "reserved bit set"),
+            (5, "protection key"),
+            (6, "shadow stack"),
+            (15, "sgx"),
+        ] {
+            if code & (1 << bit) != 0 {
+                write!(f, ", {name}")?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn fmt_selector(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let code = self.code;
+
+        if code == 0 {
+            return write!(f, "no selector");
+        }
+
+        let table = match (code >> 1) & 0b11 {
+            0 => "gdt",
+            2 => "ldt",
+            _ => "idt",
+        };
+
+        write!(f, "{table} index {}", (code >> 3) & 0x1FFF)?;
+
+        if code & 1 != 0 {
+            write!(f, ", external event")?;
+        }
+
+        Ok(())
+    }
+
+    fn fmt_control_protection(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let reason = match self.code & 0x7FFF {
+            1 => "near ret",
+            2 => "far ret or iret",
+            3 => "missing endbranch",
+            4 => "rstorssp",
+            5 => "setssbsy",
+            _ => "unknown reason",
+        };
+
+        write!(f, "{reason}")?;
+
+        if self.code & (1 << 15) != 0 {
+            write!(f, ", in enclave")?;
+        }
+
+        Ok(())
+    }
+}
+
+impl fmt::Display for ErrorCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.vector {
+            PAGE_FAULT => self.fmt_page_fault(f),
+            10..=13 => self.fmt_selector(f),
+            CONTROL_PROTECTION => self.fmt_control_protection(f),
+            _ => write!(f, "no extra information"),
+        }
+    }
+}
@@ -0,0 +1,355 @@
+//======---------------------------------------------------------------======//
+//                                                                           //
+// Copyright 2022-2023 Evan Cox <evanacox00@gmail.com>. All rights reserved. //
+//                                                                           //
+// Use of this source code is governed by a BSD-style license that can be    //
+// found in the LICENSE.txt file at the root of this project, or at the      //
+// following link: https://opensource.org/licenses/BSD-3-Clause              //
+//                                                                           //
+//======---------------------------------------------------------------======//
+
+//! I/O APIC routing of global system interrupts (GSIs).
+//!
+//! Every I/O APIC and every ISA interrupt source override is discovered
+//! through the MADT. ISA IRQs are identity-mapped to GSIs (active high,
+//! edge triggered) unless an override says otherwise.
+//!
+//! Every redirection entry starts out masked, nothing is delivered until
+//! a driver routes it somewhere.
+
+// every IRQ that gets routed stays enabled, and drivers other than the serial
+// port don't route their own IRQs yet
+#![allow(dead_code)]
+
+use crate::acpi::{Madt, MadtEntry};
+use crate::arch::x86_64::apic;
+use crate::arch::x86_64::interrupts::{self, InterruptError, InterruptHandler};
+use crate::mm::{mmio, PhysAddr, VirtAddr};
+use crate::utility::{KSpinMutex, KSpinOnceCell};
+use ksupport::sync::BasicMutex;
+use log::{info, trace, warn};
+
+const MAX_IO_APICS: usize = 8;
+const ISA_IRQS: usize = 16;
+
+const REG_SELECT: u64 = 0x00;
+const REG_WINDOW: u64 = 0x10;
+const REG_ID: u32 = 0x00;
+const REG_VERSION: u32 = 0x01;
+const REG_REDIRECTION_BASE: u32 = 0x10;
+
+const ENTRY_ACTIVE_LOW: u64 = 1 << 13;
+const ENTRY_LEVEL_TRIGGERED: u64 = 1 << 15;
+const ENTRY_MASKED: u64 = 1 << 16;
+
+/// The polarity of an interrupt line.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Polarity {
+    /// The line is asserted when it's high.
+    ActiveHigh,
+    /// The line is asserted when it's low.
+    ActiveLow,
+}
+
+/// How an interrupt line signals an interrupt.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum TriggerMode {
+    /// An interrupt is signalled by the line changing state.
+    Edge,
+    /// An interrupt is signalled for as long as the line is asserted.
+    Level,
+}
+
+/// Where and how a GSI should be delivered.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Route {
+    /// The vector to deliver the interrupt on.
+    pub vector: u8,
+    /// The APIC ID of the CPU to deliver the interrupt to.
+    pub destination: u32,
+    /// The polarity of the line.
+    pub polarity: Polarity,
+    /// The trigger mode of the line.
+    pub trigger: TriggerMode,
+}
+
+/// The ways that routing an interrupt can fail.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum IoApicError {
+    /// No I/O APIC handles the GSI.
+    NoSuchGsi,
+    /// The destination APIC ID can't be addressed by an I/O APIC.
+    InvalidDestination,
+    /// A vector couldn't be registered for the interrupt.
+    Vector(InterruptError),
+}
+
+impl From<InterruptError> for IoApicError {
+    fn from(e: InterruptError) -> Self {
+        Self::Vector(e)
+    }
+}
+
+struct IoApic {
+    base: VirtAddr,
+    gsi_base: u32,
+    entries: u32,
+}
+
+impl IoApic {
+    fn read(&self, reg: u32) -> u32 {
+        unsafe {
+            self.base
+                .add(REG_SELECT)
+                .as_mut_ptr::<u32>()
+                .write_volatile(reg);
+            self.base
+                .add(REG_WINDOW)
+                .as_mut_ptr::<u32>()
+                .read_volatile()
+        }
+    }
+
+    fn write(&self, reg: u32, value: u32) {
+        unsafe {
+            self.base
+                .add(REG_SELECT)
+                .as_mut_ptr::<u32>()
+                .write_volatile(reg);
+            self.base
+                .add(REG_WINDOW)
+                .as_mut_ptr::<u32>()
+                .write_volatile(value);
+        }
+    }
+
+    fn read_entry(&self, index: u32) -> u64 {
+        let reg = REG_REDIRECTION_BASE + index * 2;
+
+        u64::from(self.read(reg)) | (u64::from(self.read(reg + 1)) << 32)
+    }
+
+    #[allow(clippy::cast_possible_truncation)]
+    fn write_entry(&self, index: u32, entry: u64) {
+        let reg = REG_REDIRECTION_BASE + index * 2;
+
+        // keep the entry masked while the halves are inconsistent
+        self.write(reg, (entry as u32) | ENTRY_MASKED as u32);
+        self.write(reg + 1, (entry >> 32) as u32);
+        self.write(reg, entry as u32);
+    }
+
+    const fn handles(&self, gsi: u32) -> bool {
+        gsi >= self.gsi_base && gsi < self.gsi_base + self.entries
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+struct IsaRoute {
+    gsi: u32,
+    polarity: Polarity,
+    trigger: TriggerMode,
+}
+
+struct IoApics {
+    apics: [Option<KSpinMutex<IoApic>>; MAX_IO_APICS],
+    isa: [IsaRoute; ISA_IRQS],
+}
+
+static IO_APICS: KSpinOnceCell<IoApics> = KSpinOnceCell::uninit();
+
+// decodes MPS INTI flags, "conforming" means whatever the bus default is
+const fn decode_flags(flags: u16, default: IsaRoute) -> (Polarity, TriggerMode) {
+    let polarity = match flags & 0b11 {
+        0b11 => Polarity::ActiveLow,
+        0b01 => Polarity::ActiveHigh,
+        _ => default.polarity,
+    };
+
+    let trigger = match (flags >> 2) & 0b11 {
+        0b11 => TriggerMode::Level,
+        0b01 => TriggerMode::Edge,
+        _ => default.trigger,
+    };
+
+    (polarity, trigger)
+}
+
+/// Finds every I/O APIC and ISA override in the MADT, and masks every
+/// redirection entry.
+pub fn init() {
+    let mut apics = [const { None }; MAX_IO_APICS];
+    // ISA IRQs are identity-mapped to GSIs unless they're overridden
+    #[allow(clippy::cast_possible_truncation)]
+    let mut isa = core::array::from_fn(|irq| IsaRoute {
+        gsi: irq as u32,
+        polarity: Polarity::ActiveHigh,
+        trigger: TriggerMode::Edge,
+    });
+
+    let Some(madt) = Madt::get() else {
+        warn!("no madt found, no i/o apics are available");
+
+        let _ = IO_APICS.set(IoApics { apics, isa });
+
+        return;
+    };
+
+    let mut count = 0;
+
+    for entry in madt.entries() {
+        match entry {
+            MadtEntry::IoApic {
+                id,
+                address,
+                gsi_base,
+            } => {
+                if count == MAX_IO_APICS {
+                    warn!("too many i/o apics, ignoring i/o apic {id}");
+
+                    continue;
+                }
+
+                let Ok(base) = mmio::map(PhysAddr::new(u64::from(address)), 0x20) else {
+                    warn!("unable to map i/o apic {id} at {address:#x}");
+
+                    continue;
+                };
+
+                let mut apic = IoApic {
+                    base,
+                    gsi_base,
+                    entries: 0,
+                };
+
+                apic.entries = ((apic.read(REG_VERSION) >> 16) & 0xFF) + 1;
+
+                for index in 0..apic.entries {
+                    apic.write_entry(index, ENTRY_MASKED);
+                }
+
+                info!(
+                    "found i/o apic {} at {address:#x}, handling gsi {gsi_base}..{}",
+                    apic.read(REG_ID) >> 24,
+                    gsi_base + apic.entries
+                );
+
+                apics[count] = Some(KSpinMutex::new(apic));
+                count += 1;
+            }
+            MadtEntry::InterruptSourceOverride {
+                bus: 0,
+                source,
+                gsi,
+                flags,
+            } if usize::from(source) < ISA_IRQS => {
+                let route = &mut isa[usize::from(source)];
+                let (polarity, trigger) = decode_flags(flags, *route);
+
+                trace!("isa irq {source} is overridden to gsi {gsi} ({polarity:?}, {trigger:?})");
+
+                *route = IsaRoute {
+                    gsi,
+                    polarity,
+                    trigger,
+                };
+            }
+            _ => {}
+        }
+    }
+
+    let _ = IO_APICS.set(IoApics { apics, isa });
+}
+
+// runs `f` on the I/O APIC that handles `gsi`, along with the entry index
+fn with_entry<R>(gsi: u32, f: impl FnOnce(&IoApic, u32) -> R) -> Result<R, IoApicError> {
+    let apic = IO_APICS
+        .get()
+        .apics
+        .iter()
+        .flatten()
+        .find(|apic| apic.lock().handles(gsi))
+        .ok_or(IoApicError::NoSuchGsi)?
+        .lock();
+
+    Ok(f(&apic, gsi - apic.gsi_base))
+}
+
+/// Routes `gsi` according to `route`, and unmasks it.
+///
+/// # Errors
+/// Fails if no I/O APIC handles `gsi`, or if `route.destination` can't
+/// be used as a physical destination.
+pub fn route(gsi: u32, route: Route) -> Result<(), IoApicError> {
+    let destination =
+        u8::try_from(route.destination).map_err(|_| IoApicError::InvalidDestination)?;
+    let mut entry = u64::from(route.vector) | (u64::from(destination) << 56);
+
+    if route.polarity == Polarity::ActiveLow {
+        entry |= ENTRY_ACTIVE_LOW;
+    }
+
+    if route.trigger == TriggerMode::Level {
+        entry |= ENTRY_LEVEL_TRIGGERED;
+    }
+
+    with_entry(gsi, |apic, index| apic.write_entry(index, entry))
+}
+
+/// Masks `gsi`, no interrupts are delivered from it until it's unmasked.
+///
+/// # Errors
+/// Fails if no I/O APIC handles `gsi`.
+pub fn mask(gsi: u32) -> Result<(), IoApicError> {
+    with_entry(gsi, |apic, index| {
+        apic.write_entry(index, apic.read_entry(index) | ENTRY_MASKED);
+    })
+}
+
+/// Unmasks `gsi`, using whatever route it had before.
+///
+/// # Errors
+/// Fails if no I/O APIC handles `gsi`.
+pub fn unmask(gsi: u32) -> Result<(), IoApicError> {
+    with_entry(gsi, |apic, index| {
+        apic.write_entry(index, apic.read_entry(index) & !ENTRY_MASKED);
+    })
+}
+
+/// Gets the GSI that an ISA IRQ is connected to, after applying overrides.
+///
+/// # Panics
+/// Panics if `irq` is not an ISA IRQ (i.e. is 16 or higher).
+pub fn isa_gsi(irq: u8) -> u32 {
+    IO_APICS.get().isa[usize::from(irq)].gsi
+}
+
+/// Allocates a vector for `handler`, and routes ISA IRQ `irq` to it on
+/// the current CPU. Returns the vector that was allocated.
+///
+/// # Errors
+/// Fails if a vector can't be allocated, or if the IRQ can't be routed.
+///
+/// # Panics
+/// Panics if `irq` is not an ISA IRQ (i.e. is 16 or higher).
+pub fn route_isa(irq: u8, handler: InterruptHandler) -> Result<u8, IoApicError> {
+    let isa = IO_APICS.get().isa[usize::from(irq)];
+    let vector = interrupts::allocate_vector(handler)?;
+    let result = route(
+        isa.gsi,
+        Route {
+            vector,
+            destination: apic::id(),
+            polarity: isa.polarity,
+            trigger: isa.trigger,
+        },
+    );
+
+    if let Err(e) = result {
+        interrupts::unregister(vector);
+
+        return Err(e);
+    }
+
+    Ok(vector)
+}
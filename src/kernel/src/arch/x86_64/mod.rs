@@ -14,4 +14,14 @@
 
 mod start;
 
+pub mod apic;
+pub mod cpu;
+pub mod gdt;
 pub mod hal;
+pub mod idt;
+pub mod interrupts;
+pub mod ioapic;
+pub mod paging;
+pub mod power;
+pub mod smp;
+pub mod tsc;
@@ -0,0 +1,331 @@
+//======---------------------------------------------------------------======//
+//                                                                           //
+// Copyright 2022-2023 Evan Cox <evanacox00@gmail.com>. All rights reserved. //
+//                                                                           //
+// Use of this source code is governed by a BSD-style license that can be    //
+// found in the LICENSE.txt file at the root of this project, or at the      //
+// following link: https://opensource.org/licenses/BSD-3-Clause              //
+//                                                                           //
+//======---------------------------------------------------------------======//
+
+//! x86-64 page table management.
+//!
+//! Page tables are walked and edited through the higher-half direct map,
+//! so every table needs to be in memory that the HHDM covers (which is
+//! true for anything the physical memory manager hands out).
+//!
+//! Both 4-level and 5-level paging are supported, whichever is active
+//! is detected through `CR4.LA57`.
+
+use crate::arch::x86_64::cpu;
+use crate::arch::{MapError, PageFlags, PageMapper, PageSize, Translation};
+use crate::mm::{pmm, PhysAddr, VirtAddr};
+use crate::utility::KSpinLazy;
+use core::ptr;
+
+const ENTRIES_PER_TABLE: usize = 512;
+const ADDRESS_MASK: u64 = 0x000F_FFFF_FFFF_F000;
+const CR4_LA57: u64 = 1 << 12;
+
+static HAS_1GIB_PAGES: KSpinLazy<bool> = KSpinLazy::new(cpu::has_1gib_pages);
+
+#[repr(transparent)]
+#[derive(Copy, Clone, PartialEq, Eq)]
+struct Entry(u64);
+
+impl Entry {
+    const PRESENT: u64 = 1 << 0;
+    const WRITABLE: u64 = 1 << 1;
+    const USER: u64 = 1 << 2;
+    const WRITE_THROUGH: u64 = 1 << 3;
+    const NO_CACHE: u64 = 1 << 4;
+    const HUGE: u64 = 1 << 7;
+    const GLOBAL: u64 = 1 << 8;
+    const NO_EXECUTE: u64 = 1 << 63;
+
+    const fn is_present(self) -> bool {
+        self.0 & Self::PRESENT != 0
+    }
+
+    const fn is_huge(self) -> bool {
+        self.0 & Self::HUGE != 0
+    }
+
+    const fn address(self) -> PhysAddr {
+        PhysAddr::new(self.0 & ADDRESS_MASK)
+    }
+
+    const fn from_flags(flags: PageFlags) -> u64 {
+        let mut bits = Self::PRESENT;
+
+        if flags.contains(PageFlags::WRITABLE) {
+            bits |= Self::WRITABLE;
+        }
+
+        if flags.contains(PageFlags::USER) {
+            bits |= Self::USER;
+        }
+
+        if !flags.contains(PageFlags::EXECUTABLE) {
+            bits |= Self::NO_EXECUTE;
+        }
+
+        if flags.contains(PageFlags::NO_CACHE) {
+            bits |= Self::NO_CACHE;
+        }
+
+        if flags.contains(PageFlags::WRITE_THROUGH) {
+            bits |= Self::WRITE_THROUGH;
+        }
+
+        if flags.contains(PageFlags::GLOBAL) {
+            bits |= Self::GLOBAL;
+        }
+
+        bits
+    }
+
+    fn flags(self) -> PageFlags {
+        let mut flags = PageFlags::NONE;
+
+        if self.0 & Self::WRITABLE != 0 {
+            flags |= PageFlags::WRITABLE;
+        }
+
+        if self.0 & Self::USER != 0 {
+            flags |= PageFlags::USER;
+        }
+
+        if self.0 & Self::NO_EXECUTE == 0 {
+            flags |= PageFlags::EXECUTABLE;
+        }
+
+        if self.0 & Self::NO_CACHE != 0 {
+            flags |= PageFlags::NO_CACHE;
+        }
+
+        if self.0 & Self::WRITE_THROUGH != 0 {
+            flags |= PageFlags::WRITE_THROUGH;
+        }
+
+        if self.0 & Self::GLOBAL != 0 {
+            flags |= PageFlags::GLOBAL;
+        }
+
+        flags
+    }
+}
+
+// the page size that a leaf entry at a given level maps
+const fn level_size(level: usize) -> Option<PageSize> {
+    match level {
+        1 => Some(PageSize::Size4KiB),
+        2 => Some(PageSize::Size2MiB),
+        3 => Some(PageSize::Size1GiB),
+        _ => None,
+    }
+}
+
+const fn size_level(size: PageSize) -> usize {
+    match size {
+        PageSize::Size4KiB => 1,
+        PageSize::Size2MiB => 2,
+        PageSize::Size1GiB => 3,
+    }
+}
+
+/// A set of x86-64 page tables, identified by the physical address of
+/// the top-level table (i.e. what would be loaded into `CR3`).
+pub struct PageTables {
+    root: PhysAddr,
+    levels: usize,
+}
+
+impl PageTables {
+    /// Gets the page tables that are currently active on this CPU.
+    ///
+    /// # Safety
+    /// Only one [`PageTables`] referring to the same tables may be edited at
+    /// a time, otherwise concurrent edits will corrupt the tables.
+    pub unsafe fn active() -> Self {
+        let levels = if cpu::read_cr4() & CR4_LA57 != 0 {
+            5
+        } else {
+            4
+        };
+
+        Self {
+            root: PhysAddr::new(cpu::read_cr3() & ADDRESS_MASK),
+            levels,
+        }
+    }
+
+    // gets a pointer to entry `index` in the table at `table`
+    fn entry(table: PhysAddr, index: usize) -> *mut Entry {
+        debug_assert!(index < ENTRIES_PER_TABLE);
+
+        unsafe { table.to_virt().as_mut_ptr::<Entry>().add(index) }
+    }
+
+    const fn index(virt: VirtAddr, level: usize) -> usize {
+        ((virt.as_u64() >> (12 + 9 * (level - 1))) & 0x1FF) as usize
+    }
+
+    const fn is_canonical(&self, virt: VirtAddr) -> bool {
+        // everything above the top implemented bit must be a copy of it
+        let bits = 12 + 9 * self.levels;
+        let top = virt.as_u64() >> (bits - 1);
+
+        top == 0 || top == (1 << (65 - bits)) - 1
+    }
+
+    // walks down to the entry for `virt` at `level`, allocating any missing
+    // tables along the way. `user` controls whether new tables are user-accessible
+    fn walk_create(
+        &self,
+        virt: VirtAddr,
+        level: usize,
+        user: bool,
+    ) -> Result<*mut Entry, MapError> {
+        let mut table = self.root;
+
+        for current in (level + 1..=self.levels).rev() {
+            let entry = Self::entry(table, Self::index(virt, current));
+            let mut value = unsafe { entry.read() };
+
+            if !value.is_present() {
+                let frame = pmm::allocate_frame().ok_or(MapError::OutOfMemory)?;
+
+                unsafe {
+                    ptr::write_bytes(frame.to_virt().as_mut_ptr::<Entry>(), 0, ENTRIES_PER_TABLE);
+                }
+
+                value = Entry(frame.as_u64() | Entry::PRESENT | Entry::WRITABLE);
+            } else if value.is_huge() {
+                return Err(MapError::HugePageConflict);
+            }
+
+            // intermediate tables are as permissive as possible, the leaf
+            // entries are what actually restrict access
+            if user {
+                value.0 |= Entry::USER;
+            }
+
+            unsafe { entry.write(value) };
+
+            table = value.address();
+        }
+
+        Ok(Self::entry(table, Self::index(virt, level)))
+    }
+
+    // finds the leaf entry that maps `virt`, along with its level
+    fn walk_leaf(&self, virt: VirtAddr) -> Option<(*mut Entry, usize)> {
+        if !self.is_canonical(virt) {
+            return None;
+        }
+
+        let mut table = self.root;
+
+        for level in (1..=self.levels).rev() {
+            let entry = Self::entry(table, Self::index(virt, level));
+            let value = unsafe { entry.read() };
+
+            if !value.is_present() {
+                return None;
+            }
+
+            if level == 1 || value.is_huge() {
+                return Some((entry, level));
+            }
+
+            table = value.address();
+        }
+
+        None
+    }
+}
+
+impl PageMapper for PageTables {
+    unsafe fn map_sized(
+        &mut self,
+        virt: VirtAddr,
+        phys: PhysAddr,
+        size: PageSize,
+        flags: PageFlags,
+    ) -> Result<(), MapError> {
+        if !virt.is_aligned(size.bytes()) || !phys.is_aligned(size.bytes()) {
+            return Err(MapError::Misaligned);
+        }
+
+        if !self.is_canonical(virt) || phys.as_u64() & !ADDRESS_MASK != 0 {
+            return Err(MapError::InvalidAddress);
+        }
+
+        if size == PageSize::Size1GiB && !*HAS_1GIB_PAGES {
+            return Err(MapError::Unsupported);
+        }
+
+        let level = size_level(size);
+        let entry = self.walk_create(virt, level, flags.contains(PageFlags::USER))?;
+        let value = entry.read();
+
+        if value.is_present() {
+            // a present non-leaf entry means smaller pages are mapped inside the range
+            return Err(if level == 1 || value.is_huge() {
+                MapError::AlreadyMapped
+            } else {
+                MapError::HugePageConflict
+            });
+        }
+
+        let huge = if level == 1 { 0 } else { Entry::HUGE };
+
+        entry.write(Entry(phys.as_u64() | huge | Entry::from_flags(flags)));
+        cpu::invlpg(virt.as_u64());
+
+        Ok(())
+    }
+
+    unsafe fn unmap(&mut self, virt: VirtAddr) -> Result<Translation, MapError> {
+        let (entry, level) = self.walk_leaf(virt).ok_or(MapError::NotMapped)?;
+        let value = entry.read();
+        let size = level_size(level).ok_or(MapError::Unsupported)?;
+
+        entry.write(Entry(0));
+        cpu::invlpg(virt.align_down(size.bytes()).as_u64());
+
+        Ok(Translation {
+            phys: value.address().align_down(size.bytes()),
+            size,
+            flags: value.flags(),
+        })
+    }
+
+    unsafe fn protect(&mut self, virt: VirtAddr, flags: PageFlags) -> Result<(), MapError> {
+        let (entry, level) = self.walk_leaf(virt).ok_or(MapError::NotMapped)?;
+        let size = level_size(level).ok_or(MapError::Unsupported)?;
+        let value = entry.read();
+
+        entry.write(Entry(
+            value.address().as_u64() | (value.0 & Entry::HUGE) | Entry::from_flags(flags),
+        ));
+        cpu::invlpg(virt.align_down(size.bytes()).as_u64());
+
+        Ok(())
+    }
+
+    fn translate(&self, virt: VirtAddr) -> Option<Translation> {
+        let (entry, level) = self.walk_leaf(virt)?;
+        let value = unsafe { entry.read() };
+        let size = level_size(level)?;
+        let offset = virt.as_u64() & (size.bytes() - 1);
+
+        Some(Translation {
+            // for huge pages, bit 12 is the PAT bit and not part of the address
+            phys: value.address().align_down(size.bytes()).add(offset),
+            size,
+            flags: value.flags(),
+        })
+    }
+}
@@ -0,0 +1,283 @@
+//======---------------------------------------------------------------======//
+//                                                                           //
+// Copyright 2022-2023 Evan Cox <evanacox00@gmail.com>. All rights reserved. //
+//                                                                           //
+// Use of this source code is governed by a BSD-style license that can be    //
+// found in the LICENSE.txt file at the root of this project, or at the      //
+// following link: https://opensource.org/licenses/BSD-3-Clause              //
+//                                                                           //
+//======---------------------------------------------------------------======//
+
+//! Shutting down and rebooting the machine.
+//!
+//! Shutdown is done by entering ACPI sleep state S5, which needs the
+//! `SLP_TYPx` values from `\_S5`. Reboot tries the ACPI reset register
+//! first, then the keyboard controller, and triple-faults as a last resort.
+
+use crate::acpi::{self, AddressSpace, Fadt, GenericAddress};
+use crate::arch::x86_64::cpu::{self, inb, inl, inw, outb, outl, outw, DescriptorTablePointer};
+use crate::arch::x86_64::hal;
+use crate::mm::{mmio, PhysAddr, VirtAddr};
+use core::arch::asm;
+use log::{error, info, warn};
+
+const PM1_SCI_EN: u64 = 1 << 0;
+const PM1_SLP_TYP_SHIFT: u64 = 10;
+const PM1_SLP_TYP_MASK: u64 = 0x7 << PM1_SLP_TYP_SHIFT;
+const PM1_SLP_EN: u64 = 1 << 13;
+
+const SLEEP_CONTROL_SLP_TYP_SHIFT: u64 = 2;
+const SLEEP_CONTROL_SLP_EN: u64 = 1 << 5;
+
+const KBC_STATUS: u16 = 0x64;
+const KBC_COMMAND: u16 = 0x64;
+const KBC_INPUT_FULL: u8 = 1 << 1;
+const KBC_PULSE_RESET: u8 = 0xFE;
+
+const PCI_CONFIG_ADDRESS: u16 = 0xCF8;
+const PCI_CONFIG_DATA: u16 = 0xCFC;
+
+// writes to port 0x80 (the POST code port) take about a microsecond, and
+// work before the TSC has been calibrated
+const IO_DELAY_PORT: u16 = 0x80;
+
+// how many I/O delays to wait for something to happen, about a second
+const TIMEOUT: u32 = 1_000_000;
+
+fn io_delay() {
+    unsafe { outb(IO_DELAY_PORT, 0) };
+}
+
+// a register from a `GenericAddress`, resolved so it can be accessed
+// repeatedly without setting up a new mapping each time
+#[derive(Copy, Clone)]
+enum Register {
+    Port(u16, u8),
+    Memory(VirtAddr, u8),
+    // the legacy PCI configuration mechanism only reaches bus 0
+    PciConfig {
+        device: u16,
+        function: u16,
+        offset: u16,
+    },
+}
+
+impl Register {
+    #[allow(clippy::cast_possible_truncation)]
+    fn new(gas: &GenericAddress) -> Option<Self> {
+        if !gas.is_present() {
+            return None;
+        }
+
+        let width = match gas.bit_width {
+            0 => match gas.access_size {
+                2 => 16,
+                3 => 32,
+                4 => 64,
+                _ => 8,
+            },
+            width => width,
+        };
+
+        match gas.space() {
+            AddressSpace::SystemIo => u16::try_from(gas.address)
+                .ok()
+                .map(|port| Self::Port(port, width)),
+            AddressSpace::SystemMemory => mmio::map(PhysAddr::new(gas.address), 8)
+                .ok()
+                .map(|addr| Self::Memory(addr, width)),
+            AddressSpace::PciConfig => Some(Self::PciConfig {
+                device: (gas.address >> 32) as u16,
+                function: (gas.address >> 16) as u16,
+                offset: gas.address as u16,
+            }),
+            AddressSpace::Other(_) => None,
+        }
+    }
+
+    fn select_pci(device: u16, function: u16, offset: u16) {
+        let address = (1 << 31)
+            | (u32::from(device & 0x1F) << 11)
+            | (u32::from(function & 0x7) << 8)
+            | u32::from(offset & 0xFC);
+
+        unsafe { outl(PCI_CONFIG_ADDRESS, address) };
+    }
+
+    fn read(self) -> u64 {
+        unsafe {
+            match self {
+                Self::Port(port, 16) => u64::from(inw(port)),
+                Self::Port(port, 32 | 64) => u64::from(inl(port)),
+                Self::Port(port, _) => u64::from(inb(port)),
+                Self::Memory(addr, 16) => u64::from(addr.as_mut_ptr::<u16>().read_volatile()),
+                Self::Memory(addr, 32) => u64::from(addr.as_mut_ptr::<u32>().read_volatile()),
+                Self::Memory(addr, 64) => addr.as_mut_ptr::<u64>().read_volatile(),
+                Self::Memory(addr, _) => u64::from(addr.as_mut_ptr::<u8>().read_volatile()),
+                Self::PciConfig {
+                    device,
+                    function,
+                    offset,
+                } => {
+                    Self::select_pci(device, function, offset);
+
+                    u64::from(inb(PCI_CONFIG_DATA + (offset & 0x3)))
+                }
+            }
+        }
+    }
+
+    // writes are truncated to the width of the register
+    #[allow(clippy::cast_possible_truncation)]
+    fn write(self, value: u64) {
+        unsafe {
+            match self {
+                Self::Port(port, 16) => outw(port, value as u16),
+                Self::Port(port, 32 | 64) => outl(port, value as u32),
+                Self::Port(port, _) => outb(port, value as u8),
+                Self::Memory(addr, 16) => addr.as_mut_ptr::<u16>().write_volatile(value as u16),
+                Self::Memory(addr, 32) => addr.as_mut_ptr::<u32>().write_volatile(value as u32),
+                Self::Memory(addr, 64) => addr.as_mut_ptr::<u64>().write_volatile(value),
+                Self::Memory(addr, _) => addr.as_mut_ptr::<u8>().write_volatile(value as u8),
+                Self::PciConfig {
+                    device,
+                    function,
+                    offset,
+                } => {
+                    Self::select_pci(device, function, offset);
+
+                    outb(PCI_CONFIG_DATA + (offset & 0x3), value as u8);
+                }
+            }
+        }
+    }
+}
+
+// switches from legacy mode into ACPI mode if the firmware hasn't already
+fn enable_acpi_mode(fadt: &Fadt, pm1a_control: Register) -> Result<(), &'static str> {
+    if pm1a_control.read() & PM1_SCI_EN != 0 || fadt.smi_command_port == 0 || fadt.acpi_enable == 0
+    {
+        return Ok(());
+    }
+
+    let port =
+        u16::try_from(fadt.smi_command_port).map_err(|_| "the smi command port is invalid")?;
+
+    unsafe { outb(port, fadt.acpi_enable) };
+
+    for _ in 0..TIMEOUT {
+        if pm1a_control.read() & PM1_SCI_EN != 0 {
+            return Ok(());
+        }
+
+        io_delay();
+    }
+
+    Err("the firmware never switched into acpi mode")
+}
+
+fn enter_s5() -> Result<(), &'static str> {
+    let fadt = Fadt::get().ok_or("there is no fadt")?;
+    let sleep = acpi::sleep_type(5).ok_or("there is no \\_S5 object")?;
+
+    if fadt.is_hardware_reduced() {
+        let control = Register::new(&fadt.sleep_control_register)
+            .ok_or("there is no sleep control register")?;
+
+        control.write((u64::from(sleep.a) << SLEEP_CONTROL_SLP_TYP_SHIFT) | SLEEP_CONTROL_SLP_EN);
+    } else {
+        let control_a =
+            Register::new(&fadt.pm1a_control_block).ok_or("there is no pm1a control block")?;
+        let control_b = Register::new(&fadt.pm1b_control_block);
+
+        enable_acpi_mode(&fadt, control_a)?;
+
+        // the sleep type has to be set before the sleep enable bit is
+        let blocks = [(Some(control_a), sleep.a), (control_b, sleep.b)];
+
+        for (block, kind) in blocks {
+            if let Some(block) = block {
+                let value = (block.read() & !(PM1_SLP_TYP_MASK | PM1_SLP_EN))
+                    | (u64::from(kind) << PM1_SLP_TYP_SHIFT);
+
+                block.write(value);
+            }
+        }
+
+        for block in blocks.iter().filter_map(|(block, _)| *block) {
+            block.write(block.read() | PM1_SLP_EN);
+        }
+    }
+
+    for _ in 0..TIMEOUT {
+        io_delay();
+    }
+
+    Err("the system never powered off")
+}
+
+fn wait_for_reset() {
+    for _ in 0..TIMEOUT {
+        io_delay();
+    }
+}
+
+/// Powers off the machine.
+///
+/// If ACPI can't do it, this logs why and halts the current CPU instead.
+pub fn shutdown() -> ! {
+    info!("shutting down");
+
+    cpu::disable_interrupts();
+
+    if let Err(reason) = enter_s5() {
+        error!("unable to shut down, halting instead: {reason}");
+    }
+
+    unsafe { hal::privileged_halt_thread() }
+}
+
+/// Resets the machine.
+///
+/// This tries the ACPI reset register, then the keyboard controller, and
+/// then triple-faults.
+pub fn reboot() -> ! {
+    info!("rebooting");
+
+    cpu::disable_interrupts();
+
+    let reset = Fadt::get()
+        .filter(Fadt::supports_reset_register)
+        .and_then(|fadt| Some((Register::new(&fadt.reset_register)?, fadt.reset_value)));
+
+    if let Some((register, value)) = reset {
+        register.write(u64::from(value));
+
+        wait_for_reset();
+
+        warn!("acpi reset register didn't reset the machine");
+    }
+
+    // writes to a keyboard controller that isn't there are harmless, so
+    // this doesn't bother checking the boot architecture flags
+    for _ in 0..TIMEOUT {
+        if unsafe { inb(KBC_STATUS) } & KBC_INPUT_FULL == 0 {
+            break;
+        }
+
+        io_delay();
+    }
+
+    unsafe { outb(KBC_COMMAND, KBC_PULSE_RESET) };
+
+    wait_for_reset();
+
+    warn!("keyboard controller didn't reset the machine, triple faulting");
+
+    // with an empty IDT any exception escalates into a triple fault
+    unsafe {
+        cpu::lidt(&DescriptorTablePointer { limit: 0, base: 0 });
+
+        asm!("int3", options(noreturn));
+    }
+}
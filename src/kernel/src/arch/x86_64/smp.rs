@@ -0,0 +1,300 @@
+//======---------------------------------------------------------------======//
+//                                                                           //
+// Copyright 2022-2023 Evan Cox <evanacox00@gmail.com>. All rights reserved. //
+//                                                                           //
+// Use of this source code is governed by a BSD-style license that can be    //
+// found in the LICENSE.txt file at the root of this project, or at the      //
+// following link: https://opensource.org/licenses/BSD-3-Clause              //
+//                                                                           //
+//======---------------------------------------------------------------======//
+
+//! Bringing up the application processors (APs).
+//!
+//! The bootloader starts every CPU and parks it. Each one is recorded in
+//! the CPU table at boot, and [`start`] later hands every AP its own stack,
+//! GDT/TSS and IDT, and brings up its local APIC. APs then sit in an idle
+//! loop until [`release`] gives them something to run.
+//!
+//! The bootstrap processor is always CPU `0`.
+
+// there's no scheduler to hand the parked APs to yet
+#![allow(dead_code)]
+
+use crate::arch::x86_64::apic::{self, IpiTarget};
+use crate::arch::x86_64::gdt::{CpuTables, IST_STACKS, IST_STACK_SIZE};
+use crate::arch::x86_64::interrupts::{self, TrapFrame};
+use crate::arch::x86_64::{cpu, idt, tsc};
+use crate::arch::MAX_CPUS;
+use crate::mm::VirtAddr;
+use crate::percpu;
+use alloc::alloc::{alloc, handle_alloc_error};
+use alloc::boxed::Box;
+use core::alloc::Layout;
+use core::arch::asm;
+use core::sync::atomic::{AtomicBool, AtomicPtr, AtomicU32, AtomicU64, AtomicUsize, Ordering};
+use core::{hint, mem, ptr};
+use limine::{SmpInfo, SmpResponse};
+use log::{info, trace, warn};
+
+/// The vector that parked and sleeping CPUs are woken up with.
+pub const WAKE_VECTOR: u8 = 0xF1;
+
+const AP_STACK_SIZE: usize = 64 * 1024;
+
+const START_TIMEOUT_NANOS: u64 = 1_000_000_000;
+
+/// Information about a single CPU.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct CpuInfo {
+    /// The index of the CPU in the CPU table.
+    pub index: usize,
+    /// The CPU's local APIC ID.
+    pub apic_id: u32,
+    /// The ACPI processor UID of the CPU.
+    pub processor_id: u32,
+    /// Whether the CPU has been brought up by the kernel yet.
+    pub online: bool,
+}
+
+struct Cpu {
+    apic_id: AtomicU32,
+    processor_id: AtomicU32,
+    online: AtomicBool,
+    // where the bootloader is waiting for the AP to be started
+    boot: AtomicPtr<SmpInfo>,
+}
+
+impl Cpu {
+    const fn new() -> Self {
+        Self {
+            apic_id: AtomicU32::new(0),
+            processor_id: AtomicU32::new(0),
+            online: AtomicBool::new(false),
+            boot: AtomicPtr::new(ptr::null_mut()),
+        }
+    }
+}
+
+// everything an AP needs to set itself up, allocated by the BSP
+struct ApBoot {
+    index: usize,
+    stack: VirtAddr,
+    tables: &'static CpuTables,
+    ist: [VirtAddr; IST_STACKS],
+}
+
+static CPUS: [Cpu; MAX_CPUS] = [const { Cpu::new() }; MAX_CPUS];
+
+static CPU_COUNT: AtomicUsize = AtomicUsize::new(1);
+
+/// The number of CPUs that are online, including the BSP.
+pub static ONLINE: AtomicUsize = AtomicUsize::new(1);
+
+// what parked APs should run, a `fn() -> !` once it's set
+static ENTRY: AtomicPtr<()> = AtomicPtr::new(ptr::null_mut());
+
+/// Records every CPU that the bootloader started in the CPU table, and
+/// returns how many there are.
+///
+/// CPUs past [`MAX_CPUS`] are ignored.
+pub fn register_cpus(response: &mut SmpResponse) -> usize {
+    let bsp = response.bsp_lapic_id;
+    let mut count = 1;
+
+    CPUS[0].apic_id.store(bsp, Ordering::Relaxed);
+    CPUS[0].online.store(true, Ordering::Relaxed);
+
+    for info in response.cpus() {
+        if info.lapic_id == bsp {
+            CPUS[0]
+                .processor_id
+                .store(info.processor_id, Ordering::Relaxed);
+
+            continue;
+        }
+
+        if count == MAX_CPUS {
+            warn!("too many cpus, ignoring cpu with apic id {}", info.lapic_id);
+
+            continue;
+        }
+
+        let cpu = &CPUS[count];
+
+        cpu.apic_id.store(info.lapic_id, Ordering::Relaxed);
+        cpu.processor_id.store(info.processor_id, Ordering::Relaxed);
+        cpu.boot.store(info.as_ptr(), Ordering::Relaxed);
+
+        count += 1;
+    }
+
+    CPU_COUNT.store(count, Ordering::Release);
+
+    count
+}
+
+fn allocate_stack(size: usize) -> VirtAddr {
+    let layout = Layout::from_size_align(size, 16).expect("stack layout should be valid");
+    let base = unsafe { alloc(layout) };
+
+    if base.is_null() {
+        handle_alloc_error(layout);
+    }
+
+    VirtAddr::from_ptr(base).add(size as u64)
+}
+
+const fn wake_interrupt(_: &mut TrapFrame) {}
+
+/// Starts every AP that was registered, and waits for them to come online.
+///
+/// This needs the heap and the BSP's local APIC to be initialized.
+pub fn start() {
+    if let Err(e) = interrupts::register(WAKE_VECTOR, wake_interrupt) {
+        warn!("unable to register the wake vector: {e:?}");
+    }
+
+    let count = cpu_count();
+    let entry: extern "C" fn(*const SmpInfo) -> ! = ap_entry;
+
+    for (index, cpu) in CPUS.iter().enumerate().take(count).skip(1) {
+        let info = cpu.boot.load(Ordering::Relaxed);
+        let boot = Box::leak(Box::new(ApBoot {
+            index,
+            stack: allocate_stack(AP_STACK_SIZE),
+            tables: Box::leak(Box::new(CpuTables::new())),
+            ist: core::array::from_fn(|_| allocate_stack(IST_STACK_SIZE)),
+        }));
+
+        unsafe {
+            ptr::addr_of_mut!((*info).extra_argument).write_volatile(ptr::from_mut(boot) as u64);
+
+            // the AP jumps as soon as this is written, so it has to be last
+            AtomicU64::from_ptr(ptr::addr_of_mut!((*info).goto_address).cast())
+                .store(entry as usize as u64, Ordering::SeqCst);
+        }
+    }
+
+    let deadline = tsc::nanos_since_boot() + START_TIMEOUT_NANOS;
+
+    while online_count() < count && tsc::nanos_since_boot() < deadline {
+        hint::spin_loop();
+    }
+
+    let online = online_count();
+
+    if online < count {
+        warn!("only {online} of {count} cpus came online");
+
+        for cpu in cpus().filter(|cpu| !cpu.online) {
+            warn!(
+                "cpu {} (apic id {}, acpi uid {}) never came online",
+                cpu.index, cpu.apic_id, cpu.processor_id
+            );
+        }
+    } else {
+        info!("all {count} cpus are online");
+    }
+}
+
+// the bootloader's stack is in reclaimable memory, so the first thing an
+// AP does is switch to the stack that was allocated for it
+extern "C" fn ap_entry(info: *const SmpInfo) -> ! {
+    unsafe {
+        let boot = (*info).extra_argument as *const ApBoot;
+
+        asm!(
+            "mov rsp, {stack}",
+            "xor ebp, ebp",
+            "call {main}",
+            "ud2",
+            stack = in(reg) (*boot).stack.as_u64(),
+            main = sym ap_main,
+            in("rdi") boot,
+            options(noreturn)
+        );
+    }
+}
+
+extern "C" fn ap_main(boot: *const ApBoot) -> ! {
+    let boot = unsafe { &*boot };
+
+    unsafe { percpu::init_current(boot.index) };
+
+    unsafe { boot.tables.install(boot.ist) };
+
+    idt::load();
+    apic::init_current();
+
+    let cpu = &CPUS[boot.index];
+
+    cpu.apic_id.store(apic::id(), Ordering::Relaxed);
+    cpu.online.store(true, Ordering::Release);
+    ONLINE.fetch_add(1, Ordering::AcqRel);
+
+    trace!("cpu {} (apic id {}) is online", boot.index, apic::id());
+
+    park()
+}
+
+fn park() -> ! {
+    loop {
+        cpu::disable_interrupts();
+
+        let entry = ENTRY.load(Ordering::Acquire);
+
+        if !entry.is_null() {
+            unsafe { cpu::enable_interrupts() };
+
+            let entry = unsafe { mem::transmute::<*mut (), fn() -> !>(entry) };
+
+            entry();
+        }
+
+        // `release` sends an IPI after setting the entry point, and this
+        // can't miss it since interrupts were disabled while checking
+        unsafe { cpu::enable_interrupts_and_halt() };
+    }
+}
+
+/// Makes every parked AP run `entry`, e.g. once the scheduler is ready.
+pub fn release(entry: fn() -> !) {
+    ENTRY.store(entry as *mut (), Ordering::Release);
+
+    apic::send_ipi(IpiTarget::Others, WAKE_VECTOR);
+}
+
+/// Sends a wake IPI to CPU `index`, if it's online.
+pub fn wake(index: usize) {
+    let cpu = &CPUS[index];
+
+    if cpu.online.load(Ordering::Acquire) {
+        apic::send_ipi(
+            IpiTarget::Cpu(cpu.apic_id.load(Ordering::Relaxed)),
+            WAKE_VECTOR,
+        );
+    }
+}
+
+/// The number of CPUs that the bootloader started, including the BSP.
+pub fn cpu_count() -> usize {
+    CPU_COUNT.load(Ordering::Acquire)
+}
+
+/// The number of CPUs that are online, including the BSP.
+pub fn online_count() -> usize {
+    ONLINE.load(Ordering::Acquire)
+}
+
+/// Iterates over the CPU table.
+pub fn cpus() -> impl Iterator<Item = CpuInfo> {
+    CPUS.iter()
+        .enumerate()
+        .take(cpu_count())
+        .map(|(index, cpu)| CpuInfo {
+            index,
+            apic_id: cpu.apic_id.load(Ordering::Relaxed),
+            processor_id: cpu.processor_id.load(Ordering::Relaxed),
+            online: cpu.online.load(Ordering::Acquire),
+        })
+}
@@ -9,12 +9,17 @@
 //======---------------------------------------------------------------======//
 
 use crate::arch::x86_64::hal::SerialPort;
-use crate::arch::SystemInfo;
+use crate::arch::x86_64::{gdt, idt, smp};
+use crate::arch::{MemoryMap, MemoryRegion, MemoryRegionKind, SystemInfo};
 use crate::drivers::kframebuffer::LinearFramebuffer;
 use crate::drivers::{kframebuffer, klog, kserial};
+use crate::percpu;
 use core::arch::asm;
-use limine::{BootInfoRequest, Framebuffer, FramebufferRequest, StackSizeRequest};
-use log::{trace, LevelFilter};
+use limine::{
+    BootInfoRequest, FramebufferRequest, HhdmRequest, MemmapRequest, MemoryMapEntryType,
+    RsdpRequest, SmpRequest, StackSizeRequest,
+};
+use log::{trace, warn, LevelFilter};
 
 const EIGHT_MB_STACK: u64 = 8 * 1024 * 1024;
 
@@ -30,6 +35,18 @@ static STACK_SIZE_REQUEST: StackSizeRequest = StackSizeRequest::new(0).stack_siz
 // get limine info for logging purposes
 static BOOT_INFO_REQUEST: BootInfoRequest = BootInfoRequest::new(0);
 
+// get the physical memory map
+static MEMMAP_REQUEST: MemmapRequest = MemmapRequest::new(0);
+
+// get the offset of the higher-half direct map
+static HHDM_REQUEST: HhdmRequest = HhdmRequest::new(0);
+
+// get the location of the ACPI tables
+static RSDP_REQUEST: RsdpRequest = RsdpRequest::new(0);
+
+// get every other CPU started and parked, with x2APIC enabled if it's available
+static SMP_REQUEST: SmpRequest = SmpRequest::new(0).flags(1);
+
 fn initialize_klog() {
     kserial::serial_init(|| unsafe { SerialPort::default_com1() });
     klog::logger_init(LevelFilter::Trace);
@@ -64,6 +81,42 @@ fn initialize_kframebuffer() {
     trace!("initialized framebuffer");
 }
 
+const fn region_kind(kind: MemoryMapEntryType) -> MemoryRegionKind {
+    match kind {
+        MemoryMapEntryType::Usable => MemoryRegionKind::Usable,
+        MemoryMapEntryType::BootloaderReclaimable => MemoryRegionKind::BootloaderReclaimable,
+        MemoryMapEntryType::AcpiReclaimable => MemoryRegionKind::AcpiReclaimable,
+        MemoryMapEntryType::AcpiNvs => MemoryRegionKind::AcpiNvs,
+        MemoryMapEntryType::KernelAndModules => MemoryRegionKind::KernelAndModules,
+        MemoryMapEntryType::Framebuffer => MemoryRegionKind::Framebuffer,
+        MemoryMapEntryType::Reserved => MemoryRegionKind::Reserved,
+        MemoryMapEntryType::BadMemory => MemoryRegionKind::Bad,
+    }
+}
+
+fn collect_memory_map() -> MemoryMap {
+    let response = MEMMAP_REQUEST.get_response();
+    let memmap = response.get().expect("should get a memory map from limine");
+    let mut map = MemoryMap::new();
+
+    for entry in memmap.memmap() {
+        let region = MemoryRegion::new(entry.base, entry.len, region_kind(entry.typ));
+
+        trace!(
+            "found {} region! [{:#x}, {:#x})",
+            region.kind.name(),
+            region.base,
+            region.end()
+        );
+
+        if let Err(region) = map.push(region) {
+            warn!("memory map is full, dropping region {region:?}");
+        }
+    }
+
+    map
+}
+
 #[no_mangle]
 extern "C" fn _start() -> ! {
     if LIMINE_BASE_REVISION[2] != 0 {
@@ -77,40 +130,71 @@ extern "C" fn _start() -> ! {
         }
     }
 
+    // locks use the per-CPU data, so this has to come before anything else
+    unsafe { percpu::init_current(0) };
+
     initialize_klog();
+    gdt::init();
+    idt::init();
     initialize_kframebuffer();
 
-    let mut memory = 0usize;
-    let mut usable = 0usize;
-
-    /*
+    let memory_map = collect_memory_map();
+    let memory = usize::try_from(memory_map.total()).expect("total memory should fit in usize");
 
-    for region in info.memory_regions.iter() {
-        let (start, end) = (region.start, region.end);
+    trace!("kernel address = {:?}", _start as *mut u8);
 
-        memory += (end - start) as usize;
+    for kind in MemoryRegionKind::ALL {
+        trace!(
+            "total {} memory = {} (in bytes)",
+            kind.name(),
+            memory_map.total_of(kind)
+        );
+    }
 
-        match region.kind {
-            MemoryRegionKind::Usable => {
-                trace!("found usable page! [{start:0x}, {end:0x}]");
+    trace!("total memory = {memory} (in bytes)");
+    trace!("total usable memory = {} (in bytes)", memory_map.usable());
+    trace!(
+        "total unusable memory = {} (in bytes)",
+        memory_map.total() - memory_map.usable()
+    );
 
-                usable += (end - start) as usize;
+    let hhdm_offset = HHDM_REQUEST
+        .get_response()
+        .get()
+        .expect("should get a hhdm response from limine")
+        .offset;
+
+    trace!("hhdm offset = {hhdm_offset:#x}");
+
+    let rsdp_address = RSDP_REQUEST
+        .get_response()
+        .get()
+        .and_then(|rsdp| rsdp.address.as_ptr())
+        .map(|ptr| {
+            let addr = ptr as u64;
+
+            // with our base revision the RSDP is given to us through the HHDM,
+            // newer base revisions give the physical address directly
+            if addr >= hhdm_offset {
+                addr - hhdm_offset
+            } else {
+                addr
             }
-            MemoryRegionKind::Bootloader => trace!("found bootloader page! [{start:0x}, {end:0x}]"),
-            MemoryRegionKind::UnknownUefi(_) => {
-                trace!("found unknown uefi page! [{start:0x}, {end:0x}]")
-            }
-            MemoryRegionKind::UnknownBios(_) => {
-                trace!("found unknown bios page! [{start:0x}, {end:0x}]")
-            }
-            kind => panic!("unknown type of memory page '{kind:?}'"),
-        }
-    }*/
+        });
 
-    trace!("kernel address = {:?}", _start as *mut u8);
-    trace!("total memory = {memory} (in bytes)");
-    trace!("total usable memory = {usable} (in bytes)");
-    trace!("total unusable memory = {} (in bytes)", memory - usable);
+    trace!("rsdp address = {rsdp_address:#x?}");
+
+    let mut response = SMP_REQUEST.get_response();
+    let cpus_registered = response.get_mut().map_or(1, smp::register_cpus);
+
+    trace!("{cpus_registered} cpus registered");
 
-    crate::kernel_main(SystemInfo { memory })
+    crate::kernel_main(SystemInfo {
+        memory,
+        memory_map,
+        hhdm_offset,
+        rsdp_address,
+        cpus_registered,
+        online_cpus: &smp::ONLINE,
+    })
 }
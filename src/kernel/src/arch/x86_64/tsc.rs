@@ -0,0 +1,62 @@
+//======---------------------------------------------------------------======//
+//                                                                           //
+// Copyright 2022-2023 Evan Cox <evanacox00@gmail.com>. All rights reserved. //
+//                                                                           //
+// Use of this source code is governed by a BSD-style license that can be    //
+// found in the LICENSE.txt file at the root of this project, or at the      //
+// following link: https://opensource.org/licenses/BSD-3-Clause              //
+//                                                                           //
+//======---------------------------------------------------------------======//
+
+//! Timekeeping through the time-stamp counter.
+//!
+//! The TSC frequency isn't architecturally discoverable everywhere, so it
+//! gets measured during APIC timer calibration. Until then, every time
+//! reads as `0`.
+
+use crate::arch::x86_64::cpu;
+use core::sync::atomic::{AtomicU64, Ordering};
+
+const NANOS_PER_SECOND: u128 = 1_000_000_000;
+
+static FREQUENCY: AtomicU64 = AtomicU64::new(0);
+
+/// Sets the measured TSC frequency, in Hz.
+pub fn set_frequency(hz: u64) {
+    FREQUENCY.store(hz, Ordering::Relaxed);
+}
+
+/// Gets the TSC frequency in Hz, or `0` if it hasn't been measured yet.
+#[inline]
+pub fn frequency() -> u64 {
+    FREQUENCY.load(Ordering::Relaxed)
+}
+
+/// Checks whether the TSC ticks at a constant rate regardless of power
+/// states, which is needed for it to be a reliable clock.
+pub fn is_invariant() -> bool {
+    cpu::max_extended_leaf() >= 0x8000_0007 && cpu::cpuid(0x8000_0007, 0)[3] & (1 << 8) != 0
+}
+
+/// Converts a number of nanoseconds into a number of TSC ticks.
+#[allow(clippy::cast_possible_truncation)]
+pub fn nanos_to_ticks(nanos: u64) -> u64 {
+    (u128::from(nanos) * u128::from(frequency()) / NANOS_PER_SECOND) as u64
+}
+
+/// Converts a number of TSC ticks into a number of nanoseconds.
+#[allow(clippy::cast_possible_truncation)]
+pub fn ticks_to_nanos(ticks: u64) -> u64 {
+    match frequency() {
+        0 => 0,
+        hz => (u128::from(ticks) * NANOS_PER_SECOND / u128::from(hz)) as u64,
+    }
+}
+
+/// The number of nanoseconds since the CPU was reset, which is close
+/// enough to when the system booted.
+///
+/// This is `0` until the TSC frequency is known.
+pub fn nanos_since_boot() -> u64 {
+    ticks_to_nanos(cpu::rdtsc())
+}
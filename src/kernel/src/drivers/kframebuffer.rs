@@ -16,7 +16,7 @@
 //!
 //! This is intended to be linked directly into the kernel.
 
-use crate::utility::{KSpinFairMutex, KSpinOnceCell};
+use crate::utility::{KSpinFairRwLock, KSpinOnceCell};
 
 #[cfg(target_arch = "x86_64")]
 use limine::Framebuffer;
@@ -149,6 +149,12 @@ impl LinearFramebuffer {
         self.raw.as_mut_ptr()
     }
 
+    /// The size of the entire framebuffer in bytes
+    #[inline]
+    pub const fn size_in_bytes(&self) -> usize {
+        self.raw.len()
+    }
+
     /// Returns a slice that contains the entire framebuffer
     #[inline]
     pub fn full_raw_buffer(&mut self) -> &mut [u8] {
@@ -180,7 +186,7 @@ impl From<&'static mut Framebuffer> for LinearFramebuffer {
     }
 }
 
-static FRAMEBUFFER: KSpinOnceCell<KSpinFairMutex<LinearFramebuffer>> = KSpinOnceCell::uninit();
+static FRAMEBUFFER: KSpinOnceCell<KSpinFairRwLock<LinearFramebuffer>> = KSpinOnceCell::uninit();
 
 /// Initializes the framebuffer with a given function.
 ///
@@ -188,11 +194,11 @@ static FRAMEBUFFER: KSpinOnceCell<KSpinFairMutex<LinearFramebuffer>> = KSpinOnce
 /// where the framebuffer is initialized and then is ready to use from then on.
 #[inline]
 pub fn framebuffer_init(f: impl FnOnce() -> LinearFramebuffer) {
-    let _ = FRAMEBUFFER.set(KSpinFairMutex::new(f()));
+    let _ = FRAMEBUFFER.set(KSpinFairRwLock::new(f()));
 }
 
 /// Returns a reference to the framebuffer.
 #[inline]
-pub fn framebuffer() -> &'static KSpinFairMutex<LinearFramebuffer> {
+pub fn framebuffer() -> &'static KSpinFairRwLock<LinearFramebuffer> {
     FRAMEBUFFER.get()
 }
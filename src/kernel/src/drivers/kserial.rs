@@ -28,8 +28,12 @@ pub trait SerialBackend: Write {
     /// Writes a single byte to the serial backend.
     fn send(&mut self, byte: u8);
 
-    /// Reads a single byte from the serial backend
-    fn recv(&mut self) -> u8;
+    /// Reads a single byte from the serial backend, if one has been received.
+    ///
+    /// This never waits, the port is usually behind a spinlock and the
+    /// bytes may only arrive through an interrupt that can't be taken
+    /// while it's held.
+    fn recv(&mut self) -> Option<u8>;
 }
 
 static SERIAL_PORT: KSpinOnceCell<KSpinFairMutex<SerialPort>> = KSpinOnceCell::uninit();
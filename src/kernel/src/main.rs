@@ -19,18 +19,28 @@
 #![deny(missing_abi)]
 #![deny(clippy::all, clippy::pedantic, clippy::nursery)]
 #![allow(clippy::mod_module_files, clippy::pub_use)]
-#![feature(abi_x86_interrupt)]
+#![feature(alloc_error_handler)]
 
+extern crate alloc;
+
+mod acpi;
 mod arch;
 mod drivers;
+mod mm;
+mod percpu;
+mod random;
 mod utility;
 
 use crate::arch::{hal, SystemInfo};
 use crate::drivers::kframebuffer;
-use core::mem::MaybeUninit;
+use crate::mm::PhysAddr;
+use crate::utility::krcu;
+use alloc::vec;
+use core::alloc::Layout;
 use core::panic::PanicInfo;
 use core::ptr;
-use ksupport::sync::BasicMutex;
+use core::sync::atomic::Ordering;
+use ksupport::sync::BasicRwLock;
 use ksupport::Xoshiro256;
 use log::{error, trace};
 
@@ -43,36 +53,85 @@ use log::{error, trace};
 /// At this point, the stack is expected to be set up, drivers initialized, anything else
 /// that is "reasonable" to use is ready (except floating-point).
 pub fn kernel_main(info: SystemInfo) -> ! {
-    trace!("entered `::kernel_main`! system memory: {}", info.memory);
+    trace!(
+        "entered `::kernel_main`! system memory: {} (in bytes), usable: {} (in bytes)",
+        info.memory,
+        info.memory_map.usable()
+    );
+
+    krcu::online();
+
+    mm::init(&info);
+    acpi::init(info.rsdp_address.map(PhysAddr::new));
+    hal::init();
+    random::init();
+
+    trace!(
+        "{} of {} cpus are online",
+        info.online_cpus.load(Ordering::Acquire),
+        info.cpus_registered
+    );
 
-    let mut buf = kframebuffer::framebuffer();
-    let mut value = 0x01u8;
-    let mut local = [0u8; 4096000];
+    trace!(
+        "platform initialized! time since boot: {} ns",
+        hal::nanos_since_boot()
+    );
+
+    let buf = kframebuffer::framebuffer();
+    let size = buf.read().size_in_bytes();
+    let mut local = vec![0u8; size];
 
     trace!("zeroed double-buffer");
 
+    // the pattern doesn't need to be unpredictable, it just shouldn't be
+    // the same on every boot. an unseeded pool leaves it at the default
+    let mut rng = Xoshiro256::with_seed_xor([random::next_u64().unwrap_or_default(), 0, 0, 0]);
+
     loop {
-        for byte in local.iter_mut() {
-            *byte = value;
+        // nothing is held on to between iterations
+        krcu::quiescent();
 
-            value ^= value.wrapping_mul(71);
+        for chunk in local.chunks_mut(8) {
+            chunk.copy_from_slice(&rng.next().to_le_bytes()[..chunk.len()]);
         }
 
         {
-            let mut raw = buf.lock();
-            let size = raw.full_raw_buffer().len();
+            let mut raw = buf.write();
 
             unsafe {
-                ptr::copy_nonoverlapping(local.as_ptr(), raw.raw_buffer(), size);
+                ptr::copy_nonoverlapping(local.as_ptr(), raw.raw_buffer(), local.len());
             }
         }
     }
 }
 
+#[alloc_error_handler]
+fn alloc_error(layout: Layout) -> ! {
+    let stats = mm::heap::stats();
+
+    error!("kernel heap allocation failed! layout = {layout:?}");
+    error!(
+        "heap state: {} bytes mapped, {} bytes used, {} bytes free",
+        stats.mapped, stats.used, stats.free
+    );
+
+    panic!("out of memory while allocating {} bytes", layout.size());
+}
+
 #[panic_handler]
 fn panic(info: &PanicInfo) -> ! {
     error!("kernel panic! [rust-level]: {info}");
 
+    // by default the machine stays up so that its state can be inspected
+    // with a debugger
+    if cfg!(feature = "reboot-on-panic") {
+        hal::reboot()
+    }
+
+    if cfg!(feature = "shutdown-on-panic") {
+        hal::shutdown()
+    }
+
     unsafe {
         hal::privileged_halt_thread();
     }
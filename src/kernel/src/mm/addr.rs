@@ -0,0 +1,107 @@
+//======---------------------------------------------------------------======//
+//                                                                           //
+// Copyright 2022-2023 Evan Cox <evanacox00@gmail.com>. All rights reserved. //
+//                                                                           //
+// Use of this source code is governed by a BSD-style license that can be    //
+// found in the LICENSE.txt file at the root of this project, or at the      //
+// following link: https://opensource.org/licenses/BSD-3-Clause              //
+//                                                                           //
+//======---------------------------------------------------------------======//
+
+// both address types get the same helpers, virtual addresses don't need
+// all of them yet
+#![allow(dead_code)]
+
+use crate::mm;
+use core::fmt;
+
+/// A physical memory address.
+///
+/// These can't be dereferenced directly, they need to be turned into a
+/// virtual address first (see [`PhysAddr::to_virt`]).
+#[repr(transparent)]
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct PhysAddr(u64);
+
+/// A virtual memory address in the current address space.
+#[repr(transparent)]
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct VirtAddr(u64);
+
+macro_rules! addr_common {
+    ($name:ident) => {
+        impl $name {
+            /// Wraps a raw address.
+            #[inline]
+            pub const fn new(addr: u64) -> Self {
+                Self(addr)
+            }
+
+            /// Gets the raw address.
+            #[inline]
+            pub const fn as_u64(self) -> u64 {
+                self.0
+            }
+
+            /// Rounds the address down to a multiple of `align`, which must
+            /// be a power of two.
+            #[inline]
+            pub const fn align_down(self, align: u64) -> Self {
+                Self(self.0 & !(align - 1))
+            }
+
+            /// Rounds the address up to a multiple of `align`, which must
+            /// be a power of two.
+            #[inline]
+            pub const fn align_up(self, align: u64) -> Self {
+                Self((self.0 + align - 1) & !(align - 1))
+            }
+
+            /// Checks if the address is a multiple of `align`, which must
+            /// be a power of two.
+            #[inline]
+            pub const fn is_aligned(self, align: u64) -> bool {
+                self.0 & (align - 1) == 0
+            }
+
+            /// Offsets the address by `bytes`.
+            #[inline]
+            pub const fn add(self, bytes: u64) -> Self {
+                Self(self.0 + bytes)
+            }
+        }
+
+        impl fmt::Debug for $name {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                write!(f, concat!(stringify!($name), "({:#x})"), self.0)
+            }
+        }
+    };
+}
+
+addr_common!(PhysAddr);
+
+addr_common!(VirtAddr);
+
+impl PhysAddr {
+    /// Gets the address that this physical address is mapped at
+    /// inside of the higher-half direct map.
+    #[inline]
+    pub fn to_virt(self) -> VirtAddr {
+        VirtAddr(self.0 + mm::hhdm_offset())
+    }
+}
+
+impl VirtAddr {
+    /// Creates a virtual address from a pointer.
+    #[inline]
+    pub fn from_ptr<T>(ptr: *const T) -> Self {
+        Self(ptr as u64)
+    }
+
+    /// Turns the address into a pointer.
+    #[inline]
+    pub const fn as_mut_ptr<T>(self) -> *mut T {
+        self.0 as *mut T
+    }
+}
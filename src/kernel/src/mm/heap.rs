@@ -0,0 +1,167 @@
+//======---------------------------------------------------------------======//
+//                                                                           //
+// Copyright 2022-2023 Evan Cox <evanacox00@gmail.com>. All rights reserved. //
+//                                                                           //
+// Use of this source code is governed by a BSD-style license that can be    //
+// found in the LICENSE.txt file at the root of this project, or at the      //
+// following link: https://opensource.org/licenses/BSD-3-Clause              //
+//                                                                           //
+//======---------------------------------------------------------------======//
+
+//! The kernel heap, i.e. what backs `alloc` inside of the kernel.
+//!
+//! The heap lives in its own region of the higher half, and grows on demand
+//! by mapping fresh frames from the physical memory manager onto its end.
+
+use crate::arch::{PageFlags, PageMapper};
+use crate::mm::{self, pmm, VirtAddr, PAGE_SIZE};
+use crate::utility::KSpinMutex;
+use core::alloc::{GlobalAlloc, Layout};
+use core::ptr::{self, NonNull};
+use ksupport::mem::FreeListHeap;
+use ksupport::sync::BasicMutex;
+use log::{info, warn};
+
+const HEAP_START: u64 = 0xFFFF_D000_0000_0000;
+const HEAP_MAX_SIZE: u64 = 64 * 1024 * 1024 * 1024;
+const HEAP_INITIAL_SIZE: u64 = 1024 * 1024;
+const HEAP_MIN_GROWTH: u64 = 256 * 1024;
+
+/// A snapshot of the heap's counters.
+#[derive(Copy, Clone, Debug)]
+pub struct HeapStats {
+    /// The number of bytes of virtual memory mapped for the heap.
+    pub mapped: u64,
+    /// The number of bytes handed out to allocations.
+    pub used: usize,
+    /// The number of bytes available for allocations without growing.
+    pub free: usize,
+}
+
+struct KernelHeap {
+    heap: FreeListHeap,
+    end: VirtAddr,
+    initialized: bool,
+}
+
+impl KernelHeap {
+    // maps at least `bytes` more memory onto the end of the heap, returns
+    // whether any memory was actually added
+    fn grow(&mut self, bytes: u64) -> bool {
+        if !self.initialized {
+            return false;
+        }
+
+        let wanted = bytes.max(HEAP_MIN_GROWTH).next_multiple_of(PAGE_SIZE);
+        let start = self.end;
+        let mut tables = mm::kernel_page_tables().lock();
+        let mut mapped = 0;
+
+        while mapped < wanted && start.as_u64() + mapped < HEAP_START + HEAP_MAX_SIZE {
+            let Some(frame) = pmm::allocate_frame() else {
+                break;
+            };
+
+            let page = start.add(mapped);
+
+            if let Err(e) = unsafe { tables.map(page, frame, PageFlags::WRITABLE) } {
+                warn!("unable to map heap page at {page:?}: {e:?}");
+
+                unsafe { pmm::free_frame(frame) };
+
+                break;
+            }
+
+            mapped += PAGE_SIZE;
+        }
+
+        if mapped == 0 {
+            return false;
+        }
+
+        self.end = start.add(mapped);
+
+        unsafe {
+            self.heap
+                .add_region(start.as_mut_ptr(), usize::try_from(mapped).unwrap_or(0));
+        }
+
+        true
+    }
+}
+
+/// The kernel's `#[global_allocator]`.
+pub struct KernelAllocator {
+    inner: KSpinMutex<KernelHeap>,
+}
+
+#[global_allocator]
+static ALLOCATOR: KernelAllocator = KernelAllocator {
+    inner: KSpinMutex::new(KernelHeap {
+        heap: FreeListHeap::empty(),
+        end: VirtAddr::new(HEAP_START),
+        initialized: false,
+    }),
+};
+
+unsafe impl GlobalAlloc for KernelAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let mut heap = self.inner.lock();
+
+        if let Some(ptr) = heap.heap.allocate(layout) {
+            return ptr.as_ptr();
+        }
+
+        // enough room for the allocation no matter how it ends up aligned
+        let needed = (layout.size() + layout.align()) as u64 + PAGE_SIZE;
+
+        if heap.grow(needed) {
+            heap.heap
+                .allocate(layout)
+                .map_or(ptr::null_mut(), NonNull::as_ptr)
+        } else {
+            ptr::null_mut()
+        }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        self.inner
+            .lock()
+            .heap
+            .deallocate(NonNull::new_unchecked(ptr), layout);
+    }
+}
+
+/// Initializes the heap, after this `alloc` can be used.
+///
+/// This needs the physical memory manager and the kernel page tables
+/// to be initialized first.
+///
+/// # Panics
+/// Panics if the initial heap can't be mapped.
+pub fn init() {
+    let mut heap = ALLOCATOR.inner.lock();
+
+    heap.initialized = true;
+
+    assert!(
+        heap.grow(HEAP_INITIAL_SIZE),
+        "unable to map the initial kernel heap"
+    );
+
+    info!(
+        "kernel heap initialized at {HEAP_START:#x} ({} bytes)",
+        heap.heap.total_bytes()
+    );
+}
+
+/// Gets the current heap counters.
+pub fn stats() -> HeapStats {
+    let heap = ALLOCATOR.inner.lock();
+
+    HeapStats {
+        mapped: heap.end.as_u64() - HEAP_START,
+        used: heap.heap.used_bytes(),
+        free: heap.heap.free_bytes(),
+    }
+}
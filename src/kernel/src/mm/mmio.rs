@@ -0,0 +1,55 @@
+//======---------------------------------------------------------------======//
+//                                                                           //
+// Copyright 2022-2023 Evan Cox <evanacox00@gmail.com>. All rights reserved. //
+//                                                                           //
+// Use of this source code is governed by a BSD-style license that can be    //
+// found in the LICENSE.txt file at the root of this project, or at the      //
+// following link: https://opensource.org/licenses/BSD-3-Clause              //
+//                                                                           //
+//======---------------------------------------------------------------======//
+
+//! Mappings for memory-mapped device registers.
+//!
+//! The HHDM maps memory as write-back cacheable, which is wrong for device
+//! registers. Anything that needs to talk to a device through MMIO gets an
+//! uncached mapping in a dedicated region of the higher half instead.
+
+use crate::arch::{MapError, PageFlags, PageMapper};
+use crate::mm::{self, PhysAddr, VirtAddr, PAGE_SIZE};
+use core::sync::atomic::{AtomicU64, Ordering};
+use ksupport::sync::BasicMutex;
+
+const MMIO_START: u64 = 0xFFFF_E000_0000_0000;
+const MMIO_MAX_SIZE: u64 = 64 * 1024 * 1024 * 1024;
+
+static NEXT: AtomicU64 = AtomicU64::new(MMIO_START);
+
+/// Maps `size` bytes of device memory starting at `phys` as uncached,
+/// returning the virtual address that `phys` ended up at.
+///
+/// Mappings are never torn down, this is meant for device registers that
+/// the kernel keeps using forever.
+///
+/// # Errors
+/// Fails if the MMIO region is exhausted or the pages can't be mapped.
+pub fn map(phys: PhysAddr, size: u64) -> Result<VirtAddr, MapError> {
+    let start = phys.align_down(PAGE_SIZE);
+    let length = phys.add(size).align_up(PAGE_SIZE).as_u64() - start.as_u64();
+    let base = NEXT.fetch_add(length, Ordering::Relaxed);
+
+    if base + length > MMIO_START + MMIO_MAX_SIZE {
+        return Err(MapError::OutOfMemory);
+    }
+
+    let flags = PageFlags::WRITABLE | PageFlags::NO_CACHE | PageFlags::WRITE_THROUGH;
+    let mut tables = mm::kernel_page_tables().lock();
+    let mut offset = 0;
+
+    while offset < length {
+        unsafe { tables.map(VirtAddr::new(base + offset), start.add(offset), flags)? };
+
+        offset += PAGE_SIZE;
+    }
+
+    Ok(VirtAddr::new(base + phys.as_u64() - start.as_u64()))
+}
@@ -0,0 +1,66 @@
+//======---------------------------------------------------------------======//
+//                                                                           //
+// Copyright 2022-2023 Evan Cox <evanacox00@gmail.com>. All rights reserved. //
+//                                                                           //
+// Use of this source code is governed by a BSD-style license that can be    //
+// found in the LICENSE.txt file at the root of this project, or at the      //
+// following link: https://opensource.org/licenses/BSD-3-Clause              //
+//                                                                           //
+//======---------------------------------------------------------------======//
+
+//! Platform-independent memory management.
+//!
+//! All of physical memory is expected to be mapped somewhere in the higher
+//! half of the address space by the boot code (the "HHDM"), everything in here
+//! accesses physical memory through that mapping.
+
+mod addr;
+pub mod heap;
+pub mod mmio;
+pub mod pmm;
+pub mod slab;
+
+use crate::arch::{hal, SystemInfo};
+use crate::utility::{KSpinMutex, KSpinOnceCell};
+
+pub use addr::{PhysAddr, VirtAddr};
+
+/// The size of a single frame of physical memory (and the smallest page size).
+pub const PAGE_SIZE: u64 = 4096;
+
+static HHDM_OFFSET: KSpinOnceCell<u64> = KSpinOnceCell::uninit();
+
+static KERNEL_PAGE_TABLES: KSpinOnceCell<KSpinMutex<hal::PageTables>> = KSpinOnceCell::uninit();
+
+/// Initializes the memory management subsystem from the information
+/// that boot code collected.
+///
+/// This must be called before any physical memory is touched.
+pub fn init(info: &SystemInfo) {
+    let _ = HHDM_OFFSET.set(info.hhdm_offset);
+
+    pmm::init(&info.memory_map);
+
+    // we keep using the tables that the bootloader set up, we just take
+    // ownership of them now
+    let tables = unsafe { hal::PageTables::active() };
+
+    let _ = KERNEL_PAGE_TABLES.set(KSpinMutex::new(tables));
+
+    heap::init();
+}
+
+/// The virtual address that physical address `0` is mapped to.
+#[inline]
+pub fn hhdm_offset() -> u64 {
+    *HHDM_OFFSET.get()
+}
+
+/// The page tables for the kernel's address space.
+///
+/// Anything edited in the higher half through these is visible in
+/// every address space.
+#[inline]
+pub fn kernel_page_tables() -> &'static KSpinMutex<hal::PageTables> {
+    KERNEL_PAGE_TABLES.get()
+}
@@ -0,0 +1,193 @@
+//======---------------------------------------------------------------======//
+//                                                                           //
+// Copyright 2022-2023 Evan Cox <evanacox00@gmail.com>. All rights reserved. //
+//                                                                           //
+// Use of this source code is governed by a BSD-style license that can be    //
+// found in the LICENSE.txt file at the root of this project, or at the      //
+// following link: https://opensource.org/licenses/BSD-3-Clause              //
+//                                                                           //
+//======---------------------------------------------------------------======//
+
+//! The physical memory manager, i.e. the thing that hands out frames.
+//!
+//! This is a [`FrameBitmap`] covering every frame from physical address `0`
+//! up to the end of the highest usable region. The bitmap itself is placed at
+//! the start of the first usable region big enough to hold it.
+
+// bootloader memory can't be reclaimed until the kernel runs on its own page
+// tables and stacks
+#![allow(dead_code)]
+
+use crate::arch::{MemoryMap, MemoryRegion, MemoryRegionKind};
+use crate::mm::{PhysAddr, PAGE_SIZE};
+use crate::utility::{KSpinMutex, KSpinOnceCell};
+use core::slice;
+use ksupport::mem::FrameBitmap;
+use ksupport::sync::BasicMutex;
+use log::{info, trace};
+
+/// A snapshot of how much physical memory is in use.
+#[derive(Copy, Clone, Debug)]
+pub struct FrameStats {
+    /// The number of frames the allocator is tracking.
+    pub total: usize,
+    /// The number of frames that are currently free.
+    pub free: usize,
+    /// The number of frames that are currently in use.
+    pub used: usize,
+}
+
+struct PhysicalMemoryManager {
+    frames: FrameBitmap<'static>,
+    map: MemoryMap,
+}
+
+static PMM: KSpinOnceCell<KSpinMutex<PhysicalMemoryManager>> = KSpinOnceCell::uninit();
+
+const fn frame_index(addr: u64) -> usize {
+    (addr / PAGE_SIZE) as usize
+}
+
+const fn frame_count(length: u64) -> usize {
+    frame_index(length)
+}
+
+/// Initializes the physical memory manager from the boot memory map.
+///
+/// Only [`MemoryRegionKind::Usable`] memory is made available here, see
+/// [`reclaim_bootloader_memory`] for the rest.
+///
+/// # Panics
+/// Panics if there is no usable region big enough to hold the frame bitmap.
+pub fn init(map: &MemoryMap) {
+    let highest = map
+        .regions()
+        .iter()
+        .filter(|region| {
+            matches!(
+                region.kind,
+                MemoryRegionKind::Usable | MemoryRegionKind::BootloaderReclaimable
+            )
+        })
+        .map(MemoryRegion::end)
+        .max()
+        .unwrap_or(0);
+
+    let frames = frame_index(highest);
+    let bytes = (FrameBitmap::words_needed(frames) * 8) as u64;
+    let storage = map
+        .regions_of(MemoryRegionKind::Usable)
+        .find(|region| region.length >= bytes)
+        .expect("no usable region is big enough for the frame bitmap");
+
+    trace!(
+        "placing frame bitmap for {frames} frames at {:#x} ({bytes} bytes)",
+        storage.base
+    );
+
+    let words = unsafe {
+        let ptr = PhysAddr::new(storage.base).to_virt().as_mut_ptr::<u64>();
+
+        slice::from_raw_parts_mut(ptr, FrameBitmap::words_needed(frames))
+    };
+
+    let mut bitmap = FrameBitmap::new(words, frames);
+
+    for region in map.regions_of(MemoryRegionKind::Usable) {
+        bitmap.mark_free(frame_index(region.base), frame_count(region.length));
+    }
+
+    // the bitmap can't hand out its own storage, and frame 0 is kept
+    // reserved so that a physical address of 0 is never valid
+    bitmap.mark_used(
+        frame_index(storage.base),
+        frame_count(PhysAddr::new(bytes).align_up(PAGE_SIZE).as_u64()),
+    );
+    bitmap.mark_used(0, 1);
+
+    let _ = PMM.set(KSpinMutex::new(PhysicalMemoryManager {
+        frames: bitmap,
+        map: *map,
+    }));
+
+    let stats = stats();
+
+    info!(
+        "pmm initialized! {} free frames, {} used frames ({} total)",
+        stats.free, stats.used, stats.total
+    );
+}
+
+/// Allocates a single 4 KiB frame.
+///
+/// The contents of the frame are unspecified.
+pub fn allocate_frame() -> Option<PhysAddr> {
+    allocate_frames(0)
+}
+
+/// Allocates `2^order` physically contiguous frames, the returned address
+/// is aligned to `2^order` frames.
+///
+/// The contents of the frames are unspecified.
+pub fn allocate_frames(order: u32) -> Option<PhysAddr> {
+    let frame = PMM.get().lock().frames.allocate_order(order)?;
+
+    Some(PhysAddr::new(frame as u64 * PAGE_SIZE))
+}
+
+/// Frees a single frame previously returned from [`allocate_frame`].
+///
+/// # Safety
+/// The frame must not be accessed after being freed.
+pub unsafe fn free_frame(frame: PhysAddr) {
+    free_frames(frame, 0);
+}
+
+/// Frees `2^order` frames previously returned from [`allocate_frames`]
+/// with the same `order`.
+///
+/// # Safety
+/// None of the frames may be accessed after being freed.
+pub unsafe fn free_frames(frame: PhysAddr, order: u32) {
+    debug_assert!(frame.is_aligned(PAGE_SIZE << order));
+
+    PMM.get()
+        .lock()
+        .frames
+        .free_order(frame_index(frame.as_u64()), order);
+}
+
+/// Gives every [`MemoryRegionKind::BootloaderReclaimable`] region to the
+/// allocator, returning the number of frames that were reclaimed.
+///
+/// # Safety
+/// Nothing the bootloader provided may be used after this, including the
+/// page tables and stacks that the bootloader set up. Anything that needs
+/// to survive must have been copied out already.
+pub unsafe fn reclaim_bootloader_memory() -> usize {
+    let mut pmm = PMM.get().lock();
+    let before = pmm.frames.free_frames();
+    let map = pmm.map;
+
+    for region in map.regions_of(MemoryRegionKind::BootloaderReclaimable) {
+        pmm.frames
+            .mark_free(frame_index(region.base), frame_count(region.length));
+    }
+
+    let reclaimed = pmm.frames.free_frames() - before;
+
+    info!("reclaimed {reclaimed} frames of bootloader memory");
+
+    reclaimed
+}
+
+/// Gets the current frame counters.
+pub fn stats() -> FrameStats {
+    let pmm = PMM.get().lock();
+
+    FrameStats {
+        total: pmm.frames.frames(),
+        free: pmm.frames.free_frames(),
+        used: pmm.frames.used_frames(),
+    }
+}
@@ -0,0 +1,265 @@
+//======---------------------------------------------------------------======//
+//                                                                           //
+// Copyright 2022-2023 Evan Cox <evanacox00@gmail.com>. All rights reserved. //
+//                                                                           //
+// Use of this source code is governed by a BSD-style license that can be    //
+// found in the LICENSE.txt file at the root of this project, or at the      //
+// following link: https://opensource.org/licenses/BSD-3-Clause              //
+//                                                                           //
+//======---------------------------------------------------------------======//
+
+//! Object caches for fixed-size kernel objects.
+//!
+//! Each [`ObjectCache`] is a [`SlabCache`] that gets its slabs directly
+//! from the physical memory manager, with a small per-CPU "magazine" of
+//! free objects in front of it. Most allocations and frees only touch the
+//! current CPU's magazine, the shared slab cache is only locked to refill
+//! or drain a magazine in bulk.
+
+// nothing allocates its objects from a cache yet
+#![allow(dead_code)]
+
+use crate::arch::MAX_CPUS;
+use crate::mm::{self, pmm, PhysAddr, PAGE_SIZE};
+use crate::percpu::this_cpu;
+use crate::utility::KSpinMutex;
+use core::alloc::Layout;
+use core::ops::{Deref, DerefMut};
+use core::ptr::{self, NonNull};
+use ksupport::mem::{SlabCache, SlabSource, SlabStats};
+use ksupport::sync::BasicMutex;
+use log::info;
+
+const MAGAZINE_SIZE: usize = 16;
+
+/// Gets slabs directly from the physical memory manager, through the HHDM.
+pub struct FrameSlabSource;
+
+unsafe impl SlabSource for FrameSlabSource {
+    fn allocate_slab(&self, size: usize) -> Option<NonNull<u8>> {
+        let order = (size as u64 / PAGE_SIZE).trailing_zeros();
+        let frames = pmm::allocate_frames(order)?;
+
+        NonNull::new(frames.to_virt().as_mut_ptr::<u8>())
+    }
+
+    unsafe fn free_slab(&self, slab: NonNull<u8>, size: usize) {
+        let order = (size as u64 / PAGE_SIZE).trailing_zeros();
+        let phys = PhysAddr::new(slab.as_ptr() as u64 - mm::hhdm_offset());
+
+        pmm::free_frames(phys, order);
+    }
+}
+
+// a stack of free objects owned by a single CPU
+struct Magazine {
+    objects: [Option<NonNull<u8>>; MAGAZINE_SIZE],
+    len: usize,
+}
+
+impl Magazine {
+    const fn new() -> Self {
+        Self {
+            objects: [None; MAGAZINE_SIZE],
+            len: 0,
+        }
+    }
+
+    const fn push(&mut self, object: NonNull<u8>) {
+        self.objects[self.len] = Some(object);
+        self.len += 1;
+    }
+
+    fn pop(&mut self) -> Option<NonNull<u8>> {
+        self.len = self.len.checked_sub(1)?;
+        self.objects[self.len].take()
+    }
+}
+
+// the objects in a magazine are only ever touched by whoever holds its lock
+unsafe impl Send for Magazine {}
+
+/// A snapshot of an [`ObjectCache`]'s counters.
+#[derive(Copy, Clone, Debug)]
+pub struct CacheStats {
+    /// The counters for the underlying slab cache. Objects sitting in
+    /// magazines count as being in use there.
+    pub slabs: SlabStats,
+    /// The number of free objects sitting in per-CPU magazines.
+    pub cached: usize,
+}
+
+impl CacheStats {
+    /// The number of objects that are actually allocated right now.
+    #[must_use]
+    pub const fn objects_in_use(&self) -> usize {
+        self.slabs.objects_in_use - self.cached
+    }
+}
+
+/// A cache of objects of type `T`, meant to be put in a `static`.
+///
+/// Objects are created through the cache's constructor (or with an explicit
+/// value through [`Self::alloc_with`]), and are given back to the cache
+/// when the [`SlabBox`] holding them is dropped.
+pub struct ObjectCache<T: 'static> {
+    name: &'static str,
+    ctor: fn() -> T,
+    depot: KSpinMutex<SlabCache<FrameSlabSource>>,
+    magazines: [KSpinMutex<Magazine>; MAX_CPUS],
+}
+
+impl<T: 'static> ObjectCache<T> {
+    /// Creates a cache named `name`, with `ctor` creating new objects.
+    ///
+    /// No memory is allocated until the first object is.
+    #[must_use]
+    pub const fn new(name: &'static str, ctor: fn() -> T) -> Self {
+        Self {
+            name,
+            ctor,
+            depot: KSpinMutex::new(SlabCache::new(Layout::new::<T>(), FrameSlabSource)),
+            magazines: [const { KSpinMutex::new(Magazine::new()) }; MAX_CPUS],
+        }
+    }
+
+    /// The name the cache was created with.
+    #[must_use]
+    pub const fn name(&self) -> &'static str {
+        self.name
+    }
+
+    /// Allocates an object, initialized with the cache's constructor.
+    ///
+    /// Returns `None` if the system is out of memory.
+    pub fn alloc(&'static self) -> Option<SlabBox<T>> {
+        self.alloc_with((self.ctor)())
+    }
+
+    /// Allocates an object, initialized with `value`.
+    ///
+    /// Returns `None` if the system is out of memory.
+    pub fn alloc_with(&'static self, value: T) -> Option<SlabBox<T>> {
+        let object = self.allocate_raw()?.cast::<T>();
+
+        unsafe { object.as_ptr().write(value) };
+
+        Some(SlabBox {
+            object,
+            cache: self,
+        })
+    }
+
+    /// Gives every free object and every empty slab back to the physical
+    /// memory manager, returning the number of slabs freed.
+    pub fn reclaim(&self) -> usize {
+        for magazine in &self.magazines {
+            let mut magazine = magazine.lock();
+            let mut depot = self.depot.lock();
+
+            while let Some(object) = magazine.pop() {
+                unsafe { depot.deallocate(object) };
+            }
+        }
+
+        self.depot.lock().shrink()
+    }
+
+    /// Gets the current counters for the cache.
+    pub fn stats(&self) -> CacheStats {
+        let cached = self.magazines.iter().map(|m| m.lock().len).sum();
+
+        CacheStats {
+            slabs: self.depot.lock().stats(),
+            cached,
+        }
+    }
+
+    /// Logs the current counters for the cache.
+    pub fn dump_stats(&self) {
+        let stats = self.stats();
+
+        info!(
+            "slab cache '{}': {} objects in use, {} cached, {} slabs of {} bytes ({} objects of {} bytes each), {} bytes wasted",
+            self.name,
+            stats.objects_in_use(),
+            stats.cached,
+            stats.slabs.slabs,
+            stats.slabs.slab_size,
+            stats.slabs.objects_per_slab,
+            stats.slabs.object_size,
+            stats.slabs.waste
+        );
+    }
+
+    fn allocate_raw(&self) -> Option<NonNull<u8>> {
+        let mut magazine = self.magazines[this_cpu().index()].lock();
+
+        if magazine.len == 0 {
+            let mut depot = self.depot.lock();
+
+            // only fill it halfway so that a few frees don't immediately drain it
+            while magazine.len < MAGAZINE_SIZE / 2 {
+                let Some(object) = depot.allocate() else {
+                    break;
+                };
+
+                magazine.push(object);
+            }
+        }
+
+        magazine.pop()
+    }
+
+    unsafe fn free_raw(&self, object: NonNull<u8>) {
+        let mut magazine = self.magazines[this_cpu().index()].lock();
+
+        if magazine.len == MAGAZINE_SIZE {
+            let mut depot = self.depot.lock();
+
+            while magazine.len > MAGAZINE_SIZE / 2 {
+                if let Some(object) = magazine.pop() {
+                    depot.deallocate(object);
+                }
+            }
+        }
+
+        magazine.push(object);
+    }
+}
+
+/// An owning pointer to an object allocated from an [`ObjectCache`].
+///
+/// Dropping this drops the object and gives its memory back to the cache.
+pub struct SlabBox<T: 'static> {
+    object: NonNull<T>,
+    cache: &'static ObjectCache<T>,
+}
+
+impl<T: 'static> Deref for SlabBox<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { self.object.as_ref() }
+    }
+}
+
+impl<T: 'static> DerefMut for SlabBox<T> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { self.object.as_mut() }
+    }
+}
+
+impl<T: 'static> Drop for SlabBox<T> {
+    fn drop(&mut self) {
+        unsafe {
+            ptr::drop_in_place(self.object.as_ptr());
+
+            self.cache.free_raw(self.object.cast());
+        }
+    }
+}
+
+unsafe impl<T: Send + 'static> Send for SlabBox<T> {}
+
+unsafe impl<T: Sync + 'static> Sync for SlabBox<T> {}
@@ -0,0 +1,229 @@
+//======---------------------------------------------------------------======//
+//                                                                           //
+// Copyright 2022-2023 Evan Cox <evanacox00@gmail.com>. All rights reserved. //
+//                                                                           //
+// Use of this source code is governed by a BSD-style license that can be    //
+// found in the LICENSE.txt file at the root of this project, or at the      //
+// following link: https://opensource.org/licenses/BSD-3-Clause              //
+//                                                                           //
+//======---------------------------------------------------------------======//
+
+//! Data that every CPU has its own copy of.
+//!
+//! Every CPU has a [`CpuLocal`] block, which the arch code keeps the
+//! address of in a register (`GS` base on x86-64, `TPIDR_EL1` on aarch64)
+//! so that [`this_cpu`] is a single load. Anything else that needs to be
+//! per-CPU can be declared with [`percpu!`](crate::percpu!), which gives
+//! every CPU its own slot in a static array.
+//!
+//! References given out here stay valid forever, but they're only *local*
+//! as long as the current thread stays on the same CPU.
+
+use crate::arch::{hal, MAX_CPUS};
+use crate::mm::VirtAddr;
+use core::ptr;
+use core::sync::atomic::{AtomicBool, AtomicPtr, AtomicUsize, Ordering};
+
+/// The per-CPU block that the per-CPU base register points at.
+#[repr(C)]
+pub struct CpuLocal {
+    // the arch code reads the block's address out of this, it has to stay first
+    this: AtomicPtr<Self>,
+    index: AtomicUsize,
+    lock_depth: AtomicUsize,
+    interrupt_depth: AtomicUsize,
+    interrupts_off_depth: AtomicUsize,
+    // whether interrupts were enabled before the outermost push
+    interrupts_were_enabled: AtomicBool,
+}
+
+impl CpuLocal {
+    const fn new() -> Self {
+        Self {
+            this: AtomicPtr::new(ptr::null_mut()),
+            index: AtomicUsize::new(0),
+            lock_depth: AtomicUsize::new(0),
+            interrupt_depth: AtomicUsize::new(0),
+            interrupts_off_depth: AtomicUsize::new(0),
+            interrupts_were_enabled: AtomicBool::new(false),
+        }
+    }
+
+    /// The index of this CPU, the bootstrap processor is always `0`.
+    #[inline]
+    pub fn index(&self) -> usize {
+        self.index.load(Ordering::Relaxed)
+    }
+
+    /// The number of kernel spinlocks that this CPU is holding.
+    #[inline]
+    pub fn lock_depth(&self) -> usize {
+        self.lock_depth.load(Ordering::Relaxed)
+    }
+
+    /// How many interrupt handlers deep this CPU is, `0` outside of any.
+    #[inline]
+    pub fn interrupt_depth(&self) -> usize {
+        self.interrupt_depth.load(Ordering::Relaxed)
+    }
+
+    /// Whether this CPU is currently handling an interrupt.
+    #[inline]
+    pub fn in_interrupt(&self) -> bool {
+        self.interrupt_depth() != 0
+    }
+
+    /// Records that a kernel spinlock was acquired.
+    #[inline]
+    pub fn lock_acquired(&self) {
+        self.lock_depth.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Records that a kernel spinlock was released.
+    #[inline]
+    pub fn lock_released(&self) {
+        let old = self.lock_depth.fetch_sub(1, Ordering::Relaxed);
+
+        debug_assert!(old != 0, "released more locks than were acquired");
+    }
+
+    /// Disables interrupts on this CPU until a matching
+    /// [`pop_interrupts_off`](Self::pop_interrupts_off).
+    ///
+    /// Pushes nest, and pops don't need to happen in the reverse order of
+    /// pushes. Interrupts are only enabled again (if they were enabled to
+    /// begin with) once every push has been popped.
+    ///
+    /// This must only be called on the current CPU's block.
+    #[inline]
+    pub fn push_interrupts_off(&self) {
+        let were_enabled = hal::interrupts_disable();
+
+        if self.interrupts_off_depth.fetch_add(1, Ordering::Relaxed) == 0 {
+            self.interrupts_were_enabled
+                .store(were_enabled, Ordering::Relaxed);
+        }
+    }
+
+    /// Undoes a [`push_interrupts_off`](Self::push_interrupts_off).
+    ///
+    /// In debug builds, this asserts that interrupts weren't enabled by
+    /// something else while they were pushed off.
+    ///
+    /// This must only be called on the current CPU's block.
+    #[inline]
+    pub fn pop_interrupts_off(&self) {
+        debug_assert!(
+            !hal::interrupts_enabled(),
+            "interrupts were enabled while they were pushed off"
+        );
+
+        let old = self.interrupts_off_depth.fetch_sub(1, Ordering::Relaxed);
+
+        debug_assert!(old != 0, "popped interrupts off more than they were pushed");
+
+        if old == 1 {
+            let were_enabled = self.interrupts_were_enabled.load(Ordering::Relaxed);
+
+            unsafe { hal::interrupts_restore(were_enabled) };
+        }
+    }
+
+    /// How many times interrupts have been pushed off on this CPU.
+    #[inline]
+    pub fn interrupts_off_depth(&self) -> usize {
+        self.interrupts_off_depth.load(Ordering::Relaxed)
+    }
+
+    /// Records that an interrupt handler was entered.
+    #[inline]
+    pub fn interrupt_entered(&self) {
+        self.interrupt_depth.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Records that an interrupt handler was left.
+    #[inline]
+    pub fn interrupt_exited(&self) {
+        let old = self.interrupt_depth.fetch_sub(1, Ordering::Relaxed);
+
+        debug_assert!(old != 0, "left more interrupts than were entered");
+    }
+}
+
+static CPU_LOCALS: [CpuLocal; MAX_CPUS] = [const { CpuLocal::new() }; MAX_CPUS];
+
+/// Sets up the per-CPU block for CPU `index`, and points the current CPU's
+/// per-CPU base register at it.
+///
+/// # Safety
+/// This must be the first thing that a CPU does, before anything takes a
+/// lock. Every CPU must use a different `index`.
+///
+/// # Panics
+/// Panics if `index` is not less than [`MAX_CPUS`].
+pub unsafe fn init_current(index: usize) {
+    let local = &CPU_LOCALS[index];
+
+    local
+        .this
+        .store(ptr::from_ref(local).cast_mut(), Ordering::Relaxed);
+    local.index.store(index, Ordering::Relaxed);
+
+    hal::set_percpu_base(VirtAddr::from_ptr(local));
+}
+
+/// Gets the per-CPU block of the current CPU.
+#[inline]
+pub fn this_cpu() -> &'static CpuLocal {
+    unsafe { &*hal::percpu_base().as_mut_ptr::<CpuLocal>() }
+}
+
+/// A value that every CPU has its own copy of, see [`percpu!`](crate::percpu!).
+pub struct PerCpu<T> {
+    values: [T; MAX_CPUS],
+}
+
+impl<T> PerCpu<T> {
+    /// Creates the per-CPU value from every CPU's initial value.
+    pub const fn new(values: [T; MAX_CPUS]) -> Self {
+        Self { values }
+    }
+
+    /// Gets the current CPU's copy.
+    #[inline]
+    pub fn get(&self) -> &T {
+        &self.values[this_cpu().index()]
+    }
+
+    /// Gets CPU `index`'s copy.
+    ///
+    /// # Panics
+    /// Panics if `index` is not less than [`MAX_CPUS`].
+    #[inline]
+    pub const fn get_for(&self, index: usize) -> &T {
+        &self.values[index]
+    }
+}
+
+/// Declares statics that every CPU has its own copy of.
+///
+/// The initializer has to be a constant expression, every CPU starts
+/// out with the same value.
+///
+/// ```ignore
+/// percpu! {
+///     static TICKS: AtomicU64 = AtomicU64::new(0);
+/// }
+///
+/// TICKS.get().fetch_add(1, Ordering::Relaxed);
+/// ```
+#[macro_export]
+macro_rules! percpu {
+    ($($(#[$meta:meta])* $vis:vis static $name:ident: $ty:ty = $init:expr;)*) => {
+        $(
+            $(#[$meta])*
+            $vis static $name: $crate::percpu::PerCpu<$ty> =
+                $crate::percpu::PerCpu::new([const { $init }; $crate::arch::MAX_CPUS]);
+        )*
+    };
+}
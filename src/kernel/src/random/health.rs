@@ -0,0 +1,54 @@
+//======---------------------------------------------------------------======//
+//                                                                           //
+// Copyright 2022-2023 Evan Cox <evanacox00@gmail.com>. All rights reserved. //
+//                                                                           //
+// Use of this source code is governed by a BSD-style license that can be    //
+// found in the LICENSE.txt file at the root of this project, or at the      //
+// following link: https://opensource.org/licenses/BSD-3-Clause              //
+//                                                                           //
+//======---------------------------------------------------------------======//
+
+//! Continuous health tests for entropy sources, so that a source that
+//! breaks at runtime stops being trusted instead of silently feeding the
+//! pool predictable data.
+
+/// The repetition count test from NIST SP 800-90B (section 4.4.1): a source
+/// fails once it produces the same sample `cutoff` times in a row.
+pub struct RepetitionCount {
+    last: Option<u64>,
+    run: u32,
+    cutoff: u32,
+    failed: bool,
+}
+
+impl RepetitionCount {
+    /// Creates a test that fails after `cutoff` identical samples in a row.
+    pub const fn new(cutoff: u32) -> Self {
+        Self {
+            last: None,
+            run: 0,
+            cutoff,
+            failed: false,
+        }
+    }
+
+    /// Feeds a sample to the test, returning whether the source is
+    /// still healthy. Once a source fails, it stays failed.
+    pub fn check(&mut self, sample: u64) -> bool {
+        if self.last == Some(sample) {
+            self.run += 1;
+        } else {
+            self.last = Some(sample);
+            self.run = 1;
+        }
+
+        self.failed |= self.run >= self.cutoff;
+
+        !self.failed
+    }
+
+    /// Whether the source has ever failed the test.
+    pub const fn failed(&self) -> bool {
+        self.failed
+    }
+}
@@ -0,0 +1,65 @@
+//======---------------------------------------------------------------======//
+//                                                                           //
+// Copyright 2022-2023 Evan Cox <evanacox00@gmail.com>. All rights reserved. //
+//                                                                           //
+// Use of this source code is governed by a BSD-style license that can be    //
+// found in the LICENSE.txt file at the root of this project, or at the      //
+// following link: https://opensource.org/licenses/BSD-3-Clause              //
+//                                                                           //
+//======---------------------------------------------------------------======//
+
+//! Entropy from the timing of interrupts.
+//!
+//! Interrupt handlers can't take the pool's lock (a CPU that interrupted
+//! itself while holding it would deadlock), so samples go into a small
+//! lock-free "fast pool" that the main pool drains every so often.
+
+use crate::arch::hal;
+use core::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+
+/// How many interrupts it takes to be worth a bit of entropy. Interrupts
+/// are often periodic (timers especially), so this is very conservative.
+pub const INTERRUPTS_PER_BIT: usize = 64;
+
+const WORDS: usize = 4;
+
+static FAST_POOL: [AtomicU64; WORDS] = [const { AtomicU64::new(0) }; WORDS];
+
+static COUNT: AtomicUsize = AtomicUsize::new(0);
+
+/// Records that an interrupt arrived on `vector`. This is cheap enough to
+/// do for every interrupt, and can be called from any context.
+#[inline]
+pub fn record(vector: u8) {
+    let count = COUNT.fetch_add(1, Ordering::Relaxed);
+    let time = hal::cycle_counter();
+
+    // only the low bits of the time are unpredictable, the multiply
+    // spreads them out and the rotation keeps consecutive interrupts
+    // from cancelling each other out
+    #[allow(clippy::cast_possible_truncation)]
+    let sample = (time ^ (u64::from(vector) << 56))
+        .wrapping_mul(0x9E37_79B9_7F4A_7C15)
+        .rotate_left((count * 13 % 64) as u32);
+
+    FAST_POOL[count % WORDS].fetch_xor(sample, Ordering::Relaxed);
+}
+
+/// The number of interrupts recorded since the last [`drain`].
+#[inline]
+pub fn pending() -> usize {
+    COUNT.load(Ordering::Relaxed)
+}
+
+/// Empties the fast pool, returning what was in it and how many
+/// interrupts it came from.
+pub fn drain() -> ([u8; WORDS * 8], usize) {
+    let count = COUNT.swap(0, Ordering::Relaxed);
+    let mut bytes = [0; WORDS * 8];
+
+    for (chunk, word) in bytes.as_chunks_mut::<8>().0.iter_mut().zip(&FAST_POOL) {
+        *chunk = word.swap(0, Ordering::Relaxed).to_le_bytes();
+    }
+
+    (bytes, count)
+}
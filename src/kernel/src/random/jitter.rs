@@ -0,0 +1,121 @@
+//======---------------------------------------------------------------======//
+//                                                                           //
+// Copyright 2022-2023 Evan Cox <evanacox00@gmail.com>. All rights reserved. //
+//                                                                           //
+// Use of this source code is governed by a BSD-style license that can be    //
+// found in the LICENSE.txt file at the root of this project, or at the      //
+// following link: https://opensource.org/licenses/BSD-3-Clause              //
+//                                                                           //
+//======---------------------------------------------------------------======//
+
+//! Entropy from CPU timing jitter.
+//!
+//! The time that a fixed chunk of work takes varies a tiny bit every time
+//! it's run, because of caches, pipelines, bus contention and the like.
+//! Nobody can predict those variations exactly, not even the CPU, so the
+//! low bits of a fine-grained timer around the work are a little random.
+//! This works on every CPU, but it's slow and yields very little per
+//! sample, so it's mostly there for when the hardware has no RNG.
+
+use super::health::RepetitionCount;
+use crate::arch::hal;
+use core::hint;
+
+// how many samples have to vary before they're worth a bit of entropy.
+// this is deliberately conservative, the timers on some machines are
+// coarse enough that most of a sample is predictable
+const SAMPLES_PER_BIT: u32 = 8;
+
+// a timer that's stuck at the same delta this many times in a row is
+// broken (or is being emulated), and isn't trusted anymore
+const REPETITION_CUTOFF: u32 = 32;
+
+// the work being timed walks this many bytes, which is enough to touch
+// a few cache lines without taking long
+const SCRATCH_BYTES: usize = 1024;
+
+/// Collects jitter samples and hands them to the pool.
+pub struct JitterSource {
+    scratch: [u8; SCRATCH_BYTES],
+    position: usize,
+    last_delta: u64,
+    last_delta2: u64,
+    varied: u32,
+    health: RepetitionCount,
+}
+
+impl JitterSource {
+    /// Creates a source with nothing collected yet.
+    pub const fn new() -> Self {
+        Self {
+            scratch: [0; SCRATCH_BYTES],
+            position: 0,
+            last_delta: 0,
+            last_delta2: 0,
+            varied: 0,
+            health: RepetitionCount::new(REPETITION_CUTOFF),
+        }
+    }
+
+    /// Whether the timer has failed its health test, after which
+    /// nothing from it is credited.
+    pub const fn failed(&self) -> bool {
+        self.health.failed()
+    }
+
+    /// Takes `samples` samples, passing each one to `sink` along with how
+    /// many bits of entropy it's worth (almost always `0`).
+    pub fn collect(&mut self, samples: usize, mut sink: impl FnMut(u64, u32)) {
+        for _ in 0..samples {
+            let start = hal::cycle_counter();
+
+            self.work();
+
+            let delta = hal::cycle_counter().wrapping_sub(start);
+            let credit = u32::from(self.varied(delta));
+
+            sink(delta, credit);
+        }
+    }
+
+    // checks whether a sample is worth anything. the first, second and
+    // third derivatives all have to be non-zero, otherwise the timer is
+    // just ticking along at a steady rate
+    fn varied(&mut self, delta: u64) -> bool {
+        let delta2 = delta.wrapping_sub(self.last_delta);
+        let delta3 = delta2.wrapping_sub(self.last_delta2);
+
+        self.last_delta = delta;
+        self.last_delta2 = delta2;
+
+        let healthy = self.health.check(delta);
+
+        if !healthy || delta == 0 || delta2 == 0 || delta3 == 0 {
+            return false;
+        }
+
+        self.varied += 1;
+
+        if self.varied == SAMPLES_PER_BIT {
+            self.varied = 0;
+
+            return true;
+        }
+
+        false
+    }
+
+    // a memory walk with data-dependent strides, so that its timing
+    // depends on the state of the caches
+    fn work(&mut self) {
+        for _ in 0..64 {
+            let byte = &mut self.scratch[self.position];
+
+            *byte = byte.wrapping_add(1);
+            self.position = (self.position + 67 + usize::from(*byte)) % SCRATCH_BYTES;
+        }
+
+        // the walk doesn't have any visible effects, make sure it happens
+        hint::black_box(&mut self.scratch);
+    }
+}
@@ -0,0 +1,260 @@
+//======---------------------------------------------------------------======//
+//                                                                           //
+// Copyright 2022-2023 Evan Cox <evanacox00@gmail.com>. All rights reserved. //
+//                                                                           //
+// Use of this source code is governed by a BSD-style license that can be    //
+// found in the LICENSE.txt file at the root of this project, or at the      //
+// following link: https://opensource.org/licenses/BSD-3-Clause              //
+//                                                                           //
+//======---------------------------------------------------------------======//
+
+//! The kernel's random number generator, for anything that needs numbers
+//! that can't be predicted (stack and address space randomization,
+//! capability badges, and user mode through [`getrandom`](syscall::getrandom)).
+//!
+//! Entropy is collected from the CPU's random number generator if it has
+//! one (`rdseed`/`rdrand` on x86-64, `RNDRRS`/`RNDR` on aarch64), timing
+//! jitter, and the timing of interrupts. All of it gets mixed into a
+//! [`ChaCha20Rng`], which everything is generated from.
+//!
+//! Every source is credited with how many bits of entropy it's assumed to
+//! have provided, and nothing is handed out until that adds up to at least
+//! [`SEED_BITS`]. The hardware DRBGs (`rdrand`, `RNDR`) are mixed in but
+//! never credited, since there's no way to tell from here how much of
+//! their output is entropy and how much is stretched from a seed.
+
+mod health;
+mod interrupts;
+mod jitter;
+pub mod syscall;
+
+use crate::arch::hal;
+use crate::utility::KSpinMutex;
+use core::sync::atomic::{AtomicBool, Ordering};
+use health::RepetitionCount;
+use jitter::JitterSource;
+use ksupport::sync::BasicMutex;
+use ksupport::ChaCha20Rng;
+use log::{trace, warn};
+
+/// How many bits of entropy have to be collected before anything is
+/// generated.
+pub const SEED_BITS: u32 = 256;
+
+// how many times the hardware entropy source is read when collecting
+const HARDWARE_SAMPLES: usize = 8;
+
+// any sample that repeats is a broken source, the odds of it happening
+// by chance with 64-bit samples are negligible
+const HARDWARE_REPETITION_CUTOFF: u32 = 2;
+
+// jitter is collected in batches, so the pool's lock isn't held for long
+const JITTER_BATCH: usize = 256;
+
+// how much jitter `init` collects before it gives up on seeding the pool
+const JITTER_BOOT_BATCHES: usize = 256;
+
+/// The ways that getting random numbers can fail.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum RandomError {
+    /// Not enough entropy has been collected yet, and waiting for it
+    /// wasn't allowed.
+    NotSeeded,
+    /// Unknown flags were passed to [`getrandom`](syscall::getrandom).
+    InvalidFlags,
+}
+
+struct Pool {
+    rng: ChaCha20Rng,
+    credited: u32,
+    hardware_health: RepetitionCount,
+    jitter: JitterSource,
+}
+
+impl Pool {
+    const fn new() -> Self {
+        Self {
+            // the key doesn't matter, none of the output is used until
+            // enough entropy has been mixed in
+            rng: ChaCha20Rng::new([0; 32]),
+            credited: 0,
+            hardware_health: RepetitionCount::new(HARDWARE_REPETITION_CUTOFF),
+            jitter: JitterSource::new(),
+        }
+    }
+
+    fn credit(&mut self, bits: u32) {
+        self.credited = self.credited.saturating_add(bits);
+
+        if self.credited >= SEED_BITS {
+            SEEDED.store(true, Ordering::Release);
+        }
+    }
+
+    // returns how many bits were credited
+    fn add_hardware(&mut self) -> u32 {
+        let mut bits = 0;
+
+        for _ in 0..HARDWARE_SAMPLES {
+            if let Some(value) = hal::hardware_random() {
+                self.rng.mix(&value.to_le_bytes());
+            }
+
+            let Some(seed) = hal::hardware_seed() else {
+                continue;
+            };
+
+            // some CPUs have shipped with an RNG that always returned
+            // all ones after a suspend, which also fails this
+            let healthy = self.hardware_health.check(seed) && seed != 0 && seed != u64::MAX;
+
+            self.rng.mix(&seed.to_le_bytes());
+
+            if healthy {
+                bits += u64::BITS;
+            }
+        }
+
+        self.credit(bits);
+
+        bits
+    }
+
+    // returns how many bits were credited
+    fn add_jitter(&mut self, samples: usize) -> u32 {
+        let Self { rng, jitter, .. } = self;
+        let mut batch = [0; 32];
+        let mut filled = 0;
+        let mut bits = 0;
+
+        jitter.collect(samples, |delta, credit| {
+            batch[filled..filled + 8].copy_from_slice(&delta.to_le_bytes());
+            filled += 8;
+            bits += credit;
+
+            if filled == batch.len() {
+                rng.mix(&batch);
+                filled = 0;
+            }
+        });
+
+        self.rng.mix(&batch[..filled]);
+        self.credit(bits);
+
+        bits
+    }
+
+    // returns how many bits were credited
+    fn absorb_interrupts(&mut self) -> u32 {
+        if interrupts::pending() < interrupts::INTERRUPTS_PER_BIT {
+            return 0;
+        }
+
+        let (bytes, count) = interrupts::drain();
+
+        #[allow(clippy::cast_possible_truncation)]
+        let bits = (count / interrupts::INTERRUPTS_PER_BIT) as u32;
+
+        self.rng.mix(&bytes);
+        self.credit(bits);
+
+        bits
+    }
+}
+
+static POOL: KSpinMutex<Pool> = KSpinMutex::new(Pool::new());
+
+static SEEDED: AtomicBool = AtomicBool::new(false);
+
+/// Collects entropy from everything that's available at boot, which is
+/// usually enough to seed the pool.
+///
+/// Interrupts should be set up, since the jitter from them helps.
+pub fn init() {
+    let (mut hardware, mut jitter, mut interrupts) = (0, 0, 0);
+
+    // this is far from secret, but it's different on every boot
+    add_entropy(&hal::cycle_counter().to_le_bytes(), 0);
+
+    hardware += POOL.lock().add_hardware();
+
+    // jitter is cheap enough to always mix some in, and it's the only
+    // source that doesn't depend on trusting the hardware
+    for batch in 0..JITTER_BOOT_BATCHES {
+        if batch > 0 && is_seeded() {
+            break;
+        }
+
+        let mut pool = POOL.lock();
+
+        // interrupts that arrived since the last batch are mixed in too
+        interrupts += pool.absorb_interrupts();
+        jitter += pool.add_jitter(JITTER_BATCH);
+    }
+
+    if is_seeded() {
+        trace!("entropy pool seeded, {hardware} bits from hardware, {jitter} from jitter and {interrupts} from interrupts");
+    } else {
+        warn!("entropy pool isn't seeded, only {hardware} bits from hardware, {jitter} from jitter and {interrupts} from interrupts were collected");
+    }
+
+    if POOL.lock().jitter.failed() {
+        warn!("the cycle counter failed its health test, jitter isn't being credited");
+    }
+}
+
+/// Checks whether enough entropy has been collected to generate anything.
+#[inline]
+pub fn is_seeded() -> bool {
+    SEEDED.load(Ordering::Acquire)
+}
+
+/// Mixes `data` into the pool, crediting it with `bits` bits of entropy.
+/// Data that isn't secret (or isn't known to be) can be mixed in with no
+/// credit, it can't hurt.
+pub fn add_entropy(data: &[u8], bits: u32) {
+    let mut pool = POOL.lock();
+
+    pool.rng.mix(data);
+    pool.credit(bits);
+}
+
+/// Records the timing of an interrupt on `vector`. This is cheap enough
+/// to call for every interrupt, and can be called from any context.
+#[inline]
+pub fn add_interrupt_timing(vector: u8) {
+    interrupts::record(vector);
+}
+
+/// Fills `buffer` with random bytes.
+///
+/// # Errors
+/// Fails if the pool isn't seeded yet.
+pub fn fill(buffer: &mut [u8]) -> Result<(), RandomError> {
+    if !is_seeded() {
+        return Err(RandomError::NotSeeded);
+    }
+
+    generate(buffer);
+
+    Ok(())
+}
+
+/// Produces a random `u64`.
+///
+/// # Errors
+/// Fails if the pool isn't seeded yet.
+pub fn next_u64() -> Result<u64, RandomError> {
+    let mut bytes = [0; 8];
+
+    fill(&mut bytes)?;
+
+    Ok(u64::from_le_bytes(bytes))
+}
+
+fn generate(buffer: &mut [u8]) {
+    let mut pool = POOL.lock();
+
+    pool.absorb_interrupts();
+    pool.rng.fill_bytes(buffer);
+}
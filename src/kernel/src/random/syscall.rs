@@ -0,0 +1,114 @@
+//======---------------------------------------------------------------======//
+//                                                                           //
+// Copyright 2022-2023 Evan Cox <evanacox00@gmail.com>. All rights reserved. //
+//                                                                           //
+// Use of this source code is governed by a BSD-style license that can be    //
+// found in the LICENSE.txt file at the root of this project, or at the      //
+// following link: https://opensource.org/licenses/BSD-3-Clause              //
+//                                                                           //
+//======---------------------------------------------------------------======//
+
+//! The user mode side of the pool, i.e. what the `getrandom` system call
+//! is implemented with.
+//!
+//! Unlike [`fill`](super::fill), user mode gets to choose whether it waits
+//! for the pool to be seeded, fails, or takes whatever the pool has.
+
+// there's no system call layer to call into this from yet
+#![allow(dead_code)]
+
+use crate::random::{generate, is_seeded, RandomError, JITTER_BATCH, POOL};
+use core::hint;
+use core::ops::{BitOr, BitOrAssign};
+use ksupport::sync::BasicMutex;
+
+/// Flags that change how [`getrandom`] behaves. These have the same values
+/// as Linux's `GRND_*` flags.
+#[repr(transparent)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, Default)]
+pub struct GetRandomFlags(u32);
+
+impl GetRandomFlags {
+    /// Waits until the pool is seeded before generating anything.
+    pub const NONE: Self = Self(0);
+
+    /// Fails with [`RandomError::NotSeeded`] instead of waiting.
+    pub const NONBLOCK: Self = Self(1 << 0);
+
+    /// Generates output even if the pool isn't seeded yet, which is fine
+    /// for things like hash table seeds but nothing secret.
+    pub const INSECURE: Self = Self(1 << 2);
+
+    /// Checks the flags that user mode passed in.
+    ///
+    /// # Errors
+    /// Fails if any unknown flags are set.
+    pub const fn from_bits(bits: u32) -> Result<Self, RandomError> {
+        if bits & !(Self::NONBLOCK.0 | Self::INSECURE.0) == 0 {
+            Ok(Self(bits))
+        } else {
+            Err(RandomError::InvalidFlags)
+        }
+    }
+
+    /// Checks if every flag in `other` is also set in `self`.
+    #[inline]
+    pub const fn contains(self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+}
+
+impl BitOr for GetRandomFlags {
+    type Output = Self;
+
+    #[inline]
+    fn bitor(self, rhs: Self) -> Self::Output {
+        Self(self.0 | rhs.0)
+    }
+}
+
+impl BitOrAssign for GetRandomFlags {
+    #[inline]
+    fn bitor_assign(&mut self, rhs: Self) {
+        self.0 |= rhs.0;
+    }
+}
+
+/// Fills `buffer` with random bytes on behalf of user mode, returning how
+/// many bytes were written.
+///
+/// If the pool isn't seeded yet, this collects entropy until it is, unless
+/// `flags` says otherwise.
+///
+/// # Errors
+/// Fails with [`RandomError::NotSeeded`] if the pool isn't seeded, and
+/// [`GetRandomFlags::NONBLOCK`] was passed.
+pub fn getrandom(buffer: &mut [u8], flags: GetRandomFlags) -> Result<usize, RandomError> {
+    if !is_seeded() && !flags.contains(GetRandomFlags::INSECURE) {
+        if flags.contains(GetRandomFlags::NONBLOCK) {
+            return Err(RandomError::NotSeeded);
+        }
+
+        wait_until_seeded();
+    }
+
+    generate(buffer);
+
+    Ok(buffer.len())
+}
+
+// there's nothing to block on, so this goes looking for entropy instead.
+// the lock is dropped between attempts so that interrupts can arrive
+fn wait_until_seeded() {
+    while !is_seeded() {
+        let mut pool = POOL.lock();
+
+        pool.add_hardware();
+        pool.absorb_interrupts();
+        pool.add_jitter(JITTER_BATCH);
+
+        drop(pool);
+
+        hint::spin_loop();
+    }
+}
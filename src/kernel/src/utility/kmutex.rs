@@ -8,25 +8,36 @@
 //                                                                           //
 //======---------------------------------------------------------------======//
 
+#[cfg(feature = "lockdep")]
+use super::lockdep::{self, LockClass};
+use crate::percpu::this_cpu;
 use core::mem;
+#[cfg(feature = "lockdep")]
+use core::panic::Location;
+#[cfg(feature = "lockdep")]
+use core::ptr;
 use ksupport::sync::{BasicMutex, MutexGuard, SpinFairMutex, SpinMutex};
 
 /// Wraps a [`SpinMutex<T>`] and adds interrupt
 /// handling to make sure that interrupts don't screw up locking/unlocking.
 ///
 /// Other than that, everything true about `SpinMutex<T>` is true here.
-#[repr(transparent)]
+#[cfg_attr(not(feature = "lockdep"), repr(transparent))]
 pub struct KSpinMutex<T> {
     inner: SpinMutex<T>,
+    #[cfg(feature = "lockdep")]
+    class: LockClass,
 }
 
 /// Wraps a [`SpinFairMutex<T>`] and adds interrupt
 /// handling to make sure that interrupts don't screw up locking/unlocking.
 ///
 /// Other than that, everything true about `SpinFairMutex<T>` is true here.
-#[repr(transparent)]
+#[cfg_attr(not(feature = "lockdep"), repr(transparent))]
 pub struct KSpinFairMutex<T> {
     inner: SpinFairMutex<T>,
+    #[cfg(feature = "lockdep")]
+    class: LockClass,
 }
 
 macro_rules! kmutex_wrapper {
@@ -35,20 +46,35 @@ macro_rules! kmutex_wrapper {
             #[doc = concat!("Wraps [`", stringify!($name), "::new`]. Nothing changes here, the lock")]
             #[doc = "is created in an unlocked state, and the value given is the initial"]
             #[doc = "value for the held object."]
+            #[cfg_attr(feature = "lockdep", track_caller)]
             pub const fn new(value: T) -> Self {
                 Self {
                     inner: $inner::new(value),
+                    #[cfg(feature = "lockdep")]
+                    class: LockClass::new(),
                 }
             }
         }
 
         impl<T> BasicMutex<T> for $name<T> {
             #[inline(always)]
+            #[cfg_attr(feature = "lockdep", track_caller)]
             fn lock(&self) -> MutexGuard<'_, Self, T> {
-                // TODO: disable interrupts, disable preemption, etc
+                let cpu = this_cpu();
+
+                // an interrupt handler on this CPU taking the lock while
+                // it's held would spin forever
+                cpu.push_interrupts_off();
+
+                // this has to happen before waiting, or a deadlock
+                // would never get reported
+                #[cfg(feature = "lockdep")]
+                lockdep::acquire(&self.class, ptr::from_ref(self).cast(), Location::caller());
 
                 let inner = self.inner.lock();
 
+                cpu.lock_acquired();
+
                 // once we've locked the inner lock, drop the guard
                 // so that we don't unlock `inner` when we make a new guard
                 mem::forget(inner);
@@ -57,29 +83,39 @@ macro_rules! kmutex_wrapper {
             }
 
             #[inline(always)]
+            #[cfg_attr(feature = "lockdep", track_caller)]
             fn try_lock(&self) -> Option<MutexGuard<'_, Self, T>> {
-                self.inner.try_lock().map(|guard| {
-                    // TODO: disable interrupts, disable preemption, etc
+                let cpu = this_cpu();
 
-                    mem::forget(guard);
+                cpu.push_interrupts_off();
 
-                    unsafe { MutexGuard::new_from_unlocked(self) }
-                })
-            }
+                let Some(guard) = self.inner.try_lock() else {
+                    cpu.pop_interrupts_off();
+
+                    return None;
+                };
 
-            #[inline(always)]
-            fn unlock(&self, guard: MutexGuard<'_, Self, T>) {
                 mem::forget(guard);
 
-                self.inner
-                    .unlock(unsafe { MutexGuard::new_from_unlocked(&self.inner) })
+                #[cfg(feature = "lockdep")]
+                lockdep::acquired_try(&self.class, ptr::from_ref(self).cast(), Location::caller());
+
+                cpu.lock_acquired();
+
+                Some(unsafe { MutexGuard::new_from_unlocked(self) })
             }
 
             #[inline(always)]
             unsafe fn unlock_unchecked(&self) {
+                let cpu = this_cpu();
+
                 self.inner.unlock_unchecked();
 
-                // TODO: re-enable interrupts, re-enable preemption, etc
+                #[cfg(feature = "lockdep")]
+                lockdep::release(ptr::from_ref(self).cast());
+
+                cpu.lock_released();
+                cpu.pop_interrupts_off();
             }
 
             #[inline(always)]
@@ -94,9 +130,12 @@ macro_rules! kmutex_wrapper {
         }
 
         impl<T: Default> Default for $name<T> {
+            #[cfg_attr(feature = "lockdep", track_caller)]
             fn default() -> Self {
                 Self {
                     inner: $inner::default(),
+                    #[cfg(feature = "lockdep")]
+                    class: LockClass::new(),
                 }
             }
         }
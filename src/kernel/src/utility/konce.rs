@@ -8,8 +8,10 @@
 //                                                                           //
 //======---------------------------------------------------------------======//
 
+use crate::percpu::this_cpu;
 use core::mem;
-use ksupport::SpinOnceCell;
+use core::ops::Deref;
+use ksupport::{SpinLazy, SpinOnceCell};
 
 /// Wraps a [`SpinOnceCell`] with correct interrupt/preemption handling.
 ///
@@ -35,19 +37,33 @@ impl<T> KSpinOnceCell<T> {
     /// to the value.
     #[inline(always)]
     pub fn get(&self) -> &T {
-        // TODO: interrupts
-
+        // nothing on this CPU can be halfway through initializing the value,
+        // since interrupts are off while that happens. any wait here is on
+        // another CPU, so interrupts can stay on
         self.inner.get()
     }
 
+    /// If the value has been initialized, returns a reference to the value.
+    /// Otherwise, returns `None`.
+    ///
+    /// Unlike [`get`](Self::get), this never waits.
+    #[inline]
+    pub fn try_get(&self) -> Option<&T> {
+        self.inner.try_get()
+    }
+
+    /// Checks whether an initializer panicked while initializing the cell.
+    #[inline]
+    pub fn is_poisoned(&self) -> bool {
+        self.inner.is_poisoned()
+    }
+
     /// If the value has been initialized, returns a mutable reference to the value.
     ///
     /// Otherwise, spins until it is initialized, then returns a mutable reference
     /// to the value.
     #[inline(always)]
     pub fn get_mut(&mut self) -> &mut T {
-        // TODO: interrupts
-
         self.inner.get_mut()
     }
 
@@ -57,9 +73,17 @@ impl<T> KSpinOnceCell<T> {
     /// to the value.
     #[inline(always)]
     pub fn get_or_init(&self, init: impl FnOnce() -> T) -> &T {
-        // TODO: interrupts
+        let cpu = this_cpu();
+
+        // an interrupt handler waiting for the value while it's being
+        // initialized on this CPU would spin forever
+        cpu.push_interrupts_off();
+
+        let value = self.inner.get_or_init(init);
 
-        self.inner.get_or_init(init)
+        cpu.pop_interrupts_off();
+
+        value
     }
 
     /// If the value isn't initialized, initializes it and returns `Ok(())`.
@@ -67,17 +91,21 @@ impl<T> KSpinOnceCell<T> {
     /// Otherwise, returns `Err(value)`.
     #[inline(always)]
     pub fn set(&self, value: T) -> Result<(), T> {
-        // TODO: interrupts
+        let cpu = this_cpu();
+
+        cpu.push_interrupts_off();
+
+        let result = self.inner.set(value);
 
-        self.inner.set(value)
+        cpu.pop_interrupts_off();
+
+        result
     }
 
     /// If the value is initialized, takes it out and sets `self`
     /// back to the uninitialized state.
     #[inline(always)]
     pub fn take(&mut self) -> Option<T> {
-        // TODO: interrupts
-
         let old = mem::take(&mut self.inner);
 
         old.into_inner()
@@ -91,3 +119,57 @@ impl<T> KSpinOnceCell<T> {
         self.inner.into_inner()
     }
 }
+
+/// Wraps a [`SpinLazy`] with correct interrupt/preemption handling.
+///
+/// Other than that, it works exactly the same.
+#[repr(transparent)]
+pub struct KSpinLazy<T, F = fn() -> T> {
+    inner: SpinLazy<T, F>,
+}
+
+impl<T, F: FnOnce() -> T> KSpinLazy<T, F> {
+    /// Creates a new lazy value that will be initialized with `init`.
+    #[inline]
+    pub const fn new(init: F) -> Self {
+        Self {
+            inner: SpinLazy::new(init),
+        }
+    }
+
+    /// Forces the value to be initialized if it hasn't been yet, and
+    /// returns a reference to it.
+    #[inline]
+    pub fn force(this: &Self) -> &T {
+        if let Some(value) = Self::get(this) {
+            return value;
+        }
+
+        let cpu = this_cpu();
+
+        // same as `KSpinOnceCell::get_or_init`
+        cpu.push_interrupts_off();
+
+        let value = SpinLazy::force(&this.inner);
+
+        cpu.pop_interrupts_off();
+
+        value
+    }
+
+    /// Returns a reference to the value if it's been initialized, without
+    /// initializing it or waiting for it.
+    #[inline]
+    pub fn get(this: &Self) -> Option<&T> {
+        SpinLazy::get(&this.inner)
+    }
+}
+
+impl<T, F: FnOnce() -> T> Deref for KSpinLazy<T, F> {
+    type Target = T;
+
+    #[inline]
+    fn deref(&self) -> &Self::Target {
+        Self::force(self)
+    }
+}
@@ -0,0 +1,128 @@
+//======---------------------------------------------------------------======//
+//                                                                           //
+// Copyright 2022-2023 Evan Cox <evanacox00@gmail.com>. All rights reserved. //
+//                                                                           //
+// Use of this source code is governed by a BSD-style license that can be    //
+// found in the LICENSE.txt file at the root of this project, or at the      //
+// following link: https://opensource.org/licenses/BSD-3-Clause              //
+//                                                                           //
+//======---------------------------------------------------------------======//
+
+//! The kernel's RCU domain, where every CPU is a participant.
+//!
+//! CPUs start out offline. Once a CPU is brought online with [`online`],
+//! it has to report a quiescent state with [`quiescent`] every so often
+//! (i.e. from the idle loop, and eventually on every context switch), or
+//! go offline. A CPU sleeping in [`KPark`](super::kwait::KPark) is offline
+//! while it sleeps.
+
+// nothing is read under RCU yet, and CPUs only go offline while they sleep
+// through `while_idle`
+#![allow(dead_code)]
+
+use crate::arch::MAX_CPUS;
+use crate::percpu::this_cpu;
+use alloc::boxed::Box;
+use ksupport::sync::{RcuDomain, RcuPtr, RcuReadGuard};
+
+static DOMAIN: RcuDomain<MAX_CPUS> = RcuDomain::new();
+
+/// Brings the current CPU online, after which it can read.
+pub fn online() {
+    DOMAIN.online(this_cpu().index());
+}
+
+/// Takes the current CPU offline, it stops holding up grace periods.
+pub fn offline() {
+    DOMAIN.offline(this_cpu().index());
+}
+
+/// Reports that the current CPU isn't holding on to anything that it
+/// read under RCU.
+#[inline]
+pub fn quiescent() {
+    DOMAIN.quiescent(this_cpu().index());
+}
+
+/// Runs `f` with the current CPU offline (if it was online), for things
+/// that might not come back for a while like sleeping.
+pub fn while_idle<R>(f: impl FnOnce() -> R) -> R {
+    let index = this_cpu().index();
+    let was_online = DOMAIN.is_online(index);
+
+    if was_online {
+        DOMAIN.offline(index);
+    }
+
+    let result = f();
+
+    if was_online {
+        DOMAIN.online(index);
+    }
+
+    result
+}
+
+/// Starts a read-side section on the current CPU.
+#[inline]
+pub fn read_lock() -> RcuReadGuard<'static, MAX_CPUS> {
+    DOMAIN.read_lock(this_cpu().index())
+}
+
+/// Waits until every online CPU has been quiescent at least once.
+pub fn synchronize() {
+    let cpu = this_cpu();
+
+    // anything that another CPU would need to get to its next
+    // quiescent state could be held up by us
+    debug_assert!(
+        cpu.lock_depth() == 0 && !cpu.in_interrupt(),
+        "waited for a grace period while holding a spinlock or handling an interrupt"
+    );
+
+    DOMAIN.synchronize(Some(cpu.index()));
+}
+
+/// A heap-allocated value that's read under RCU, and replaced by
+/// copying and updating it.
+pub struct KRcuBox<T> {
+    ptr: RcuPtr<T>,
+}
+
+impl<T> KRcuBox<T> {
+    /// Allocates `value`.
+    pub fn new(value: T) -> Self {
+        Self {
+            ptr: unsafe { RcuPtr::new(Box::into_raw(Box::new(value))) },
+        }
+    }
+
+    /// Reads the value, the reference can't outlive `guard`.
+    #[inline]
+    pub fn read<'g>(&self, guard: &'g RcuReadGuard<'_, MAX_CPUS>) -> &'g T {
+        self.ptr
+            .read(guard)
+            .expect("`KRcuBox` should never be null")
+    }
+
+    /// Publishes `value`, waits for a grace period and frees the old
+    /// value. See [`synchronize`].
+    pub fn replace(&self, value: T) {
+        let old = unsafe { self.ptr.replace(Box::into_raw(Box::new(value))) };
+
+        synchronize();
+
+        drop(unsafe { Box::from_raw(old) });
+    }
+}
+
+impl<T> Drop for KRcuBox<T> {
+    fn drop(&mut self) {
+        // nobody else can have a reference to us, so nobody can be reading
+        drop(unsafe { Box::from_raw(self.ptr.replace(core::ptr::null_mut())) });
+    }
+}
+
+unsafe impl<T: Send + Sync> Send for KRcuBox<T> {}
+
+unsafe impl<T: Send + Sync> Sync for KRcuBox<T> {}
@@ -0,0 +1,227 @@
+//======---------------------------------------------------------------======//
+//                                                                           //
+// Copyright 2022-2023 Evan Cox <evanacox00@gmail.com>. All rights reserved. //
+//                                                                           //
+// Use of this source code is governed by a BSD-style license that can be    //
+// found in the LICENSE.txt file at the root of this project, or at the      //
+// following link: https://opensource.org/licenses/BSD-3-Clause              //
+//                                                                           //
+//======---------------------------------------------------------------======//
+
+// `KSpinRwLock` doesn't have any users yet
+#![allow(dead_code)]
+
+use crate::percpu::this_cpu;
+use core::mem;
+use ksupport::sync::{
+    BasicRwLock, RwLockReadGuard, RwLockUpgradeableGuard, RwLockWriteGuard, SpinFairRwLock,
+    SpinRwLock,
+};
+
+/// Wraps a [`SpinRwLock<T>`] and adds interrupt
+/// handling to make sure that interrupts don't screw up locking/unlocking.
+///
+/// Other than that, everything true about `SpinRwLock<T>` is true here.
+#[repr(transparent)]
+pub struct KSpinRwLock<T> {
+    inner: SpinRwLock<T>,
+}
+
+/// Wraps a [`SpinFairRwLock<T>`] and adds interrupt
+/// handling to make sure that interrupts don't screw up locking/unlocking.
+///
+/// Other than that, everything true about `SpinFairRwLock<T>` is true here.
+#[repr(transparent)]
+pub struct KSpinFairRwLock<T> {
+    inner: SpinFairRwLock<T>,
+}
+
+macro_rules! krwlock_wrapper {
+    ($name:ident, $inner:ident) => {
+        impl<T> $name<T> {
+            #[doc = concat!("Wraps [`", stringify!($inner), "::new`]. Nothing changes here, the lock")]
+            #[doc = "is created in an unlocked state, and the value given is the initial"]
+            #[doc = "value for the held object."]
+            pub const fn new(value: T) -> Self {
+                Self {
+                    inner: $inner::new(value),
+                }
+            }
+        }
+
+        impl<T: Send + Sync> BasicRwLock<T> for $name<T> {
+            #[inline(always)]
+            fn read(&self) -> RwLockReadGuard<'_, Self, T> {
+                let cpu = this_cpu();
+
+                // same reasoning as the mutexes, an interrupt handler on this
+                // CPU waiting for a lock that this CPU holds would spin forever
+                cpu.push_interrupts_off();
+
+                mem::forget(self.inner.read());
+
+                cpu.lock_acquired();
+
+                unsafe { RwLockReadGuard::new_from_unlocked(self) }
+            }
+
+            #[inline(always)]
+            fn try_read(&self) -> Option<RwLockReadGuard<'_, Self, T>> {
+                let cpu = this_cpu();
+
+                cpu.push_interrupts_off();
+
+                let Some(guard) = self.inner.try_read() else {
+                    cpu.pop_interrupts_off();
+
+                    return None;
+                };
+
+                mem::forget(guard);
+
+                cpu.lock_acquired();
+
+                Some(unsafe { RwLockReadGuard::new_from_unlocked(self) })
+            }
+
+            #[inline(always)]
+            fn write(&self) -> RwLockWriteGuard<'_, Self, T> {
+                let cpu = this_cpu();
+
+                cpu.push_interrupts_off();
+
+                mem::forget(self.inner.write());
+
+                cpu.lock_acquired();
+
+                unsafe { RwLockWriteGuard::new_from_unlocked(self) }
+            }
+
+            #[inline(always)]
+            fn try_write(&self) -> Option<RwLockWriteGuard<'_, Self, T>> {
+                let cpu = this_cpu();
+
+                cpu.push_interrupts_off();
+
+                let Some(guard) = self.inner.try_write() else {
+                    cpu.pop_interrupts_off();
+
+                    return None;
+                };
+
+                mem::forget(guard);
+
+                cpu.lock_acquired();
+
+                Some(unsafe { RwLockWriteGuard::new_from_unlocked(self) })
+            }
+
+            #[inline(always)]
+            fn upgradeable_read(&self) -> RwLockUpgradeableGuard<'_, Self, T> {
+                let cpu = this_cpu();
+
+                cpu.push_interrupts_off();
+
+                mem::forget(self.inner.upgradeable_read());
+
+                cpu.lock_acquired();
+
+                unsafe { RwLockUpgradeableGuard::new_from_unlocked(self) }
+            }
+
+            #[inline(always)]
+            fn try_upgradeable_read(&self) -> Option<RwLockUpgradeableGuard<'_, Self, T>> {
+                let cpu = this_cpu();
+
+                cpu.push_interrupts_off();
+
+                let Some(guard) = self.inner.try_upgradeable_read() else {
+                    cpu.pop_interrupts_off();
+
+                    return None;
+                };
+
+                mem::forget(guard);
+
+                cpu.lock_acquired();
+
+                Some(unsafe { RwLockUpgradeableGuard::new_from_unlocked(self) })
+            }
+
+            #[inline(always)]
+            unsafe fn unlock_read_unchecked(&self) {
+                let cpu = this_cpu();
+
+                self.inner.unlock_read_unchecked();
+
+                cpu.lock_released();
+                cpu.pop_interrupts_off();
+            }
+
+            #[inline(always)]
+            unsafe fn unlock_write_unchecked(&self) {
+                let cpu = this_cpu();
+
+                self.inner.unlock_write_unchecked();
+
+                cpu.lock_released();
+                cpu.pop_interrupts_off();
+            }
+
+            #[inline(always)]
+            unsafe fn unlock_upgradeable_unchecked(&self) {
+                let cpu = this_cpu();
+
+                self.inner.unlock_upgradeable_unchecked();
+
+                cpu.lock_released();
+                cpu.pop_interrupts_off();
+            }
+
+            // the lock is held the whole time for these, so interrupts
+            // are already off and nothing needs to be tracked
+
+            #[inline(always)]
+            unsafe fn upgrade_unchecked(&self) {
+                self.inner.upgrade_unchecked();
+            }
+
+            #[inline(always)]
+            unsafe fn try_upgrade_unchecked(&self) -> bool {
+                self.inner.try_upgrade_unchecked()
+            }
+
+            #[inline(always)]
+            unsafe fn downgrade_write_unchecked(&self) {
+                self.inner.downgrade_write_unchecked();
+            }
+
+            #[inline(always)]
+            unsafe fn downgrade_upgradeable_unchecked(&self) {
+                self.inner.downgrade_upgradeable_unchecked();
+            }
+
+            #[inline(always)]
+            unsafe fn data_unguarded(&self) -> &T {
+                self.inner.data_unguarded()
+            }
+
+            #[inline(always)]
+            unsafe fn data_mut_unguarded(&self) -> &mut T {
+                self.inner.data_mut_unguarded()
+            }
+        }
+
+        impl<T: Default> Default for $name<T> {
+            fn default() -> Self {
+                Self {
+                    inner: $inner::default(),
+                }
+            }
+        }
+    };
+}
+
+krwlock_wrapper!(KSpinRwLock, SpinRwLock);
+
+krwlock_wrapper!(KSpinFairRwLock, SpinFairRwLock);
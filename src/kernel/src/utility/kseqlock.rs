@@ -0,0 +1,63 @@
+//======---------------------------------------------------------------======//
+//                                                                           //
+// Copyright 2022-2023 Evan Cox <evanacox00@gmail.com>. All rights reserved. //
+//                                                                           //
+// Use of this source code is governed by a BSD-style license that can be    //
+// found in the LICENSE.txt file at the root of this project, or at the      //
+// following link: https://opensource.org/licenses/BSD-3-Clause              //
+//                                                                           //
+//======---------------------------------------------------------------======//
+
+// nothing needs a sequence lock yet
+#![allow(dead_code)]
+
+use crate::percpu::this_cpu;
+use ksupport::sync::SeqLock;
+
+/// Wraps a [`SeqLock`] so that writers can't be interrupted.
+///
+/// Readers spin while a write is in progress, so an interrupt handler
+/// that reads on the same CPU as an in-progress write would spin forever.
+/// Reads are safe from anywhere, including interrupt handlers.
+#[repr(transparent)]
+pub struct KSeqLock<T: Copy> {
+    inner: SeqLock<T>,
+}
+
+impl<T: Copy> KSeqLock<T> {
+    /// Creates a new sequence lock holding `value`.
+    pub const fn new(value: T) -> Self {
+        Self {
+            inner: SeqLock::new(value),
+        }
+    }
+
+    /// Reads a consistent copy of the data, see [`SeqLock::read`].
+    #[inline]
+    pub fn read(&self) -> T {
+        self.inner.read()
+    }
+
+    /// Makes one attempt to read the data, see [`SeqLock::try_read`].
+    #[inline]
+    pub fn try_read(&self) -> Option<T> {
+        self.inner.try_read()
+    }
+
+    /// Replaces the data with `value`.
+    #[inline]
+    pub fn write(&self, value: T) {
+        self.update(|data| *data = value);
+    }
+
+    /// Modifies the data in place, with interrupts disabled.
+    pub fn update(&self, f: impl FnOnce(&mut T)) {
+        let cpu = this_cpu();
+
+        cpu.push_interrupts_off();
+
+        self.inner.update(f);
+
+        cpu.pop_interrupts_off();
+    }
+}
@@ -0,0 +1,112 @@
+//======---------------------------------------------------------------======//
+//                                                                           //
+// Copyright 2022-2023 Evan Cox <evanacox00@gmail.com>. All rights reserved. //
+//                                                                           //
+// Use of this source code is governed by a BSD-style license that can be    //
+// found in the LICENSE.txt file at the root of this project, or at the      //
+// following link: https://opensource.org/licenses/BSD-3-Clause              //
+//                                                                           //
+//======---------------------------------------------------------------======//
+
+//! The kernel's [`Park`] implementation, and the sleeping primitives
+//! from [`ksupport::sync`] that use it.
+//!
+//! There's no scheduler yet, so the thing that sleeps is the CPU itself:
+//! a parked CPU halts until another CPU wakes it with an IPI, or until
+//! its deadline (in nanoseconds since boot) passes.
+
+// nothing sleeps yet, there's no scheduler to hand the CPU to
+#![allow(dead_code)]
+
+use crate::arch::hal;
+use crate::percpu;
+use crate::percpu::this_cpu;
+use crate::utility::krcu;
+use core::hint;
+use core::sync::atomic::{AtomicBool, Ordering};
+use ksupport::sync::{Condvar, Mutex, Park, Semaphore, WaitQueue};
+
+percpu! {
+    // set when a CPU is unparked, so that an unpark can't be lost if it
+    // happens before the CPU actually goes to sleep
+    static WAKE_PENDING: AtomicBool = AtomicBool::new(false);
+}
+
+/// Parks the current CPU, see the [module docs](self).
+pub struct KPark;
+
+impl Park for KPark {
+    type Token = usize;
+
+    #[inline]
+    fn current() -> usize {
+        this_cpu().index()
+    }
+
+    fn park(deadline: Option<u64>) {
+        let cpu = this_cpu();
+
+        debug_assert!(
+            cpu.lock_depth() == 0 && !cpu.in_interrupt(),
+            "tried to sleep while holding a spinlock or handling an interrupt"
+        );
+
+        // sleeping needs interrupts, if they're off (e.g. during early boot)
+        // the best we can do is let the caller poll
+        if !hal::interrupts_enabled() {
+            hint::spin_loop();
+
+            return;
+        }
+
+        cpu.push_interrupts_off();
+
+        // with interrupts off, a wake IPI that arrives after this check
+        // stays pending until the CPU halts and then wakes it right up
+        if !WAKE_PENDING.get().swap(false, Ordering::Acquire) {
+            // a sleeping CPU isn't reading anything, it shouldn't hold
+            // up grace periods until it wakes up
+            krcu::while_idle(|| unsafe { hal::wait_for_wake(deadline) });
+
+            WAKE_PENDING.get().store(false, Ordering::Relaxed);
+        }
+
+        cpu.pop_interrupts_off();
+    }
+
+    fn unpark(token: usize) {
+        WAKE_PENDING.get_for(token).store(true, Ordering::Release);
+
+        if token != this_cpu().index() {
+            hal::wake_cpu(token);
+        }
+    }
+
+    #[inline]
+    fn now() -> u64 {
+        hal::nanos_since_boot()
+    }
+
+    // waiters can be notified from interrupt handlers
+    #[inline]
+    fn critical_enter() {
+        this_cpu().push_interrupts_off();
+    }
+
+    #[inline]
+    fn critical_exit() {
+        this_cpu().pop_interrupts_off();
+    }
+}
+
+/// A [`WaitQueue`] for the kernel.
+pub type KWaitQueue = WaitQueue<KPark>;
+
+/// A sleeping [`Mutex`] for the kernel.
+pub type KMutex<T> = Mutex<T, KPark>;
+
+/// A [`Semaphore`] for the kernel.
+pub type KSemaphore = Semaphore<KPark>;
+
+/// A [`Condvar`] for the kernel.
+pub type KCondvar = Condvar<KPark>;
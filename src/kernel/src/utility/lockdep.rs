@@ -0,0 +1,499 @@
+//======---------------------------------------------------------------======//
+//                                                                           //
+// Copyright 2022-2023 Evan Cox <evanacox00@gmail.com>. All rights reserved. //
+//                                                                           //
+// Use of this source code is governed by a BSD-style license that can be    //
+// found in the LICENSE.txt file at the root of this project, or at the      //
+// following link: https://opensource.org/licenses/BSD-3-Clause              //
+//                                                                           //
+//======---------------------------------------------------------------======//
+
+//! A runtime lock dependency validator for the kernel's spinlocks.
+//!
+//! Every lock belongs to a *class*, which is the place in the source where
+//! the lock was created. Whenever a lock is acquired while another one is
+//! held, the order between their classes is recorded in a graph. Problems
+//! are reported as soon as they're *possible*, not when they actually
+//! deadlock:
+//!
+//! - acquiring a lock that would close a cycle in the graph (i.e. some
+//!   other path takes the same locks in the opposite order)
+//! - acquiring a lock that the current CPU is already holding
+//! - taking a lock class in an interrupt handler when it's also acquired
+//!   with interrupts enabled somewhere, since the handler could interrupt
+//!   the holder and wait for it forever
+//!
+//! Every report logs the acquisition sites involved. After the first one,
+//! the validator turns itself off, since whatever it reported may well
+//! have left the graph in an odd state.
+//!
+//! This is only compiled with the `lockdep` feature, it's slow and uses
+//! quite a bit of memory.
+
+// reports carry the dependency chain they found, they're rare enough
+// that the size doesn't matter
+#![allow(clippy::large_enum_variant, clippy::result_large_err)]
+
+use crate::arch::hal;
+use crate::percpu::this_cpu;
+use core::panic::Location;
+use core::sync::atomic::{AtomicBool, AtomicU16, Ordering};
+use ksupport::sync::{BasicMutex, SpinMutex};
+use log::{error, warn};
+
+const MAX_CLASSES: usize = 512;
+const MAX_EDGES: usize = 4096;
+const MAX_HELD: usize = 32;
+const MAX_CHAIN: usize = 16;
+const WORDS: usize = MAX_CLASSES / 64;
+
+// class IDs are stored off by one in `LockClass`, so that `0` can mean
+// "not registered yet"
+const UNREGISTERED: u16 = 0;
+
+type Site = &'static Location<'static>;
+
+/// The class that a lock belongs to, every lock created at the same place
+/// in the source code has the same class.
+pub struct LockClass {
+    key: Site,
+    id: AtomicU16,
+}
+
+impl LockClass {
+    /// Creates a class keyed by wherever the caller was called from.
+    #[track_caller]
+    pub const fn new() -> Self {
+        Self {
+            key: Location::caller(),
+            id: AtomicU16::new(UNREGISTERED),
+        }
+    }
+}
+
+#[derive(Copy, Clone)]
+struct Class {
+    key: Site,
+    // the first place this class was used in an interrupt handler
+    irq_site: Option<Site>,
+    // the first place this class was acquired with interrupts enabled
+    irqs_enabled_site: Option<Site>,
+}
+
+// records that `to` was acquired while `from` was held
+#[derive(Copy, Clone)]
+struct Edge {
+    from: u16,
+    to: u16,
+    from_site: Site,
+    to_site: Site,
+}
+
+struct Graph {
+    classes: [Option<Class>; MAX_CLASSES],
+    class_count: usize,
+    // adjacency matrix, bit `to` of `after[from]` is set if there's an edge
+    after: [[u64; WORDS]; MAX_CLASSES],
+    edges: [Option<Edge>; MAX_EDGES],
+    edge_count: usize,
+}
+
+#[derive(Copy, Clone)]
+struct HeldLock {
+    lock: *const (),
+    class: u16,
+    site: Site,
+}
+
+struct HeldLocks {
+    locks: [Option<HeldLock>; MAX_HELD],
+    len: usize,
+}
+
+enum Report {
+    Recursive {
+        held: HeldLock,
+        site: Site,
+    },
+    Cycle {
+        held: HeldLock,
+        class: Site,
+        site: Site,
+        chain: [Option<Edge>; MAX_CHAIN],
+    },
+    IrqUnsafe {
+        class: Site,
+        irq_site: Site,
+        irqs_enabled_site: Site,
+    },
+    Exhausted(&'static str),
+}
+
+static ENABLED: AtomicBool = AtomicBool::new(true);
+
+// only ever locked with interrupts off, since it's only used from inside
+// the kernel lock wrappers. these are raw locks so that they aren't
+// validated themselves
+static GRAPH: SpinMutex<Graph> = SpinMutex::new(Graph {
+    classes: [None; MAX_CLASSES],
+    class_count: 0,
+    after: [[0; WORDS]; MAX_CLASSES],
+    edges: [None; MAX_EDGES],
+    edge_count: 0,
+});
+
+crate::percpu! {
+    static HELD: SpinMutex<HeldLocks> = SpinMutex::new(HeldLocks {
+        locks: [None; MAX_HELD],
+        len: 0,
+    });
+}
+
+impl Graph {
+    fn class_id(&mut self, class: &LockClass) -> Result<u16, Report> {
+        match class.id.load(Ordering::Relaxed) {
+            UNREGISTERED => {}
+            id => return Ok(id - 1),
+        }
+
+        let existing = self.classes[..self.class_count]
+            .iter()
+            .flatten()
+            .position(|c| core::ptr::eq(c.key, class.key));
+
+        let index = match existing {
+            Some(index) => index,
+            None if self.class_count == MAX_CLASSES => {
+                return Err(Report::Exhausted("lock classes"));
+            }
+            None => {
+                self.classes[self.class_count] = Some(Class {
+                    key: class.key,
+                    irq_site: None,
+                    irqs_enabled_site: None,
+                });
+                self.class_count += 1;
+
+                self.class_count - 1
+            }
+        };
+
+        // MAX_CLASSES is well under u16::MAX
+        #[allow(clippy::cast_possible_truncation)]
+        let id = index as u16;
+
+        class.id.store(id + 1, Ordering::Relaxed);
+
+        Ok(id)
+    }
+
+    fn class(&self, id: u16) -> Class {
+        self.classes[usize::from(id)].expect("class ids are only handed out once registered")
+    }
+
+    fn has_edge(&self, from: u16, to: u16) -> bool {
+        self.after[usize::from(from)][usize::from(to) / 64] & (1 << (to % 64)) != 0
+    }
+
+    fn find_edge(&self, from: u16, to: u16) -> Option<Edge> {
+        self.edges[..self.edge_count]
+            .iter()
+            .flatten()
+            .find(|e| e.from == from && e.to == to)
+            .copied()
+    }
+
+    // walks a path found by `path` back from `to`, keeping the first few hops
+    fn chain(&self, parent: &[u16; MAX_CLASSES], from: u16, to: u16) -> [Option<Edge>; MAX_CHAIN] {
+        let mut length = 0;
+        let mut at = to;
+
+        while at != from {
+            at = parent[usize::from(at)];
+            length += 1;
+        }
+
+        let mut chain = [None; MAX_CHAIN];
+        let mut at = to;
+
+        for index in (0..length).rev() {
+            let prev = parent[usize::from(at)];
+
+            if index < MAX_CHAIN {
+                chain[index] = self.find_edge(prev, at);
+            }
+
+            at = prev;
+        }
+
+        chain
+    }
+
+    // breadth-first search for a path `from -> ... -> to`, returning the
+    // edges along it
+    #[allow(clippy::cast_possible_truncation)]
+    fn path(&self, from: u16, to: u16) -> Option<[Option<Edge>; MAX_CHAIN]> {
+        let mut parent = [u16::MAX; MAX_CLASSES];
+        let mut queue = [0u16; MAX_CLASSES];
+        let (mut head, mut tail) = (0, 1);
+
+        queue[0] = from;
+        parent[usize::from(from)] = from;
+
+        while head < tail {
+            let current = queue[head];
+
+            head += 1;
+
+            if current == to {
+                return Some(self.chain(&parent, from, to));
+            }
+
+            for next in 0..self.class_count as u16 {
+                if parent[usize::from(next)] == u16::MAX && self.has_edge(current, next) {
+                    parent[usize::from(next)] = current;
+                    queue[tail] = next;
+                    tail += 1;
+                }
+            }
+        }
+
+        None
+    }
+
+    fn add_edge(&mut self, held: HeldLock, to: u16, site: Site) -> Result<(), Report> {
+        let from = held.class;
+
+        if from == to || self.has_edge(from, to) {
+            return Ok(());
+        }
+
+        // if `to` can already reach `from`, adding `from -> to` closes a cycle
+        if let Some(chain) = self.path(to, from) {
+            return Err(Report::Cycle {
+                held,
+                class: self.class(to).key,
+                site,
+                chain,
+            });
+        }
+
+        if self.edge_count == MAX_EDGES {
+            return Err(Report::Exhausted("lock dependencies"));
+        }
+
+        self.edges[self.edge_count] = Some(Edge {
+            from,
+            to,
+            from_site: held.site,
+            to_site: site,
+        });
+        self.edge_count += 1;
+        self.after[usize::from(from)][usize::from(to) / 64] |= 1 << (to % 64);
+
+        Ok(())
+    }
+
+    // a lock that's only ever held with interrupts off can be shared with
+    // interrupt handlers just fine, a handler can't interrupt its holder
+    fn check_context(
+        &mut self,
+        id: u16,
+        site: Site,
+        interrupts_enabled: bool,
+    ) -> Result<(), Report> {
+        let class = self.classes[usize::from(id)]
+            .as_mut()
+            .expect("class ids are only handed out once registered");
+
+        let first_use = class.irq_site.is_none() || class.irqs_enabled_site.is_none();
+
+        if this_cpu().in_interrupt() {
+            class.irq_site.get_or_insert(site);
+        }
+
+        if interrupts_enabled {
+            class.irqs_enabled_site.get_or_insert(site);
+        }
+
+        match (class.irq_site, class.irqs_enabled_site) {
+            (Some(irq_site), Some(irqs_enabled_site)) if first_use => Err(Report::IrqUnsafe {
+                class: class.key,
+                irq_site,
+                irqs_enabled_site,
+            }),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl HeldLocks {
+    const fn push(&mut self, lock: HeldLock) -> Result<(), Report> {
+        if self.len == MAX_HELD {
+            return Err(Report::Exhausted("held lock slots"));
+        }
+
+        self.locks[self.len] = Some(lock);
+        self.len += 1;
+
+        Ok(())
+    }
+
+    fn remove(&mut self, lock: *const ()) {
+        // locks are usually released in reverse order, but they don't have to be
+        let found = self.locks[..self.len]
+            .iter()
+            .rposition(|held| held.is_some_and(|held| held.lock == lock));
+
+        if let Some(index) = found {
+            self.locks.copy_within(index + 1..self.len, index);
+            self.len -= 1;
+            self.locks[self.len] = None;
+        }
+    }
+
+    fn top(&self) -> Option<HeldLock> {
+        self.len.checked_sub(1).and_then(|top| self.locks[top])
+    }
+
+    fn find(&self, lock: *const ()) -> Option<HeldLock> {
+        self.locks[..self.len]
+            .iter()
+            .flatten()
+            .find(|held| held.lock == lock)
+            .copied()
+    }
+}
+
+fn validate(
+    class: &LockClass,
+    lock: *const (),
+    site: Site,
+    trying: bool,
+    interrupts_enabled: bool,
+) -> Result<(), Report> {
+    let mut held = HELD.get().lock();
+    let mut graph = GRAPH.lock();
+    let id = graph.class_id(class)?;
+
+    graph.check_context(id, site, interrupts_enabled)?;
+
+    // a try doesn't wait, so it can't deadlock by itself
+    if !trying {
+        if let Some(already) = held.find(lock) {
+            return Err(Report::Recursive {
+                held: already,
+                site,
+            });
+        }
+
+        if let Some(top) = held.top() {
+            graph.add_edge(top, id, site)?;
+        }
+    }
+
+    drop(graph);
+
+    held.push(HeldLock {
+        lock,
+        class: id,
+        site,
+    })
+}
+
+fn report(report: &Report) {
+    let cpu = this_cpu().index();
+
+    match report {
+        Report::Recursive { held, site } => {
+            error!("lockdep: recursive locking detected on cpu {cpu}");
+            error!("lockdep:   trying to acquire lock at {site}");
+            error!(
+                "lockdep:   but it's already held, acquired at {}",
+                held.site
+            );
+        }
+        Report::Cycle {
+            held,
+            class,
+            site,
+            chain,
+        } => {
+            error!("lockdep: possible circular locking dependency detected on cpu {cpu}");
+            error!("lockdep:   trying to acquire lock of class {class} at {site}");
+            error!("lockdep:   while holding a lock acquired at {}", held.site);
+            error!("lockdep:   but these locks were previously taken in the other order:");
+
+            for edge in chain.iter().flatten() {
+                error!(
+                    "lockdep:     acquired at {}, then acquired at {}",
+                    edge.from_site, edge.to_site
+                );
+            }
+        }
+        Report::IrqUnsafe {
+            class,
+            irq_site,
+            irqs_enabled_site,
+        } => {
+            warn!("lockdep: lock class {class} is used in interrupt handlers, but isn't irq-safe");
+            warn!("lockdep:   acquired in an interrupt handler at {irq_site}");
+            warn!("lockdep:   acquired with interrupts enabled at {irqs_enabled_site}");
+        }
+        Report::Exhausted(what) => {
+            warn!("lockdep: ran out of {what}");
+        }
+    }
+
+    warn!("lockdep: turning off lock validation");
+}
+
+fn check(class: &LockClass, lock: *const (), site: Site, trying: bool) {
+    if !ENABLED.load(Ordering::Relaxed) {
+        return;
+    }
+
+    // this is the state that the lock is held in, it has to be read before
+    // the validator's own locks turn interrupts off
+    let interrupts_enabled = hal::interrupts_enabled();
+    let cpu = this_cpu();
+
+    cpu.push_interrupts_off();
+
+    let result = validate(class, lock, site, trying, interrupts_enabled);
+
+    cpu.pop_interrupts_off();
+
+    if let Err(problem) = result {
+        // turn off first, logging is going to take locks of its own
+        if ENABLED.swap(false, Ordering::Relaxed) {
+            report(&problem);
+        }
+    }
+}
+
+/// Validates and records an acquisition of `lock` at `site`, before the
+/// lock is actually waited on.
+///
+/// Interrupts must be in the state that the lock is going to be held in,
+/// i.e. already pushed off for locks that turn them off.
+pub fn acquire(class: &LockClass, lock: *const (), site: Site) {
+    check(class, lock, site, false);
+}
+
+/// Records that `lock` was acquired at `site` without waiting for it.
+///
+/// Interrupts must be in the state that the lock is held in, like with
+/// [`acquire`].
+pub fn acquired_try(class: &LockClass, lock: *const (), site: Site) {
+    check(class, lock, site, true);
+}
+
+/// Records that `lock` was released.
+///
+/// This must be called with interrupts pushed off.
+pub fn release(lock: *const ()) {
+    if ENABLED.load(Ordering::Relaxed) {
+        HELD.get().lock().remove(lock);
+    }
+}
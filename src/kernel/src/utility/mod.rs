@@ -13,6 +13,13 @@
 
 mod kmutex;
 mod konce;
+pub mod krcu;
+pub mod krwlock;
+pub mod kseqlock;
+pub mod kwait;
+#[cfg(feature = "lockdep")]
+mod lockdep;
 
 pub use kmutex::{KSpinFairMutex, KSpinMutex};
-pub use konce::KSpinOnceCell;
+pub use konce::{KSpinLazy, KSpinOnceCell};
+pub use krwlock::KSpinFairRwLock;
@@ -0,0 +1,103 @@
+//======---------------------------------------------------------------======//
+//                                                                           //
+// Copyright 2022-2023 Evan Cox <evanacox00@gmail.com>. All rights reserved. //
+//                                                                           //
+// Use of this source code is governed by a BSD-style license that can be    //
+// found in the LICENSE.txt file at the root of this project, or at the      //
+// following link: https://opensource.org/licenses/BSD-3-Clause              //
+//                                                                           //
+//======---------------------------------------------------------------======//
+
+//! Compares the spinlock mutexes under contention from host threads.
+//!
+//! Every thread hammers one shared counter, holding the lock for a short
+//! critical section each time. Run with `cargo bench -p ksupport`, and
+//! pass a number of iterations per thread to change the default.
+
+use ksupport::sync::{BasicMutex, McsMutex, SpinFairMutex, SpinMutex};
+use std::hint;
+use std::sync::{Arc, Barrier};
+use std::thread;
+use std::time::{Duration, Instant};
+
+const DEFAULT_ITERATIONS: usize = 100_000;
+
+fn contend<L: BasicMutex<u64> + 'static>(lock: L, threads: usize, iterations: usize) -> Duration {
+    let lock = Arc::new(lock);
+    let barrier = Arc::new(Barrier::new(threads + 1));
+    let handles: Vec<_> = (0..threads)
+        .map(|_| {
+            let lock = Arc::clone(&lock);
+            let barrier = Arc::clone(&barrier);
+
+            thread::spawn(move || {
+                barrier.wait();
+
+                for _ in 0..iterations {
+                    let mut guard = lock.lock();
+
+                    *guard = hint::black_box(*guard + 1);
+                }
+            })
+        })
+        .collect();
+
+    barrier.wait();
+
+    let start = Instant::now();
+
+    for handle in handles {
+        handle.join().unwrap();
+    }
+
+    let elapsed = start.elapsed();
+
+    assert_eq!(*lock.lock(), (threads * iterations) as u64);
+
+    elapsed
+}
+
+fn report(name: &str, threads: usize, iterations: usize, elapsed: Duration) {
+    let total = (threads * iterations) as f64;
+
+    println!(
+        "{name:>14} {threads:>3} threads: {:>10.3} ms, {:>8.1} ns/op",
+        elapsed.as_secs_f64() * 1000.0,
+        elapsed.as_secs_f64() * 1e9 / total
+    );
+}
+
+fn main() {
+    // `cargo bench` passes `--bench`, skip over any flags
+    let iterations = std::env::args()
+        .skip(1)
+        .find_map(|arg| arg.parse().ok())
+        .unwrap_or(DEFAULT_ITERATIONS);
+    let cpus = thread::available_parallelism().map_or(4, |n| n.get());
+    let mut threads = 1;
+
+    while threads <= cpus {
+        report(
+            "SpinMutex",
+            threads,
+            iterations,
+            contend(SpinMutex::new(0), threads, iterations),
+        );
+        report(
+            "SpinFairMutex",
+            threads,
+            iterations,
+            contend(SpinFairMutex::new(0), threads, iterations),
+        );
+        report(
+            "McsMutex",
+            threads,
+            iterations,
+            contend(McsMutex::new(0), threads, iterations),
+        );
+
+        println!();
+
+        threads *= 2;
+    }
+}
@@ -0,0 +1,352 @@
+//======---------------------------------------------------------------======//
+//                                                                           //
+// Copyright 2022-2023 Evan Cox <evanacox00@gmail.com>. All rights reserved. //
+//                                                                           //
+// Use of this source code is governed by a BSD-style license that can be    //
+// found in the LICENSE.txt file at the root of this project, or at the      //
+// following link: https://opensource.org/licenses/BSD-3-Clause              //
+//                                                                           //
+//======---------------------------------------------------------------======//
+
+use core::ptr;
+
+const CONSTANTS: [u32; 4] = [0x6170_7865, 0x3320_646E, 0x7962_2D32, 0x6B20_6574];
+
+const BLOCK_BYTES: usize = 64;
+
+// how many blocks get generated at once, the first 32 bytes become the
+// next key and the rest are handed out
+const BUFFER_BLOCKS: usize = 4;
+
+const BUFFER_BYTES: usize = BUFFER_BLOCKS * BLOCK_BYTES - 32;
+
+// output and mixing use different nonces, so that nothing that was mixed
+// in can ever come back out as output
+const OUTPUT_NONCE: [u32; 3] = [0, 0, 0];
+const MIX_NONCE: [u32; 3] = [0, 0, 1];
+
+#[inline]
+const fn quarter_round(state: &mut [u32; 16], a: usize, b: usize, c: usize, d: usize) {
+    state[a] = state[a].wrapping_add(state[b]);
+    state[d] = (state[d] ^ state[a]).rotate_left(16);
+    state[c] = state[c].wrapping_add(state[d]);
+    state[b] = (state[b] ^ state[c]).rotate_left(12);
+    state[a] = state[a].wrapping_add(state[b]);
+    state[d] = (state[d] ^ state[a]).rotate_left(8);
+    state[c] = state[c].wrapping_add(state[d]);
+    state[b] = (state[b] ^ state[c]).rotate_left(7);
+}
+
+// the ChaCha20 block function, as laid out in RFC 8439
+fn block(key: &[u32; 8], counter: u32, nonce: &[u32; 3]) -> [u32; 16] {
+    let mut input = [0; 16];
+
+    input[..4].copy_from_slice(&CONSTANTS);
+    input[4..12].copy_from_slice(key);
+    input[12] = counter;
+    input[13..].copy_from_slice(nonce);
+
+    let mut state = input;
+
+    for _ in 0..10 {
+        quarter_round(&mut state, 0, 4, 8, 12);
+        quarter_round(&mut state, 1, 5, 9, 13);
+        quarter_round(&mut state, 2, 6, 10, 14);
+        quarter_round(&mut state, 3, 7, 11, 15);
+        quarter_round(&mut state, 0, 5, 10, 15);
+        quarter_round(&mut state, 1, 6, 11, 12);
+        quarter_round(&mut state, 2, 7, 8, 13);
+        quarter_round(&mut state, 3, 4, 9, 14);
+    }
+
+    for (word, input) in state.iter_mut().zip(input) {
+        *word = word.wrapping_add(input);
+    }
+
+    state
+}
+
+// overwrites secrets in a way that can't be optimized out
+fn erase<T: Copy + Default>(values: &mut [T]) {
+    for value in values {
+        unsafe { ptr::write_volatile(value, T::default()) };
+    }
+}
+
+/// A cryptographically secure random number generator built on the
+/// `ChaCha20` stream cipher.
+///
+/// This uses "fast key erasure": every batch of output starts with
+/// a new key that replaces the old one, and output is wiped from the
+/// generator as it's handed out. Compromising the generator's state
+/// doesn't reveal anything that it produced beforehand.
+pub struct ChaCha20Rng {
+    key: [u32; 8],
+    buffer: [u8; BUFFER_BYTES],
+    // the unread bytes are at the end of `buffer`
+    available: usize,
+}
+
+impl ChaCha20Rng {
+    /// Creates a generator keyed with `seed`.
+    ///
+    /// The generator is only as unpredictable as `seed` is.
+    #[must_use]
+    pub const fn new(seed: [u8; 32]) -> Self {
+        let mut key = [0; 8];
+        let mut i = 0;
+
+        while i < 8 {
+            key[i] = u32::from_le_bytes([
+                seed[i * 4],
+                seed[i * 4 + 1],
+                seed[i * 4 + 2],
+                seed[i * 4 + 3],
+            ]);
+            i += 1;
+        }
+
+        Self {
+            key,
+            buffer: [0; BUFFER_BYTES],
+            available: 0,
+        }
+    }
+
+    /// Fills `dst` with random bytes.
+    pub fn fill_bytes(&mut self, dst: &mut [u8]) {
+        let mut dst = dst;
+
+        while !dst.is_empty() {
+            if self.available == 0 {
+                self.refill();
+            }
+
+            let start = BUFFER_BYTES - self.available;
+            let n = self.available.min(dst.len());
+            let (now, rest) = dst.split_at_mut(n);
+
+            now.copy_from_slice(&self.buffer[start..start + n]);
+            erase(&mut self.buffer[start..start + n]);
+
+            self.available -= n;
+            dst = rest;
+        }
+    }
+
+    /// Produces a random `u64`.
+    pub fn next_u64(&mut self) -> u64 {
+        let mut bytes = [0; 8];
+
+        self.fill_bytes(&mut bytes);
+
+        u64::from_le_bytes(bytes)
+    }
+
+    /// Produces a random `u32`.
+    pub fn next_u32(&mut self) -> u32 {
+        let mut bytes = [0; 4];
+
+        self.fill_bytes(&mut bytes);
+
+        u32::from_le_bytes(bytes)
+    }
+
+    /// Mixes `input` into the key, along with everything that was mixed
+    /// in before. The output becomes unpredictable as soon as any of it
+    /// is, even if the rest is chosen by an attacker.
+    ///
+    /// Anything that was generated but not handed out yet is thrown away.
+    pub fn mix(&mut self, input: &[u8]) {
+        for chunk in input.chunks(32) {
+            let mut words = [0; 8];
+
+            for (word, bytes) in words.iter_mut().zip(chunk.chunks(4)) {
+                let mut padded = [0; 4];
+
+                padded[..bytes.len()].copy_from_slice(bytes);
+
+                *word = u32::from_le_bytes(padded);
+            }
+
+            for (key, word) in self.key.iter_mut().zip(words) {
+                *key ^= word;
+            }
+
+            let mut next = block(&self.key, 0, &MIX_NONCE);
+
+            self.key.copy_from_slice(&next[..8]);
+            erase(&mut next);
+        }
+
+        erase(&mut self.buffer);
+        self.available = 0;
+    }
+
+    fn refill(&mut self) {
+        let mut output = [0; BUFFER_BLOCKS * 16];
+
+        for (i, words) in (0..).zip(output.as_chunks_mut::<16>().0) {
+            *words = block(&self.key, i, &OUTPUT_NONCE);
+        }
+
+        self.key.copy_from_slice(&output[..8]);
+
+        for (bytes, word) in self
+            .buffer
+            .as_chunks_mut::<4>()
+            .0
+            .iter_mut()
+            .zip(&output[8..])
+        {
+            *bytes = word.to_le_bytes();
+        }
+
+        self.available = BUFFER_BYTES;
+
+        erase(&mut output);
+    }
+}
+
+impl Drop for ChaCha20Rng {
+    fn drop(&mut self) {
+        erase(&mut self.key);
+        erase(&mut self.buffer);
+    }
+}
+
+#[cfg(feature = "rand_core")]
+impl rand_core::RngCore for ChaCha20Rng {
+    fn next_u32(&mut self) -> u32 {
+        Self::next_u32(self)
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        Self::next_u64(self)
+    }
+
+    fn fill_bytes(&mut self, dst: &mut [u8]) {
+        Self::fill_bytes(self, dst);
+    }
+}
+
+#[cfg(feature = "rand_core")]
+impl rand_core::CryptoRng for ChaCha20Rng {}
+
+#[cfg(feature = "rand_core")]
+impl rand_core::SeedableRng for ChaCha20Rng {
+    type Seed = [u8; 32];
+
+    fn from_seed(seed: Self::Seed) -> Self {
+        Self::new(seed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bytes(words: &[u32]) -> Vec<u8> {
+        words.iter().flat_map(|word| word.to_le_bytes()).collect()
+    }
+
+    fn hex(s: &str) -> Vec<u8> {
+        let digits: Vec<_> = s.bytes().filter(u8::is_ascii_hexdigit).collect();
+
+        digits
+            .chunks(2)
+            .map(|pair| u8::from_str_radix(std::str::from_utf8(pair).unwrap(), 16).unwrap())
+            .collect()
+    }
+
+    // RFC 8439, appendix A.1, test vectors #1 and #2
+    const ZERO_KEY_BLOCK_0: &str = "
+        76 b8 e0 ad a0 f1 3d 90 40 5d 6a e5 53 86 bd 28
+        bd d2 19 b8 a0 8d ed 1a a8 36 ef cc 8b 77 0d c7
+        da 41 59 7c 51 57 48 8d 77 24 e0 3f b8 d8 4a 37
+        6a 43 b8 f4 15 18 a1 1c c3 87 b6 69 b2 ee 65 86";
+
+    const ZERO_KEY_BLOCK_1: &str = "
+        9f 07 e7 be 55 51 38 7a 98 ba 97 7c 73 2d 08 0d
+        cb 0f 29 a0 48 e3 65 69 12 c6 53 3e 32 ee 7a ed
+        29 b7 21 76 9c e6 4e 43 d5 71 33 b0 74 d8 39 d5
+        31 ed 1f 28 51 0a fb 45 ac e1 0a 1f 4b 79 4d 6f";
+
+    #[test]
+    fn block_function_matches_rfc() {
+        // RFC 8439, section 2.3.2
+        let key = core::array::from_fn(|i| {
+            let i = u8::try_from(i).unwrap() * 4;
+
+            u32::from_le_bytes([i, i + 1, i + 2, i + 3])
+        });
+
+        let output = block(&key, 1, &[0x0900_0000, 0x4A00_0000, 0]);
+
+        assert_eq!(
+            bytes(&output),
+            hex("
+                10 f1 e7 e4 d1 3b 59 15 50 0f dd 1f a3 20 71 c4
+                c7 d1 f4 c7 33 c0 68 03 04 22 aa 9a c3 d4 6c 4e
+                d2 82 64 46 07 9f aa 09 14 c2 d7 05 d9 8b 02 a2
+                b5 12 9c d1 de 16 4e b9 cb d0 83 e8 a2 50 3c 4e")
+        );
+
+        assert_eq!(bytes(&block(&[0; 8], 0, &[0; 3])), hex(ZERO_KEY_BLOCK_0));
+        assert_eq!(bytes(&block(&[0; 8], 1, &[0; 3])), hex(ZERO_KEY_BLOCK_1));
+    }
+
+    #[test]
+    fn output_is_the_keystream_after_the_next_key() {
+        let mut rng = ChaCha20Rng::new([0; 32]);
+        let mut output = [0; 96];
+
+        rng.fill_bytes(&mut output);
+
+        let block_0 = hex(ZERO_KEY_BLOCK_0);
+
+        assert_eq!(output[..32], block_0[32..]);
+        assert_eq!(output[32..], hex(ZERO_KEY_BLOCK_1));
+        assert_eq!(bytes(&rng.key), block_0[..32]);
+    }
+
+    #[test]
+    fn reads_dont_depend_on_how_theyre_split() {
+        let mut whole = ChaCha20Rng::new([7; 32]);
+        let mut pieces = ChaCha20Rng::new([7; 32]);
+        let mut expected = [0; 1000];
+        let mut actual = [0; 1000];
+
+        whole.fill_bytes(&mut expected);
+
+        for chunk in actual.chunks_mut(13) {
+            pieces.fill_bytes(chunk);
+        }
+
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    fn output_is_erased_once_read() {
+        let mut rng = ChaCha20Rng::new([1; 32]);
+
+        rng.next_u64();
+
+        assert!(rng.buffer[..8].iter().all(|&byte| byte == 0));
+    }
+
+    #[test]
+    fn mixing_changes_the_output() {
+        let mut original = ChaCha20Rng::new([3; 32]);
+        let mut mixed = ChaCha20Rng::new([3; 32]);
+        let mut other = ChaCha20Rng::new([3; 32]);
+
+        mixed.mix(b"some input that's longer than 32 bytes");
+        other.mix(b"some input that's longer than 32 bytes!");
+
+        let outputs = [original.next_u64(), mixed.next_u64(), other.next_u64()];
+
+        assert_ne!(outputs[0], outputs[1]);
+        assert_ne!(outputs[1], outputs[2]);
+    }
+}
@@ -0,0 +1,634 @@
+//======---------------------------------------------------------------======//
+//                                                                           //
+// Copyright 2022-2023 Evan Cox <evanacox00@gmail.com>. All rights reserved. //
+//                                                                           //
+// Use of this source code is governed by a BSD-style license that can be    //
+// found in the LICENSE.txt file at the root of this project, or at the      //
+// following link: https://opensource.org/licenses/BSD-3-Clause              //
+//                                                                           //
+//======---------------------------------------------------------------======//
+
+use core::cell::Cell;
+use core::cmp::Ordering;
+use core::marker::{PhantomData, PhantomPinned};
+use core::pin::Pin;
+use core::ptr::{self, NonNull};
+
+/// The link that a value embeds to be put in an [`AvlTree`].
+pub struct AvlLink {
+    parent: Cell<*const Self>,
+    left: Cell<*const Self>,
+    right: Cell<*const Self>,
+    // the height of the subtree rooted here, 0 means it isn't in a tree
+    height: Cell<u32>,
+    _pin: PhantomPinned,
+}
+
+impl AvlLink {
+    /// Creates a link that isn't in any tree.
+    #[must_use]
+    pub const fn new() -> Self {
+        Self {
+            parent: Cell::new(ptr::null()),
+            left: Cell::new(ptr::null()),
+            right: Cell::new(ptr::null()),
+            height: Cell::new(0),
+            _pin: PhantomPinned,
+        }
+    }
+
+    /// Checks whether the link is currently in a tree.
+    #[must_use]
+    pub const fn is_linked(&self) -> bool {
+        self.height.get() != 0
+    }
+
+    fn reset(&self) {
+        self.parent.set(ptr::null());
+        self.left.set(ptr::null());
+        self.right.set(ptr::null());
+        self.height.set(0);
+    }
+}
+
+impl Default for AvlLink {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A value that can be put in an [`AvlTree`], use [`avl_node!`](crate::avl_node) to
+/// implement this.
+///
+/// `Tag` distinguishes between trees when a value has more than one link.
+///
+/// # Safety
+/// [`link`](Self::link) must always return the same [`AvlLink`], which must
+/// be embedded in `self`. [`from_link`](Self::from_link) must be its inverse.
+pub unsafe trait AvlNode<Tag = ()> {
+    /// Gets the link embedded in `self`.
+    fn link(&self) -> &AvlLink;
+
+    /// Gets the value that `link` is embedded in.
+    ///
+    /// # Safety
+    /// `link` must have come from [`link`](Self::link).
+    unsafe fn from_link(link: NonNull<AvlLink>) -> NonNull<Self>;
+}
+
+/// Implements [`AvlNode`] for a type, given the field that its
+/// [`AvlLink`] is in. See [`list_node!`](crate::list_node!).
+#[macro_export]
+macro_rules! avl_node {
+    ($ty:ty, $field:ident) => {
+        $crate::avl_node!($ty, $field, ());
+    };
+    ($ty:ty, $field:ident, $tag:ty) => {
+        unsafe impl $crate::collections::AvlNode<$tag> for $ty {
+            #[allow(clippy::misnamed_getters)]
+            fn link(&self) -> &$crate::collections::AvlLink {
+                &self.$field
+            }
+
+            unsafe fn from_link(
+                link: ::core::ptr::NonNull<$crate::collections::AvlLink>,
+            ) -> ::core::ptr::NonNull<Self> {
+                link.byte_sub(::core::mem::offset_of!($ty, $field)).cast()
+            }
+        }
+    };
+}
+
+// all of these work on links that are in a tree, which makes any
+// non-null pointer in them valid
+
+const unsafe fn get<'x>(link: *const AvlLink) -> Option<&'x AvlLink> {
+    link.as_ref()
+}
+
+unsafe fn height(link: *const AvlLink) -> u32 {
+    get(link).map_or(0, |link| link.height.get())
+}
+
+unsafe fn balance(link: &AvlLink) -> i64 {
+    i64::from(height(link.right.get())) - i64::from(height(link.left.get()))
+}
+
+unsafe fn update_height(link: &AvlLink) {
+    link.height
+        .set(1 + height(link.left.get()).max(height(link.right.get())));
+}
+
+const unsafe fn leftmost(mut link: &AvlLink) -> &AvlLink {
+    while let Some(left) = get(link.left.get()) {
+        link = left;
+    }
+
+    link
+}
+
+const unsafe fn rightmost(mut link: &AvlLink) -> &AvlLink {
+    while let Some(right) = get(link.right.get()) {
+        link = right;
+    }
+
+    link
+}
+
+// the next link in order
+unsafe fn successor(link: &AvlLink) -> Option<&AvlLink> {
+    if let Some(right) = get(link.right.get()) {
+        return Some(leftmost(right));
+    }
+
+    let mut child = link;
+
+    while let Some(parent) = get(child.parent.get()) {
+        if ptr::eq(parent.left.get(), child) {
+            return Some(parent);
+        }
+
+        child = parent;
+    }
+
+    None
+}
+
+/// An intrusive, self-balancing (AVL) binary search tree of values
+/// that live for `'a`, ordered by their [`Ord`] implementation.
+///
+/// Inserting and removing are `O(log n)`. Equal values are allowed, they're
+/// kept in the order they were inserted in.
+pub struct AvlTree<'a, T: AvlNode<Tag> + Ord, Tag = ()> {
+    root: *const AvlLink,
+    len: usize,
+    _values: PhantomData<(&'a T, Tag)>,
+}
+
+impl<'a, T: AvlNode<Tag> + Ord, Tag> AvlTree<'a, T, Tag> {
+    /// Creates an empty tree.
+    #[must_use]
+    pub const fn new() -> Self {
+        Self {
+            root: ptr::null(),
+            len: 0,
+            _values: PhantomData,
+        }
+    }
+
+    /// The number of values in the tree.
+    #[must_use]
+    pub const fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Checks whether the tree is empty.
+    #[must_use]
+    pub const fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Adds `value` to the tree.
+    ///
+    /// # Panics
+    /// Panics if `value` is already in a tree.
+    pub fn insert(&mut self, value: Pin<&'a T>) {
+        let value = value.get_ref();
+        let link = value.link();
+
+        assert!(!link.is_linked(), "value is already in a tree");
+
+        link.height.set(1);
+        self.len += 1;
+
+        let Some(mut parent) = (unsafe { get(self.root) }) else {
+            self.root = link;
+
+            return;
+        };
+
+        loop {
+            // equal values go to the right, after the ones already there
+            let side = if value < unsafe { Self::value(parent) } {
+                &parent.left
+            } else {
+                &parent.right
+            };
+
+            let Some(child) = (unsafe { get(side.get()) }) else {
+                side.set(link);
+                link.parent.set(parent);
+
+                unsafe { self.retrace(parent) };
+
+                return;
+            };
+
+            parent = child;
+        }
+    }
+
+    /// Finds a value that `compare` says is equal to what's being searched
+    /// for. `compare` says how a value is ordered relative to the target,
+    /// like with [`slice::binary_search_by`].
+    pub fn find(&self, mut compare: impl FnMut(&T) -> Ordering) -> Option<&'a T> {
+        let mut link = self.root;
+
+        while let Some(current) = unsafe { get(link) } {
+            let value = unsafe { Self::value(current) };
+
+            link = match compare(value) {
+                Ordering::Less => current.right.get(),
+                Ordering::Greater => current.left.get(),
+                Ordering::Equal => return Some(value),
+            };
+        }
+
+        None
+    }
+
+    /// Gets the smallest value in the tree.
+    #[must_use]
+    pub fn first(&self) -> Option<&'a T> {
+        unsafe { get(self.root).map(|root| Self::value(leftmost(root))) }
+    }
+
+    /// Gets the largest value in the tree.
+    #[must_use]
+    pub fn last(&self) -> Option<&'a T> {
+        unsafe { get(self.root).map(|root| Self::value(rightmost(root))) }
+    }
+
+    /// Removes the smallest value in the tree.
+    pub fn pop_first(&mut self) -> Option<Pin<&'a T>> {
+        let value = self.first()?;
+
+        unsafe { self.unlink(value.link()) };
+
+        Some(unsafe { Pin::new_unchecked(value) })
+    }
+
+    /// Removes the largest value in the tree.
+    pub fn pop_last(&mut self) -> Option<Pin<&'a T>> {
+        let value = self.last()?;
+
+        unsafe { self.unlink(value.link()) };
+
+        Some(unsafe { Pin::new_unchecked(value) })
+    }
+
+    /// Removes `value` from the tree.
+    ///
+    /// # Safety
+    /// `value` must be in this tree (not just in *a* tree).
+    pub unsafe fn remove(&mut self, value: Pin<&'a T>) {
+        self.unlink(value.get_ref().link());
+    }
+
+    /// Iterates over the tree in order.
+    pub fn iter(&self) -> impl Iterator<Item = &'a T> + '_ {
+        let mut link = unsafe { get(self.root).map(|root| leftmost(root)) };
+
+        core::iter::from_fn(move || {
+            let current = link?;
+
+            link = unsafe { successor(current) };
+
+            Some(unsafe { Self::value(current) })
+        })
+    }
+
+    /// Unlinks every value in the tree.
+    pub fn clear(&mut self) {
+        while self.pop_first().is_some() {}
+    }
+
+    unsafe fn value(link: &AvlLink) -> &'a T {
+        T::from_link(NonNull::from(link)).as_ref()
+    }
+
+    unsafe fn replace_child(&mut self, parent: *const AvlLink, old: &AvlLink, new: *const AvlLink) {
+        match get(parent) {
+            Some(parent) if ptr::eq(parent.left.get(), old) => parent.left.set(new),
+            Some(parent) => parent.right.set(new),
+            None => self.root = new,
+        }
+    }
+
+    unsafe fn rotate_left<'x>(&mut self, link: &'x AvlLink) -> &'x AvlLink {
+        let pivot = &*link.right.get();
+        let inner = pivot.left.get();
+
+        link.right.set(inner);
+
+        if let Some(inner) = get(inner) {
+            inner.parent.set(link);
+        }
+
+        pivot.parent.set(link.parent.get());
+        self.replace_child(link.parent.get(), link, pivot);
+        pivot.left.set(link);
+        link.parent.set(pivot);
+
+        update_height(link);
+        update_height(pivot);
+
+        pivot
+    }
+
+    unsafe fn rotate_right<'x>(&mut self, link: &'x AvlLink) -> &'x AvlLink {
+        let pivot = &*link.left.get();
+        let inner = pivot.right.get();
+
+        link.left.set(inner);
+
+        if let Some(inner) = get(inner) {
+            inner.parent.set(link);
+        }
+
+        pivot.parent.set(link.parent.get());
+        self.replace_child(link.parent.get(), link, pivot);
+        pivot.right.set(link);
+        link.parent.set(pivot);
+
+        update_height(link);
+        update_height(pivot);
+
+        pivot
+    }
+
+    // fixes the heights and balance of everything from `link` up to the root
+    unsafe fn retrace(&mut self, link: *const AvlLink) {
+        let mut link = get(link);
+
+        while let Some(current) = link {
+            update_height(current);
+
+            let factor = balance(current);
+            let top = if factor > 1 {
+                if balance(&*current.right.get()) < 0 {
+                    self.rotate_right(&*current.right.get());
+                }
+
+                self.rotate_left(current)
+            } else if factor < -1 {
+                if balance(&*current.left.get()) > 0 {
+                    self.rotate_left(&*current.left.get());
+                }
+
+                self.rotate_right(current)
+            } else {
+                current
+            };
+
+            link = get(top.parent.get());
+        }
+    }
+
+    unsafe fn unlink(&mut self, link: &AvlLink) {
+        let (left, right) = (link.left.get(), link.right.get());
+        let parent = link.parent.get();
+
+        let retrace_from = if left.is_null() || right.is_null() {
+            let child = if left.is_null() { right } else { left };
+
+            self.replace_child(parent, link, child);
+
+            if let Some(child) = get(child) {
+                child.parent.set(parent);
+            }
+
+            parent
+        } else {
+            // two children, the successor takes our place
+            let successor = leftmost(&*right);
+            let retrace_from = if ptr::eq(successor.parent.get(), link) {
+                ptr::from_ref(successor)
+            } else {
+                let successor_parent = &*successor.parent.get();
+                let successor_right = successor.right.get();
+
+                successor_parent.left.set(successor_right);
+
+                if let Some(successor_right) = get(successor_right) {
+                    successor_right.parent.set(successor_parent);
+                }
+
+                successor.right.set(right);
+                (*right).parent.set(successor);
+
+                successor_parent
+            };
+
+            successor.left.set(left);
+            (*left).parent.set(successor);
+            successor.parent.set(parent);
+            successor.height.set(link.height.get());
+            self.replace_child(parent, link, successor);
+
+            retrace_from
+        };
+
+        link.reset();
+        self.len -= 1;
+        self.retrace(retrace_from);
+    }
+}
+
+impl<T: AvlNode<Tag> + Ord, Tag> Default for AvlTree<'_, T, Tag> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: AvlNode<Tag> + Ord, Tag> Drop for AvlTree<'_, T, Tag> {
+    fn drop(&mut self) {
+        // the values outlive the tree, they have to be usable again
+        self.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Xorshift128Plus;
+    use std::vec::Vec;
+
+    struct Node {
+        key: u32,
+        // distinguishes between equal keys
+        order: usize,
+        link: AvlLink,
+    }
+
+    crate::avl_node!(Node, link);
+
+    impl PartialEq for Node {
+        fn eq(&self, other: &Self) -> bool {
+            self.key == other.key
+        }
+    }
+
+    impl Eq for Node {}
+
+    impl PartialOrd for Node {
+        fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+            Some(self.cmp(other))
+        }
+    }
+
+    impl Ord for Node {
+        fn cmp(&self, other: &Self) -> Ordering {
+            self.key.cmp(&other.key)
+        }
+    }
+
+    fn nodes(keys: impl IntoIterator<Item = u32>) -> Vec<Node> {
+        keys.into_iter()
+            .enumerate()
+            .map(|(order, key)| Node {
+                key,
+                order,
+                link: AvlLink::new(),
+            })
+            .collect()
+    }
+
+    fn pin(node: &Node) -> Pin<&Node> {
+        unsafe { Pin::new_unchecked(node) }
+    }
+
+    // checks the parent pointers, the heights and the balance factor of
+    // every link, returning the height of the subtree
+    fn check(link: *const AvlLink, parent: *const AvlLink) -> u32 {
+        let Some(link) = (unsafe { get(link) }) else {
+            return 0;
+        };
+
+        assert!(ptr::eq(link.parent.get(), parent));
+
+        let left = check(link.left.get(), link);
+        let right = check(link.right.get(), link);
+
+        assert!(left.abs_diff(right) <= 1, "tree is unbalanced");
+        assert_eq!(link.height.get(), 1 + left.max(right));
+
+        link.height.get()
+    }
+
+    fn check_tree(tree: &AvlTree<'_, Node>) {
+        check(tree.root, ptr::null());
+
+        let keys: Vec<_> = tree.iter().map(|node| node.key).collect();
+
+        assert!(keys.windows(2).all(|pair| pair[0] <= pair[1]));
+        assert_eq!(keys.len(), tree.len());
+    }
+
+    #[test]
+    fn sorted_inserts_stay_balanced() {
+        let nodes = nodes(0..1000);
+        let mut tree = AvlTree::new();
+
+        for node in &nodes {
+            tree.insert(pin(node));
+        }
+
+        check_tree(&tree);
+
+        // a perfectly balanced tree of 1000 has a height of 10
+        assert!(unsafe { height(tree.root) } <= 11);
+        assert_eq!(tree.first().map(|n| n.key), Some(0));
+        assert_eq!(tree.last().map(|n| n.key), Some(999));
+    }
+
+    #[test]
+    fn find_by_key() {
+        let nodes = nodes((0..100).map(|i| i * 3));
+        let mut tree = AvlTree::new();
+
+        for node in &nodes {
+            tree.insert(pin(node));
+        }
+
+        assert_eq!(tree.find(|n| n.key.cmp(&42)).map(|n| n.key), Some(42));
+        assert!(tree.find(|n| n.key.cmp(&43)).is_none());
+    }
+
+    #[test]
+    fn equal_keys_keep_insertion_order() {
+        let nodes = nodes([5, 1, 5, 5, 1]);
+        let mut tree = AvlTree::new();
+
+        for node in &nodes {
+            tree.insert(pin(node));
+        }
+
+        let order: Vec<_> = tree.iter().map(|n| n.order).collect();
+
+        assert_eq!(order, [1, 4, 0, 2, 3]);
+    }
+
+    #[test]
+    fn random_inserts_and_removes() {
+        let mut rng = Xorshift128Plus::with_seed([0x1234_5678, 0x9abc_def0]);
+        let nodes = nodes((0..500).map(|_| (rng.next() % 200) as u32));
+        let mut tree = AvlTree::new();
+
+        for node in &nodes {
+            tree.insert(pin(node));
+        }
+
+        check_tree(&tree);
+
+        for node in nodes.iter().filter(|_| rng.next() % 2 == 0) {
+            unsafe { tree.remove(pin(node)) };
+
+            assert!(!node.link.is_linked());
+        }
+
+        check_tree(&tree);
+
+        // and then put them all back
+        for node in nodes.iter().filter(|node| !node.link.is_linked()) {
+            tree.insert(pin(node));
+        }
+
+        check_tree(&tree);
+        assert_eq!(tree.len(), nodes.len());
+
+        let mut previous = 0;
+
+        while let Some(node) = tree.pop_first() {
+            assert!(node.key >= previous);
+
+            previous = node.key;
+        }
+    }
+
+    #[test]
+    #[should_panic = "already in a tree"]
+    fn double_insert_panics() {
+        let nodes = nodes([1]);
+        let mut first = AvlTree::new();
+        let mut second = AvlTree::new();
+
+        first.insert(pin(&nodes[0]));
+        second.insert(pin(&nodes[0]));
+    }
+
+    #[test]
+    fn dropping_the_tree_unlinks() {
+        let nodes = nodes(0..10);
+
+        {
+            let mut tree = AvlTree::new();
+
+            for node in &nodes {
+                tree.insert(pin(node));
+            }
+        }
+
+        assert!(nodes.iter().all(|node| !node.link.is_linked()));
+    }
+}
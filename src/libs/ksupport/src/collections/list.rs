@@ -0,0 +1,441 @@
+//======---------------------------------------------------------------======//
+//                                                                           //
+// Copyright 2022-2023 Evan Cox <evanacox00@gmail.com>. All rights reserved. //
+//                                                                           //
+// Use of this source code is governed by a BSD-style license that can be    //
+// found in the LICENSE.txt file at the root of this project, or at the      //
+// following link: https://opensource.org/licenses/BSD-3-Clause              //
+//                                                                           //
+//======---------------------------------------------------------------======//
+
+use core::cell::Cell;
+use core::marker::{PhantomData, PhantomPinned};
+use core::pin::Pin;
+use core::ptr::{self, NonNull};
+
+/// The link that a value embeds to be put in a [`List`].
+pub struct ListLink {
+    prev: Cell<*const Self>,
+    next: Cell<*const Self>,
+    linked: Cell<bool>,
+    _pin: PhantomPinned,
+}
+
+impl ListLink {
+    /// Creates a link that isn't in any list.
+    #[must_use]
+    pub const fn new() -> Self {
+        Self {
+            prev: Cell::new(ptr::null()),
+            next: Cell::new(ptr::null()),
+            linked: Cell::new(false),
+            _pin: PhantomPinned,
+        }
+    }
+
+    /// Checks whether the link is currently in a list.
+    #[must_use]
+    pub const fn is_linked(&self) -> bool {
+        self.linked.get()
+    }
+
+    fn reset(&self) {
+        self.prev.set(ptr::null());
+        self.next.set(ptr::null());
+        self.linked.set(false);
+    }
+}
+
+impl Default for ListLink {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A value that can be put in a [`List`], use [`list_node!`](crate::list_node)
+/// to implement this.
+///
+/// `Tag` distinguishes between lists when a value has more than one link.
+///
+/// # Safety
+/// [`link`](Self::link) must always return the same [`ListLink`], which must
+/// be embedded in `self`. [`from_link`](Self::from_link) must be its inverse.
+pub unsafe trait ListNode<Tag = ()> {
+    /// Gets the link embedded in `self`.
+    fn link(&self) -> &ListLink;
+
+    /// Gets the value that `link` is embedded in.
+    ///
+    /// # Safety
+    /// `link` must have come from [`link`](Self::link).
+    unsafe fn from_link(link: NonNull<ListLink>) -> NonNull<Self>;
+}
+
+/// Implements [`ListNode`] for a type, given the field that its
+/// [`ListLink`] is in.
+///
+/// ```
+/// # use ksupport::collections::ListLink;
+/// struct Task {
+///     id: u32,
+///     run_link: ListLink,
+/// }
+///
+/// ksupport::list_node!(Task, run_link);
+/// ```
+///
+/// A tag type can be given after the field, for types with multiple links.
+#[macro_export]
+macro_rules! list_node {
+    ($ty:ty, $field:ident) => {
+        $crate::list_node!($ty, $field, ());
+    };
+    ($ty:ty, $field:ident, $tag:ty) => {
+        unsafe impl $crate::collections::ListNode<$tag> for $ty {
+            #[allow(clippy::misnamed_getters)]
+            fn link(&self) -> &$crate::collections::ListLink {
+                &self.$field
+            }
+
+            unsafe fn from_link(
+                link: ::core::ptr::NonNull<$crate::collections::ListLink>,
+            ) -> ::core::ptr::NonNull<Self> {
+                link.byte_sub(::core::mem::offset_of!($ty, $field)).cast()
+            }
+        }
+    };
+}
+
+/// An intrusive doubly-linked list of values that live for `'a`.
+///
+/// Pushing, popping and removing are all `O(1)`.
+pub struct List<'a, T: ListNode<Tag>, Tag = ()> {
+    head: *const ListLink,
+    tail: *const ListLink,
+    len: usize,
+    _values: PhantomData<(&'a T, Tag)>,
+}
+
+impl<'a, T: ListNode<Tag>, Tag> List<'a, T, Tag> {
+    /// Creates an empty list.
+    #[must_use]
+    pub const fn new() -> Self {
+        Self {
+            head: ptr::null(),
+            tail: ptr::null(),
+            len: 0,
+            _values: PhantomData,
+        }
+    }
+
+    /// The number of values in the list.
+    #[must_use]
+    pub const fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Checks whether the list is empty.
+    #[must_use]
+    pub const fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Gets the value at the front of the list.
+    #[must_use]
+    pub fn front(&self) -> Option<&'a T> {
+        unsafe { Self::value(self.head) }
+    }
+
+    /// Gets the value at the back of the list.
+    #[must_use]
+    pub fn back(&self) -> Option<&'a T> {
+        unsafe { Self::value(self.tail) }
+    }
+
+    /// Adds `value` to the front of the list.
+    ///
+    /// # Panics
+    /// Panics if `value` is already in a list.
+    pub fn push_front(&mut self, value: Pin<&'a T>) {
+        let link = Self::claim(value);
+
+        link.next.set(self.head);
+
+        match unsafe { self.head.as_ref() } {
+            Some(head) => head.prev.set(link),
+            None => self.tail = link,
+        }
+
+        self.head = link;
+        self.len += 1;
+    }
+
+    /// Adds `value` to the back of the list.
+    ///
+    /// # Panics
+    /// Panics if `value` is already in a list.
+    pub fn push_back(&mut self, value: Pin<&'a T>) {
+        let link = Self::claim(value);
+
+        link.prev.set(self.tail);
+
+        match unsafe { self.tail.as_ref() } {
+            Some(tail) => tail.next.set(link),
+            None => self.head = link,
+        }
+
+        self.tail = link;
+        self.len += 1;
+    }
+
+    /// Removes the value at the front of the list.
+    pub fn pop_front(&mut self) -> Option<Pin<&'a T>> {
+        let value = self.front()?;
+
+        unsafe { self.unlink(value.link()) };
+
+        Some(unsafe { Pin::new_unchecked(value) })
+    }
+
+    /// Removes the value at the back of the list.
+    pub fn pop_back(&mut self) -> Option<Pin<&'a T>> {
+        let value = self.back()?;
+
+        unsafe { self.unlink(value.link()) };
+
+        Some(unsafe { Pin::new_unchecked(value) })
+    }
+
+    /// Removes `value` from the list.
+    ///
+    /// # Safety
+    /// `value` must be in this list (not just in *a* list).
+    pub unsafe fn remove(&mut self, value: Pin<&'a T>) {
+        self.unlink(value.get_ref().link());
+    }
+
+    /// Iterates over the list, front to back.
+    pub fn iter(&self) -> impl Iterator<Item = &'a T> + '_ {
+        let mut link = self.head;
+
+        core::iter::from_fn(move || {
+            let value = unsafe { Self::value(link) }?;
+
+            link = value.link().next.get();
+
+            Some(value)
+        })
+    }
+
+    /// Gets a cursor pointing at the front of the list.
+    pub const fn cursor_front_mut(&mut self) -> ListCursor<'_, 'a, T, Tag> {
+        ListCursor {
+            current: self.head,
+            list: self,
+        }
+    }
+
+    /// Unlinks every value in the list.
+    pub fn clear(&mut self) {
+        while self.pop_front().is_some() {}
+    }
+
+    fn claim(value: Pin<&'a T>) -> &'a ListLink {
+        let link = value.get_ref().link();
+
+        assert!(!link.is_linked(), "value is already in a list");
+
+        link.linked.set(true);
+
+        link
+    }
+
+    unsafe fn value(link: *const ListLink) -> Option<&'a T> {
+        NonNull::new(link.cast_mut()).map(|link| T::from_link(link).as_ref())
+    }
+
+    unsafe fn unlink(&mut self, link: &ListLink) {
+        let (prev, next) = (link.prev.get(), link.next.get());
+
+        match prev.as_ref() {
+            Some(prev) => prev.next.set(next),
+            None => self.head = next,
+        }
+
+        match next.as_ref() {
+            Some(next) => next.prev.set(prev),
+            None => self.tail = prev,
+        }
+
+        link.reset();
+        self.len -= 1;
+    }
+}
+
+impl<T: ListNode<Tag>, Tag> Default for List<'_, T, Tag> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: ListNode<Tag>, Tag> Drop for List<'_, T, Tag> {
+    fn drop(&mut self) {
+        // the values outlive the list, they have to be usable again
+        self.clear();
+    }
+}
+
+/// A cursor over a [`List`], which can remove the value it points at.
+pub struct ListCursor<'l, 'a, T: ListNode<Tag>, Tag = ()> {
+    list: &'l mut List<'a, T, Tag>,
+    current: *const ListLink,
+}
+
+impl<'a, T: ListNode<Tag>, Tag> ListCursor<'_, 'a, T, Tag> {
+    /// Gets the value that the cursor points at, or `None` if it's
+    /// past the end of the list.
+    #[must_use]
+    pub fn current(&self) -> Option<&'a T> {
+        unsafe { List::<T, Tag>::value(self.current) }
+    }
+
+    /// Moves the cursor to the next value.
+    pub fn move_next(&mut self) {
+        if let Some(value) = self.current() {
+            self.current = value.link().next.get();
+        }
+    }
+
+    /// Removes the value that the cursor points at, and moves the cursor
+    /// to the next value.
+    pub fn remove_current(&mut self) -> Option<Pin<&'a T>> {
+        let value = self.current()?;
+        let link = value.link();
+
+        self.current = link.next.get();
+
+        unsafe { self.list.unlink(link) };
+
+        Some(unsafe { Pin::new_unchecked(value) })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::vec::Vec;
+
+    struct Node {
+        value: i32,
+        link: ListLink,
+        other: ListLink,
+    }
+
+    struct Other;
+
+    crate::list_node!(Node, link);
+    crate::list_node!(Node, other, Other);
+
+    fn node(value: i32) -> Node {
+        Node {
+            value,
+            link: ListLink::new(),
+            other: ListLink::new(),
+        }
+    }
+
+    fn values<Tag>(list: &List<'_, Node, Tag>) -> Vec<i32>
+    where
+        Node: ListNode<Tag>,
+    {
+        list.iter().map(|node| node.value).collect()
+    }
+
+    #[test]
+    fn order_and_removal() {
+        let nodes: Vec<_> = (0..4).map(node).collect();
+        let pinned: Vec<_> = nodes
+            .iter()
+            .map(|n| unsafe { Pin::new_unchecked(n) })
+            .collect();
+        let mut list = List::<Node>::new();
+
+        list.push_back(pinned[1]);
+        list.push_back(pinned[2]);
+        list.push_front(pinned[0]);
+        list.push_back(pinned[3]);
+
+        assert_eq!(values(&list), [0, 1, 2, 3]);
+        assert_eq!(list.len(), 4);
+
+        unsafe { list.remove(pinned[2]) };
+
+        assert!(!nodes[2].link.is_linked());
+        assert_eq!(values(&list), [0, 1, 3]);
+        assert_eq!(list.pop_back().map(|n| n.value), Some(3));
+        assert_eq!(list.pop_front().map(|n| n.value), Some(0));
+        assert_eq!(values(&list), [1]);
+        assert_eq!(list.front().map(|n| n.value), list.back().map(|n| n.value));
+    }
+
+    #[test]
+    fn cursor_removes() {
+        let nodes: Vec<_> = (0..6).map(node).collect();
+        let mut list = List::<Node>::new();
+
+        for n in &nodes {
+            list.push_back(unsafe { Pin::new_unchecked(n) });
+        }
+
+        let mut cursor = list.cursor_front_mut();
+
+        while let Some(n) = cursor.current() {
+            if n.value % 2 == 0 {
+                cursor.remove_current();
+            } else {
+                cursor.move_next();
+            }
+        }
+
+        assert_eq!(values(&list), [1, 3, 5]);
+    }
+
+    #[test]
+    fn a_value_can_be_in_one_list_per_tag() {
+        let n = node(7);
+        let n = unsafe { Pin::new_unchecked(&n) };
+        let mut first = List::<Node>::new();
+        let mut second = List::<Node, Other>::new();
+
+        first.push_back(n);
+        second.push_back(n);
+
+        assert_eq!(values(&first), values(&second));
+    }
+
+    #[test]
+    #[should_panic = "already in a list"]
+    fn double_insert_panics() {
+        let n = node(7);
+        let n = unsafe { Pin::new_unchecked(&n) };
+        let mut first = List::<Node>::new();
+        let mut second = List::<Node>::new();
+
+        first.push_back(n);
+        second.push_back(n);
+    }
+
+    #[test]
+    fn dropping_the_list_unlinks() {
+        let n = node(7);
+        let n = unsafe { Pin::new_unchecked(&n) };
+
+        {
+            let mut list = List::<Node>::new();
+
+            list.push_back(n);
+        }
+
+        assert!(!n.link.is_linked());
+    }
+}
@@ -0,0 +1,27 @@
+//======---------------------------------------------------------------======//
+//                                                                           //
+// Copyright 2022-2023 Evan Cox <evanacox00@gmail.com>. All rights reserved. //
+//                                                                           //
+// Use of this source code is governed by a BSD-style license that can be    //
+// found in the LICENSE.txt file at the root of this project, or at the      //
+// following link: https://opensource.org/licenses/BSD-3-Clause              //
+//                                                                           //
+//======---------------------------------------------------------------======//
+
+//! Intrusive containers, where the links live inside of the values
+//! being stored instead of in separately allocated nodes.
+//!
+//! Nothing here allocates. Values are borrowed by the container for its
+//! whole lifetime (`'a`) and have to be pinned, so they can't be moved or
+//! freed while they're linked in. Every value embeds one link per
+//! container it can be in, distinguished by a `Tag` type if there's more
+//! than one, and linking a value that's already linked in panics.
+//!
+//! The link-to-value conversions are implemented with
+//! [`list_node!`](crate::list_node) and [`avl_node!`](crate::avl_node!).
+
+mod avl;
+mod list;
+
+pub use avl::{AvlLink, AvlNode, AvlTree};
+pub use list::{List, ListCursor, ListLink, ListNode};
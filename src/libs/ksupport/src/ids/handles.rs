@@ -0,0 +1,224 @@
+//======---------------------------------------------------------------======//
+//                                                                           //
+// Copyright 2022-2023 Evan Cox <evanacox00@gmail.com>. All rights reserved. //
+//                                                                           //
+// Use of this source code is governed by a BSD-style license that can be    //
+// found in the LICENSE.txt file at the root of this project, or at the      //
+// following link: https://opensource.org/licenses/BSD-3-Clause              //
+//                                                                           //
+//======---------------------------------------------------------------======//
+
+/// A reference to a slot in a [`HandleAllocator`], which stops being
+/// live once the slot is freed, even if the slot gets reused.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Handle {
+    index: u32,
+    generation: u32,
+}
+
+impl Handle {
+    /// The slot that the handle refers to, which can be used to index
+    /// into a table of `N` entries.
+    #[must_use]
+    pub const fn index(self) -> usize {
+        self.index as usize
+    }
+
+    /// Which use of the slot the handle refers to.
+    #[must_use]
+    pub const fn generation(self) -> u32 {
+        self.generation
+    }
+
+    /// Packs the handle into a single integer, e.g. to hand to user mode.
+    #[must_use]
+    pub const fn to_raw(self) -> u64 {
+        ((self.generation as u64) << 32) | self.index as u64
+    }
+
+    /// Unpacks a handle from [`to_raw`](Self::to_raw). Nothing is
+    /// validated here, but a garbage handle just won't be live.
+    #[must_use]
+    #[allow(clippy::cast_possible_truncation)]
+    pub const fn from_raw(raw: u64) -> Self {
+        Self {
+            index: raw as u32,
+            generation: (raw >> 32) as u32,
+        }
+    }
+}
+
+/// Hands out [`Handle`]s to `N` slots, and detects handles that are used
+/// after their slot was freed.
+///
+/// Every slot has a generation that's bumped both when it's allocated and
+/// when it's freed, so live slots have odd generations. Slots that were
+/// never used are handed out first, and freed slots are reused in FIFO
+/// order, which makes it take as long as possible for a slot to come
+/// back. A slot whose generation would wrap around is retired instead of
+/// being reused.
+pub struct HandleAllocator<const N: usize> {
+    generations: [u32; N],
+    // a queue of freed slots
+    free: [u32; N],
+    free_head: usize,
+    free_len: usize,
+    // slots from here up have never been allocated
+    fresh: usize,
+    live: usize,
+}
+
+impl<const N: usize> HandleAllocator<N> {
+    /// Creates an allocator where every slot is free.
+    ///
+    /// # Panics
+    /// Panics if `N` doesn't fit in a `u32`.
+    #[must_use]
+    pub const fn new() -> Self {
+        assert!(N <= u32::MAX as usize, "too many slots");
+
+        Self {
+            generations: [0; N],
+            free: [0; N],
+            free_head: 0,
+            free_len: 0,
+            fresh: 0,
+            live: 0,
+        }
+    }
+
+    /// The number of handles that are currently live.
+    #[must_use]
+    pub const fn live(&self) -> usize {
+        self.live
+    }
+
+    /// Checks whether `handle` refers to the current use of its slot.
+    #[must_use]
+    pub const fn is_live(&self, handle: Handle) -> bool {
+        handle.index() < N
+            && handle.generation % 2 == 1
+            && self.generations[handle.index()] == handle.generation
+    }
+
+    /// Allocates a slot, or returns `None` if they're all in use.
+    #[allow(clippy::cast_possible_truncation)]
+    pub const fn allocate(&mut self) -> Option<Handle> {
+        let index = if self.fresh < N {
+            self.fresh += 1;
+
+            // `new` checks that this fits
+            (self.fresh - 1) as u32
+        } else if self.free_len > 0 {
+            let index = self.free[self.free_head];
+
+            self.free_head = (self.free_head + 1) % N;
+            self.free_len -= 1;
+
+            index
+        } else {
+            return None;
+        };
+
+        let generation = &mut self.generations[index as usize];
+
+        *generation += 1;
+        self.live += 1;
+
+        Some(Handle {
+            index,
+            generation: *generation,
+        })
+    }
+
+    /// Frees the slot that `handle` refers to, returning `false` (and doing
+    /// nothing) if the handle isn't live.
+    pub const fn free(&mut self, handle: Handle) -> bool {
+        if !self.is_live(handle) {
+            return false;
+        }
+
+        let generation = &mut self.generations[handle.index()];
+
+        *generation += 1;
+        self.live -= 1;
+
+        // an even generation is never `u32::MAX`, but the next use would
+        // wrap around and make ancient handles live again
+        if *generation != u32::MAX - 1 {
+            self.free[(self.free_head + self.free_len) % N] = handle.index;
+            self.free_len += 1;
+        }
+
+        true
+    }
+}
+
+impl<const N: usize> Default for HandleAllocator<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stale_handles_arent_live() {
+        let mut handles = HandleAllocator::<4>::new();
+        let first = handles.allocate().unwrap();
+
+        assert!(handles.is_live(first));
+        assert!(handles.free(first));
+        assert!(!handles.is_live(first));
+        assert!(!handles.free(first));
+
+        // everything else gets used before the freed slot comes back
+        let others: Vec<_> = (0..3).map(|_| handles.allocate().unwrap()).collect();
+
+        assert!(others.iter().all(|h| h.index() != first.index()));
+
+        let reused = handles.allocate().unwrap();
+
+        assert_eq!(reused.index(), first.index());
+        assert_ne!(reused, first);
+        assert!(!handles.is_live(first));
+        assert!(handles.allocate().is_none());
+        assert_eq!(handles.live(), 4);
+    }
+
+    #[test]
+    fn freed_slots_are_reused_in_order() {
+        let mut handles = HandleAllocator::<3>::new();
+        let all: Vec<_> = (0..3).map(|_| handles.allocate().unwrap()).collect();
+
+        handles.free(all[2]);
+        handles.free(all[0]);
+
+        assert_eq!(handles.allocate().map(Handle::index), Some(2));
+        assert_eq!(handles.allocate().map(Handle::index), Some(0));
+    }
+
+    #[test]
+    fn raw_round_trips() {
+        let mut handles = HandleAllocator::<8>::new();
+        let handle = handles.allocate().unwrap();
+
+        assert_eq!(Handle::from_raw(handle.to_raw()), handle);
+        assert!(!handles.is_live(Handle::from_raw(u64::MAX)));
+    }
+
+    #[test]
+    fn worn_out_slots_are_retired() {
+        let mut handles = HandleAllocator::<1>::new();
+
+        handles.generations[0] = u32::MAX - 3;
+
+        let handle = handles.allocate().unwrap();
+
+        assert_eq!(handle.generation(), u32::MAX - 2);
+        assert!(handles.free(handle));
+        assert!(handles.allocate().is_none());
+    }
+}
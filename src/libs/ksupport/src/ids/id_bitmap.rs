@@ -0,0 +1,240 @@
+//======---------------------------------------------------------------======//
+//                                                                           //
+// Copyright 2022-2023 Evan Cox <evanacox00@gmail.com>. All rights reserved. //
+//                                                                           //
+// Use of this source code is governed by a BSD-style license that can be    //
+// found in the LICENSE.txt file at the root of this project, or at the      //
+// following link: https://opensource.org/licenses/BSD-3-Clause              //
+//                                                                           //
+//======---------------------------------------------------------------======//
+
+use core::ops::Range;
+
+const BITS: usize = u64::BITS as usize;
+
+/// Allocates IDs out of `0..capacity` by tracking them with one bit each,
+/// for up to `WORDS * 64` IDs.
+///
+/// IDs can either be handed out lowest-first, cyclically (so that a freed
+/// ID isn't immediately reused, like with PIDs), or from within a range.
+pub struct IdBitmap<const WORDS: usize> {
+    bits: [u64; WORDS],
+    limit: usize,
+    allocated: usize,
+    // where `allocate_cyclic` starts searching
+    next: usize,
+}
+
+impl<const WORDS: usize> IdBitmap<WORDS> {
+    /// Creates a bitmap that can hand out every ID in `0..WORDS * 64`.
+    #[must_use]
+    pub const fn new() -> Self {
+        Self::with_limit(WORDS * BITS)
+    }
+
+    /// Creates a bitmap that can only hand out IDs in `0..limit`.
+    ///
+    /// # Panics
+    /// Panics if `limit` is greater than `WORDS * 64`.
+    #[must_use]
+    pub const fn with_limit(limit: usize) -> Self {
+        assert!(limit <= WORDS * BITS, "limit is past the end of the bitmap");
+
+        Self {
+            bits: [0; WORDS],
+            limit,
+            allocated: 0,
+            next: 0,
+        }
+    }
+
+    /// The number of IDs that can be allocated at once.
+    #[must_use]
+    pub const fn capacity(&self) -> usize {
+        self.limit
+    }
+
+    /// The number of IDs that are currently allocated.
+    #[must_use]
+    pub const fn allocated(&self) -> usize {
+        self.allocated
+    }
+
+    /// Checks whether `id` is currently allocated. IDs that are out of
+    /// range never are.
+    #[must_use]
+    pub const fn is_allocated(&self, id: usize) -> bool {
+        id < self.limit && self.bits[id / BITS] & (1 << (id % BITS)) != 0
+    }
+
+    /// Allocates the lowest free ID.
+    pub fn allocate(&mut self) -> Option<usize> {
+        self.allocate_in(0..self.limit)
+    }
+
+    /// Allocates the lowest free ID in `range`. Any part of `range` past
+    /// the capacity is ignored.
+    pub fn allocate_in(&mut self, range: Range<usize>) -> Option<usize> {
+        let id = self.find_free(range.start, range.end.min(self.limit))?;
+
+        self.set(id);
+
+        Some(id)
+    }
+
+    /// Allocates the first free ID after the one that was last allocated
+    /// this way, wrapping around at the end.
+    pub fn allocate_cyclic(&mut self) -> Option<usize> {
+        let id = self
+            .find_free(self.next, self.limit)
+            .or_else(|| self.find_free(0, self.next))?;
+
+        self.set(id);
+        self.next = (id + 1) % self.limit;
+
+        Some(id)
+    }
+
+    /// Marks a specific ID as allocated, returning `false` if it already was.
+    ///
+    /// # Panics
+    /// Panics if `id` is out of range.
+    pub fn reserve(&mut self, id: usize) -> bool {
+        assert!(id < self.limit, "id {id} is out of range");
+
+        if self.is_allocated(id) {
+            return false;
+        }
+
+        self.set(id);
+
+        true
+    }
+
+    /// Frees `id` so that it can be allocated again.
+    ///
+    /// # Panics
+    /// Panics if `id` isn't allocated.
+    pub fn free(&mut self, id: usize) {
+        assert!(self.is_allocated(id), "freed id {id} that wasn't allocated");
+
+        self.bits[id / BITS] &= !(1 << (id % BITS));
+        self.allocated -= 1;
+    }
+
+    const fn set(&mut self, id: usize) {
+        self.bits[id / BITS] |= 1 << (id % BITS);
+        self.allocated += 1;
+    }
+
+    // finds the lowest clear bit in `start..end`
+    fn find_free(&self, start: usize, end: usize) -> Option<usize> {
+        if start >= end {
+            return None;
+        }
+
+        let first = start / BITS;
+        let words = self.bits[first..=(end - 1) / BITS].iter();
+
+        for (index, &word) in (first..).zip(words) {
+            let mut word = word;
+            let base = index * BITS;
+
+            // anything outside of the range counts as taken
+            if start > base {
+                word |= (1 << (start - base)) - 1;
+            }
+
+            if end < base + BITS {
+                word |= !((1 << (end - base)) - 1);
+            }
+
+            if word != u64::MAX {
+                return Some(base + word.trailing_ones() as usize);
+            }
+        }
+
+        None
+    }
+}
+
+impl<const WORDS: usize> Default for IdBitmap<WORDS> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn allocates_lowest_first() {
+        let mut ids = IdBitmap::<2>::new();
+
+        assert_eq!(ids.capacity(), 128);
+
+        for expected in 0..128 {
+            assert_eq!(ids.allocate(), Some(expected));
+        }
+
+        assert_eq!(ids.allocate(), None);
+
+        ids.free(70);
+        ids.free(3);
+
+        assert_eq!(ids.allocate(), Some(3));
+        assert_eq!(ids.allocate(), Some(70));
+        assert_eq!(ids.allocated(), 128);
+    }
+
+    #[test]
+    fn limit_is_respected() {
+        let mut ids = IdBitmap::<1>::with_limit(3);
+
+        assert_eq!(ids.allocate(), Some(0));
+        assert_eq!(ids.allocate(), Some(1));
+        assert_eq!(ids.allocate(), Some(2));
+        assert_eq!(ids.allocate(), None);
+        assert!(!ids.is_allocated(3));
+    }
+
+    #[test]
+    fn cyclic_doesnt_reuse_immediately() {
+        let mut ids = IdBitmap::<1>::with_limit(4);
+
+        assert_eq!(ids.allocate_cyclic(), Some(0));
+        assert_eq!(ids.allocate_cyclic(), Some(1));
+
+        ids.free(0);
+
+        assert_eq!(ids.allocate_cyclic(), Some(2));
+        assert_eq!(ids.allocate_cyclic(), Some(3));
+        // wraps back around to the one that was freed
+        assert_eq!(ids.allocate_cyclic(), Some(0));
+        assert_eq!(ids.allocate_cyclic(), None);
+    }
+
+    #[test]
+    fn allocate_in_range() {
+        let mut ids = IdBitmap::<4>::new();
+
+        assert!(ids.reserve(64));
+        assert!(!ids.reserve(64));
+        assert_eq!(ids.allocate_in(60..66), Some(60));
+        assert_eq!(ids.allocate_in(64..66), Some(65));
+        assert_eq!(ids.allocate_in(64..66), None);
+        assert_eq!(ids.allocate_in(250..1000), Some(250));
+        assert_eq!(ids.allocate_in(5..5), None);
+    }
+
+    #[test]
+    #[should_panic = "wasn't allocated"]
+    fn double_free_panics() {
+        let mut ids = IdBitmap::<1>::new();
+        let id = ids.allocate().unwrap();
+
+        ids.free(id);
+        ids.free(id);
+    }
+}
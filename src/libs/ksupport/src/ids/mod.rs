@@ -0,0 +1,22 @@
+//======---------------------------------------------------------------======//
+//                                                                           //
+// Copyright 2022-2023 Evan Cox <evanacox00@gmail.com>. All rights reserved. //
+//                                                                           //
+// Use of this source code is governed by a BSD-style license that can be    //
+// found in the LICENSE.txt file at the root of this project, or at the      //
+// following link: https://opensource.org/licenses/BSD-3-Clause              //
+//                                                                           //
+//======---------------------------------------------------------------======//
+
+//! Allocators for small integer IDs, like PIDs, IRQ vectors and
+//! capability slots.
+//!
+//! Both allocators have a fixed capacity and store everything inline,
+//! so they can live in `static`s (behind a lock). Neither one does any
+//! synchronization on its own.
+
+mod handles;
+mod id_bitmap;
+
+pub use handles::{Handle, HandleAllocator};
+pub use id_bitmap::IdBitmap;
@@ -13,18 +13,28 @@
 //!
 //! This is the "catch-all" crate for that type of code.
 
-#![no_std]
+#![cfg_attr(not(test), no_std)]
 #![feature(core_intrinsics)]
 #![deny(missing_docs)]
 #![deny(missing_abi)]
 #![deny(clippy::all, clippy::pedantic, clippy::nursery)]
 #![allow(clippy::mod_module_files, clippy::pub_use)]
 
+mod chacha20;
+pub mod collections;
+pub mod ids;
+pub mod mem;
+mod model;
+pub mod ring;
+mod rng;
+mod spin_lazy;
 mod spin_once;
 pub mod sync;
 mod xorshift128p;
 mod xoshiro256ss;
 
+pub use chacha20::ChaCha20Rng;
+pub use spin_lazy::SpinLazy;
 pub use spin_once::SpinOnceCell;
 pub use xorshift128p::Xorshift128Plus;
 pub use xoshiro256ss::Xoshiro256;
@@ -0,0 +1,377 @@
+//======---------------------------------------------------------------======//
+//                                                                           //
+// Copyright 2022-2023 Evan Cox <evanacox00@gmail.com>. All rights reserved. //
+//                                                                           //
+// Use of this source code is governed by a BSD-style license that can be    //
+// found in the LICENSE.txt file at the root of this project, or at the      //
+// following link: https://opensource.org/licenses/BSD-3-Clause              //
+//                                                                           //
+//======---------------------------------------------------------------======//
+
+const BITS: usize = u64::BITS as usize;
+
+/// A bitmap-based allocator for fixed-size frames of memory.
+///
+/// Every frame is tracked by a single bit (`1` meaning "in use"), and frames
+/// are identified purely by their index. Mapping those indices to actual
+/// addresses (and deciding what size a frame is) is up to the user.
+///
+/// Contiguous runs of `2^order` frames can also be allocated, these
+/// are always aligned to `2^order` frames.
+///
+/// The bitmap doesn't own its storage, it's given a slice of words
+/// to work with. This makes it possible to place the bitmap inside
+/// of the memory that it's managing.
+pub struct FrameBitmap<'a> {
+    words: &'a mut [u64],
+    frames: usize,
+    free: usize,
+    // every word before this index is known to be completely full
+    hint: usize,
+}
+
+impl<'a> FrameBitmap<'a> {
+    /// The number of `u64`s of storage needed to track `frames` frames.
+    #[must_use]
+    pub const fn words_needed(frames: usize) -> usize {
+        frames.div_ceil(BITS)
+    }
+
+    /// Creates a bitmap tracking `frames` frames using `words` as storage.
+    ///
+    /// Every frame starts out as "in use", usable frames need to be
+    /// explicitly made available with [`Self::mark_free`].
+    ///
+    /// # Panics
+    /// Panics if `words` isn't at least [`Self::words_needed`] long.
+    pub fn new(words: &'a mut [u64], frames: usize) -> Self {
+        assert!(
+            words.len() >= Self::words_needed(frames),
+            "bitmap storage is too small for {frames} frames"
+        );
+
+        words.fill(u64::MAX);
+
+        Self {
+            words,
+            frames,
+            free: 0,
+            hint: 0,
+        }
+    }
+
+    /// The total number of frames being tracked, free or not.
+    #[must_use]
+    pub const fn frames(&self) -> usize {
+        self.frames
+    }
+
+    /// The number of frames that are currently free.
+    #[must_use]
+    pub const fn free_frames(&self) -> usize {
+        self.free
+    }
+
+    /// The number of frames that are currently in use.
+    #[must_use]
+    pub const fn used_frames(&self) -> usize {
+        self.frames - self.free
+    }
+
+    /// Checks whether a given frame is free.
+    ///
+    /// # Panics
+    /// Panics if `frame` is out of range.
+    #[must_use]
+    pub fn is_free(&self, frame: usize) -> bool {
+        assert!(frame < self.frames, "frame {frame} is out of range");
+
+        self.words[frame / BITS] & (1 << (frame % BITS)) == 0
+    }
+
+    /// Marks every frame in `[start, start + count)` as free. Frames in the
+    /// range that are already free are left alone.
+    ///
+    /// # Panics
+    /// Panics if the range is out of bounds.
+    pub fn mark_free(&mut self, start: usize, count: usize) {
+        self.free += self.fill_range(start, count, false);
+        self.hint = self.hint.min(start / BITS);
+    }
+
+    /// Marks every frame in `[start, start + count)` as in use. Frames in
+    /// the range that are already in use are left alone.
+    ///
+    /// # Panics
+    /// Panics if the range is out of bounds.
+    pub fn mark_used(&mut self, start: usize, count: usize) {
+        self.free -= self.fill_range(start, count, true);
+    }
+
+    /// Allocates a single frame, returning its index.
+    ///
+    /// The lowest free frame is always the one returned.
+    pub fn allocate(&mut self) -> Option<usize> {
+        self.allocate_order(0)
+    }
+
+    /// Allocates `2^order` contiguous frames, returning the index of
+    /// the first one. The index is always a multiple of `2^order`.
+    pub fn allocate_order(&mut self, order: u32) -> Option<usize> {
+        let count = 1usize.checked_shl(order)?;
+
+        if count > self.free {
+            return None;
+        }
+
+        while self.hint < self.words.len() && self.words[self.hint] == u64::MAX {
+            self.hint += 1;
+        }
+
+        let start = self.find_free_run(count)?;
+
+        self.free -= self.fill_range(start, count, true);
+
+        Some(start)
+    }
+
+    /// Frees a single frame previously returned from [`Self::allocate`].
+    ///
+    /// # Panics
+    /// Panics if the frame is out of range or is already free.
+    pub fn free(&mut self, frame: usize) {
+        self.free_order(frame, 0);
+    }
+
+    /// Frees `2^order` frames previously returned from [`Self::allocate_order`].
+    ///
+    /// # Panics
+    /// Panics if any of the frames are out of range or are already free.
+    pub fn free_order(&mut self, frame: usize, order: u32) {
+        let count = 1 << order;
+        let freed = self.fill_range(frame, count, false);
+
+        assert_eq!(freed, count, "double free of frames at {frame}");
+
+        self.free += freed;
+        self.hint = self.hint.min(frame / BITS);
+    }
+
+    fn find_free_run(&self, count: usize) -> Option<usize> {
+        if count <= BITS {
+            let mask = Self::mask(count);
+
+            for (i, &word) in self.words.iter().enumerate().skip(self.hint) {
+                if word == u64::MAX {
+                    continue;
+                }
+
+                // bits past `self.frames` are always set, so any run found here
+                // is guaranteed to be completely in range
+                if let Some(shift) = (0..BITS)
+                    .step_by(count)
+                    .find(|&shift| word & (mask << shift) == 0)
+                {
+                    return Some(i * BITS + shift);
+                }
+            }
+
+            return None;
+        }
+
+        // any run bigger than a word is made up of `count / BITS` completely
+        // empty words, and needs to be aligned to that many words
+        let per_run = count / BITS;
+        let first = self.hint.next_multiple_of(per_run);
+
+        (first..self.words.len())
+            .step_by(per_run)
+            .filter(|&i| (i + per_run) * BITS <= self.frames)
+            .find(|&i| self.words[i..i + per_run].iter().all(|&word| word == 0))
+            .map(|i| i * BITS)
+    }
+
+    // sets (or clears) every bit in the range, returns the number of bits that changed
+    fn fill_range(&mut self, start: usize, count: usize, used: bool) -> usize {
+        assert!(
+            start
+                .checked_add(count)
+                .is_some_and(|end| end <= self.frames),
+            "frames [{start}, {start} + {count}) are out of range"
+        );
+
+        let end = start + count;
+        let mut frame = start;
+        let mut changed = 0;
+
+        while frame < end {
+            let bit = frame % BITS;
+            let n = (BITS - bit).min(end - frame);
+            let mask = Self::mask(n) << bit;
+            let old = self.words[frame / BITS];
+            let new = if used { old | mask } else { old & !mask };
+
+            changed += (old ^ new).count_ones() as usize;
+            self.words[frame / BITS] = new;
+            frame += n;
+        }
+
+        changed
+    }
+
+    const fn mask(n: usize) -> u64 {
+        if n == BITS {
+            u64::MAX
+        } else {
+            (1 << n) - 1
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn starts_fully_used() {
+        let mut words = [0; 4];
+        let mut bitmap = FrameBitmap::new(&mut words, 200);
+
+        assert_eq!(bitmap.frames(), 200);
+        assert_eq!(bitmap.free_frames(), 0);
+        assert_eq!(bitmap.used_frames(), 200);
+        assert_eq!(bitmap.allocate(), None);
+    }
+
+    #[test]
+    fn allocates_lowest_free_frame() {
+        let mut words = [0; 4];
+        let mut bitmap = FrameBitmap::new(&mut words, 200);
+
+        bitmap.mark_free(10, 5);
+        bitmap.mark_free(100, 1);
+
+        assert_eq!(bitmap.free_frames(), 6);
+        assert_eq!(bitmap.allocate(), Some(10));
+        assert_eq!(bitmap.allocate(), Some(11));
+
+        bitmap.free(10);
+
+        assert_eq!(bitmap.allocate(), Some(10));
+        assert_eq!(bitmap.allocate(), Some(12));
+        assert_eq!(bitmap.allocate(), Some(13));
+        assert_eq!(bitmap.allocate(), Some(14));
+        assert_eq!(bitmap.allocate(), Some(100));
+        assert_eq!(bitmap.allocate(), None);
+        assert_eq!(bitmap.free_frames(), 0);
+    }
+
+    #[test]
+    fn marking_is_idempotent() {
+        let mut words = [0; 2];
+        let mut bitmap = FrameBitmap::new(&mut words, 128);
+
+        bitmap.mark_free(0, 128);
+        bitmap.mark_free(60, 10);
+        assert_eq!(bitmap.free_frames(), 128);
+
+        bitmap.mark_used(60, 10);
+        bitmap.mark_used(65, 10);
+        assert_eq!(bitmap.free_frames(), 113);
+        assert!(bitmap.is_free(59));
+        assert!(!bitmap.is_free(60));
+        assert!(!bitmap.is_free(74));
+        assert!(bitmap.is_free(75));
+    }
+
+    #[test]
+    fn never_returns_frames_past_the_end() {
+        let mut words = [0; 1];
+        let mut bitmap = FrameBitmap::new(&mut words, 3);
+
+        bitmap.mark_free(0, 3);
+
+        assert_eq!(bitmap.allocate_order(2), None);
+        assert_eq!(bitmap.allocate_order(1), Some(0));
+        assert_eq!(bitmap.allocate(), Some(2));
+        assert_eq!(bitmap.allocate(), None);
+    }
+
+    #[test]
+    fn runs_are_aligned() {
+        let mut words = [0; 4];
+        let mut bitmap = FrameBitmap::new(&mut words, 256);
+
+        bitmap.mark_free(1, 255);
+
+        assert_eq!(bitmap.allocate_order(3), Some(8));
+        assert_eq!(bitmap.allocate_order(2), Some(4));
+        assert_eq!(bitmap.allocate_order(1), Some(2));
+        assert_eq!(bitmap.allocate(), Some(1));
+        assert_eq!(bitmap.allocate_order(6), Some(64));
+        assert_eq!(bitmap.allocate_order(7), Some(128));
+        assert_eq!(bitmap.allocate_order(7), None);
+        assert_eq!(bitmap.allocate_order(4), Some(16));
+    }
+
+    #[test]
+    fn multi_word_runs() {
+        let mut words = [0; 8];
+        let mut bitmap = FrameBitmap::new(&mut words, 512);
+
+        bitmap.mark_free(0, 512);
+        bitmap.mark_used(130, 1);
+
+        // [0, 256) is broken up by frame 130, so the first run is [256, 512)
+        assert_eq!(bitmap.allocate_order(8), Some(256));
+        assert_eq!(bitmap.allocate_order(8), None);
+        assert_eq!(bitmap.free_frames(), 255);
+
+        bitmap.free_order(256, 8);
+        bitmap.free(130);
+
+        assert_eq!(bitmap.allocate_order(9), Some(0));
+        assert_eq!(bitmap.free_frames(), 0);
+    }
+
+    #[test]
+    fn free_resets_the_search_hint() {
+        let mut words = [0; 4];
+        let mut bitmap = FrameBitmap::new(&mut words, 256);
+
+        bitmap.mark_free(0, 256);
+
+        for expected in 0..256 {
+            assert_eq!(bitmap.allocate(), Some(expected));
+        }
+
+        bitmap.free(3);
+        bitmap.free(200);
+
+        assert_eq!(bitmap.allocate(), Some(3));
+        assert_eq!(bitmap.allocate(), Some(200));
+    }
+
+    #[test]
+    #[should_panic(expected = "double free")]
+    fn double_free_panics() {
+        let mut words = [0; 1];
+        let mut bitmap = FrameBitmap::new(&mut words, 64);
+
+        bitmap.mark_free(0, 64);
+
+        let frame = bitmap.allocate().unwrap();
+
+        bitmap.free(frame);
+        bitmap.free(frame);
+    }
+
+    #[test]
+    #[should_panic(expected = "out of range")]
+    fn out_of_range_panics() {
+        let mut words = [0; 1];
+        let mut bitmap = FrameBitmap::new(&mut words, 10);
+
+        bitmap.mark_free(5, 6);
+    }
+}
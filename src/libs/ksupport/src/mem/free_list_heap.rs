@@ -0,0 +1,342 @@
+//======---------------------------------------------------------------======//
+//                                                                           //
+// Copyright 2022-2023 Evan Cox <evanacox00@gmail.com>. All rights reserved. //
+//                                                                           //
+// Use of this source code is governed by a BSD-style license that can be    //
+// found in the LICENSE.txt file at the root of this project, or at the      //
+// following link: https://opensource.org/licenses/BSD-3-Clause              //
+//                                                                           //
+//======---------------------------------------------------------------======//
+
+use core::alloc::Layout;
+use core::mem;
+use core::ptr::{self, NonNull};
+
+// a free block, these live inside of the free memory they describe
+struct Block {
+    size: usize,
+    next: Option<NonNull<Self>>,
+}
+
+const MIN_BLOCK: usize = mem::size_of::<Block>();
+const BLOCK_ALIGN: usize = mem::align_of::<Block>();
+
+/// A first-fit, address-ordered free list allocator.
+///
+/// The heap doesn't own any memory by itself, regions are given to it with
+/// [`Self::add_region`] and it carves allocations out of those. Every free
+/// block stores its bookkeeping inside of itself, and adjacent free blocks
+/// are merged back together when memory is freed.
+///
+/// This is not thread-safe, it needs to be wrapped in a lock to be used
+/// as a global allocator.
+pub struct FreeListHeap {
+    head: Option<NonNull<Block>>,
+    total: usize,
+    used: usize,
+}
+
+impl FreeListHeap {
+    /// Creates a heap with no memory in it.
+    #[must_use]
+    pub const fn empty() -> Self {
+        Self {
+            head: None,
+            total: 0,
+            used: 0,
+        }
+    }
+
+    /// The total number of bytes the heap manages.
+    #[must_use]
+    pub const fn total_bytes(&self) -> usize {
+        self.total
+    }
+
+    /// The number of bytes handed out to allocations (including any rounding).
+    #[must_use]
+    pub const fn used_bytes(&self) -> usize {
+        self.used
+    }
+
+    /// The number of bytes that are not being used by allocations.
+    #[must_use]
+    pub const fn free_bytes(&self) -> usize {
+        self.total - self.used
+    }
+
+    /// Gives a region of memory to the heap. Bytes at the start and end
+    /// of the region may be ignored to satisfy alignment.
+    ///
+    /// # Safety
+    /// `[start, start + size)` must be valid for reads and writes, must not
+    /// be used by anything else, and must outlive the heap.
+    pub unsafe fn add_region(&mut self, start: *mut u8, size: usize) {
+        let addr = start as usize;
+        let aligned = addr.next_multiple_of(BLOCK_ALIGN);
+        let size = (size.saturating_sub(aligned - addr)) & !(BLOCK_ALIGN - 1);
+
+        if size < MIN_BLOCK {
+            return;
+        }
+
+        self.total += size;
+        self.insert_free(aligned as *mut u8, size);
+    }
+
+    /// Allocates a block of memory fitting `layout`.
+    ///
+    /// Returns `None` if no free block is big enough.
+    pub fn allocate(&mut self, layout: Layout) -> Option<NonNull<u8>> {
+        let size = Self::block_size(layout);
+        let align = layout.align().max(BLOCK_ALIGN);
+        let mut prev: Option<NonNull<Block>> = None;
+        let mut current = self.head;
+
+        while let Some(block) = current {
+            let (block_size, next) = unsafe { (block.as_ref().size, block.as_ref().next) };
+            let start = block.as_ptr() as usize;
+            let end = start + block_size;
+
+            if let Some(alloc) = Self::fit(start, end, size, align) {
+                // unlink the block, then give back whatever's left on either side
+                match prev {
+                    Some(mut prev) => unsafe { prev.as_mut().next = next },
+                    None => self.head = next,
+                }
+
+                unsafe {
+                    if alloc > start {
+                        self.insert_free(start as *mut u8, alloc - start);
+                    }
+
+                    if alloc + size < end {
+                        self.insert_free((alloc + size) as *mut u8, end - alloc - size);
+                    }
+                }
+
+                self.used += size;
+
+                return NonNull::new(alloc as *mut u8);
+            }
+
+            prev = current;
+            current = next;
+        }
+
+        None
+    }
+
+    /// Frees a block of memory previously returned by [`Self::allocate`].
+    ///
+    /// # Safety
+    /// `ptr` must have come from [`Self::allocate`] on this heap with the
+    /// same `layout`, and must not be used after this call.
+    pub unsafe fn deallocate(&mut self, ptr: NonNull<u8>, layout: Layout) {
+        let size = Self::block_size(layout);
+
+        self.used -= size;
+        self.insert_free(ptr.as_ptr(), size);
+    }
+
+    // every allocation is padded so that it can hold a `Block` once freed
+    const fn block_size(layout: Layout) -> usize {
+        let size = if layout.size() < MIN_BLOCK {
+            MIN_BLOCK
+        } else {
+            layout.size()
+        };
+
+        size.next_multiple_of(BLOCK_ALIGN)
+    }
+
+    // finds where in `[start, end)` an allocation could go, making sure that
+    // whatever is left over on either side is either empty or big enough
+    // to be a free block by itself
+    fn fit(start: usize, end: usize, size: usize, align: usize) -> Option<usize> {
+        let mut alloc = start.next_multiple_of(align);
+
+        while alloc > start && alloc - start < MIN_BLOCK {
+            alloc += align;
+        }
+
+        let alloc_end = alloc.checked_add(size)?;
+
+        if alloc_end > end || (alloc_end < end && end - alloc_end < MIN_BLOCK) {
+            return None;
+        }
+
+        Some(alloc)
+    }
+
+    // inserts a free block in address order, merging it with its neighbors
+    unsafe fn insert_free(&mut self, start: *mut u8, size: usize) {
+        let addr = start as usize;
+        let mut prev: Option<NonNull<Block>> = None;
+        let mut next = self.head;
+
+        while let Some(block) = next {
+            if block.as_ptr() as usize > addr {
+                break;
+            }
+
+            prev = next;
+            next = block.as_ref().next;
+        }
+
+        // every free block starts at a multiple of `BLOCK_ALIGN`
+        #[allow(clippy::cast_ptr_alignment)]
+        let mut block = NonNull::new_unchecked(start.cast::<Block>());
+
+        ptr::write(block.as_ptr(), Block { size, next });
+
+        // merge with the block after this one
+        if let Some(after) = next {
+            if addr + size == after.as_ptr() as usize {
+                block.as_mut().size += after.as_ref().size;
+                block.as_mut().next = after.as_ref().next;
+            }
+        }
+
+        // merge with the block before this one
+        match prev {
+            Some(mut before) if before.as_ptr() as usize + before.as_ref().size == addr => {
+                before.as_mut().size += block.as_ref().size;
+                before.as_mut().next = block.as_ref().next;
+            }
+            Some(mut before) => before.as_mut().next = Some(block),
+            None => self.head = Some(block),
+        }
+    }
+}
+
+impl Default for FreeListHeap {
+    fn default() -> Self {
+        Self::empty()
+    }
+}
+
+unsafe impl Send for FreeListHeap {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[repr(align(4096))]
+    struct Arena([u8; 4096]);
+
+    fn layout(size: usize, align: usize) -> Layout {
+        Layout::from_size_align(size, align).unwrap()
+    }
+
+    #[test]
+    fn empty_heap_fails() {
+        let mut heap = FreeListHeap::empty();
+
+        assert_eq!(heap.allocate(layout(8, 8)), None);
+        assert_eq!(heap.total_bytes(), 0);
+    }
+
+    #[test]
+    fn allocations_are_disjoint_and_aligned() {
+        let mut arena = Arena([0; 4096]);
+        let mut heap = FreeListHeap::empty();
+
+        unsafe { heap.add_region(arena.0.as_mut_ptr(), arena.0.len()) };
+
+        let mut ptrs = [(0usize, 0usize); 16];
+
+        for (i, slot) in ptrs.iter_mut().enumerate() {
+            let layout = layout(8 + i * 13, 1 << (i % 7));
+            let ptr = heap.allocate(layout).unwrap().as_ptr() as usize;
+
+            assert_eq!(ptr % layout.align(), 0);
+
+            *slot = (ptr, ptr + layout.size());
+        }
+
+        for (i, a) in ptrs.iter().enumerate() {
+            for b in &ptrs[i + 1..] {
+                assert!(a.1 <= b.0 || b.1 <= a.0, "{a:?} overlaps {b:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn freeing_everything_coalesces() {
+        let mut arena = Arena([0; 4096]);
+        let mut heap = FreeListHeap::empty();
+
+        unsafe { heap.add_region(arena.0.as_mut_ptr(), arena.0.len()) };
+
+        let a = heap.allocate(layout(1000, 8)).unwrap();
+        let b = heap.allocate(layout(1000, 64)).unwrap();
+        let c = heap.allocate(layout(1000, 8)).unwrap();
+
+        assert!(heap.used_bytes() >= 3000);
+
+        unsafe {
+            heap.deallocate(b, layout(1000, 64));
+            heap.deallocate(a, layout(1000, 8));
+            heap.deallocate(c, layout(1000, 8));
+        }
+
+        assert_eq!(heap.used_bytes(), 0);
+        assert_eq!(heap.free_bytes(), 4096);
+
+        // only possible if every block was merged back together
+        assert!(heap.allocate(layout(4096, 8)).is_some());
+    }
+
+    #[test]
+    fn exhaustion_returns_none() {
+        let mut arena = Arena([0; 4096]);
+        let mut heap = FreeListHeap::empty();
+
+        unsafe { heap.add_region(arena.0.as_mut_ptr(), arena.0.len()) };
+
+        assert!(heap.allocate(layout(4097, 8)).is_none());
+
+        let all = heap.allocate(layout(4096, 8)).unwrap();
+
+        assert!(heap.allocate(layout(1, 1)).is_none());
+
+        unsafe { heap.deallocate(all, layout(4096, 8)) };
+
+        assert!(heap.allocate(layout(1, 1)).is_some());
+    }
+
+    #[test]
+    fn multiple_regions() {
+        let mut first = Arena([0; 4096]);
+        let mut second = Arena([0; 4096]);
+        let mut heap = FreeListHeap::empty();
+
+        unsafe {
+            heap.add_region(first.0.as_mut_ptr(), 2048);
+            heap.add_region(second.0.as_mut_ptr(), 4096);
+        }
+
+        assert_eq!(heap.total_bytes(), 6144);
+        assert!(heap.allocate(layout(3000, 8)).is_some());
+        assert!(heap.allocate(layout(2048, 8)).is_some());
+        assert!(heap.allocate(layout(2048, 8)).is_none());
+    }
+
+    #[test]
+    fn large_alignment_leaves_usable_padding() {
+        let mut arena = Arena([0; 4096]);
+        let mut heap = FreeListHeap::empty();
+
+        unsafe { heap.add_region(arena.0.as_mut_ptr().add(16), 4080) };
+
+        let aligned = heap.allocate(layout(64, 1024)).unwrap();
+
+        assert_eq!(aligned.as_ptr() as usize % 1024, 0);
+
+        // the padding before the aligned block is still usable
+        let small = heap.allocate(layout(16, 8)).unwrap();
+
+        assert!((small.as_ptr() as usize) < aligned.as_ptr() as usize);
+    }
+}
@@ -0,0 +1,23 @@
+//======---------------------------------------------------------------======//
+//                                                                           //
+// Copyright 2022-2023 Evan Cox <evanacox00@gmail.com>. All rights reserved. //
+//                                                                           //
+// Use of this source code is governed by a BSD-style license that can be    //
+// found in the LICENSE.txt file at the root of this project, or at the      //
+// following link: https://opensource.org/licenses/BSD-3-Clause              //
+//                                                                           //
+//======---------------------------------------------------------------======//
+
+//! Memory-management building blocks that are independent of any
+//! particular architecture or address space.
+//!
+//! Nothing in here touches memory that it wasn't explicitly given, which
+//! means all of it can be (and is) tested on the host.
+
+mod frame_bitmap;
+mod free_list_heap;
+mod slab;
+
+pub use frame_bitmap::FrameBitmap;
+pub use free_list_heap::FreeListHeap;
+pub use slab::{SlabCache, SlabSource, SlabStats};
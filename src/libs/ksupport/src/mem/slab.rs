@@ -0,0 +1,473 @@
+//======---------------------------------------------------------------======//
+//                                                                           //
+// Copyright 2022-2023 Evan Cox <evanacox00@gmail.com>. All rights reserved. //
+//                                                                           //
+// Use of this source code is governed by a BSD-style license that can be    //
+// found in the LICENSE.txt file at the root of this project, or at the      //
+// following link: https://opensource.org/licenses/BSD-3-Clause              //
+//                                                                           //
+//======---------------------------------------------------------------======//
+
+use core::alloc::Layout;
+use core::mem;
+use core::ptr::NonNull;
+
+/// Provides the memory that a [`SlabCache`] carves objects out of.
+///
+/// # Safety
+/// [`Self::allocate_slab`] must return memory that is valid for reads and
+/// writes of `size` bytes, aligned to `size`, and not used by anything else
+/// until it's given back through [`Self::free_slab`].
+pub unsafe trait SlabSource {
+    /// Allocates a slab of `size` bytes, aligned to `size`. `size` is
+    /// always a power of two that is at least 4 KiB.
+    fn allocate_slab(&self, size: usize) -> Option<NonNull<u8>>;
+
+    /// Gives back a slab previously returned by [`Self::allocate_slab`].
+    ///
+    /// # Safety
+    /// `slab` must have come from [`Self::allocate_slab`] with the same `size`.
+    unsafe fn free_slab(&self, slab: NonNull<u8>, size: usize);
+}
+
+/// A snapshot of a [`SlabCache`]'s counters.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct SlabStats {
+    /// The size of each object (after padding), in bytes.
+    pub object_size: usize,
+    /// The size of each slab, in bytes.
+    pub slab_size: usize,
+    /// How many objects fit in each slab.
+    pub objects_per_slab: usize,
+    /// The number of objects currently allocated.
+    pub objects_in_use: usize,
+    /// The number of slabs currently allocated.
+    pub slabs: usize,
+    /// The number of bytes in allocated slabs that can never hold an
+    /// object or that are lost to padding in allocated objects.
+    pub waste: usize,
+}
+
+// lives at the very start of each slab
+struct SlabHeader {
+    next: Option<NonNull<Self>>,
+    prev: Option<NonNull<Self>>,
+    free: Option<NonNull<FreeObject>>,
+    in_use: usize,
+}
+
+// lives inside of each free object
+struct FreeObject {
+    next: Option<NonNull<Self>>,
+}
+
+// a doubly-linked list of slabs, threaded through their headers
+struct SlabList {
+    head: Option<NonNull<SlabHeader>>,
+    len: usize,
+}
+
+impl SlabList {
+    const fn new() -> Self {
+        Self { head: None, len: 0 }
+    }
+
+    const unsafe fn push(&mut self, mut slab: NonNull<SlabHeader>) {
+        slab.as_mut().prev = None;
+        slab.as_mut().next = self.head;
+
+        if let Some(mut head) = self.head {
+            head.as_mut().prev = Some(slab);
+        }
+
+        self.head = Some(slab);
+        self.len += 1;
+    }
+
+    const unsafe fn remove(&mut self, slab: NonNull<SlabHeader>) {
+        let (prev, next) = (slab.as_ref().prev, slab.as_ref().next);
+
+        match prev {
+            Some(mut prev) => prev.as_mut().next = next,
+            None => self.head = next,
+        }
+
+        if let Some(mut next) = next {
+            next.as_mut().prev = prev;
+        }
+
+        self.len -= 1;
+    }
+}
+
+/// A cache of fixed-size objects, allocated out of larger "slabs" of memory.
+///
+/// Each slab is split up into equally-sized objects, and slabs are tracked
+/// as being full, partially full or empty. Allocations always come out of
+/// partially full slabs first to keep memory usage dense, and at most one
+/// completely empty slab is kept around before slabs are given back to
+/// the [`SlabSource`].
+///
+/// Slabs are aligned to their size, so the slab an object belongs to can
+/// be found by just masking off the low bits of its address.
+///
+/// This is not thread-safe, it needs to be wrapped in a lock to be shared.
+pub struct SlabCache<S: SlabSource> {
+    source: S,
+    requested_size: usize,
+    object_size: usize,
+    slab_size: usize,
+    first_object: usize,
+    objects_per_slab: usize,
+    partial: SlabList,
+    full: SlabList,
+    empty: SlabList,
+    in_use: usize,
+}
+
+impl<S: SlabSource> SlabCache<S> {
+    const MIN_OBJECTS_PER_SLAB: usize = 8;
+    const MIN_SLAB_SIZE: usize = 4096;
+    const MAX_EMPTY_SLABS: usize = 1;
+
+    /// Creates a cache for objects with a given layout, getting slabs from `source`.
+    ///
+    /// No memory is allocated until the first object is.
+    ///
+    /// # Panics
+    /// Panics if `layout` is aligned to more than 4 KiB.
+    #[must_use]
+    pub const fn new(layout: Layout, source: S) -> Self {
+        assert!(
+            layout.align() <= Self::MIN_SLAB_SIZE,
+            "objects cannot be aligned to more than 4 KiB"
+        );
+
+        let align = if layout.align() > mem::align_of::<FreeObject>() {
+            layout.align()
+        } else {
+            mem::align_of::<FreeObject>()
+        };
+
+        let size = if layout.size() > mem::size_of::<FreeObject>() {
+            layout.size()
+        } else {
+            mem::size_of::<FreeObject>()
+        };
+
+        let object_size = size.next_multiple_of(align);
+        let first_object = mem::size_of::<SlabHeader>().next_multiple_of(align);
+        let mut slab_size = Self::MIN_SLAB_SIZE;
+
+        while (slab_size - first_object) / object_size < Self::MIN_OBJECTS_PER_SLAB {
+            slab_size *= 2;
+        }
+
+        Self {
+            source,
+            requested_size: layout.size(),
+            object_size,
+            slab_size,
+            first_object,
+            objects_per_slab: (slab_size - first_object) / object_size,
+            partial: SlabList::new(),
+            full: SlabList::new(),
+            empty: SlabList::new(),
+            in_use: 0,
+        }
+    }
+
+    /// Allocates a single object. The contents of the object are unspecified.
+    ///
+    /// Returns `None` if a new slab was needed and the source couldn't provide one.
+    ///
+    /// # Panics
+    /// Panics if the cache's bookkeeping has been corrupted, e.g. by a double free.
+    pub fn allocate(&mut self) -> Option<NonNull<u8>> {
+        unsafe {
+            let mut slab = match (self.partial.head, self.empty.head) {
+                (Some(slab), _) => slab,
+                (None, Some(slab)) => {
+                    self.empty.remove(slab);
+                    self.partial.push(slab);
+
+                    slab
+                }
+                (None, None) => {
+                    let slab = self.new_slab()?;
+
+                    self.partial.push(slab);
+
+                    slab
+                }
+            };
+
+            let header = slab.as_mut();
+            let object = header.free.expect("partial slab should have a free object");
+
+            header.free = object.as_ref().next;
+            header.in_use += 1;
+
+            if header.in_use == self.objects_per_slab {
+                self.partial.remove(slab);
+                self.full.push(slab);
+            }
+
+            self.in_use += 1;
+
+            Some(object.cast())
+        }
+    }
+
+    /// Frees an object previously returned from [`Self::allocate`].
+    ///
+    /// # Safety
+    /// `object` must have been allocated from this cache, and must not be
+    /// used after this call.
+    pub unsafe fn deallocate(&mut self, object: NonNull<u8>) {
+        let mut slab = self.slab_of(object);
+        let header = slab.as_mut();
+        let was_full = header.in_use == self.objects_per_slab;
+        let mut free = object.cast::<FreeObject>();
+
+        free.as_mut().next = header.free;
+        header.free = Some(free);
+        header.in_use -= 1;
+        self.in_use -= 1;
+
+        if was_full {
+            self.full.remove(slab);
+            self.partial.push(slab);
+        }
+
+        if slab.as_ref().in_use == 0 {
+            self.partial.remove(slab);
+
+            if self.empty.len < Self::MAX_EMPTY_SLABS {
+                self.empty.push(slab);
+            } else {
+                self.source.free_slab(slab.cast(), self.slab_size);
+            }
+        }
+    }
+
+    /// Gives every completely empty slab back to the source, returning
+    /// how many were freed.
+    pub fn shrink(&mut self) -> usize {
+        let mut freed = 0;
+
+        while let Some(slab) = self.empty.head {
+            unsafe {
+                self.empty.remove(slab);
+                self.source.free_slab(slab.cast(), self.slab_size);
+            }
+
+            freed += 1;
+        }
+
+        freed
+    }
+
+    /// Gets the current counters for the cache.
+    #[must_use]
+    pub const fn stats(&self) -> SlabStats {
+        let slabs = self.partial.len + self.full.len + self.empty.len;
+        let unusable = self.slab_size - self.objects_per_slab * self.object_size;
+
+        SlabStats {
+            object_size: self.object_size,
+            slab_size: self.slab_size,
+            objects_per_slab: self.objects_per_slab,
+            objects_in_use: self.in_use,
+            slabs,
+            waste: slabs * unusable + self.in_use * (self.object_size - self.requested_size),
+        }
+    }
+
+    /// Gets the source that slabs are allocated from.
+    #[must_use]
+    pub const fn source(&self) -> &S {
+        &self.source
+    }
+
+    unsafe fn new_slab(&mut self) -> Option<NonNull<SlabHeader>> {
+        let base = self.source.allocate_slab(self.slab_size)?;
+        let mut free = None;
+
+        // build the free list backwards so that objects get handed out in address order
+        for i in (0..self.objects_per_slab).rev() {
+            let mut object = base
+                .add(self.first_object + i * self.object_size)
+                .cast::<FreeObject>();
+
+            object.as_mut().next = free;
+            free = Some(object);
+        }
+
+        let header = base.cast::<SlabHeader>();
+
+        header.write(SlabHeader {
+            next: None,
+            prev: None,
+            free,
+            in_use: 0,
+        });
+
+        Some(header)
+    }
+
+    fn slab_of(&self, object: NonNull<u8>) -> NonNull<SlabHeader> {
+        let addr = object.as_ptr() as usize & !(self.slab_size - 1);
+
+        // the address came from a non-null object, so the slab is non-null too
+        unsafe { NonNull::new_unchecked(addr as *mut SlabHeader) }
+    }
+}
+
+// slabs that still have live objects in them are leaked, since
+// those objects may still be referenced
+impl<S: SlabSource> Drop for SlabCache<S> {
+    fn drop(&mut self) {
+        self.shrink();
+    }
+}
+
+// every slab is owned by the cache, nothing else refers to them
+#[allow(clippy::non_send_fields_in_send_ty)]
+unsafe impl<S: SlabSource + Send> Send for SlabCache<S> {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::alloc;
+    use std::cell::Cell;
+    use std::collections::HashSet;
+    use std::vec::Vec;
+
+    #[derive(Default)]
+    struct HostSource {
+        live: Cell<usize>,
+    }
+
+    unsafe impl SlabSource for HostSource {
+        fn allocate_slab(&self, size: usize) -> Option<NonNull<u8>> {
+            self.live.set(self.live.get() + 1);
+
+            NonNull::new(unsafe { alloc::alloc(Layout::from_size_align(size, size).unwrap()) })
+        }
+
+        unsafe fn free_slab(&self, slab: NonNull<u8>, size: usize) {
+            self.live.set(self.live.get() - 1);
+
+            alloc::dealloc(slab.as_ptr(), Layout::from_size_align(size, size).unwrap());
+        }
+    }
+
+    fn cache<T>() -> SlabCache<HostSource> {
+        SlabCache::new(Layout::new::<T>(), HostSource::default())
+    }
+
+    #[test]
+    fn objects_are_distinct_and_aligned() {
+        let mut cache = cache::<[u64; 5]>();
+        let mut seen = HashSet::new();
+
+        for _ in 0..1000 {
+            let object = cache.allocate().unwrap();
+
+            assert_eq!(object.as_ptr() as usize % 8, 0);
+            assert!(seen.insert(object.as_ptr() as usize));
+
+            unsafe { object.cast::<[u64; 5]>().write([0xAA; 5]) };
+        }
+
+        assert_eq!(cache.stats().objects_in_use, 1000);
+    }
+
+    #[test]
+    fn tiny_objects_are_padded() {
+        let cache = cache::<u8>();
+        let stats = cache.stats();
+
+        assert_eq!(stats.object_size, mem::size_of::<usize>());
+        assert_eq!(stats.slab_size, 4096);
+    }
+
+    #[test]
+    fn big_objects_get_bigger_slabs() {
+        let cache = cache::<[u8; 2000]>();
+        let stats = cache.stats();
+
+        assert!(stats.objects_per_slab >= 8);
+        assert_eq!(stats.slab_size, 16384);
+    }
+
+    #[test]
+    fn slabs_are_returned_when_empty() {
+        let mut cache = cache::<[u64; 8]>();
+        let per_slab = cache.stats().objects_per_slab;
+        let objects: Vec<_> = (0..per_slab * 3)
+            .map(|_| cache.allocate().unwrap())
+            .collect();
+
+        assert_eq!(cache.stats().slabs, 3);
+        assert_eq!(cache.source().live.get(), 3);
+
+        for object in objects {
+            unsafe { cache.deallocate(object) };
+        }
+
+        // one empty slab is kept around
+        assert_eq!(cache.stats().slabs, 1);
+        assert_eq!(cache.stats().objects_in_use, 0);
+        assert_eq!(cache.source().live.get(), 1);
+        assert_eq!(cache.shrink(), 1);
+        assert_eq!(cache.source().live.get(), 0);
+    }
+
+    #[test]
+    fn freed_objects_are_reused() {
+        let mut cache = cache::<u64>();
+        let a = cache.allocate().unwrap();
+        let b = cache.allocate().unwrap();
+
+        unsafe { cache.deallocate(a) };
+
+        assert_eq!(cache.allocate(), Some(a));
+        assert_ne!(cache.allocate(), Some(b));
+    }
+
+    #[test]
+    fn waste_accounts_for_padding() {
+        let mut cache = SlabCache::new(
+            Layout::from_size_align(12, 8).unwrap(),
+            HostSource::default(),
+        );
+
+        let object = cache.allocate().unwrap();
+        let stats = cache.stats();
+        let per_slab = stats.slab_size - stats.objects_per_slab * stats.object_size;
+
+        assert_eq!(stats.object_size, 16);
+        assert_eq!(stats.waste, per_slab + 4);
+
+        unsafe { cache.deallocate(object) };
+    }
+
+    #[test]
+    fn allocation_failure_is_reported() {
+        struct NoMemory;
+
+        unsafe impl SlabSource for NoMemory {
+            fn allocate_slab(&self, _: usize) -> Option<NonNull<u8>> {
+                None
+            }
+
+            unsafe fn free_slab(&self, _: NonNull<u8>, _: usize) {}
+        }
+
+        let mut cache = SlabCache::new(Layout::new::<u64>(), NoMemory);
+
+        assert_eq!(cache.allocate(), None);
+    }
+}
@@ -0,0 +1,84 @@
+//======---------------------------------------------------------------======//
+//                                                                           //
+// Copyright 2022-2023 Evan Cox <evanacox00@gmail.com>. All rights reserved. //
+//                                                                           //
+// Use of this source code is governed by a BSD-style license that can be    //
+// found in the LICENSE.txt file at the root of this project, or at the      //
+// following link: https://opensource.org/licenses/BSD-3-Clause              //
+//                                                                           //
+//======---------------------------------------------------------------======//
+
+//! Indirection that lets the synchronization primitives be model checked.
+//!
+//! Normally this just re-exports `core`. When built with `--cfg loom`,
+//! the atomics, spin hints and `UnsafeCell` are replaced with the ones
+//! from [loom](https://docs.rs/loom), which explore every interleaving
+//! and check that memory orderings are strong enough.
+//!
+//! Loom's types can't be created in a `const` context, so constructors
+//! that have to be `const` in the kernel use [`const_fn_unless_loom!`].
+
+#[cfg(loom)]
+pub use loom::cell::UnsafeCell;
+#[cfg(loom)]
+pub use loom::{hint, sync::atomic};
+
+#[cfg(not(loom))]
+pub use core::{hint, sync::atomic};
+
+/// A `core::cell::UnsafeCell` with loom's closure-based API, so that
+/// both can be used the same way.
+#[cfg(not(loom))]
+#[repr(transparent)]
+pub struct UnsafeCell<T>(core::cell::UnsafeCell<T>);
+
+#[cfg(not(loom))]
+impl<T> UnsafeCell<T> {
+    /// Wraps `value`.
+    #[inline]
+    pub const fn new(value: T) -> Self {
+        Self(core::cell::UnsafeCell::new(value))
+    }
+
+    /// Unwraps the value.
+    #[inline]
+    pub fn into_inner(self) -> T {
+        self.0.into_inner()
+    }
+
+    /// Calls `f` with an immutable pointer to the value.
+    #[inline]
+    pub fn with<R>(&self, f: impl FnOnce(*const T) -> R) -> R {
+        f(self.0.get())
+    }
+
+    /// Calls `f` with a mutable pointer to the value.
+    #[inline]
+    pub fn with_mut<R>(&self, f: impl FnOnce(*mut T) -> R) -> R {
+        f(self.0.get())
+    }
+}
+
+/// Declares a function that is `const`, except when it's built for loom.
+macro_rules! const_fn_unless_loom {
+    ($(#[$attr:meta])* $vis:vis const fn $name:ident($($args:tt)*) -> $ret:ty $body:block) => {
+        #[cfg(not(loom))]
+        $(#[$attr])*
+        $vis const fn $name($($args)*) -> $ret $body
+
+        #[cfg(loom)]
+        $(#[$attr])*
+        $vis fn $name($($args)*) -> $ret $body
+    };
+    ($(#[$attr:meta])* $vis:vis const unsafe fn $name:ident($($args:tt)*) -> $ret:ty $body:block) => {
+        #[cfg(not(loom))]
+        $(#[$attr])*
+        $vis const unsafe fn $name($($args)*) -> $ret $body
+
+        #[cfg(loom)]
+        $(#[$attr])*
+        $vis unsafe fn $name($($args)*) -> $ret $body
+    };
+}
+
+pub(crate) use const_fn_unless_loom;
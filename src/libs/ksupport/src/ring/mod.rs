@@ -0,0 +1,32 @@
+//======---------------------------------------------------------------======//
+//                                                                           //
+// Copyright 2022-2023 Evan Cox <evanacox00@gmail.com>. All rights reserved. //
+//                                                                           //
+// Use of this source code is governed by a BSD-style license that can be    //
+// found in the LICENSE.txt file at the root of this project, or at the      //
+// following link: https://opensource.org/licenses/BSD-3-Clause              //
+//                                                                           //
+//======---------------------------------------------------------------======//
+
+//! Fixed-capacity, allocation-free ring buffers for handing values
+//! between threads (or from interrupt handlers to normal context)
+//! without taking a lock.
+//!
+//! The capacity is a const generic and has to be a power of two. Rings
+//! can be created in a `const` context, so they can live in `static`s.
+//! Pushing to a full ring fails and gives the value back.
+
+mod mpsc;
+mod spsc;
+
+pub use mpsc::{MpscConsumer, MpscRing};
+pub use spsc::{SpscConsumer, SpscProducer, SpscRing};
+
+use crate::model::atomic::{AtomicBool, Ordering};
+
+// claims a role (e.g. "the consumer") that only one handle can have at a time
+#[inline]
+fn claim(flag: &AtomicBool) -> bool {
+    flag.compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed)
+        .is_ok()
+}
@@ -0,0 +1,297 @@
+//======---------------------------------------------------------------======//
+//                                                                           //
+// Copyright 2022-2023 Evan Cox <evanacox00@gmail.com>. All rights reserved. //
+//                                                                           //
+// Use of this source code is governed by a BSD-style license that can be    //
+// found in the LICENSE.txt file at the root of this project, or at the      //
+// following link: https://opensource.org/licenses/BSD-3-Clause              //
+//                                                                           //
+//======---------------------------------------------------------------======//
+
+use crate::model::atomic::{AtomicBool, AtomicUsize, Ordering};
+use crate::model::{const_fn_unless_loom, hint, UnsafeCell};
+use core::mem::MaybeUninit;
+
+// every slot has a sequence number saying what it's ready for. for the
+// position `pos` that maps to a slot, with `lap = pos - pos % N`:
+//
+//   seq == lap      the slot is empty, and can be written for `pos`
+//   seq == lap + 1  the slot was written for `pos`, and can be read
+//
+// reading it sets `seq` to `lap + N`, i.e. empty for the next lap. this
+// is Vyukov's bounded queue, just offset so that every slot starts at `0`.
+// with `N == 1` "written" and "empty for the next lap" are the same, so
+// there have to be at least two slots
+struct Slot<T> {
+    seq: AtomicUsize,
+    value: UnsafeCell<MaybeUninit<T>>,
+}
+
+impl<T> Slot<T> {
+    const_fn_unless_loom! {
+        const fn new() -> Self {
+            Self {
+                seq: AtomicUsize::new(0),
+                value: UnsafeCell::new(MaybeUninit::uninit()),
+            }
+        }
+    }
+}
+
+#[cfg(not(loom))]
+const fn slots<T, const N: usize>() -> [Slot<T>; N] {
+    [const { Slot::new() }; N]
+}
+
+#[cfg(loom)]
+fn slots<T, const N: usize>() -> [Slot<T>; N] {
+    core::array::from_fn(|_| Slot::new())
+}
+
+/// A multi-producer, single-consumer ring buffer that holds up to `N`
+/// values.
+///
+/// Anything can [`push`](Self::push), but only one [`MpscConsumer`] can
+/// exist at a time, it's claimed at runtime with [`consumer`](Self::consumer).
+///
+/// Producers only contend with each other over claiming a slot, never
+/// while writing to it. A producer that's interrupted between claiming
+/// and writing a slot holds up the consumer (but not other producers)
+/// until it finishes.
+pub struct MpscRing<T, const N: usize> {
+    slots: [Slot<T>; N],
+    head: AtomicUsize,
+    tail: AtomicUsize,
+    consumer: AtomicBool,
+}
+
+impl<T, const N: usize> MpscRing<T, N> {
+    const_fn_unless_loom! {
+        /// Creates an empty ring.
+        ///
+        /// # Panics
+        /// Panics (at compile time, if used in a `const`) if `N` isn't
+        /// a power of two, or is less than two.
+        pub const fn new() -> Self {
+            assert!(N.is_power_of_two(), "ring capacity must be a power of two");
+            assert!(N >= 2, "ring needs at least two slots");
+
+            Self {
+                slots: slots(),
+                head: AtomicUsize::new(0),
+                tail: AtomicUsize::new(0),
+                consumer: AtomicBool::new(false),
+            }
+        }
+    }
+
+    /// Pushes `value` onto the ring, or gives it back if the ring is full.
+    ///
+    /// # Errors
+    /// Returns `Err(value)` if the ring is full.
+    #[allow(clippy::cast_possible_wrap)]
+    pub fn push(&self, value: T) -> Result<(), T> {
+        let mut pos = self.tail.load(Ordering::Relaxed);
+
+        loop {
+            let slot = &self.slots[pos % N];
+            let lap = pos - pos % N;
+            let seq = slot.seq.load(Ordering::Acquire);
+
+            // wrapping, so that this still works once the positions wrap
+            match (seq.wrapping_sub(lap) as isize).signum() {
+                0 => {
+                    if let Err(current) = self.tail.compare_exchange_weak(
+                        pos,
+                        pos.wrapping_add(1),
+                        Ordering::Relaxed,
+                        Ordering::Relaxed,
+                    ) {
+                        pos = current;
+
+                        continue;
+                    }
+
+                    slot.value.with_mut(|v| unsafe { (*v).write(value) });
+                    slot.seq.store(lap.wrapping_add(1), Ordering::Release);
+
+                    return Ok(());
+                }
+                // the slot still has last lap's value in it
+                -1 => return Err(value),
+                // somebody else already claimed `pos`
+                _ => {
+                    hint::spin_loop();
+
+                    pos = self.tail.load(Ordering::Relaxed);
+                }
+            }
+        }
+    }
+
+    /// Claims the consumer side of the ring, returning `None` if there's
+    /// already a consumer.
+    pub fn consumer(&self) -> Option<MpscConsumer<'_, T, N>> {
+        super::claim(&self.consumer).then_some(MpscConsumer { ring: self })
+    }
+
+    /// The maximum number of values that the ring can hold.
+    pub const fn capacity(&self) -> usize {
+        N
+    }
+}
+
+impl<T, const N: usize> Default for MpscRing<T, N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T, const N: usize> Drop for MpscRing<T, N> {
+    fn drop(&mut self) {
+        // nothing else can have a handle while we're being dropped
+        if let Some(mut consumer) = self.consumer() {
+            while consumer.pop().is_some() {}
+        }
+    }
+}
+
+unsafe impl<T: Send, const N: usize> Send for MpscRing<T, N> {}
+
+unsafe impl<T: Send, const N: usize> Sync for MpscRing<T, N> {}
+
+/// The consumer side of an [`MpscRing`].
+pub struct MpscConsumer<'a, T, const N: usize> {
+    ring: &'a MpscRing<T, N>,
+}
+
+impl<T, const N: usize> MpscConsumer<'_, T, N> {
+    /// Pops the oldest value off of the ring, if there is one.
+    ///
+    /// This also returns `None` if the oldest value's producer hasn't
+    /// finished writing it yet.
+    pub fn pop(&mut self) -> Option<T> {
+        let ring = self.ring;
+        let pos = ring.head.load(Ordering::Relaxed);
+        let slot = &ring.slots[pos % N];
+        let lap = pos - pos % N;
+
+        if slot.seq.load(Ordering::Acquire) != lap.wrapping_add(1) {
+            return None;
+        }
+
+        let value = slot.value.with(|v| unsafe { (*v).assume_init_read() });
+
+        slot.seq.store(lap.wrapping_add(N), Ordering::Release);
+        ring.head.store(pos.wrapping_add(1), Ordering::Relaxed);
+
+        Some(value)
+    }
+}
+
+impl<T, const N: usize> Drop for MpscConsumer<'_, T, N> {
+    fn drop(&mut self) {
+        self.ring.consumer.store(false, Ordering::Release);
+    }
+}
+
+#[cfg(all(test, not(loom)))]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use std::thread;
+
+    #[test]
+    fn fifo_order() {
+        let ring = MpscRing::<i32, 4>::new();
+        let mut consumer = ring.consumer().unwrap();
+
+        for i in 0..4 {
+            ring.push(i).unwrap();
+        }
+
+        assert_eq!(ring.push(4), Err(4));
+
+        for i in 0..4 {
+            assert_eq!(consumer.pop(), Some(i));
+        }
+
+        assert_eq!(consumer.pop(), None);
+
+        // the slots are reusable on the next lap
+        ring.push(5).unwrap();
+
+        assert_eq!(consumer.pop(), Some(5));
+    }
+
+    #[test]
+    #[should_panic(expected = "at least two slots")]
+    fn one_slot_is_rejected() {
+        // a second push would see the first value's slot as empty and
+        // overwrite it before it's read
+        let ring = MpscRing::<i32, 1>::new();
+
+        ring.push(1).unwrap();
+
+        assert_eq!(ring.push(2), Err(2));
+    }
+
+    #[test]
+    fn only_one_consumer() {
+        let ring = MpscRing::<i32, 2>::new();
+        let consumer = ring.consumer().unwrap();
+
+        assert!(ring.consumer().is_none());
+
+        drop(consumer);
+
+        assert!(ring.consumer().is_some());
+    }
+
+    #[test]
+    fn drops_leftovers() {
+        let value = Arc::new(());
+        let ring = MpscRing::<_, 4>::new();
+
+        ring.push(Arc::clone(&value)).unwrap();
+        drop(ring);
+
+        assert_eq!(Arc::strong_count(&value), 1);
+    }
+
+    #[test]
+    fn many_producers() {
+        static RING: MpscRing<(usize, usize), 16> = MpscRing::new();
+
+        let producers: Vec<_> = (0..4)
+            .map(|id| {
+                thread::spawn(move || {
+                    for i in 0..1000 {
+                        while RING.push((id, i)).is_err() {
+                            thread::yield_now();
+                        }
+                    }
+                })
+            })
+            .collect();
+
+        // every producer's values come out in the order they went in
+        let mut next = [0; 4];
+        let mut consumer = RING.consumer().unwrap();
+
+        while next.iter().any(|&n| n < 1000) {
+            match consumer.pop() {
+                Some((id, i)) => {
+                    assert_eq!(i, next[id]);
+
+                    next[id] += 1;
+                }
+                None => thread::yield_now(),
+            }
+        }
+
+        for producer in producers {
+            producer.join().unwrap();
+        }
+    }
+}
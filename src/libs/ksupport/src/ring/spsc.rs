@@ -0,0 +1,274 @@
+//======---------------------------------------------------------------======//
+//                                                                           //
+// Copyright 2022-2023 Evan Cox <evanacox00@gmail.com>. All rights reserved. //
+//                                                                           //
+// Use of this source code is governed by a BSD-style license that can be    //
+// found in the LICENSE.txt file at the root of this project, or at the      //
+// following link: https://opensource.org/licenses/BSD-3-Clause              //
+//                                                                           //
+//======---------------------------------------------------------------======//
+
+use crate::model::atomic::{AtomicBool, AtomicUsize, Ordering};
+use crate::model::{const_fn_unless_loom, UnsafeCell};
+use core::mem::MaybeUninit;
+
+/// A single-producer, single-consumer ring buffer that holds up to `N`
+/// values.
+///
+/// Only one [`SpscProducer`] and one [`SpscConsumer`] can exist at a time,
+/// they're claimed at runtime with [`producer`](Self::producer) and
+/// [`consumer`](Self::consumer). Neither side ever waits for the other.
+pub struct SpscRing<T, const N: usize> {
+    slots: [UnsafeCell<MaybeUninit<T>>; N],
+    // both of these only ever increase (and wrap), `tail - head` is the length
+    head: AtomicUsize,
+    tail: AtomicUsize,
+    producer: AtomicBool,
+    consumer: AtomicBool,
+}
+
+#[cfg(not(loom))]
+const fn slots<T, const N: usize>() -> [UnsafeCell<MaybeUninit<T>>; N] {
+    [const { UnsafeCell::new(MaybeUninit::uninit()) }; N]
+}
+
+#[cfg(loom)]
+fn slots<T, const N: usize>() -> [UnsafeCell<MaybeUninit<T>>; N] {
+    core::array::from_fn(|_| UnsafeCell::new(MaybeUninit::uninit()))
+}
+
+impl<T, const N: usize> SpscRing<T, N> {
+    const_fn_unless_loom! {
+        /// Creates an empty ring.
+        ///
+        /// # Panics
+        /// Panics (at compile time, if used in a `const`) if `N` isn't
+        /// a power of two.
+        pub const fn new() -> Self {
+            assert!(N.is_power_of_two(), "ring capacity must be a power of two");
+
+            Self {
+                slots: slots(),
+                head: AtomicUsize::new(0),
+                tail: AtomicUsize::new(0),
+                producer: AtomicBool::new(false),
+                consumer: AtomicBool::new(false),
+            }
+        }
+    }
+
+    /// Claims the producer side of the ring, returning `None` if there's
+    /// already a producer.
+    pub fn producer(&self) -> Option<SpscProducer<'_, T, N>> {
+        super::claim(&self.producer).then_some(SpscProducer { ring: self })
+    }
+
+    /// Claims the consumer side of the ring, returning `None` if there's
+    /// already a consumer.
+    pub fn consumer(&self) -> Option<SpscConsumer<'_, T, N>> {
+        super::claim(&self.consumer).then_some(SpscConsumer { ring: self })
+    }
+
+    /// The number of values in the ring. This is only a snapshot if
+    /// something is pushing or popping at the same time.
+    pub fn len(&self) -> usize {
+        let head = self.head.load(Ordering::Acquire);
+
+        self.tail.load(Ordering::Acquire).wrapping_sub(head)
+    }
+
+    /// Checks whether the ring is empty, see [`len`](Self::len).
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The maximum number of values that the ring can hold.
+    pub const fn capacity(&self) -> usize {
+        N
+    }
+}
+
+impl<T, const N: usize> Default for SpscRing<T, N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T, const N: usize> Drop for SpscRing<T, N> {
+    fn drop(&mut self) {
+        // nothing else can have a handle while we're being dropped
+        if let Some(mut consumer) = self.consumer() {
+            while consumer.pop().is_some() {}
+        }
+    }
+}
+
+unsafe impl<T: Send, const N: usize> Send for SpscRing<T, N> {}
+
+unsafe impl<T: Send, const N: usize> Sync for SpscRing<T, N> {}
+
+/// The producer side of an [`SpscRing`].
+pub struct SpscProducer<'a, T, const N: usize> {
+    ring: &'a SpscRing<T, N>,
+}
+
+impl<T, const N: usize> SpscProducer<'_, T, N> {
+    /// Pushes `value` onto the ring, or gives it back if the ring is full.
+    ///
+    /// # Errors
+    /// Returns `Err(value)` if the ring is full.
+    pub fn push(&mut self, value: T) -> Result<(), T> {
+        let ring = self.ring;
+        let tail = ring.tail.load(Ordering::Relaxed);
+
+        // the consumer is done reading the slot once it bumps `head`
+        if tail.wrapping_sub(ring.head.load(Ordering::Acquire)) == N {
+            return Err(value);
+        }
+
+        ring.slots[tail % N].with_mut(|slot| unsafe { (*slot).write(value) });
+        ring.tail.store(tail.wrapping_add(1), Ordering::Release);
+
+        Ok(())
+    }
+
+    /// Checks whether the ring is full.
+    #[must_use]
+    pub fn is_full(&self) -> bool {
+        self.ring.len() == N
+    }
+}
+
+impl<T, const N: usize> Drop for SpscProducer<'_, T, N> {
+    fn drop(&mut self) {
+        self.ring.producer.store(false, Ordering::Release);
+    }
+}
+
+/// The consumer side of an [`SpscRing`].
+pub struct SpscConsumer<'a, T, const N: usize> {
+    ring: &'a SpscRing<T, N>,
+}
+
+impl<T, const N: usize> SpscConsumer<'_, T, N> {
+    /// Pops the oldest value off of the ring, if there is one.
+    pub fn pop(&mut self) -> Option<T> {
+        let ring = self.ring;
+        let head = ring.head.load(Ordering::Relaxed);
+
+        // the producer is done writing the slot once it bumps `tail`
+        if head == ring.tail.load(Ordering::Acquire) {
+            return None;
+        }
+
+        let value = ring.slots[head % N].with(|slot| unsafe { (*slot).assume_init_read() });
+
+        ring.head.store(head.wrapping_add(1), Ordering::Release);
+
+        Some(value)
+    }
+}
+
+impl<T, const N: usize> Drop for SpscConsumer<'_, T, N> {
+    fn drop(&mut self) {
+        self.ring.consumer.store(false, Ordering::Release);
+    }
+}
+
+#[cfg(all(test, not(loom)))]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use std::thread;
+
+    #[test]
+    fn fifo_order() {
+        let ring = SpscRing::<i32, 4>::new();
+        let mut producer = ring.producer().unwrap();
+        let mut consumer = ring.consumer().unwrap();
+
+        for i in 0..4 {
+            producer.push(i).unwrap();
+        }
+
+        assert!(producer.is_full());
+        assert_eq!(producer.push(4), Err(4));
+
+        for i in 0..4 {
+            assert_eq!(consumer.pop(), Some(i));
+        }
+
+        assert_eq!(consumer.pop(), None);
+    }
+
+    #[test]
+    fn only_one_of_each_side() {
+        let ring = SpscRing::<i32, 2>::new();
+        let producer = ring.producer().unwrap();
+        let consumer = ring.consumer().unwrap();
+
+        assert!(ring.producer().is_none());
+        assert!(ring.consumer().is_none());
+
+        drop(producer);
+        drop(consumer);
+
+        assert!(ring.producer().is_some());
+        assert!(ring.consumer().is_some());
+    }
+
+    #[test]
+    fn usable_from_statics() {
+        static RING: SpscRing<u8, 8> = SpscRing::new();
+
+        RING.producer().unwrap().push(5).unwrap();
+
+        assert_eq!(RING.len(), 1);
+        assert_eq!(RING.consumer().unwrap().pop(), Some(5));
+    }
+
+    #[test]
+    fn drops_leftovers() {
+        let value = Arc::new(());
+        let ring = SpscRing::<_, 4>::new();
+
+        ring.producer().unwrap().push(Arc::clone(&value)).unwrap();
+        drop(ring);
+
+        assert_eq!(Arc::strong_count(&value), 1);
+    }
+
+    #[test]
+    fn wraps_around_across_threads() {
+        let ring = Arc::new(SpscRing::<usize, 4>::new());
+        let producer = {
+            let ring = Arc::clone(&ring);
+
+            thread::spawn(move || {
+                let mut producer = ring.producer().unwrap();
+
+                for i in 0..10_000 {
+                    while producer.push(i).is_err() {
+                        thread::yield_now();
+                    }
+                }
+            })
+        };
+
+        let mut consumer = ring.consumer().unwrap();
+
+        for i in 0..10_000 {
+            loop {
+                if let Some(value) = consumer.pop() {
+                    assert_eq!(value, i);
+
+                    break;
+                }
+
+                thread::yield_now();
+            }
+        }
+
+        producer.join().unwrap();
+    }
+}
@@ -0,0 +1,109 @@
+//======---------------------------------------------------------------======//
+//                                                                           //
+// Copyright 2022-2023 Evan Cox <evanacox00@gmail.com>. All rights reserved. //
+//                                                                           //
+// Use of this source code is governed by a BSD-style license that can be    //
+// found in the LICENSE.txt file at the root of this project, or at the      //
+// following link: https://opensource.org/licenses/BSD-3-Clause              //
+//                                                                           //
+//======---------------------------------------------------------------======//
+
+// the pieces shared between the random number generators
+
+use core::ops::Range;
+
+// turns 64 random bits into a float in `[0, 1)`, using the top 53 since
+// that's how many an `f64` can hold
+#[allow(clippy::cast_precision_loss)]
+pub fn to_f64(bits: u64) -> f64 {
+    (bits >> 11) as f64 * (1.0 / (1u64 << 53) as f64)
+}
+
+// Lemire's "nearly divisionless" method, which maps 64 random bits onto
+// `0..bound` by multiplying, and only rejects the few outputs that would
+// bias the result. see https://arxiv.org/abs/1805.10941
+#[allow(clippy::cast_possible_truncation)]
+pub fn below(bound: u64, mut next: impl FnMut() -> u64) -> u64 {
+    assert!(bound != 0, "bound must be non-zero");
+
+    let mut product = u128::from(next()) * u128::from(bound);
+
+    if (product as u64) < bound {
+        let threshold = bound.wrapping_neg() % bound;
+
+        while (product as u64) < threshold {
+            product = u128::from(next()) * u128::from(bound);
+        }
+    }
+
+    (product >> 64) as u64
+}
+
+pub fn within(range: Range<u64>, next: impl FnMut() -> u64) -> u64 {
+    assert!(range.start < range.end, "range must not be empty");
+
+    range.start + below(range.end - range.start, next)
+}
+
+// SplitMix64, which is what the authors of the generators recommend for
+// expanding a small seed into a full state
+#[cfg(feature = "rand_core")]
+pub const fn splitmix64(state: &mut u64) -> u64 {
+    *state = state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+
+    let mut z = *state;
+
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+
+    z ^ (z >> 31)
+}
+
+// implements the `rand_core` traits for a generator with `$words` words of
+// state, which has a `with_seed([u64; $words])` and a `next()`
+macro_rules! impl_rand_core {
+    ($ty:ty, $words:literal) => {
+        #[cfg(feature = "rand_core")]
+        impl rand_core::RngCore for $ty {
+            #[allow(clippy::cast_possible_truncation)]
+            fn next_u32(&mut self) -> u32 {
+                // the high bits are the better ones
+                (self.next() >> 32) as u32
+            }
+
+            fn next_u64(&mut self) -> u64 {
+                self.next()
+            }
+
+            fn fill_bytes(&mut self, dst: &mut [u8]) {
+                rand_core::impls::fill_bytes_via_next(self, dst);
+            }
+        }
+
+        #[cfg(feature = "rand_core")]
+        impl rand_core::SeedableRng for $ty {
+            type Seed = [u8; $words * 8];
+
+            fn from_seed(seed: Self::Seed) -> Self {
+                // a state of all zeroes never produces anything else
+                if seed == [0; $words * 8] {
+                    return Self::seed_from_u64(0);
+                }
+
+                let mut state = [0; $words];
+
+                for (word, bytes) in state.iter_mut().zip(seed.chunks_exact(8)) {
+                    *word = u64::from_le_bytes(bytes.try_into().unwrap());
+                }
+
+                Self::with_seed(state)
+            }
+
+            fn seed_from_u64(mut seed: u64) -> Self {
+                Self::with_seed(core::array::from_fn(|_| $crate::rng::splitmix64(&mut seed)))
+            }
+        }
+    };
+}
+
+pub(crate) use impl_rand_core;
@@ -0,0 +1,130 @@
+//======---------------------------------------------------------------======//
+//                                                                           //
+// Copyright 2022-2023 Evan Cox <evanacox00@gmail.com>. All rights reserved. //
+//                                                                           //
+// Use of this source code is governed by a BSD-style license that can be    //
+// found in the LICENSE.txt file at the root of this project, or at the      //
+// following link: https://opensource.org/licenses/BSD-3-Clause              //
+//                                                                           //
+//======---------------------------------------------------------------======//
+
+use crate::model::{const_fn_unless_loom, UnsafeCell};
+use crate::SpinOnceCell;
+use core::ops::Deref;
+
+/// A value that's initialized the first time it's accessed, using a
+/// [`SpinOnceCell`] under the hood.
+///
+/// This is meant for `static`s that can't be built in a `const` context:
+///
+/// ```
+/// # use ksupport::SpinLazy;
+/// static TABLE: SpinLazy<[u32; 256]> = SpinLazy::new(|| core::array::from_fn(|i| i as u32 * 3));
+///
+/// assert_eq!(TABLE[5], 15);
+/// ```
+///
+/// If the initializer panics, the value is poisoned and every later
+/// access panics as well.
+///
+/// The value is shared between every thread that accesses it, so it has
+/// to be `Send + Sync` for the lazy value to be `Sync`:
+///
+/// ```compile_fail
+/// # use ksupport::SpinLazy;
+/// # use core::cell::Cell;
+/// static COUNTER: SpinLazy<Cell<u32>> = SpinLazy::new(|| Cell::new(0));
+/// ```
+pub struct SpinLazy<T, F = fn() -> T> {
+    cell: SpinOnceCell<T>,
+    init: UnsafeCell<Option<F>>,
+}
+
+impl<T, F: FnOnce() -> T> SpinLazy<T, F> {
+    const_fn_unless_loom! {
+        /// Creates a new lazy value that will be initialized with `init`.
+        pub const fn new(init: F) -> Self {
+            Self {
+                cell: SpinOnceCell::uninit(),
+                init: UnsafeCell::new(Some(init)),
+            }
+        }
+    }
+
+    /// Forces the value to be initialized if it hasn't been yet, and
+    /// returns a reference to it.
+    ///
+    /// # Panics
+    /// Panics if the initializer panics (or already did before).
+    pub fn force(this: &Self) -> &T {
+        this.cell.get_or_init(|| {
+            // only the thread that wins the race to initialize the cell gets
+            // here, and it only happens once
+            let init = this.init.with_mut(|init| unsafe { (*init).take() });
+
+            init.expect("SpinLazy initializer was already taken")()
+        })
+    }
+
+    /// Returns a reference to the value if it's been initialized, without
+    /// initializing it or waiting for it.
+    pub fn get(this: &Self) -> Option<&T> {
+        this.cell.try_get()
+    }
+}
+
+impl<T, F: FnOnce() -> T> Deref for SpinLazy<T, F> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        Self::force(self)
+    }
+}
+
+// `init` is only ever touched by whichever thread initializes the value,
+// but it may not be the thread that creates it
+unsafe impl<T: Send + Sync, F: Send> Sync for SpinLazy<T, F> {}
+
+// these use real threads, the loom versions are in `tests/loom.rs`
+#[cfg(all(test, not(loom)))]
+mod tests {
+    use super::*;
+    use std::panic::{self, AssertUnwindSafe};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::thread;
+    use std::vec::Vec;
+
+    #[test]
+    fn initializes_once() {
+        static CALLS: AtomicUsize = AtomicUsize::new(0);
+        static VALUE: SpinLazy<usize> = SpinLazy::new(|| CALLS.fetch_add(1, Ordering::SeqCst) + 10);
+
+        assert_eq!(SpinLazy::get(&VALUE), None);
+
+        let threads: Vec<_> = (0..4).map(|_| thread::spawn(|| *VALUE)).collect();
+
+        for thread in threads {
+            assert_eq!(thread.join().unwrap(), 10);
+        }
+
+        assert_eq!(SpinLazy::get(&VALUE), Some(&10));
+        assert_eq!(CALLS.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn captures_state() {
+        let base = 20;
+        let lazy = SpinLazy::new(move || base * 2);
+
+        assert_eq!(*SpinLazy::force(&lazy), 40);
+    }
+
+    #[test]
+    fn panicking_init_poisons() {
+        let lazy: SpinLazy<u32> = SpinLazy::new(|| panic!("oh no"));
+
+        assert!(panic::catch_unwind(AssertUnwindSafe(|| *lazy)).is_err());
+        assert!(panic::catch_unwind(AssertUnwindSafe(|| *lazy)).is_err());
+        assert_eq!(SpinLazy::get(&lazy), None);
+    }
+}
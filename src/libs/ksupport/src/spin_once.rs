@@ -8,34 +8,60 @@
 //                                                                           //
 //======---------------------------------------------------------------======//
 
-use core::cell::UnsafeCell;
+use crate::model::atomic::{AtomicU8, Ordering};
+use crate::model::{const_fn_unless_loom, hint, UnsafeCell};
 use core::hint::unreachable_unchecked;
 use core::intrinsics;
+use core::mem;
 use core::mem::MaybeUninit;
-use core::sync::atomic::{AtomicU8, Ordering};
-use core::{hint, mem};
 
 /// A thread-safe `OnceCell` that uses an atomic flag
 /// to determine initialized/uninitialized.
 ///
 /// If the value isn't initialized and we try to `get` it, we
 /// enter a spin loop.
+///
+/// If an initializer given to [`get_or_init`](Self::get_or_init) panics,
+/// the cell is *poisoned*. It can never be initialized after that, and
+/// anything that would wait for the value panics instead of spinning forever.
+///
+/// The value is shared between every thread that accesses it, so it has
+/// to be `Send + Sync` for the cell to be `Sync`:
+///
+/// ```compile_fail
+/// # use ksupport::SpinOnceCell;
+/// # use core::cell::Cell;
+/// static VALUE: SpinOnceCell<Cell<u32>> = SpinOnceCell::uninit();
+/// ```
 pub struct SpinOnceCell<T> {
     inner: UnsafeCell<MaybeUninit<T>>,
     init: AtomicU8,
 }
 
+// poisons the cell if it's dropped, which only happens if the
+// initializer unwinds
+struct PoisonOnUnwind<'a>(&'a AtomicU8);
+
+impl Drop for PoisonOnUnwind<'_> {
+    fn drop(&mut self) {
+        self.0.store(POISONED, Ordering::Release);
+    }
+}
+
+const EMPTY: u8 = 0;
+const FULL: u8 = 1;
+const FILLING: u8 = 2;
+const POISONED: u8 = 3;
+
 impl<T> SpinOnceCell<T> {
-    const EMPTY: u8 = 0;
-    const FULL: u8 = 1;
-    const FILLING: u8 = 2;
-
-    /// Creates an uninitialized [`SpinOnceCell`]. The value needs to be
-    /// initialized before it can be access.
-    pub const fn uninit() -> Self {
-        Self {
-            inner: UnsafeCell::new(MaybeUninit::uninit()),
-            init: AtomicU8::new(Self::EMPTY),
+    const_fn_unless_loom! {
+        /// Creates an uninitialized [`SpinOnceCell`]. The value needs to be
+        /// initialized before it can be access.
+        pub const fn uninit() -> Self {
+            Self {
+                inner: UnsafeCell::new(MaybeUninit::uninit()),
+                init: AtomicU8::new(EMPTY),
+            }
         }
     }
 
@@ -43,69 +69,86 @@ impl<T> SpinOnceCell<T> {
     ///
     /// Otherwise, spins until it is initialized, then returns a reference
     /// to the value.
+    ///
+    /// # Panics
+    /// Panics if the cell is poisoned.
     pub fn get(&self) -> &T {
         self.wait_until();
 
-        let inner = self.inner.get();
+        self.inner
+            .with(|inner| unsafe { (*inner).assume_init_ref() })
+    }
 
-        unsafe { (*inner).assume_init_ref() }
+    /// If the value has been initialized, returns a reference to the value.
+    /// Otherwise, returns `None`.
+    ///
+    /// Unlike [`get`](Self::get), this never waits.
+    pub fn try_get(&self) -> Option<&T> {
+        (self.init.load(Ordering::Acquire) == FULL).then(|| {
+            self.inner
+                .with(|inner| unsafe { (*inner).assume_init_ref() })
+        })
     }
 
     /// If the value has been initialized, returns a mutable reference to the value.
     ///
     /// Otherwise, spins until it is initialized, then returns a mutable reference
     /// to the value.
+    ///
+    /// # Panics
+    /// Panics if the cell is poisoned.
     pub fn get_mut(&mut self) -> &mut T {
         self.wait_until();
 
-        let inner = self.inner.get_mut();
-
-        unsafe { inner.assume_init_mut() }
+        self.inner
+            .with_mut(|inner| unsafe { (*inner).assume_init_mut() })
     }
 
-    /// If the value has been initialized, returns a mutable reference to the value.
+    /// If the value hasn't been initialized, initializes it with `init`.
+    /// Then, returns a reference to the value.
     ///
-    /// Otherwise, spins until it is initialized, then returns a mutable reference
-    /// to the value.
+    /// If another thread is initializing the value at the same time, this
+    /// waits for it to finish and `init` is never called.
+    ///
+    /// # Panics
+    /// Panics if the cell is poisoned, or becomes poisoned while waiting.
+    /// If `init` panics, the cell is poisoned and the panic is propagated.
     pub fn get_or_init(&self, init: impl FnOnce() -> T) -> &T {
-        let inner = self.inner.get();
-
-        if let Ok(_) = self.init.compare_exchange(
-            Self::EMPTY,
-            Self::FILLING,
-            Ordering::Relaxed,
-            Ordering::Relaxed,
-        ) {
-            unsafe {
-                let uninit = &mut *self.inner.get();
-
-                uninit.write(init());
-            }
+        if self
+            .init
+            .compare_exchange(EMPTY, FILLING, Ordering::Relaxed, Ordering::Relaxed)
+            .is_ok()
+        {
+            let poison = PoisonOnUnwind(&self.init);
+
+            self.inner.with_mut(|uninit| unsafe {
+                (*uninit).write(init());
+            });
 
-            self.init.store(Self::FULL, Ordering::Release);
+            mem::forget(poison);
+
+            self.init.store(FULL, Ordering::Release);
         }
 
-        unsafe { (*inner).assume_init_ref() }
+        // whoever won the race might still be filling it in, and the value
+        // is only visible here once `FULL` has been observed with `Acquire`
+        self.get()
     }
 
     /// If the value isn't initialized, initializes it and returns `Ok(())`.
     ///
     /// Otherwise, returns `Err(value)`.
     pub fn set(&self, value: T) -> Result<(), T> {
-        match self.init.compare_exchange(
-            Self::EMPTY,
-            Self::FILLING,
-            Ordering::Relaxed,
-            Ordering::Relaxed,
-        ) {
+        match self
+            .init
+            .compare_exchange(EMPTY, FILLING, Ordering::Relaxed, Ordering::Relaxed)
+        {
             Ok(_) => {
-                unsafe {
-                    let uninit = &mut *self.inner.get();
-
-                    uninit.write(value);
-                }
+                self.inner.with_mut(|uninit| unsafe {
+                    (*uninit).write(value);
+                });
 
-                self.init.store(Self::FULL, Ordering::Release);
+                self.init.store(FULL, Ordering::Release);
 
                 Ok(())
             }
@@ -113,6 +156,11 @@ impl<T> SpinOnceCell<T> {
         }
     }
 
+    /// Checks whether an initializer panicked while initializing the cell.
+    pub fn is_poisoned(&self) -> bool {
+        self.init.load(Ordering::Relaxed) == POISONED
+    }
+
     /// If the value is initialized, takes it out and sets `self`
     /// back to the uninitialized state.
     pub fn take(&mut self) -> Option<T> {
@@ -127,8 +175,8 @@ impl<T> SpinOnceCell<T> {
     pub fn into_inner(self) -> Option<T> {
         unsafe {
             match self.init.load(Ordering::Acquire) {
-                Self::EMPTY | Self::FILLING => None,
-                Self::FULL => Some(self.inner.into_inner().assume_init()),
+                EMPTY | FILLING | POISONED => None,
+                FULL => Some(self.inner.into_inner().assume_init()),
                 _ => unreachable_unchecked(),
             }
         }
@@ -136,8 +184,17 @@ impl<T> SpinOnceCell<T> {
 
     #[inline(always)]
     fn wait_until(&self) {
-        while intrinsics::unlikely(self.init.load(Ordering::Acquire) != Self::FULL) {
+        let mut state = self.init.load(Ordering::Acquire);
+
+        while intrinsics::unlikely(state != FULL) {
+            assert!(
+                state != POISONED,
+                "SpinOnceCell was poisoned by a panicking initializer"
+            );
+
             hint::spin_loop();
+
+            state = self.init.load(Ordering::Acquire);
         }
     }
 }
@@ -148,4 +205,100 @@ impl<T> Default for SpinOnceCell<T> {
     }
 }
 
-unsafe impl<T> Sync for SpinOnceCell<T> {}
+// the value is shared with every thread, and whichever one initializes it
+// may not be the one that drops it
+unsafe impl<T: Send + Sync> Sync for SpinOnceCell<T> {}
+
+// these use real threads, the loom versions are in `tests/loom.rs`
+#[cfg(all(test, not(loom)))]
+mod tests {
+    use super::*;
+    use std::panic::{self, AssertUnwindSafe};
+    use std::sync::atomic::AtomicBool;
+    use std::sync::Arc;
+    use std::thread;
+
+    #[test]
+    fn try_get_never_waits() {
+        let cell = SpinOnceCell::uninit();
+
+        assert_eq!(cell.try_get(), None);
+        assert_eq!(cell.set(3), Ok(()));
+        assert_eq!(cell.try_get(), Some(&3));
+        assert_eq!(cell.set(4), Err(4));
+    }
+
+    #[test]
+    fn losing_get_or_init_waits_for_the_winner() {
+        let cell = Arc::new(SpinOnceCell::uninit());
+        let started = Arc::new(AtomicBool::new(false));
+        let release = Arc::new(AtomicBool::new(false));
+
+        let winner = {
+            let cell = Arc::clone(&cell);
+            let started = Arc::clone(&started);
+            let release = Arc::clone(&release);
+
+            thread::spawn(move || {
+                *cell.get_or_init(|| {
+                    started.store(true, Ordering::SeqCst);
+
+                    while !release.load(Ordering::SeqCst) {
+                        thread::yield_now();
+                    }
+
+                    5
+                })
+            })
+        };
+
+        while !started.load(Ordering::SeqCst) {
+            thread::yield_now();
+        }
+
+        let loser = {
+            let cell = Arc::clone(&cell);
+
+            thread::spawn(move || *cell.get_or_init(|| 7))
+        };
+
+        assert_eq!(cell.try_get(), None);
+
+        release.store(true, Ordering::SeqCst);
+
+        assert_eq!(winner.join().unwrap(), 5);
+        assert_eq!(loser.join().unwrap(), 5);
+    }
+
+    #[test]
+    fn panicking_init_poisons() {
+        let cell = SpinOnceCell::uninit();
+        let result = panic::catch_unwind(AssertUnwindSafe(|| {
+            cell.get_or_init(|| panic!("oh no"));
+        }));
+
+        assert!(result.is_err());
+        assert!(cell.is_poisoned());
+        assert_eq!(cell.try_get(), None);
+        assert_eq!(cell.set(1), Err(1));
+
+        let result = panic::catch_unwind(AssertUnwindSafe(|| *cell.get_or_init(|| 2)));
+
+        assert!(result.is_err());
+        assert!(panic::catch_unwind(AssertUnwindSafe(|| *cell.get())).is_err());
+        assert_eq!(cell.into_inner(), None);
+    }
+
+    #[test]
+    fn take_resets() {
+        let mut cell = SpinOnceCell::uninit();
+
+        assert_eq!(cell.take(), None);
+
+        cell.set(8).unwrap();
+
+        assert_eq!(*cell.get_mut(), 8);
+        assert_eq!(cell.take(), Some(8));
+        assert_eq!(cell.try_get(), None);
+    }
+}
@@ -36,6 +36,16 @@ where
             _unused: PhantomData::default(),
         }
     }
+
+    /// Gets the mutex that `this` is a guard for.
+    ///
+    /// This is an associated function so that it can't conflict with
+    /// methods on `T`.
+    #[inline]
+    #[must_use]
+    pub const fn mutex(this: &Self) -> &'mutex Underlying {
+        this.mutex
+    }
 }
 
 impl<'mutex, Underlying, T> Drop for MutexGuard<'mutex, Underlying, T>
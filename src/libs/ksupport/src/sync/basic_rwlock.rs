@@ -0,0 +1,330 @@
+//======---------------------------------------------------------------======//
+//                                                                           //
+// Copyright 2022-2023 Evan Cox <evanacox00@gmail.com>. All rights reserved. //
+//                                                                           //
+// Use of this source code is governed by a BSD-style license that can be    //
+// found in the LICENSE.txt file at the root of this project, or at the      //
+// following link: https://opensource.org/licenses/BSD-3-Clause              //
+//                                                                           //
+//======---------------------------------------------------------------======//
+
+use core::marker::PhantomData;
+use core::mem;
+use core::ops::{Deref, DerefMut};
+
+/// An RAII guard that gives shared access to the data that a
+/// reader-writer lock is protecting.
+pub struct RwLockReadGuard<'lock, Underlying, T>
+where
+    Underlying: BasicRwLock<T>,
+{
+    lock: &'lock Underlying,
+    _unused: PhantomData<T>,
+}
+
+impl<'lock, Underlying, T> RwLockReadGuard<'lock, Underlying, T>
+where
+    Underlying: BasicRwLock<T>,
+{
+    /// Creates a new [`RwLockReadGuard`] from an unlocked lock.
+    ///
+    /// # Safety
+    /// `lock` **must** already be locked for reading, or the behavior is undefined.
+    #[inline]
+    pub const unsafe fn new_from_unlocked(lock: &'lock Underlying) -> Self {
+        Self {
+            lock,
+            _unused: PhantomData,
+        }
+    }
+}
+
+impl<Underlying, T> Drop for RwLockReadGuard<'_, Underlying, T>
+where
+    Underlying: BasicRwLock<T>,
+{
+    fn drop(&mut self) {
+        unsafe {
+            self.lock.unlock_read_unchecked();
+        }
+    }
+}
+
+impl<Underlying, T> Deref for RwLockReadGuard<'_, Underlying, T>
+where
+    Underlying: BasicRwLock<T>,
+{
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        unsafe { self.lock.data_unguarded() }
+    }
+}
+
+/// An RAII guard that gives exclusive access to the data that a
+/// reader-writer lock is protecting.
+pub struct RwLockWriteGuard<'lock, Underlying, T>
+where
+    Underlying: BasicRwLock<T>,
+{
+    lock: &'lock Underlying,
+    _unused: PhantomData<T>,
+}
+
+impl<'lock, Underlying, T> RwLockWriteGuard<'lock, Underlying, T>
+where
+    Underlying: BasicRwLock<T>,
+{
+    /// Creates a new [`RwLockWriteGuard`] from an unlocked lock.
+    ///
+    /// # Safety
+    /// `lock` **must** already be locked for writing, or the behavior is undefined.
+    #[inline]
+    pub const unsafe fn new_from_unlocked(lock: &'lock Underlying) -> Self {
+        Self {
+            lock,
+            _unused: PhantomData,
+        }
+    }
+
+    /// Atomically turns the write lock into a read lock, without letting
+    /// any other writer in between.
+    #[must_use]
+    pub fn downgrade(self) -> RwLockReadGuard<'lock, Underlying, T> {
+        let lock = self.lock;
+
+        mem::forget(self);
+
+        unsafe {
+            lock.downgrade_write_unchecked();
+
+            RwLockReadGuard::new_from_unlocked(lock)
+        }
+    }
+}
+
+impl<Underlying, T> Drop for RwLockWriteGuard<'_, Underlying, T>
+where
+    Underlying: BasicRwLock<T>,
+{
+    fn drop(&mut self) {
+        unsafe {
+            self.lock.unlock_write_unchecked();
+        }
+    }
+}
+
+impl<Underlying, T> Deref for RwLockWriteGuard<'_, Underlying, T>
+where
+    Underlying: BasicRwLock<T>,
+{
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        unsafe { self.lock.data_unguarded() }
+    }
+}
+
+impl<Underlying, T> DerefMut for RwLockWriteGuard<'_, Underlying, T>
+where
+    Underlying: BasicRwLock<T>,
+{
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        unsafe { self.lock.data_mut_unguarded() }
+    }
+}
+
+/// An RAII guard that gives shared access to the data that a
+/// reader-writer lock is protecting, and that can be upgraded
+/// into exclusive access.
+///
+/// Only one upgradeable guard can exist at a time, but it can exist
+/// alongside read guards. Locks may hold back new readers while it
+/// exists, so that upgrading can't be starved.
+pub struct RwLockUpgradeableGuard<'lock, Underlying, T>
+where
+    Underlying: BasicRwLock<T>,
+{
+    lock: &'lock Underlying,
+    _unused: PhantomData<T>,
+}
+
+impl<'lock, Underlying, T> RwLockUpgradeableGuard<'lock, Underlying, T>
+where
+    Underlying: BasicRwLock<T>,
+{
+    /// Creates a new [`RwLockUpgradeableGuard`] from an unlocked lock.
+    ///
+    /// # Safety
+    /// `lock` **must** already be locked for an upgradeable read, or the
+    /// behavior is undefined.
+    #[inline]
+    pub const unsafe fn new_from_unlocked(lock: &'lock Underlying) -> Self {
+        Self {
+            lock,
+            _unused: PhantomData,
+        }
+    }
+
+    /// Turns the guard into a write guard, waiting for every reader
+    /// to leave first.
+    #[must_use]
+    pub fn upgrade(self) -> RwLockWriteGuard<'lock, Underlying, T> {
+        let lock = self.lock;
+
+        mem::forget(self);
+
+        unsafe {
+            lock.upgrade_unchecked();
+
+            RwLockWriteGuard::new_from_unlocked(lock)
+        }
+    }
+
+    /// Turns the guard into a write guard if there are no readers right now.
+    ///
+    /// # Errors
+    /// Gives the guard back if there are still readers.
+    pub fn try_upgrade(self) -> Result<RwLockWriteGuard<'lock, Underlying, T>, Self> {
+        if unsafe { self.lock.try_upgrade_unchecked() } {
+            let lock = self.lock;
+
+            mem::forget(self);
+
+            Ok(unsafe { RwLockWriteGuard::new_from_unlocked(lock) })
+        } else {
+            Err(self)
+        }
+    }
+
+    /// Turns the guard into a plain read guard, letting another
+    /// upgradeable reader in.
+    #[must_use]
+    pub fn downgrade(self) -> RwLockReadGuard<'lock, Underlying, T> {
+        let lock = self.lock;
+
+        mem::forget(self);
+
+        unsafe {
+            lock.downgrade_upgradeable_unchecked();
+
+            RwLockReadGuard::new_from_unlocked(lock)
+        }
+    }
+}
+
+impl<Underlying, T> Drop for RwLockUpgradeableGuard<'_, Underlying, T>
+where
+    Underlying: BasicRwLock<T>,
+{
+    fn drop(&mut self) {
+        unsafe {
+            self.lock.unlock_upgradeable_unchecked();
+        }
+    }
+}
+
+impl<Underlying, T> Deref for RwLockUpgradeableGuard<'_, Underlying, T>
+where
+    Underlying: BasicRwLock<T>,
+{
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        unsafe { self.lock.data_unguarded() }
+    }
+}
+
+/// A basic reader-writer lock API that every reader-writer lock in
+/// the kernel follows.
+///
+/// This mirrors [`BasicMutex`](crate::sync::BasicMutex), with separate
+/// guards for each of the ways the lock can be held.
+pub trait BasicRwLock<T>: Send + Sync + Sized {
+    /// Locks the lock for shared access, waiting in a lock-specific
+    /// way if there's a writer.
+    fn read(&self) -> RwLockReadGuard<'_, Self, T>;
+
+    /// Attempts to lock the lock for shared access. Returns `None` if
+    /// it can't be done right now.
+    fn try_read(&self) -> Option<RwLockReadGuard<'_, Self, T>>;
+
+    /// Locks the lock for exclusive access, waiting in a lock-specific
+    /// way if there are any readers or a writer.
+    fn write(&self) -> RwLockWriteGuard<'_, Self, T>;
+
+    /// Attempts to lock the lock for exclusive access. Returns `None` if
+    /// it can't be done right now.
+    fn try_write(&self) -> Option<RwLockWriteGuard<'_, Self, T>>;
+
+    /// Locks the lock for shared access that can later be upgraded to
+    /// exclusive access, waiting if there's a writer or another
+    /// upgradeable reader.
+    fn upgradeable_read(&self) -> RwLockUpgradeableGuard<'_, Self, T>;
+
+    /// Attempts to lock the lock for an upgradeable read. Returns `None`
+    /// if it can't be done right now.
+    fn try_upgradeable_read(&self) -> Option<RwLockUpgradeableGuard<'_, Self, T>>;
+
+    /// Releases a read lock.
+    ///
+    /// # Safety
+    /// The lock must be held for reading, and the guard for it must be
+    /// forgotten and never used again.
+    unsafe fn unlock_read_unchecked(&self);
+
+    /// Releases a write lock.
+    ///
+    /// # Safety
+    /// The lock must be held for writing, and the guard for it must be
+    /// forgotten and never used again.
+    unsafe fn unlock_write_unchecked(&self);
+
+    /// Releases an upgradeable read lock.
+    ///
+    /// # Safety
+    /// The lock must be held for an upgradeable read, and the guard for it
+    /// must be forgotten and never used again.
+    unsafe fn unlock_upgradeable_unchecked(&self);
+
+    /// Turns an upgradeable read lock into a write lock, waiting for
+    /// readers to leave.
+    ///
+    /// # Safety
+    /// The lock must be held for an upgradeable read by the caller.
+    unsafe fn upgrade_unchecked(&self);
+
+    /// Turns an upgradeable read lock into a write lock if there are no
+    /// readers, returning whether it did.
+    ///
+    /// # Safety
+    /// The lock must be held for an upgradeable read by the caller.
+    unsafe fn try_upgrade_unchecked(&self) -> bool;
+
+    /// Turns a write lock into a read lock.
+    ///
+    /// # Safety
+    /// The lock must be held for writing by the caller.
+    unsafe fn downgrade_write_unchecked(&self);
+
+    /// Turns an upgradeable read lock into a read lock.
+    ///
+    /// # Safety
+    /// The lock must be held for an upgradeable read by the caller.
+    unsafe fn downgrade_upgradeable_unchecked(&self);
+
+    /// Provides immutable access to the underlying data.
+    ///
+    /// # Safety
+    /// `self` must be locked in some way, or it must be impossible for a
+    /// writer to exist at the same time.
+    unsafe fn data_unguarded(&self) -> &T;
+
+    /// Provides mutable access to the underlying data.
+    ///
+    /// # Safety
+    /// `self` must be locked for writing, or it must be impossible for any
+    /// other thread to access the data at the same time.
+    #[allow(clippy::mut_from_ref)]
+    unsafe fn data_mut_unguarded(&self) -> &mut T;
+}
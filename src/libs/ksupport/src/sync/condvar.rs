@@ -0,0 +1,181 @@
+//======---------------------------------------------------------------======//
+//                                                                           //
+// Copyright 2022-2023 Evan Cox <evanacox00@gmail.com>. All rights reserved. //
+//                                                                           //
+// Use of this source code is governed by a BSD-style license that can be    //
+// found in the LICENSE.txt file at the root of this project, or at the      //
+// following link: https://opensource.org/licenses/BSD-3-Clause              //
+//                                                                           //
+//======---------------------------------------------------------------======//
+
+use crate::model::const_fn_unless_loom;
+use crate::sync::basic_mutex::{BasicMutex, MutexGuard};
+use crate::sync::wait_queue::{Park, WaitQueue, WaitResult};
+
+/// A condition variable, which lets threads sleep until some condition
+/// on the data inside a mutex becomes true.
+///
+/// This works with any [`BasicMutex`], the mutex is unlocked while the
+/// thread sleeps and locked again before it returns. Waiters are woken
+/// up in FIFO order.
+pub struct Condvar<P: Park> {
+    waiters: WaitQueue<P>,
+}
+
+impl<P: Park> Condvar<P> {
+    const_fn_unless_loom! {
+        /// Creates a condition variable with nobody waiting on it.
+        pub const fn new() -> Self {
+            Self {
+                waiters: WaitQueue::new(),
+            }
+        }
+    }
+
+    /// Unlocks the mutex behind `guard` and sleeps until this is notified,
+    /// then locks the mutex again.
+    ///
+    /// Like any condition variable, the condition needs to be re-checked
+    /// after waking up. See [`wait_while`](Self::wait_while).
+    pub fn wait<'a, M: BasicMutex<T>, T>(
+        &self,
+        guard: MutexGuard<'a, M, T>,
+    ) -> MutexGuard<'a, M, T> {
+        self.wait_until(guard, None).0
+    }
+
+    /// Sleeps for as long as `condition` returns `true`, see [`wait`](Self::wait).
+    pub fn wait_while<'a, M: BasicMutex<T>, T>(
+        &self,
+        mut guard: MutexGuard<'a, M, T>,
+        mut condition: impl FnMut(&mut T) -> bool,
+    ) -> MutexGuard<'a, M, T> {
+        while condition(&mut guard) {
+            guard = self.wait(guard);
+        }
+
+        guard
+    }
+
+    /// Like [`wait`](Self::wait), but gives up once `nanos` nanoseconds
+    /// (as measured by [`Park::now`]) have passed.
+    ///
+    /// Also returns whether the wait timed out.
+    pub fn wait_timeout<'a, M: BasicMutex<T>, T>(
+        &self,
+        guard: MutexGuard<'a, M, T>,
+        nanos: u64,
+    ) -> (MutexGuard<'a, M, T>, bool) {
+        self.wait_until(guard, Some(P::now().saturating_add(nanos)))
+    }
+
+    /// Wakes up the thread that has been waiting the longest, returning
+    /// whether there was one.
+    pub fn notify_one(&self) -> bool {
+        self.waiters.notify_one()
+    }
+
+    /// Wakes up every thread that's waiting, returning how many there were.
+    pub fn notify_all(&self) -> usize {
+        self.waiters.notify_all()
+    }
+
+    fn wait_until<'a, M: BasicMutex<T>, T>(
+        &self,
+        guard: MutexGuard<'a, M, T>,
+        deadline: Option<u64>,
+    ) -> (MutexGuard<'a, M, T>, bool) {
+        let mutex = MutexGuard::mutex(&guard);
+
+        // the mutex is unlocked with the queue locked, so a notification
+        // from a thread that took the mutex after us can't be missed
+        let result = self.waiters.wait_if(
+            || {
+                drop(guard);
+
+                true
+            },
+            deadline,
+        );
+
+        (mutex.lock(), result == WaitResult::TimedOut)
+    }
+}
+
+impl<P: Park> Default for Condvar<P> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(all(test, not(loom)))]
+mod tests {
+    use super::*;
+    use crate::sync::wait_queue::tests::StdPark;
+    use crate::sync::{Mutex, SpinMutex};
+    use std::sync::Arc;
+    use std::thread;
+
+    #[test]
+    fn wait_while_sees_the_update() {
+        let pair = Arc::new((
+            Mutex::<bool, StdPark>::new(false),
+            Condvar::<StdPark>::new(),
+        ));
+        let setter = {
+            let pair = Arc::clone(&pair);
+
+            thread::spawn(move || {
+                *pair.0.lock() = true;
+                pair.1.notify_one();
+            })
+        };
+
+        let guard = pair.1.wait_while(pair.0.lock(), |ready| !*ready);
+
+        assert!(*guard);
+
+        drop(guard);
+        setter.join().unwrap();
+    }
+
+    #[test]
+    fn works_with_spin_mutexes() {
+        let pair = Arc::new((SpinMutex::new(0), Condvar::<StdPark>::new()));
+        let threads: Vec<_> = (0..3)
+            .map(|_| {
+                let pair = Arc::clone(&pair);
+
+                thread::spawn(move || {
+                    let mut guard = pair.1.wait_while(pair.0.lock(), |value| *value == 0);
+
+                    *guard += 1;
+                })
+            })
+            .collect();
+
+        *pair.0.lock() = 1;
+        pair.1.notify_all();
+
+        // anything that wasn't waiting yet sees the value and never waits
+        for thread in threads {
+            thread.join().unwrap();
+        }
+
+        assert_eq!(*pair.0.lock(), 4);
+    }
+
+    #[test]
+    fn wait_timeout_times_out() {
+        let mutex = Mutex::<i32, StdPark>::new(0);
+        let condvar = Condvar::<StdPark>::new();
+        let (guard, timed_out) = condvar.wait_timeout(mutex.lock(), 1_000_000);
+
+        assert!(timed_out);
+
+        // the mutex is locked again
+        assert!(mutex.try_lock().is_none());
+
+        drop(guard);
+    }
+}
@@ -0,0 +1,312 @@
+//======---------------------------------------------------------------======//
+//                                                                           //
+// Copyright 2022-2023 Evan Cox <evanacox00@gmail.com>. All rights reserved. //
+//                                                                           //
+// Use of this source code is governed by a BSD-style license that can be    //
+// found in the LICENSE.txt file at the root of this project, or at the      //
+// following link: https://opensource.org/licenses/BSD-3-Clause              //
+//                                                                           //
+//======---------------------------------------------------------------======//
+
+use crate::sync::basic_mutex::{BasicMutex, MutexGuard};
+use core::cell::UnsafeCell;
+use core::hint;
+use core::ptr::{self, NonNull};
+use core::sync::atomic::{AtomicPtr, Ordering};
+
+// a queue node. waiters put one of these on their stack while they wait,
+// and the lock has one of its own that stands in for the current holder
+struct Node {
+    // for a waiter: `WAITING` until the lock is handed to it.
+    // for the lock: the last node in the queue, or null if it's unlocked
+    tail: AtomicPtr<Self>,
+    // the node that's queued up behind this one
+    next: AtomicPtr<Self>,
+}
+
+// a pointer that can never be a real node, nodes are never at address 8
+const WAITING: *mut Node = NonNull::dangling().as_ptr();
+
+impl Node {
+    const fn new(tail: *mut Self) -> Self {
+        Self {
+            tail: AtomicPtr::new(tail),
+            next: AtomicPtr::new(ptr::null_mut()),
+        }
+    }
+}
+
+/// A queued spinlock-based mutex, using the MCS algorithm.
+///
+/// Like [`SpinFairMutex`](crate::sync::SpinFairMutex), waiters get the lock
+/// in the order they started waiting. Unlike it, every waiter spins on a
+/// node in its own stack frame instead of one shared counter, so a lock
+/// handoff only touches the cache line of the next waiter rather than
+/// every waiter's.
+///
+/// Normal MCS locks need the holder to keep its node around until it unlocks,
+/// which doesn't fit with a [`MutexGuard`] that only knows about the mutex.
+/// This uses the K42 variant instead, where the mutex holds a node that
+/// stands in for whoever owns the lock, and waiters only need their node
+/// while they're waiting.
+///
+/// This is not interrupt-safe, kernel use must wrap interrupt
+/// handling code around this to use it safely.
+pub struct McsMutex<T> {
+    data: UnsafeCell<T>,
+    node: Node,
+}
+
+impl<T> McsMutex<T> {
+    /// Creates a new mutex instance with a given initial value
+    /// for the held object.
+    ///
+    /// The lock starts in the "unlocked" state.
+    pub const fn new(value: T) -> Self {
+        Self {
+            data: UnsafeCell::new(value),
+            node: Node::new(ptr::null_mut()),
+        }
+    }
+
+    // the lock's own node, which is the tail whenever the holder has
+    // nobody waiting behind it
+    const fn owner(&self) -> *mut Node {
+        ptr::from_ref(&self.node).cast_mut()
+    }
+
+    fn try_lock_uncontended(&self) -> bool {
+        self.node
+            .tail
+            .compare_exchange(
+                ptr::null_mut(),
+                self.owner(),
+                Ordering::Acquire,
+                Ordering::Relaxed,
+            )
+            .is_ok()
+    }
+
+    // queues `node` up behind `prev`, and waits until it's at the front
+    fn wait_in_queue(&self, prev: *mut Node, node: &Node) {
+        let this = ptr::from_ref(node).cast_mut();
+
+        // `prev` is either a waiter that can't leave until it sees us (see
+        // below), or the lock's node if the holder has nobody behind it
+        unsafe { (*prev).next.store(this, Ordering::Release) };
+
+        while node.tail.load(Ordering::Acquire) == WAITING {
+            hint::spin_loop();
+        }
+
+        // we own the lock now, so the lock's node takes over for ours. if
+        // nobody is behind us, the lock's node becomes the tail again
+        let mut next = node.next.load(Ordering::Acquire);
+
+        if next.is_null() {
+            self.node.next.store(ptr::null_mut(), Ordering::Relaxed);
+
+            if self
+                .node
+                .tail
+                .compare_exchange(this, self.owner(), Ordering::AcqRel, Ordering::Relaxed)
+                .is_ok()
+            {
+                return;
+            }
+
+            // somebody got in behind us before we could swap ourselves out,
+            // wait for them to finish linking themselves to our node
+            loop {
+                next = node.next.load(Ordering::Acquire);
+
+                if !next.is_null() {
+                    break;
+                }
+
+                hint::spin_loop();
+            }
+        }
+
+        self.node.next.store(next, Ordering::Relaxed);
+    }
+}
+
+impl<T: Send> BasicMutex<T> for McsMutex<T> {
+    fn lock(&self) -> MutexGuard<'_, Self, T> {
+        loop {
+            let prev = self.node.tail.load(Ordering::Relaxed);
+
+            if prev.is_null() {
+                if self.try_lock_uncontended() {
+                    break;
+                }
+
+                continue;
+            }
+
+            let node = Node::new(WAITING);
+            let this = ptr::from_ref(&node).cast_mut();
+
+            if self
+                .node
+                .tail
+                .compare_exchange(prev, this, Ordering::AcqRel, Ordering::Relaxed)
+                .is_ok()
+            {
+                self.wait_in_queue(prev, &node);
+
+                break;
+            }
+        }
+
+        unsafe { MutexGuard::new_from_unlocked(self) }
+    }
+
+    fn try_lock(&self) -> Option<MutexGuard<'_, Self, T>> {
+        // pre-emptively load, same as `SpinMutex::try_lock`
+        if self.node.tail.load(Ordering::Relaxed).is_null() && self.try_lock_uncontended() {
+            Some(unsafe { MutexGuard::new_from_unlocked(self) })
+        } else {
+            None
+        }
+    }
+
+    unsafe fn unlock_unchecked(&self) {
+        let mut next = self.node.next.load(Ordering::Acquire);
+
+        if next.is_null() {
+            // nobody is visibly waiting, try to go straight to unlocked
+            if self
+                .node
+                .tail
+                .compare_exchange(
+                    self.owner(),
+                    ptr::null_mut(),
+                    Ordering::Release,
+                    Ordering::Relaxed,
+                )
+                .is_ok()
+            {
+                return;
+            }
+
+            // somebody is queued, but hasn't linked themselves in yet
+            loop {
+                next = self.node.next.load(Ordering::Acquire);
+
+                if !next.is_null() {
+                    break;
+                }
+
+                hint::spin_loop();
+            }
+        }
+
+        // hand the lock over. `next` can return and free its node as soon
+        // as this store lands, so it can't be touched afterwards
+        (*next).tail.store(ptr::null_mut(), Ordering::Release);
+    }
+
+    unsafe fn data_unguarded(&self) -> &T {
+        &*self.data.get()
+    }
+
+    unsafe fn data_mut_unguarded(&self) -> &mut T {
+        &mut *self.data.get()
+    }
+}
+
+// the node only holds pointers to nodes that are in the middle of
+// locking or unlocking this mutex
+unsafe impl<T: Send> Send for McsMutex<T> {}
+
+unsafe impl<T: Send> Sync for McsMutex<T> {}
+
+impl<T: Default> Default for McsMutex<T> {
+    fn default() -> Self {
+        Self::new(T::default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use std::thread;
+    use std::vec::Vec;
+
+    #[test]
+    fn try_lock_fails_while_locked() {
+        let mutex = McsMutex::new(5);
+        let guard = mutex.lock();
+
+        assert!(mutex.try_lock().is_none());
+
+        drop(guard);
+
+        assert_eq!(*mutex.try_lock().unwrap(), 5);
+        assert!(mutex.node.tail.load(Ordering::Relaxed).is_null());
+    }
+
+    #[test]
+    fn waiters_get_the_lock() {
+        let mutex = Arc::new(McsMutex::new(0));
+        let guard = mutex.lock();
+        let waiters: Vec<_> = (0..4)
+            .map(|_| {
+                let mutex = Arc::clone(&mutex);
+
+                thread::spawn(move || *mutex.lock() += 1)
+            })
+            .collect();
+
+        // let the waiters queue up behind us
+        while mutex.node.tail.load(Ordering::Relaxed) == mutex.owner() {
+            hint::spin_loop();
+        }
+
+        drop(guard);
+
+        for waiter in waiters {
+            waiter.join().unwrap();
+        }
+
+        assert_eq!(*mutex.lock(), 4);
+        assert!(mutex.node.tail.load(Ordering::Relaxed).is_null());
+    }
+
+    #[test]
+    fn stress() {
+        // kept small, a fair lock hands off to waiters that might not be
+        // running when the host has fewer CPUs than threads
+        const THREADS: usize = 4;
+        const ITERATIONS: usize = 500;
+
+        let mutex = Arc::new(McsMutex::new(0usize));
+        let threads: Vec<_> = (0..THREADS)
+            .map(|_| {
+                let mutex = Arc::clone(&mutex);
+
+                thread::spawn(move || {
+                    for _ in 0..ITERATIONS {
+                        let mut guard = mutex.lock();
+
+                        // split up so a lost update shows up in the count
+                        let value = *guard;
+
+                        hint::spin_loop();
+
+                        *guard = value + 1;
+                    }
+                })
+            })
+            .collect();
+
+        for thread in threads {
+            thread.join().unwrap();
+        }
+
+        assert_eq!(*mutex.lock(), THREADS * ITERATIONS);
+    }
+}
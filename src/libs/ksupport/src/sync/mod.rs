@@ -14,9 +14,30 @@
 //! These may not necessarily be safe to use *directly* (e.g. mutexes are
 //! wrapped to ensure safe interrupt handling inside the kernel), but they
 //! are always at least able to be used in both kernel and user mode.
+//!
+//! Everything that doesn't start with `Spin` puts waiting threads to sleep
+//! instead, through whatever [`Park`] implementation it's given.
 
 mod basic_mutex;
+mod basic_rwlock;
+mod condvar;
+mod mcs_mutex;
+mod mutex;
+mod rcu;
+mod semaphore;
+mod seqlock;
 mod spin_mutex;
+mod spin_rwlock;
+mod wait_queue;
 
 pub use basic_mutex::*;
+pub use basic_rwlock::*;
+pub use condvar::Condvar;
+pub use mcs_mutex::McsMutex;
+pub use mutex::Mutex;
+pub use rcu::{RcuDomain, RcuPtr, RcuReadGuard};
+pub use semaphore::Semaphore;
+pub use seqlock::SeqLock;
 pub use spin_mutex::{SpinFairMutex, SpinMutex};
+pub use spin_rwlock::{SpinFairRwLock, SpinRwLock};
+pub use wait_queue::{Park, WaitQueue, WaitResult};
@@ -0,0 +1,205 @@
+//======---------------------------------------------------------------======//
+//                                                                           //
+// Copyright 2022-2023 Evan Cox <evanacox00@gmail.com>. All rights reserved. //
+//                                                                           //
+// Use of this source code is governed by a BSD-style license that can be    //
+// found in the LICENSE.txt file at the root of this project, or at the      //
+// following link: https://opensource.org/licenses/BSD-3-Clause              //
+//                                                                           //
+//======---------------------------------------------------------------======//
+
+use crate::model::atomic::{AtomicU8, Ordering};
+use crate::model::{const_fn_unless_loom, UnsafeCell};
+use crate::sync::basic_mutex::{BasicMutex, MutexGuard};
+use crate::sync::wait_queue::{Park, WaitQueue, WaitResult};
+
+const UNLOCKED: u8 = 0;
+const LOCKED: u8 = 1;
+// locked, and there might be threads in the queue
+const CONTENDED: u8 = 2;
+
+/// A mutex that puts threads to sleep while they wait for it.
+///
+/// Waiters are queued in FIFO order, and an unlock hands the lock directly
+/// to the thread that has been waiting the longest. Nothing can barge in
+/// ahead of a queued waiter, so this is "fair" the same way that
+/// [`SpinFairMutex`](crate::sync::SpinFairMutex) is.
+///
+/// Uncontended locking and unlocking never touches the queue.
+pub struct Mutex<T, P: Park> {
+    data: UnsafeCell<T>,
+    state: AtomicU8,
+    waiters: WaitQueue<P>,
+}
+
+impl<T, P: Park> Mutex<T, P> {
+    const_fn_unless_loom! {
+        /// Creates a new mutex instance with a given initial value
+        /// for the held object.
+        ///
+        /// The lock starts in the "unlocked" state.
+        pub const fn new(value: T) -> Self {
+            Self {
+                data: UnsafeCell::new(value),
+                state: AtomicU8::new(UNLOCKED),
+                waiters: WaitQueue::new(),
+            }
+        }
+    }
+
+    #[inline]
+    fn try_acquire(&self) -> bool {
+        self.state
+            .compare_exchange(UNLOCKED, LOCKED, Ordering::Acquire, Ordering::Relaxed)
+            .is_ok()
+    }
+
+    fn lock_until(&self, deadline: Option<u64>) -> bool {
+        if self.try_acquire() {
+            return true;
+        }
+
+        // this runs with the queue locked. either the lock is free and we
+        // take it, or we mark it as contended so that the owner knows to
+        // look in the queue when it unlocks. taking it as `CONTENDED` is
+        // fine, it just sends our own unlock down the slow path
+        let must_wait = || self.state.swap(CONTENDED, Ordering::Acquire) != UNLOCKED;
+
+        // being woken up means the lock was handed to us
+        self.waiters.wait_if(must_wait, deadline) != WaitResult::TimedOut
+    }
+}
+
+impl<T: Send, P: Park> Mutex<T, P> {
+    /// Attempts to lock the mutex, sleeping until it's available or until
+    /// `nanos` nanoseconds (as measured by [`Park::now`]) have passed.
+    ///
+    /// Returns `None` if the lock couldn't be acquired in time.
+    pub fn try_lock_for(&self, nanos: u64) -> Option<MutexGuard<'_, Self, T>> {
+        let deadline = P::now().saturating_add(nanos);
+
+        self.lock_until(Some(deadline))
+            .then(|| unsafe { MutexGuard::new_from_unlocked(self) })
+    }
+}
+
+impl<T: Send, P: Park> BasicMutex<T> for Mutex<T, P> {
+    fn lock(&self) -> MutexGuard<'_, Self, T> {
+        self.lock_until(None);
+
+        unsafe { MutexGuard::new_from_unlocked(self) }
+    }
+
+    fn try_lock(&self) -> Option<MutexGuard<'_, Self, T>> {
+        self.try_acquire()
+            .then(|| unsafe { MutexGuard::new_from_unlocked(self) })
+    }
+
+    unsafe fn unlock_unchecked(&self) {
+        if self
+            .state
+            .compare_exchange(LOCKED, UNLOCKED, Ordering::Release, Ordering::Relaxed)
+            .is_ok()
+        {
+            return;
+        }
+
+        // if anyone is queued, the lock stays locked and becomes theirs.
+        // the state stays `CONTENDED` even if the queue is now empty, which
+        // just means that the next unlock takes this path for nothing
+        self.waiters
+            .notify_one_or(|| self.state.store(UNLOCKED, Ordering::Release));
+    }
+
+    unsafe fn data_unguarded(&self) -> &T {
+        self.data.with(|data| &*data)
+    }
+
+    unsafe fn data_mut_unguarded(&self) -> &mut T {
+        self.data.with_mut(|data| &mut *data)
+    }
+}
+
+// the queue only points at waiters while they're blocked on this mutex
+unsafe impl<T: Send, P: Park> Send for Mutex<T, P> {}
+
+unsafe impl<T: Send, P: Park> Sync for Mutex<T, P> {}
+
+impl<T: Default, P: Park> Default for Mutex<T, P> {
+    fn default() -> Self {
+        Self::new(T::default())
+    }
+}
+
+#[cfg(all(test, not(loom)))]
+mod tests {
+    use super::*;
+    use crate::sync::wait_queue::tests::{wait_for_waiters, StdPark};
+    use std::sync::Arc;
+    use std::thread;
+
+    #[test]
+    fn excludes() {
+        let mutex = Arc::new(Mutex::<usize, StdPark>::new(0));
+        let threads: Vec<_> = (0..4)
+            .map(|_| {
+                let mutex = Arc::clone(&mutex);
+
+                thread::spawn(move || {
+                    for _ in 0..1000 {
+                        *mutex.lock() += 1;
+                    }
+                })
+            })
+            .collect();
+
+        for thread in threads {
+            thread.join().unwrap();
+        }
+
+        assert_eq!(*mutex.lock(), 4000);
+    }
+
+    #[test]
+    fn try_lock_fails_while_locked() {
+        let mutex = Mutex::<i32, StdPark>::new(0);
+        let guard = mutex.lock();
+
+        assert!(mutex.try_lock().is_none());
+
+        drop(guard);
+
+        assert!(mutex.try_lock().is_some());
+    }
+
+    #[test]
+    fn try_lock_for_times_out() {
+        let mutex = Mutex::<i32, StdPark>::new(0);
+        let _guard = mutex.lock();
+
+        assert!(mutex.try_lock_for(1_000_000).is_none());
+    }
+
+    #[test]
+    fn unlock_hands_off_in_fifo_order() {
+        let mutex = Arc::new(Mutex::<Vec<usize>, StdPark>::new(Vec::new()));
+        let guard = mutex.lock();
+        let mut threads = Vec::new();
+
+        for i in 0..3 {
+            let waiter = Arc::clone(&mutex);
+
+            threads.push(thread::spawn(move || waiter.lock().push(i)));
+
+            wait_for_waiters(&mutex.waiters, i + 1);
+        }
+
+        drop(guard);
+
+        for thread in threads {
+            thread.join().unwrap();
+        }
+
+        assert_eq!(*mutex.lock(), [0, 1, 2]);
+    }
+}
@@ -0,0 +1,337 @@
+//======---------------------------------------------------------------======//
+//                                                                           //
+// Copyright 2022-2023 Evan Cox <evanacox00@gmail.com>. All rights reserved. //
+//                                                                           //
+// Use of this source code is governed by a BSD-style license that can be    //
+// found in the LICENSE.txt file at the root of this project, or at the      //
+// following link: https://opensource.org/licenses/BSD-3-Clause              //
+//                                                                           //
+//======---------------------------------------------------------------======//
+
+use crate::model::atomic::{self, AtomicPtr, AtomicU64, AtomicUsize, Ordering};
+use crate::model::{const_fn_unless_loom, hint};
+use core::marker::PhantomData;
+
+// a participant that's offline is permanently quiescent
+const OFFLINE: u64 = u64::MAX;
+
+struct Participant {
+    // the last grace period that the participant was quiescent in
+    seen: AtomicU64,
+    // only used to catch quiescent states inside of read-side sections
+    readers: AtomicUsize,
+}
+
+impl Participant {
+    const_fn_unless_loom! {
+        const fn new() -> Self {
+            Self {
+                seen: AtomicU64::new(OFFLINE),
+                readers: AtomicUsize::new(0),
+            }
+        }
+    }
+}
+
+#[cfg(not(loom))]
+const fn participants<const N: usize>() -> [Participant; N] {
+    [const { Participant::new() }; N]
+}
+
+#[cfg(loom)]
+fn participants<const N: usize>() -> [Participant; N] {
+    core::array::from_fn(|_| Participant::new())
+}
+
+/// Tracks grace periods for quiescent-state-based RCU (QSBR), with up to
+/// `N` participants (e.g. one per CPU).
+///
+/// Readers don't do anything to enter a read-side section, they just
+/// can't hold on to anything they read past a *quiescent state*, which
+/// every online participant has to report regularly with
+/// [`quiescent`](Self::quiescent). A writer that unpublishes something
+/// calls [`synchronize`](Self::synchronize), which waits until every
+/// online participant has been quiescent at least once. After that,
+/// nothing can still be reading it, and it can be freed.
+///
+/// Participants start out offline. An offline participant can't read
+/// anything, but it doesn't hold up grace periods either, which is what
+/// something that's about to sleep for a while wants.
+pub struct RcuDomain<const N: usize> {
+    grace_period: AtomicU64,
+    participants: [Participant; N],
+}
+
+impl<const N: usize> RcuDomain<N> {
+    const_fn_unless_loom! {
+        /// Creates a domain where every participant is offline.
+        pub const fn new() -> Self {
+            Self {
+                grace_period: AtomicU64::new(0),
+                participants: participants(),
+            }
+        }
+    }
+
+    /// Brings participant `id` online, after which it can read.
+    ///
+    /// # Panics
+    /// Panics if `id` is not less than `N`.
+    pub fn online(&self, id: usize) {
+        let participant = &self.participants[id];
+
+        participant
+            .seen
+            .store(self.grace_period.load(Ordering::SeqCst), Ordering::SeqCst);
+
+        // pairs with the fence in `synchronize`: either the writer sees
+        // that we're online, or we see what it published
+        atomic::fence(Ordering::SeqCst);
+    }
+
+    /// Takes participant `id` offline. It stops holding up grace periods,
+    /// and it can't read until it comes back online.
+    ///
+    /// # Panics
+    /// Panics if `id` is not less than `N`.
+    pub fn offline(&self, id: usize) {
+        let participant = &self.participants[id];
+
+        debug_assert_eq!(
+            participant.readers.load(Ordering::Relaxed),
+            0,
+            "went offline inside of an rcu read-side section"
+        );
+
+        participant.seen.store(OFFLINE, Ordering::Release);
+    }
+
+    /// Checks whether participant `id` is online.
+    ///
+    /// # Panics
+    /// Panics if `id` is not less than `N`.
+    #[must_use]
+    pub fn is_online(&self, id: usize) -> bool {
+        self.participants[id].seen.load(Ordering::Relaxed) != OFFLINE
+    }
+
+    /// Reports that participant `id` isn't holding on to anything that
+    /// it read, letting any pending grace period complete.
+    ///
+    /// Does nothing if the participant is offline.
+    ///
+    /// # Panics
+    /// Panics if `id` is not less than `N`.
+    pub fn quiescent(&self, id: usize) {
+        let participant = &self.participants[id];
+
+        debug_assert_eq!(
+            participant.readers.load(Ordering::Relaxed),
+            0,
+            "reported a quiescent state inside of an rcu read-side section"
+        );
+
+        if participant.seen.load(Ordering::Relaxed) != OFFLINE {
+            // everything that was read before this is done with once
+            // a writer sees the new value
+            participant
+                .seen
+                .store(self.grace_period.load(Ordering::SeqCst), Ordering::Release);
+        }
+    }
+
+    /// Marks the start of a read-side section for participant `id`.
+    ///
+    /// This costs nothing with QSBR, but the guard bounds the lifetime of
+    /// references handed out by [`RcuPtr::read`], and quiescent states
+    /// reported while it's alive are caught in debug builds.
+    ///
+    /// # Panics
+    /// Panics if `id` is not less than `N`.
+    pub fn read_lock(&self, id: usize) -> RcuReadGuard<'_, N> {
+        self.participants[id]
+            .readers
+            .fetch_add(1, Ordering::Relaxed);
+
+        RcuReadGuard {
+            domain: self,
+            id,
+            _not_send: PhantomData,
+        }
+    }
+
+    /// Waits until every online participant has been quiescent at least
+    /// once, spinning in the meantime.
+    ///
+    /// If the caller is a participant itself, it should pass its own `id`.
+    /// It's quiescent by definition (it isn't inside of a read-side section),
+    /// so it isn't waited on.
+    ///
+    /// # Panics
+    /// Panics if `current` is not less than `N`.
+    pub fn synchronize(&self, current: Option<usize>) {
+        if let Some(id) = current {
+            debug_assert_eq!(
+                self.participants[id].readers.load(Ordering::Relaxed),
+                0,
+                "waited for a grace period inside of an rcu read-side section"
+            );
+        }
+
+        let target = self.grace_period.fetch_add(1, Ordering::SeqCst) + 1;
+
+        // pairs with the fence in `online`
+        atomic::fence(Ordering::SeqCst);
+
+        for (id, participant) in self.participants.iter().enumerate() {
+            if current == Some(id) {
+                continue;
+            }
+
+            // `OFFLINE` is greater than any grace period
+            while participant.seen.load(Ordering::Acquire) < target {
+                hint::spin_loop();
+            }
+        }
+    }
+}
+
+impl<const N: usize> Default for RcuDomain<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Marks a read-side section, see [`RcuDomain::read_lock`].
+#[must_use]
+pub struct RcuReadGuard<'a, const N: usize> {
+    domain: &'a RcuDomain<N>,
+    id: usize,
+    // the section belongs to whatever participant started it
+    _not_send: PhantomData<*const ()>,
+}
+
+impl<const N: usize> Drop for RcuReadGuard<'_, N> {
+    fn drop(&mut self) {
+        self.domain.participants[self.id]
+            .readers
+            .fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// A pointer that's read under RCU protection.
+///
+/// Writers publish a new value with [`replace`](Self::replace), and can
+/// only free the old one after a grace period.
+pub struct RcuPtr<T> {
+    ptr: AtomicPtr<T>,
+    _owns: PhantomData<T>,
+}
+
+impl<T> RcuPtr<T> {
+    const_fn_unless_loom! {
+        /// Creates a pointer that initially points at `ptr`.
+        ///
+        /// # Safety
+        /// `ptr` must either be null, or be valid for reads until it's
+        /// replaced and a grace period has passed.
+        pub const unsafe fn new(ptr: *mut T) -> Self {
+            Self {
+                ptr: AtomicPtr::new(ptr),
+                _owns: PhantomData,
+            }
+        }
+    }
+
+    /// Reads the pointer, returning `None` if it's null.
+    ///
+    /// The reference can't outlive the read-side section.
+    #[must_use]
+    pub fn read<'g, const N: usize>(&self, _guard: &'g RcuReadGuard<'_, N>) -> Option<&'g T> {
+        unsafe { self.ptr.load(Ordering::Acquire).as_ref() }
+    }
+
+    /// Publishes `new`, and returns the old pointer. Readers might still
+    /// be using the old pointer until the next grace period ends.
+    ///
+    /// # Safety
+    /// `new` must satisfy the same requirements as in [`new`](Self::new).
+    pub unsafe fn replace(&self, new: *mut T) -> *mut T {
+        self.ptr.swap(new, Ordering::AcqRel)
+    }
+}
+
+#[cfg(all(test, not(loom)))]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use std::thread;
+
+    #[test]
+    fn offline_participants_dont_block() {
+        let domain = RcuDomain::<4>::new();
+
+        domain.online(0);
+
+        assert!(domain.is_online(0));
+        assert!(!domain.is_online(1));
+
+        // only participant 0 is online, and it's the one waiting
+        domain.synchronize(Some(0));
+    }
+
+    #[test]
+    fn synchronize_waits_for_quiescent_states() {
+        let domain = Arc::new(RcuDomain::<2>::new());
+        let data = Box::into_raw(Box::new(1));
+        let ptr = Arc::new(unsafe { RcuPtr::new(data) });
+
+        domain.online(0);
+        domain.online(1);
+
+        let reader = {
+            let domain = Arc::clone(&domain);
+            let ptr = Arc::clone(&ptr);
+
+            thread::spawn(move || {
+                let mut value = 1;
+
+                while value == 1 {
+                    let guard = domain.read_lock(1);
+
+                    value = *ptr.read(&guard).unwrap();
+
+                    drop(guard);
+                    domain.quiescent(1);
+                }
+
+                domain.offline(1);
+
+                value
+            })
+        };
+
+        let old = unsafe { ptr.replace(Box::into_raw(Box::new(2))) };
+
+        domain.synchronize(Some(0));
+
+        drop(unsafe { Box::from_raw(old) });
+
+        assert_eq!(reader.join().unwrap(), 2);
+
+        drop(unsafe { Box::from_raw(ptr.replace(std::ptr::null_mut())) });
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic = "read-side section"]
+    fn quiescent_inside_a_reader_panics() {
+        let domain = RcuDomain::<1>::new();
+
+        domain.online(0);
+
+        let _guard = domain.read_lock(0);
+
+        domain.quiescent(0);
+    }
+}
@@ -0,0 +1,134 @@
+//======---------------------------------------------------------------======//
+//                                                                           //
+// Copyright 2022-2023 Evan Cox <evanacox00@gmail.com>. All rights reserved. //
+//                                                                           //
+// Use of this source code is governed by a BSD-style license that can be    //
+// found in the LICENSE.txt file at the root of this project, or at the      //
+// following link: https://opensource.org/licenses/BSD-3-Clause              //
+//                                                                           //
+//======---------------------------------------------------------------======//
+
+use crate::model::atomic::{AtomicUsize, Ordering};
+use crate::model::const_fn_unless_loom;
+use crate::sync::wait_queue::{Park, WaitQueue, WaitResult};
+
+/// A counting semaphore that puts threads to sleep while they wait
+/// for a permit.
+///
+/// Like [`Mutex`](crate::sync::Mutex), waiters are queued in FIFO order
+/// and a released permit goes directly to the thread that has been
+/// waiting the longest.
+pub struct Semaphore<P: Park> {
+    permits: AtomicUsize,
+    waiters: WaitQueue<P>,
+}
+
+impl<P: Park> Semaphore<P> {
+    const_fn_unless_loom! {
+        /// Creates a semaphore with `permits` permits available.
+        pub const fn new(permits: usize) -> Self {
+            Self {
+                permits: AtomicUsize::new(permits),
+                waiters: WaitQueue::new(),
+            }
+        }
+    }
+
+    /// Takes a permit, sleeping until one is available.
+    pub fn acquire(&self) {
+        self.acquire_until(None);
+    }
+
+    /// Takes a permit if one is available, returning whether it did.
+    pub fn try_acquire(&self) -> bool {
+        let mut permits = self.permits.load(Ordering::Relaxed);
+
+        while permits != 0 {
+            match self.permits.compare_exchange_weak(
+                permits,
+                permits - 1,
+                Ordering::Acquire,
+                Ordering::Relaxed,
+            ) {
+                Ok(_) => return true,
+                Err(current) => permits = current,
+            }
+        }
+
+        false
+    }
+
+    /// Takes a permit, sleeping until one is available or until `nanos`
+    /// nanoseconds (as measured by [`Park::now`]) have passed.
+    ///
+    /// Returns whether a permit was taken.
+    pub fn try_acquire_for(&self, nanos: u64) -> bool {
+        self.acquire_until(Some(P::now().saturating_add(nanos)))
+    }
+
+    /// Gives a permit back, waking up the thread that has been waiting
+    /// for one the longest.
+    pub fn release(&self) {
+        // the permit only goes back in the pool if nobody is waiting on it
+        self.waiters
+            .notify_one_or(|| _ = self.permits.fetch_add(1, Ordering::Release));
+    }
+
+    /// The number of permits that are currently available.
+    pub fn available(&self) -> usize {
+        self.permits.load(Ordering::Relaxed)
+    }
+
+    fn acquire_until(&self, deadline: Option<u64>) -> bool {
+        if self.try_acquire() {
+            return true;
+        }
+
+        // being woken up means the permit was handed to us
+        self.waiters.wait_if(|| !self.try_acquire(), deadline) != WaitResult::TimedOut
+    }
+}
+
+#[cfg(all(test, not(loom)))]
+mod tests {
+    use super::*;
+    use crate::sync::wait_queue::tests::StdPark;
+    use std::sync::Arc;
+    use std::thread;
+
+    #[test]
+    fn counts_permits() {
+        let semaphore = Semaphore::<StdPark>::new(2);
+
+        assert!(semaphore.try_acquire());
+        assert!(semaphore.try_acquire());
+        assert!(!semaphore.try_acquire());
+
+        semaphore.release();
+
+        assert_eq!(semaphore.available(), 1);
+        assert!(semaphore.try_acquire());
+    }
+
+    #[test]
+    fn try_acquire_for_times_out() {
+        let semaphore = Semaphore::<StdPark>::new(0);
+
+        assert!(!semaphore.try_acquire_for(1_000_000));
+    }
+
+    #[test]
+    fn release_wakes_a_waiter() {
+        let semaphore = Arc::new(Semaphore::<StdPark>::new(0));
+        let waiter = {
+            let semaphore = Arc::clone(&semaphore);
+
+            thread::spawn(move || semaphore.acquire())
+        };
+
+        semaphore.release();
+        waiter.join().unwrap();
+
+        assert_eq!(semaphore.available(), 0);
+    }
+}
@@ -0,0 +1,208 @@
+//======---------------------------------------------------------------======//
+//                                                                           //
+// Copyright 2022-2023 Evan Cox <evanacox00@gmail.com>. All rights reserved. //
+//                                                                           //
+// Use of this source code is governed by a BSD-style license that can be    //
+// found in the LICENSE.txt file at the root of this project, or at the      //
+// following link: https://opensource.org/licenses/BSD-3-Clause              //
+//                                                                           //
+//======---------------------------------------------------------------======//
+
+use core::cell::UnsafeCell;
+use core::hint;
+use core::mem::MaybeUninit;
+use core::ptr;
+use core::sync::atomic::{self, AtomicUsize, Ordering};
+
+/// A sequence lock, for small `Copy` data that's read far more often
+/// than it's written.
+///
+/// Readers never write to shared memory and never block writers, they
+/// just copy the data out and retry if a write happened while they were
+/// copying. Writers are serialized with each other.
+///
+/// Readers can't make progress while a write is in progress, so in the
+/// kernel a writer must not be interruptible by anything that reads.
+///
+/// This doesn't use the loom shim, the reads are intentionally racy
+/// and that's exactly what loom would complain about.
+pub struct SeqLock<T: Copy> {
+    // odd while a write is in progress
+    seq: AtomicUsize,
+    data: UnsafeCell<T>,
+}
+
+// bumps the sequence number even if the update panics, otherwise
+// readers would spin forever
+struct EndWrite<'a>(&'a AtomicUsize, usize);
+
+impl Drop for EndWrite<'_> {
+    fn drop(&mut self) {
+        self.0.store(self.1.wrapping_add(1), Ordering::Release);
+    }
+}
+
+impl<T: Copy> SeqLock<T> {
+    /// Creates a new sequence lock holding `value`.
+    pub const fn new(value: T) -> Self {
+        Self {
+            seq: AtomicUsize::new(0),
+            data: UnsafeCell::new(value),
+        }
+    }
+
+    /// Reads a consistent copy of the data, spinning while a write
+    /// is in progress.
+    pub fn read(&self) -> T {
+        loop {
+            if let Some(value) = self.try_read() {
+                return value;
+            }
+
+            hint::spin_loop();
+        }
+    }
+
+    /// Makes one attempt to read the data, returning `None` if a write
+    /// was in progress at any point while reading.
+    pub fn try_read(&self) -> Option<T> {
+        let start = self.seq.load(Ordering::Acquire);
+
+        if start & 1 != 0 {
+            return None;
+        }
+
+        // this can race with a writer, so it's read as bytes that might be
+        // torn and only treated as a `T` once the sequence number says it wasn't
+        let value = unsafe { ptr::read_volatile(self.data.get().cast::<MaybeUninit<T>>()) };
+
+        atomic::fence(Ordering::Acquire);
+
+        (self.seq.load(Ordering::Relaxed) == start).then(|| unsafe { value.assume_init() })
+    }
+
+    /// Replaces the data with `value`.
+    pub fn write(&self, value: T) {
+        self.update(|data| *data = value);
+    }
+
+    /// Modifies the data in place. Readers retry until `f` is done.
+    pub fn update(&self, f: impl FnOnce(&mut T)) {
+        let seq = self.begin_write();
+        let _end = EndWrite(&self.seq, seq);
+
+        f(unsafe { &mut *self.data.get() });
+    }
+
+    /// Gets a mutable reference to the data. No locking is needed,
+    /// since the borrow checker proves that nothing else can access it.
+    pub const fn get_mut(&mut self) -> &mut T {
+        self.data.get_mut()
+    }
+
+    // makes the sequence number odd, waiting for any other writer to finish
+    fn begin_write(&self) -> usize {
+        loop {
+            let seq = self.seq.load(Ordering::Relaxed);
+
+            if seq & 1 == 0
+                && self
+                    .seq
+                    .compare_exchange_weak(seq, seq + 1, Ordering::Acquire, Ordering::Relaxed)
+                    .is_ok()
+            {
+                // the odd sequence number has to be visible before any
+                // of the writes to the data are
+                atomic::fence(Ordering::Release);
+
+                return seq + 1;
+            }
+
+            hint::spin_loop();
+        }
+    }
+}
+
+unsafe impl<T: Copy + Send> Send for SeqLock<T> {}
+
+unsafe impl<T: Copy + Send> Sync for SeqLock<T> {}
+
+impl<T: Copy + Default> Default for SeqLock<T> {
+    fn default() -> Self {
+        Self::new(T::default())
+    }
+}
+
+#[cfg(all(test, not(loom)))]
+mod tests {
+    use super::*;
+    use std::panic::{self, AssertUnwindSafe};
+    use std::sync::atomic::AtomicBool;
+    use std::sync::Arc;
+    use std::thread;
+
+    #[test]
+    fn reads_what_was_written() {
+        let lock = SeqLock::new((1, 2));
+
+        assert_eq!(lock.read(), (1, 2));
+
+        lock.write((3, 4));
+        lock.update(|pair| pair.0 += 10);
+
+        assert_eq!(lock.read(), (13, 4));
+    }
+
+    #[test]
+    fn try_read_fails_during_a_write() {
+        let lock = SeqLock::new(0);
+
+        lock.update(|_| assert_eq!(lock.try_read(), None));
+
+        assert_eq!(lock.try_read(), Some(0));
+    }
+
+    #[test]
+    fn panicking_update_unlocks() {
+        let lock = SeqLock::new(5);
+        let result = panic::catch_unwind(AssertUnwindSafe(|| lock.update(|_| panic!("oops"))));
+
+        assert!(result.is_err());
+        assert_eq!(lock.read(), 5);
+    }
+
+    #[test]
+    fn readers_never_see_torn_writes() {
+        let lock = Arc::new(SeqLock::new([0u64; 4]));
+        let done = Arc::new(AtomicBool::new(false));
+        let readers: Vec<_> = (0..2)
+            .map(|_| {
+                let lock = Arc::clone(&lock);
+                let done = Arc::clone(&done);
+
+                thread::spawn(move || {
+                    let mut last = 0;
+
+                    while !done.load(Ordering::Relaxed) {
+                        let value = lock.read();
+
+                        assert!(value.iter().all(|&x| x == value[0]));
+                        assert!(value[0] >= last);
+
+                        last = value[0];
+                    }
+                })
+            })
+            .collect();
+
+        for i in 1..=10_000 {
+            lock.write([i; 4]);
+        }
+
+        done.store(true, Ordering::Relaxed);
+
+        for reader in readers {
+            reader.join().unwrap();
+        }
+    }
+}
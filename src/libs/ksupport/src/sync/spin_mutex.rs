@@ -8,10 +8,9 @@
 //                                                                           //
 //======---------------------------------------------------------------======//
 
+use crate::model::atomic::{AtomicBool, AtomicUsize, Ordering};
+use crate::model::{const_fn_unless_loom, hint, UnsafeCell};
 use crate::sync::basic_mutex::{BasicMutex, MutexGuard};
-use core::cell::UnsafeCell;
-use core::hint;
-use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 
 /// A basic spinlock-based mutex.
 ///
@@ -27,16 +26,27 @@ pub struct SpinMutex<T> {
 }
 
 impl<T> SpinMutex<T> {
-    /// Creates a new mutex instance with a given initial value
-    /// for the held object.
-    ///
-    /// The lock starts in the "unlocked" state.
-    pub const fn new(value: T) -> Self {
-        Self {
-            data: UnsafeCell::new(value),
-            locked: AtomicBool::new(false),
+    const_fn_unless_loom! {
+        /// Creates a new mutex instance with a given initial value
+        /// for the held object.
+        ///
+        /// The lock starts in the "unlocked" state.
+        pub const fn new(value: T) -> Self {
+            Self {
+                data: UnsafeCell::new(value),
+                locked: AtomicBool::new(false),
+            }
         }
     }
+
+    // a failed exchange doesn't write to the flag, unlike a `swap`, so
+    // waiters don't keep stealing the cache line from each other
+    #[inline]
+    fn try_acquire(&self) -> bool {
+        self.locked
+            .compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed)
+            .is_ok()
+    }
 }
 
 impl<T> BasicMutex<T> for SpinMutex<T> {
@@ -49,7 +59,7 @@ impl<T> BasicMutex<T> for SpinMutex<T> {
         // as multiple threads won't constantly be fighting over a single
         // cache line trying to store `true` into it.
         loop {
-            if !self.locked.swap(true, Ordering::Acquire) {
+            if self.try_acquire() {
                 return unsafe { MutexGuard::new_from_unlocked(self) };
             }
 
@@ -61,7 +71,7 @@ impl<T> BasicMutex<T> for SpinMutex<T> {
 
     fn try_lock(&self) -> Option<MutexGuard<'_, Self, T>> {
         // pre-emptively load, helps in the case where `try_lock` is in a loop
-        if !self.locked.load(Ordering::Relaxed) && !self.locked.swap(true, Ordering::Acquire) {
+        if !self.locked.load(Ordering::Relaxed) && self.try_acquire() {
             Some(unsafe { MutexGuard::new_from_unlocked(self) })
         } else {
             None
@@ -74,11 +84,11 @@ impl<T> BasicMutex<T> for SpinMutex<T> {
     }
 
     unsafe fn data_unguarded(&self) -> &T {
-        &*self.data.get()
+        self.data.with(|data| &*data)
     }
 
     unsafe fn data_mut_unguarded(&self) -> &mut T {
-        &mut *self.data.get()
+        self.data.with_mut(|data| &mut *data)
     }
 }
 
@@ -109,17 +119,32 @@ pub struct SpinFairMutex<T> {
 }
 
 impl<T> SpinFairMutex<T> {
-    /// Creates a new mutex instance with a given initial value
-    /// for the held object.
-    ///
-    /// The lock starts in the "unlocked" state.
-    pub const fn new(value: T) -> Self {
-        Self {
-            data: UnsafeCell::new(value),
-            count: AtomicUsize::new(0),
-            current: AtomicUsize::new(0),
+    const_fn_unless_loom! {
+        /// Creates a new mutex instance with a given initial value
+        /// for the held object.
+        ///
+        /// The lock starts in the "unlocked" state.
+        pub const fn new(value: T) -> Self {
+            Self {
+                data: UnsafeCell::new(value),
+                count: AtomicUsize::new(0),
+                current: AtomicUsize::new(0),
+            }
         }
     }
+
+    /// Checks whether any thread is waiting for the lock, i.e. whether any
+    /// ticket after the one being served has been handed out.
+    ///
+    /// This can be out of date as soon as it returns, it's meant for things
+    /// like a long critical section briefly unlocking to let waiters in.
+    #[inline]
+    #[must_use]
+    pub fn is_contended(&self) -> bool {
+        let current = self.current.load(Ordering::Relaxed);
+
+        self.count.load(Ordering::Relaxed).wrapping_sub(current) > 1
+    }
 }
 impl<T> BasicMutex<T> for SpinFairMutex<T> {
     fn lock(&self) -> MutexGuard<'_, Self, T> {
@@ -164,11 +189,11 @@ impl<T> BasicMutex<T> for SpinFairMutex<T> {
     }
 
     unsafe fn data_unguarded(&self) -> &T {
-        &*self.data.get()
+        self.data.with(|data| &*data)
     }
 
     unsafe fn data_mut_unguarded(&self) -> &mut T {
-        &mut *self.data.get()
+        self.data.with_mut(|data| &mut *data)
     }
 }
 
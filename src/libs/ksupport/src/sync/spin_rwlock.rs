@@ -0,0 +1,483 @@
+//======---------------------------------------------------------------======//
+//                                                                           //
+// Copyright 2022-2023 Evan Cox <evanacox00@gmail.com>. All rights reserved. //
+//                                                                           //
+// Use of this source code is governed by a BSD-style license that can be    //
+// found in the LICENSE.txt file at the root of this project, or at the      //
+// following link: https://opensource.org/licenses/BSD-3-Clause              //
+//                                                                           //
+//======---------------------------------------------------------------======//
+
+use crate::sync::basic_rwlock::{
+    BasicRwLock, RwLockReadGuard, RwLockUpgradeableGuard, RwLockWriteGuard,
+};
+use core::cell::UnsafeCell;
+use core::hint;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+// the lock state is packed into one word: the low bit is set while a writer
+// holds the lock, the next bit is set while an upgradeable reader holds it
+// (or while one is waiting to upgrade), and the rest is a count of readers.
+const WRITER: usize = 1;
+const UPGRADED: usize = 1 << 1;
+const READER: usize = 1 << 2;
+
+// the actual lock, `PREFER_WRITERS` decides whether new readers are held
+// back while a writer is waiting for the lock.
+struct RawSpinRwLock<const PREFER_WRITERS: bool> {
+    state: AtomicUsize,
+    writers_waiting: AtomicUsize,
+}
+
+impl<const PREFER_WRITERS: bool> RawSpinRwLock<PREFER_WRITERS> {
+    const fn new() -> Self {
+        Self {
+            state: AtomicUsize::new(0),
+            writers_waiting: AtomicUsize::new(0),
+        }
+    }
+
+    fn try_read(&self) -> bool {
+        if PREFER_WRITERS && self.writers_waiting.load(Ordering::Relaxed) != 0 {
+            return false;
+        }
+
+        // optimistically become a reader, and back out if a writer beat us.
+        // the upgraded bit also blocks new readers so that an upgrade
+        // can't be starved forever
+        let state = self.state.fetch_add(READER, Ordering::Acquire);
+
+        if state & (WRITER | UPGRADED) == 0 {
+            true
+        } else {
+            self.state.fetch_sub(READER, Ordering::Release);
+
+            false
+        }
+    }
+
+    fn read(&self) {
+        // same TTAS idea as `SpinMutex`, only retry once it looks like
+        // the attempt would actually succeed
+        while !self.try_read() {
+            while self.state.load(Ordering::Relaxed) & (WRITER | UPGRADED) != 0
+                || (PREFER_WRITERS && self.writers_waiting.load(Ordering::Relaxed) != 0)
+            {
+                hint::spin_loop();
+            }
+        }
+    }
+
+    fn try_write(&self) -> bool {
+        self.state
+            .compare_exchange(0, WRITER, Ordering::Acquire, Ordering::Relaxed)
+            .is_ok()
+    }
+
+    fn write(&self) {
+        if self.try_write() {
+            return;
+        }
+
+        if PREFER_WRITERS {
+            self.writers_waiting.fetch_add(1, Ordering::Relaxed);
+        }
+
+        loop {
+            while self.state.load(Ordering::Relaxed) != 0 {
+                hint::spin_loop();
+            }
+
+            if self.try_write() {
+                break;
+            }
+        }
+
+        if PREFER_WRITERS {
+            self.writers_waiting.fetch_sub(1, Ordering::Relaxed);
+        }
+    }
+
+    fn try_upgradeable_read(&self) -> bool {
+        // if either bit was already set, someone else owns it and we didn't
+        // change anything by setting it again
+        self.state.fetch_or(UPGRADED, Ordering::Acquire) & (WRITER | UPGRADED) == 0
+    }
+
+    fn upgradeable_read(&self) {
+        while !self.try_upgradeable_read() {
+            while self.state.load(Ordering::Relaxed) & (WRITER | UPGRADED) != 0 {
+                hint::spin_loop();
+            }
+        }
+    }
+
+    fn try_upgrade(&self) -> bool {
+        self.state
+            .compare_exchange(UPGRADED, WRITER, Ordering::Acquire, Ordering::Relaxed)
+            .is_ok()
+    }
+
+    fn upgrade(&self) {
+        // nobody else can take the upgraded bit, and new readers are kept
+        // out while it's set, so this only waits for existing readers
+        while !self.try_upgrade() {
+            hint::spin_loop();
+        }
+    }
+
+    fn unlock_read(&self) {
+        self.state.fetch_sub(READER, Ordering::Release);
+    }
+
+    fn unlock_write(&self) {
+        // clear the upgraded bit too, in case the writer came from an upgrade
+        self.state
+            .fetch_and(!(WRITER | UPGRADED), Ordering::Release);
+    }
+
+    fn unlock_upgradeable(&self) {
+        self.state.fetch_sub(UPGRADED, Ordering::Release);
+    }
+
+    fn downgrade_write(&self) {
+        // become a reader before letting go, so no writer can sneak in
+        self.state.fetch_add(READER, Ordering::Acquire);
+        self.unlock_write();
+    }
+
+    fn downgrade_upgradeable(&self) {
+        self.state.fetch_add(READER, Ordering::Acquire);
+        self.unlock_upgradeable();
+    }
+}
+
+macro_rules! spin_rwlock {
+    ($(#[$attr:meta])* $name:ident, $prefer_writers:literal) => {
+        $(#[$attr])*
+        pub struct $name<T> {
+            data: UnsafeCell<T>,
+            raw: RawSpinRwLock<$prefer_writers>,
+        }
+
+        impl<T> $name<T> {
+            /// Creates a new lock instance with a given initial value
+            /// for the held object.
+            ///
+            /// The lock starts in the "unlocked" state.
+            pub const fn new(value: T) -> Self {
+                Self {
+                    data: UnsafeCell::new(value),
+                    raw: RawSpinRwLock::new(),
+                }
+            }
+        }
+
+        impl<T: Send + Sync> BasicRwLock<T> for $name<T> {
+            fn read(&self) -> RwLockReadGuard<'_, Self, T> {
+                self.raw.read();
+
+                unsafe { RwLockReadGuard::new_from_unlocked(self) }
+            }
+
+            fn try_read(&self) -> Option<RwLockReadGuard<'_, Self, T>> {
+                self.raw
+                    .try_read()
+                    .then(|| unsafe { RwLockReadGuard::new_from_unlocked(self) })
+            }
+
+            fn write(&self) -> RwLockWriteGuard<'_, Self, T> {
+                self.raw.write();
+
+                unsafe { RwLockWriteGuard::new_from_unlocked(self) }
+            }
+
+            fn try_write(&self) -> Option<RwLockWriteGuard<'_, Self, T>> {
+                self.raw
+                    .try_write()
+                    .then(|| unsafe { RwLockWriteGuard::new_from_unlocked(self) })
+            }
+
+            fn upgradeable_read(&self) -> RwLockUpgradeableGuard<'_, Self, T> {
+                self.raw.upgradeable_read();
+
+                unsafe { RwLockUpgradeableGuard::new_from_unlocked(self) }
+            }
+
+            fn try_upgradeable_read(&self) -> Option<RwLockUpgradeableGuard<'_, Self, T>> {
+                self.raw
+                    .try_upgradeable_read()
+                    .then(|| unsafe { RwLockUpgradeableGuard::new_from_unlocked(self) })
+            }
+
+            unsafe fn unlock_read_unchecked(&self) {
+                self.raw.unlock_read();
+            }
+
+            unsafe fn unlock_write_unchecked(&self) {
+                self.raw.unlock_write();
+            }
+
+            unsafe fn unlock_upgradeable_unchecked(&self) {
+                self.raw.unlock_upgradeable();
+            }
+
+            unsafe fn upgrade_unchecked(&self) {
+                self.raw.upgrade();
+            }
+
+            unsafe fn try_upgrade_unchecked(&self) -> bool {
+                self.raw.try_upgrade()
+            }
+
+            unsafe fn downgrade_write_unchecked(&self) {
+                self.raw.downgrade_write();
+            }
+
+            unsafe fn downgrade_upgradeable_unchecked(&self) {
+                self.raw.downgrade_upgradeable();
+            }
+
+            unsafe fn data_unguarded(&self) -> &T {
+                &*self.data.get()
+            }
+
+            unsafe fn data_mut_unguarded(&self) -> &mut T {
+                &mut *self.data.get()
+            }
+        }
+
+        // the lock state is atomic, and the data is only reachable through guards
+        unsafe impl<T: Send> Send for $name<T> {}
+
+        // readers on different threads share `&T` at the same time
+        unsafe impl<T: Send + Sync> Sync for $name<T> {}
+
+        impl<T: Default> Default for $name<T> {
+            fn default() -> Self {
+                Self::new(T::default())
+            }
+        }
+    };
+}
+
+spin_rwlock!(
+    /// A basic spinlock-based reader-writer lock.
+    ///
+    /// Any number of readers can hold the lock at once, or a single writer.
+    /// One of the readers can also be an "upgradeable" reader, which can
+    /// later turn into a writer without letting another writer in first.
+    ///
+    /// Readers are preferred, so a steady stream of readers can starve
+    /// writers. See [`SpinFairRwLock`] if that's a problem.
+    ///
+    /// This is not interrupt-safe, kernel use must wrap interrupt
+    /// handling code around this to use it safely.
+    ///
+    /// Readers share the value between threads, so it has to be
+    /// `Send + Sync` for the lock to be `Sync`:
+    ///
+    /// ```compile_fail
+    /// # use ksupport::sync::SpinRwLock;
+    /// # use core::cell::Cell;
+    /// static LOCK: SpinRwLock<Cell<u32>> = SpinRwLock::new(Cell::new(0));
+    /// ```
+    SpinRwLock,
+    false
+);
+
+spin_rwlock!(
+    /// A spinlock-based reader-writer lock that prefers writers.
+    ///
+    /// This is the same as [`SpinRwLock`], except that new readers wait
+    /// while any writer is waiting for the lock. Writers can't be starved
+    /// by readers, but readers can be starved by a steady stream of writers.
+    ///
+    /// This is not interrupt-safe, kernel use must wrap interrupt
+    /// handling code around this to use it safely.
+    ///
+    /// Readers share the value between threads, so it has to be
+    /// `Send + Sync` for the lock to be `Sync`:
+    ///
+    /// ```compile_fail
+    /// # use ksupport::sync::SpinFairRwLock;
+    /// # use core::cell::Cell;
+    /// static LOCK: SpinFairRwLock<Cell<u32>> = SpinFairRwLock::new(Cell::new(0));
+    /// ```
+    SpinFairRwLock,
+    true
+);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::sync::atomic::AtomicBool;
+    use std::sync::Arc;
+    use std::thread;
+    use std::vec::Vec;
+
+    #[test]
+    fn readers_share() {
+        let lock = SpinRwLock::new(5);
+        let a = lock.read();
+        let b = lock.try_read().unwrap();
+
+        assert_eq!(*a + *b, 10);
+        assert!(lock.try_write().is_none());
+
+        drop((a, b));
+
+        assert!(lock.try_write().is_some());
+    }
+
+    #[test]
+    fn writer_excludes() {
+        let lock = SpinFairRwLock::new(0);
+        let mut w = lock.write();
+
+        *w = 3;
+
+        assert!(lock.try_read().is_none());
+        assert!(lock.try_write().is_none());
+        assert!(lock.try_upgradeable_read().is_none());
+
+        drop(w);
+
+        assert_eq!(*lock.read(), 3);
+    }
+
+    #[test]
+    fn upgradeable_is_exclusive_with_itself() {
+        let lock = SpinRwLock::new(());
+        let up = lock.upgradeable_read();
+
+        assert!(lock.try_upgradeable_read().is_none());
+        assert!(lock.try_write().is_none());
+
+        drop(up);
+
+        assert!(lock.try_upgradeable_read().is_some());
+    }
+
+    #[test]
+    fn upgrade_waits_for_readers() {
+        let lock = SpinRwLock::new(1);
+        let reader = lock.read();
+        let up = lock.upgradeable_read();
+
+        // new readers are held back while an upgradeable reader exists
+        assert!(lock.try_read().is_none());
+
+        let Err(up) = up.try_upgrade() else {
+            panic!("upgraded while a reader held the lock");
+        };
+
+        drop(reader);
+
+        let mut w = up.try_upgrade().ok().unwrap();
+
+        *w += 1;
+
+        let r = w.downgrade();
+
+        assert_eq!(*r, 2);
+        assert!(lock.try_read().is_some());
+        assert!(lock.try_write().is_none());
+
+        drop(r);
+
+        let r = lock.upgradeable_read().downgrade();
+
+        assert!(lock.try_upgradeable_read().is_some());
+        assert!(lock.try_write().is_none());
+
+        drop(r);
+
+        assert!(lock.try_write().is_some());
+    }
+
+    #[test]
+    fn waiting_writer_blocks_new_readers() {
+        let lock = Arc::new(SpinFairRwLock::new(0));
+        let reader = lock.read();
+        let done = Arc::new(AtomicBool::new(false));
+
+        let writer = {
+            let lock = Arc::clone(&lock);
+            let done = Arc::clone(&done);
+
+            thread::spawn(move || {
+                *lock.write() = 1;
+                done.store(true, Ordering::SeqCst);
+            })
+        };
+
+        while lock.raw.writers_waiting.load(Ordering::SeqCst) == 0 {
+            hint::spin_loop();
+        }
+
+        assert!(lock.try_read().is_none());
+        assert!(!done.load(Ordering::SeqCst));
+
+        drop(reader);
+        writer.join().unwrap();
+
+        assert_eq!(*lock.read(), 1);
+    }
+
+    fn stress<L: BasicRwLock<usize> + 'static>(lock: L) {
+        const THREADS: usize = 8;
+        const ITERATIONS: usize = 2000;
+
+        let lock = Arc::new(lock);
+        let threads: Vec<_> = (0..THREADS)
+            .map(|i| {
+                let lock = Arc::clone(&lock);
+
+                thread::spawn(move || {
+                    for j in 0..ITERATIONS {
+                        match (i + j) % 3 {
+                            0 => *lock.write() += 1,
+                            1 => {
+                                let up = lock.upgradeable_read();
+                                let before = *up;
+                                let mut w = up.upgrade();
+
+                                assert_eq!(*w, before);
+
+                                *w += 1;
+                            }
+                            _ => {
+                                let r = lock.read();
+
+                                assert!(*r <= THREADS * ITERATIONS);
+                            }
+                        }
+                    }
+                })
+            })
+            .collect();
+
+        for thread in threads {
+            thread.join().unwrap();
+        }
+
+        let expected = (0..THREADS)
+            .flat_map(|i| (0..ITERATIONS).map(move |j| (i + j) % 3))
+            .filter(|&kind| kind != 2)
+            .count();
+
+        assert_eq!(*lock.read(), expected);
+    }
+
+    #[test]
+    fn stress_rwlock() {
+        stress(SpinRwLock::new(0));
+    }
+
+    #[test]
+    fn stress_fair_rwlock() {
+        stress(SpinFairRwLock::new(0));
+    }
+}
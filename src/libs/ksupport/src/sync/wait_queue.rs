@@ -0,0 +1,454 @@
+//======---------------------------------------------------------------======//
+//                                                                           //
+// Copyright 2022-2023 Evan Cox <evanacox00@gmail.com>. All rights reserved. //
+//                                                                           //
+// Use of this source code is governed by a BSD-style license that can be    //
+// found in the LICENSE.txt file at the root of this project, or at the      //
+// following link: https://opensource.org/licenses/BSD-3-Clause              //
+//                                                                           //
+//======---------------------------------------------------------------======//
+
+use crate::model::atomic::{AtomicBool, Ordering};
+use crate::model::{const_fn_unless_loom, hint};
+use crate::sync::basic_mutex::BasicMutex;
+use crate::sync::spin_mutex::SpinMutex;
+use core::cell::Cell;
+use core::ptr;
+
+/// How a [`WaitQueue`] blocks and wakes up threads.
+///
+/// This is what ties the sleeping primitives to whatever is running
+/// them, e.g. a scheduler in the kernel or OS threads in tests.
+pub trait Park {
+    /// Identifies a thread that can be woken up.
+    type Token: Clone;
+
+    /// Gets the token for the current thread.
+    fn current() -> Self::Token;
+
+    /// Blocks the current thread until it's unparked, or until
+    /// [`now`](Self::now) reaches `deadline`.
+    ///
+    /// This is allowed to return spuriously, but an [`unpark`](Self::unpark)
+    /// that happens before the thread parks must make it return immediately.
+    fn park(deadline: Option<u64>);
+
+    /// Wakes up the thread that `token` identifies, or makes its next
+    /// [`park`](Self::park) return immediately if it isn't parked.
+    fn unpark(token: Self::Token);
+
+    /// The current time in nanoseconds. Every deadline is in terms of this.
+    fn now() -> u64;
+
+    /// Called before the queue's internal spinlock is taken.
+    ///
+    /// If threads can be notified from interrupt handlers, this needs to
+    /// disable interrupts until [`critical_exit`](Self::critical_exit).
+    #[inline]
+    fn critical_enter() {}
+
+    /// Called after the queue's internal spinlock is released.
+    #[inline]
+    fn critical_exit() {}
+}
+
+/// The ways that [`WaitQueue::wait_if`] can return.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum WaitResult {
+    /// The thread was woken up by a notification.
+    Woken,
+    /// The deadline passed before the thread was woken up.
+    TimedOut,
+    /// The condition was false, so the thread never waited.
+    Skipped,
+}
+
+// lives on the stack of the thread that's waiting, the queue only
+// links to it while the thread is blocked in `wait_if`
+struct Waiter<P: Park> {
+    token: P::Token,
+    notified: AtomicBool,
+    next: Cell<*const Self>,
+}
+
+struct Queue<P: Park> {
+    head: *const Waiter<P>,
+    tail: *const Waiter<P>,
+}
+
+impl<P: Park> Queue<P> {
+    unsafe fn push(&mut self, waiter: *const Waiter<P>) {
+        if self.tail.is_null() {
+            self.head = waiter;
+        } else {
+            (*self.tail).next.set(waiter);
+        }
+
+        self.tail = waiter;
+    }
+
+    const unsafe fn pop(&mut self) -> Option<*const Waiter<P>> {
+        let waiter = self.head;
+
+        if waiter.is_null() {
+            return None;
+        }
+
+        self.head = (*waiter).next.get();
+
+        if self.head.is_null() {
+            self.tail = ptr::null();
+        }
+
+        Some(waiter)
+    }
+
+    // O(n), but this only happens when a wait times out
+    unsafe fn remove(&mut self, waiter: *const Waiter<P>) -> bool {
+        let mut prev: *const Waiter<P> = ptr::null();
+        let mut curr = self.head;
+
+        while !curr.is_null() {
+            if curr == waiter {
+                let next = (*curr).next.get();
+
+                if prev.is_null() {
+                    self.head = next;
+                } else {
+                    (*prev).next.set(next);
+                }
+
+                if self.tail == curr {
+                    self.tail = prev;
+                }
+
+                return true;
+            }
+
+            prev = curr;
+            curr = (*curr).next.get();
+        }
+
+        false
+    }
+
+    const fn take(&mut self) -> *const Waiter<P> {
+        self.tail = ptr::null();
+
+        // the detached list is still linked through `next`
+        let head = self.head;
+
+        self.head = ptr::null();
+
+        head
+    }
+}
+
+// once `notified` is set, the waiter is free to return and destroy
+// itself, so everything needed from it has to be read before that
+unsafe fn wake<P: Park>(waiter: *const Waiter<P>) {
+    let token = (*waiter).token.clone();
+
+    (*waiter).notified.store(true, Ordering::Release);
+
+    P::unpark(token);
+}
+
+/// A FIFO queue of threads that are blocked waiting for something.
+///
+/// This is the building block for the sleeping primitives: threads put
+/// themselves on the queue with [`wait_if`](Self::wait_if) and are woken
+/// up in the order that they started waiting by [`notify_one`](Self::notify_one).
+///
+/// Waiters aren't allocated, every thread's entry lives on its own stack
+/// while it waits.
+pub struct WaitQueue<P: Park> {
+    queue: SpinMutex<Queue<P>>,
+}
+
+impl<P: Park> WaitQueue<P> {
+    const_fn_unless_loom! {
+        /// Creates an empty wait queue.
+        pub const fn new() -> Self {
+            Self {
+                queue: SpinMutex::new(Queue {
+                    head: ptr::null(),
+                    tail: ptr::null(),
+                }),
+            }
+        }
+    }
+
+    // runs `f` with the queue locked
+    fn with_queue<R>(&self, f: impl FnOnce(&mut Queue<P>) -> R) -> R {
+        P::critical_enter();
+
+        let result = f(&mut self.queue.lock());
+
+        P::critical_exit();
+
+        result
+    }
+
+    /// Blocks the current thread until it's notified, as long as `condition`
+    /// returns `true`. If `deadline` is given, the thread gives up waiting
+    /// once [`Park::now`] reaches it.
+    ///
+    /// `condition` runs with the queue locked, so a notification can't
+    /// happen between it returning `true` and the thread being queued.
+    /// This is what prevents lost wake-ups: anything that changes the
+    /// state that `condition` checks before notifying is guaranteed to
+    /// either be seen by `condition` or to find this thread in the queue.
+    pub fn wait_if(&self, condition: impl FnOnce() -> bool, deadline: Option<u64>) -> WaitResult {
+        let waiter = Waiter::<P> {
+            token: P::current(),
+            notified: AtomicBool::new(false),
+            next: Cell::new(ptr::null()),
+        };
+
+        let queued = self.with_queue(|queue| {
+            let should_wait = condition();
+
+            if should_wait {
+                unsafe { queue.push(ptr::from_ref(&waiter)) };
+            }
+
+            should_wait
+        });
+
+        if !queued {
+            return WaitResult::Skipped;
+        }
+
+        loop {
+            if waiter.notified.load(Ordering::Acquire) {
+                return WaitResult::Woken;
+            }
+
+            if deadline.is_some_and(|deadline| P::now() >= deadline) {
+                if self.with_queue(|queue| unsafe { queue.remove(ptr::from_ref(&waiter)) }) {
+                    return WaitResult::TimedOut;
+                }
+
+                // somebody already took us off the queue, and they're
+                // about to set `notified`. we can't leave until they do
+                while !waiter.notified.load(Ordering::Acquire) {
+                    hint::spin_loop();
+                }
+
+                return WaitResult::Woken;
+            }
+
+            P::park(deadline);
+        }
+    }
+
+    /// Wakes up the thread that has been waiting the longest, returning
+    /// whether there was one.
+    pub fn notify_one(&self) -> bool {
+        self.notify_one_or(|| {})
+    }
+
+    /// Wakes up the thread that has been waiting the longest. If nobody
+    /// is waiting, `otherwise` is called instead.
+    ///
+    /// `otherwise` runs with the queue locked, so no thread can start
+    /// waiting while it runs. This lets a resource be handed directly
+    /// to a waiter, and only be released if nobody wants it.
+    pub fn notify_one_or(&self, otherwise: impl FnOnce()) -> bool {
+        let waiter = self.with_queue(|queue| {
+            let waiter = unsafe { queue.pop() };
+
+            if waiter.is_none() {
+                otherwise();
+            }
+
+            waiter
+        });
+
+        let Some(waiter) = waiter else {
+            return false;
+        };
+
+        unsafe { wake(waiter) };
+
+        true
+    }
+
+    /// Wakes up every thread that's waiting, returning how many there were.
+    pub fn notify_all(&self) -> usize {
+        let mut waiter = self.with_queue(Queue::take);
+        let mut count = 0;
+
+        while !waiter.is_null() {
+            unsafe {
+                let next = (*waiter).next.get();
+
+                wake(waiter);
+
+                waiter = next;
+            }
+
+            count += 1;
+        }
+
+        count
+    }
+}
+
+impl<P: Park> Default for WaitQueue<P> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(all(test, not(loom)))]
+pub mod tests {
+    use super::*;
+    use std::sync::atomic::AtomicUsize;
+    use std::sync::{Arc, OnceLock};
+    use std::thread::{self, Thread};
+    use std::time::{Duration, Instant};
+
+    /// Parks OS threads, for testing the sleeping primitives.
+    pub struct StdPark;
+
+    impl Park for StdPark {
+        type Token = Thread;
+
+        fn current() -> Thread {
+            thread::current()
+        }
+
+        fn park(deadline: Option<u64>) {
+            match deadline {
+                Some(deadline) => {
+                    thread::park_timeout(Duration::from_nanos(
+                        deadline.saturating_sub(Self::now()),
+                    ));
+                }
+                None => thread::park(),
+            }
+        }
+
+        fn unpark(token: Thread) {
+            token.unpark();
+        }
+
+        #[allow(clippy::cast_possible_truncation)]
+        fn now() -> u64 {
+            static EPOCH: OnceLock<Instant> = OnceLock::new();
+
+            EPOCH.get_or_init(Instant::now).elapsed().as_nanos() as u64
+        }
+    }
+
+    // spins until `count` threads are waiting on `queue`
+    pub fn wait_for_waiters(queue: &WaitQueue<StdPark>, count: usize) {
+        while queue.with_queue(|queue| {
+            let mut waiter = queue.head;
+            let mut n = 0;
+
+            while !waiter.is_null() {
+                waiter = unsafe { (*waiter).next.get() };
+                n += 1;
+            }
+
+            n
+        }) < count
+        {
+            thread::yield_now();
+        }
+    }
+
+    #[test]
+    fn false_condition_skips() {
+        let queue = WaitQueue::<StdPark>::new();
+
+        assert_eq!(queue.wait_if(|| false, None), WaitResult::Skipped);
+        assert!(!queue.notify_one());
+    }
+
+    #[test]
+    fn times_out() {
+        let queue = WaitQueue::<StdPark>::new();
+        let deadline = StdPark::now() + 1_000_000;
+
+        assert_eq!(queue.wait_if(|| true, Some(deadline)), WaitResult::TimedOut);
+        assert!(StdPark::now() >= deadline);
+
+        // the waiter took itself off the queue
+        assert_eq!(queue.notify_all(), 0);
+    }
+
+    #[test]
+    fn wakes_in_fifo_order() {
+        let queue = Arc::new(WaitQueue::<StdPark>::new());
+        let order = Arc::new(SpinMutex::new(Vec::new()));
+        let mut threads = Vec::new();
+
+        for i in 0..3 {
+            let waiter = Arc::clone(&queue);
+            let order = Arc::clone(&order);
+
+            threads.push(thread::spawn(move || {
+                assert_eq!(waiter.wait_if(|| true, None), WaitResult::Woken);
+
+                order.lock().push(i);
+            }));
+
+            // make sure they queue up in order
+            wait_for_waiters(&queue, i + 1);
+        }
+
+        for i in 0..3 {
+            assert!(queue.notify_one());
+
+            while order.lock().len() <= i {
+                thread::yield_now();
+            }
+        }
+
+        for thread in threads {
+            thread.join().unwrap();
+        }
+
+        assert_eq!(*order.lock(), [0, 1, 2]);
+    }
+
+    #[test]
+    fn notify_all_wakes_everyone() {
+        let queue = Arc::new(WaitQueue::<StdPark>::new());
+        let woken = Arc::new(AtomicUsize::new(0));
+        let threads: Vec<_> = (0..4)
+            .map(|_| {
+                let queue = Arc::clone(&queue);
+                let woken = Arc::clone(&woken);
+
+                thread::spawn(move || {
+                    queue.wait_if(|| true, None);
+                    woken.fetch_add(1, Ordering::Relaxed);
+                })
+            })
+            .collect();
+
+        wait_for_waiters(&queue, 4);
+
+        assert_eq!(queue.notify_all(), 4);
+
+        for thread in threads {
+            thread.join().unwrap();
+        }
+
+        assert_eq!(woken.load(Ordering::Relaxed), 4);
+    }
+
+    #[test]
+    fn notify_one_or_runs_when_empty() {
+        let queue = WaitQueue::<StdPark>::new();
+        let mut ran = false;
+
+        assert!(!queue.notify_one_or(|| ran = true));
+        assert!(ran);
+    }
+}
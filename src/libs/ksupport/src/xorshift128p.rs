@@ -8,6 +8,9 @@
 //                                                                           //
 //======---------------------------------------------------------------======//
 
+use crate::rng;
+use core::ops::Range;
+
 /// An implementation of the Xorshift random number generator algorithm.
 ///
 /// It has 128-bits of state, and produces 64-bit outputs.
@@ -16,6 +19,8 @@ pub struct Xorshift128Plus {
 }
 
 impl Xorshift128Plus {
+    const JUMP: [u64; 2] = [0x8A5C_D789_635D_2DFF, 0x121F_D215_5C47_2F96];
+
     /// Initializes the random-number generator with a given state.
     ///
     /// This seed should ideally put entropy in all 128 bits of state,
@@ -43,6 +48,8 @@ impl Xorshift128Plus {
     pub fn next(&mut self) -> u64 {
         let mut t = self.state[0];
         let s = self.state[1];
+        let result = t.wrapping_add(s);
+
         self.state[0] = s;
 
         t ^= t << 23;
@@ -51,7 +58,49 @@ impl Xorshift128Plus {
 
         self.state[1] = t;
 
-        t.wrapping_add(s)
+        result
+    }
+
+    /// Produces a float uniformly distributed in `[0, 1)`.
+    pub fn next_f64(&mut self) -> f64 {
+        rng::to_f64(self.next())
+    }
+
+    /// Produces an integer uniformly distributed in `0..bound`.
+    ///
+    /// # Panics
+    /// Panics if `bound` is zero.
+    pub fn next_below(&mut self, bound: u64) -> u64 {
+        rng::below(bound, || self.next())
+    }
+
+    /// Produces an integer uniformly distributed in `range`.
+    ///
+    /// # Panics
+    /// Panics if `range` is empty.
+    pub fn next_in(&mut self, range: Range<u64>) -> u64 {
+        rng::within(range, || self.next())
+    }
+
+    /// Advances the generator by 2<sup>64</sup> calls to [`Self::next`].
+    ///
+    /// This splits one seed into 2<sup>64</sup> non-overlapping streams,
+    /// see [`Xoshiro256::jump`](crate::Xoshiro256::jump).
+    pub fn jump(&mut self) {
+        let mut state = [0; 2];
+
+        for word in Self::JUMP {
+            for bit in 0..64 {
+                if word & (1 << bit) != 0 {
+                    state[0] ^= self.state[0];
+                    state[1] ^= self.state[1];
+                }
+
+                self.next();
+            }
+        }
+
+        self.state = state;
     }
 }
 
@@ -66,3 +115,73 @@ impl Default for Xorshift128Plus {
         }
     }
 }
+
+impl Clone for Xorshift128Plus {
+    fn clone(&self) -> Self {
+        Self { state: self.state }
+    }
+}
+
+rng::impl_rand_core!(Xorshift128Plus, 2);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // all of the expected outputs come from the reference implementation
+    // at https://prng.di.unimi.it/xorshift128plus.c
+
+    fn outputs(rng: &mut Xorshift128Plus, n: usize) -> Vec<u64> {
+        (0..n).map(|_| rng.next()).collect()
+    }
+
+    #[test]
+    fn matches_reference() {
+        let mut rng = Xorshift128Plus::with_seed([1, 2]);
+
+        assert_eq!(
+            outputs(&mut rng, 10),
+            [
+                0x0000_0000_0000_0003,
+                0x0000_0000_0080_0025,
+                0x0000_0000_0204_0083,
+                0x0000_4000_020C_2460,
+                0x0000_C000_0210_8D21,
+                0x0001_0012_0190_F76B,
+                0x0001_4038_1016_15A5,
+                0x0901_0058_349D_B3C4,
+                0x2340_C046_5E10_719B,
+                0x24C1_0848_43D5_B4A8,
+            ]
+        );
+    }
+
+    #[test]
+    fn jump_matches_reference() {
+        let mut rng = Xorshift128Plus::with_seed([1, 2]);
+
+        outputs(&mut rng, 10);
+        rng.jump();
+
+        assert_eq!(
+            outputs(&mut rng, 4),
+            [
+                0x3DB1_5F76_5D41_5430,
+                0x39F2_4258_4E9E_B8BC,
+                0xCEB8_A0D3_8A77_4C72,
+                0xC902_7D80_A58D_8363,
+            ]
+        );
+    }
+
+    #[test]
+    fn bounded_outputs_stay_in_range() {
+        let mut rng = Xorshift128Plus::default();
+
+        for _ in 0..1000 {
+            assert!((0.0..1.0).contains(&rng.next_f64()));
+            assert!(rng.next_below(3) < 3);
+            assert!((u64::MAX - 2..u64::MAX).contains(&rng.next_in(u64::MAX - 2..u64::MAX)));
+        }
+    }
+}
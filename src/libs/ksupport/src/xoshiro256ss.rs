@@ -8,7 +8,10 @@
 //                                                                           //
 //======---------------------------------------------------------------======//
 
-/// An implementation of the Xoshiro random number generator algorithm.
+use crate::rng;
+use core::ops::Range;
+
+/// An implementation of the xoshiro256** random number generator algorithm.
 ///
 /// It has 256-bits of state, and produces 64-bit outputs.
 pub struct Xoshiro256 {
@@ -16,6 +19,20 @@ pub struct Xoshiro256 {
 }
 
 impl Xoshiro256 {
+    const JUMP: [u64; 4] = [
+        0x180E_C6D3_3CFD_0ABA,
+        0xD5A6_1266_F0C9_392C,
+        0xA958_2618_E03F_C9AA,
+        0x39AB_DC45_29B1_661C,
+    ];
+
+    const LONG_JUMP: [u64; 4] = [
+        0x76E1_5D3E_FEFD_CBBF,
+        0xC500_4E44_1C52_2FB3,
+        0x7771_0069_854E_E241,
+        0x3910_9BB0_2ACB_E635,
+    ];
+
     /// Initializes the random-number generator with a given state.
     ///
     /// This seed should ideally put entropy in all 256 bits of state,
@@ -38,16 +55,12 @@ impl Xoshiro256 {
         instance
     }
 
-    fn rotate_left(x: u64, k: u64) -> u64 {
-        (x << k) | (x >> (64 - k))
-    }
-
     /// Produces the next 64-bit output from the hasher.
     ///
     /// This is relatively fast, and completely deterministic based
     /// on the seed and the previous number of calls to [`Self::next`].
     pub fn next(&mut self) -> u64 {
-        let result = Self::rotate_left(self.state[1], 7).wrapping_mul(9);
+        let result = self.state[1].wrapping_mul(5).rotate_left(7).wrapping_mul(9);
         let t = self.state[1] << 17;
 
         self.state[2] ^= self.state[0];
@@ -56,10 +69,65 @@ impl Xoshiro256 {
         self.state[0] ^= self.state[3];
 
         self.state[2] ^= t;
-        self.state[3] = Self::rotate_left(self.state[3], 45);
+        self.state[3] = self.state[3].rotate_left(45);
 
         result
     }
+
+    /// Produces a float uniformly distributed in `[0, 1)`.
+    pub fn next_f64(&mut self) -> f64 {
+        rng::to_f64(self.next())
+    }
+
+    /// Produces an integer uniformly distributed in `0..bound`.
+    ///
+    /// # Panics
+    /// Panics if `bound` is zero.
+    pub fn next_below(&mut self, bound: u64) -> u64 {
+        rng::below(bound, || self.next())
+    }
+
+    /// Produces an integer uniformly distributed in `range`.
+    ///
+    /// # Panics
+    /// Panics if `range` is empty.
+    pub fn next_in(&mut self, range: Range<u64>) -> u64 {
+        rng::within(range, || self.next())
+    }
+
+    /// Advances the generator by 2<sup>128</sup> calls to [`Self::next`].
+    ///
+    /// This splits one seed into 2<sup>128</sup> non-overlapping streams,
+    /// e.g. one per CPU: seed one generator, then give each CPU a clone
+    /// that has been jumped one more time than the last.
+    pub fn jump(&mut self) {
+        self.jump_by(&Self::JUMP);
+    }
+
+    /// Advances the generator by 2<sup>192</sup> calls to [`Self::next`].
+    ///
+    /// This can split the streams made by [`Self::jump`] even further.
+    pub fn long_jump(&mut self) {
+        self.jump_by(&Self::LONG_JUMP);
+    }
+
+    fn jump_by(&mut self, polynomial: &[u64; 4]) {
+        let mut state = [0; 4];
+
+        for word in polynomial {
+            for bit in 0..64 {
+                if word & (1 << bit) != 0 {
+                    for (to, from) in state.iter_mut().zip(self.state) {
+                        *to ^= from;
+                    }
+                }
+
+                self.next();
+            }
+        }
+
+        self.state = state;
+    }
 }
 
 impl Default for Xoshiro256 {
@@ -76,3 +144,134 @@ impl Default for Xoshiro256 {
         }
     }
 }
+
+impl Clone for Xoshiro256 {
+    fn clone(&self) -> Self {
+        Self { state: self.state }
+    }
+}
+
+rng::impl_rand_core!(Xoshiro256, 4);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // all of the expected outputs come from the reference implementation
+    // at https://prng.di.unimi.it/xoshiro256starstar.c
+
+    fn outputs(rng: &mut Xoshiro256, n: usize) -> Vec<u64> {
+        (0..n).map(|_| rng.next()).collect()
+    }
+
+    #[test]
+    fn matches_reference() {
+        let mut rng = Xoshiro256::with_seed([1, 2, 3, 4]);
+
+        assert_eq!(
+            outputs(&mut rng, 10),
+            [
+                0x0000_0000_0000_2D00,
+                0x0000_0000_0000_0000,
+                0x0000_0000_5A00_7080,
+                0x10E0_0000_0000_9D80,
+                0x10E0_B61C_E100_9D80,
+                0x0870_021C_E143_AD00,
+                0xE071_C3C2_E143_F089,
+                0x75A1_690E_F7A2_0380,
+                0x9309_685B_465C_23F9,
+                0x284F_3CC2_E13E_3C88,
+            ]
+        );
+    }
+
+    #[test]
+    fn jumps_match_reference() {
+        let mut rng = Xoshiro256::with_seed([1, 2, 3, 4]);
+
+        outputs(&mut rng, 10);
+        rng.jump();
+
+        assert_eq!(
+            outputs(&mut rng, 4),
+            [
+                0x8824_F8A9_78AA_088D,
+                0xBDF6_5C1B_DE2F_482B,
+                0x474F_A764_8DC9_B6BD,
+                0x7923_F9EB_6D3A_6792,
+            ]
+        );
+
+        rng.long_jump();
+
+        assert_eq!(
+            outputs(&mut rng, 4),
+            [
+                0x8466_FF93_9620_B7E8,
+                0xFAEB_E935_2247_EB37,
+                0xE4F8_0E01_EEA0_8748,
+                0xB2BA_6B9F_17A7_A3A5,
+            ]
+        );
+    }
+
+    #[test]
+    fn floats_are_in_range() {
+        let mut rng = Xoshiro256::default();
+
+        for _ in 0..10_000 {
+            assert!((0.0..1.0).contains(&rng.next_f64()));
+        }
+    }
+
+    #[test]
+    fn bounded_outputs_are_uniform() {
+        let mut rng = Xoshiro256::default();
+        let mut counts = [0u32; 6];
+
+        for _ in 0..60_000 {
+            counts[usize::try_from(rng.next_below(6)).unwrap()] += 1;
+        }
+
+        // each should be ~10000, this is far outside of what chance allows
+        assert!(counts.iter().all(|&count| (9_500..10_500).contains(&count)));
+
+        for _ in 0..1000 {
+            assert!((10..13).contains(&rng.next_in(10..13)));
+        }
+
+        assert_eq!(rng.next_in(7..8), 7);
+    }
+
+    #[test]
+    #[should_panic = "non-zero"]
+    fn zero_bound_panics() {
+        Xoshiro256::default().next_below(0);
+    }
+
+    #[test]
+    #[cfg(feature = "rand_core")]
+    fn rand_core_seeding() {
+        use rand_core::{RngCore, SeedableRng};
+
+        let mut seed = [0; 32];
+
+        seed[0] = 1;
+        seed[8] = 2;
+        seed[16] = 3;
+        seed[24] = 4;
+
+        let mut rng = Xoshiro256::from_seed(seed);
+
+        assert_eq!(rng.next_u64(), 0x2D00);
+
+        // an all-zero seed would get stuck
+        assert_ne!(Xoshiro256::from_seed([0; 32]).next_u64(), 0);
+
+        let mut bytes = [0; 13];
+
+        rng.fill_bytes(&mut bytes);
+
+        assert_ne!(bytes, [0; 13]);
+    }
+}
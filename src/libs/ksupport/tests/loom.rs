@@ -0,0 +1,417 @@
+//======---------------------------------------------------------------======//
+//                                                                           //
+// Copyright 2022-2023 Evan Cox <evanacox00@gmail.com>. All rights reserved. //
+//                                                                           //
+// Use of this source code is governed by a BSD-style license that can be    //
+// found in the LICENSE.txt file at the root of this project, or at the      //
+// following link: https://opensource.org/licenses/BSD-3-Clause              //
+//                                                                           //
+//======---------------------------------------------------------------======//
+
+//! Model checks for the synchronization primitives, which only build
+//! with `--cfg loom`:
+//!
+//! ```sh
+//! RUSTFLAGS="--cfg loom" cargo test -p ksupport --test loom --release
+//! ```
+
+#![cfg(loom)]
+
+use ksupport::ring::{MpscRing, SpscRing};
+use ksupport::sync::{
+    BasicMutex, Condvar, Mutex, Park, RcuDomain, RcuPtr, Semaphore, SpinFairMutex, SpinMutex,
+};
+use ksupport::{SpinLazy, SpinOnceCell};
+use loom::cell::UnsafeCell;
+use loom::model::Builder;
+use loom::sync::atomic::{AtomicUsize, Ordering};
+use loom::sync::Arc;
+use loom::thread::{self, Thread};
+
+// loom can't model time, so nothing here uses a deadline
+struct LoomPark;
+
+impl Park for LoomPark {
+    type Token = Thread;
+
+    fn current() -> Thread {
+        thread::current()
+    }
+
+    fn park(_: Option<u64>) {
+        thread::park();
+    }
+
+    fn unpark(token: Thread) {
+        token.unpark();
+    }
+
+    fn now() -> u64 {
+        0
+    }
+}
+
+// spin loops make the state space huge, bounding preemptions keeps
+// the runs short while still catching ordering bugs
+fn model(f: impl Fn() + Sync + Send + 'static) {
+    let mut builder = Builder::new();
+
+    builder.preemption_bound = Some(2);
+    builder.check(f);
+}
+
+// every thread increments the value under the lock, while checking that
+// nobody else is inside the critical section at the same time
+fn mutual_exclusion<M: BasicMutex<usize> + 'static>(make: fn() -> M) {
+    model(move || {
+        let mutex = Arc::new(make());
+        let inside = Arc::new(AtomicUsize::new(0));
+        let threads: Vec<_> = (0..2)
+            .map(|_| {
+                let mutex = Arc::clone(&mutex);
+                let inside = Arc::clone(&inside);
+
+                thread::spawn(move || {
+                    let mut guard = mutex.lock();
+
+                    assert_eq!(inside.fetch_add(1, Ordering::Relaxed), 0);
+
+                    *guard += 1;
+
+                    inside.fetch_sub(1, Ordering::Relaxed);
+                })
+            })
+            .collect();
+
+        for thread in threads {
+            thread.join().unwrap();
+        }
+
+        assert_eq!(*mutex.lock(), 2);
+    });
+}
+
+#[test]
+fn spin_mutex_excludes() {
+    mutual_exclusion(|| SpinMutex::new(0));
+}
+
+#[test]
+fn spin_fair_mutex_excludes() {
+    mutual_exclusion(|| SpinFairMutex::new(0));
+}
+
+#[test]
+fn sleeping_mutex_excludes() {
+    mutual_exclusion(|| Mutex::<_, LoomPark>::new(0));
+}
+
+#[test]
+fn try_lock_excludes() {
+    model(|| {
+        let mutex = Arc::new(SpinMutex::new(0));
+        let other = {
+            let mutex = Arc::clone(&mutex);
+
+            thread::spawn(move || mutex.try_lock().map(|mut guard| *guard += 1).is_some())
+        };
+
+        let mine = mutex.try_lock().map(|mut guard| *guard += 1).is_some();
+        let theirs = other.join().unwrap();
+
+        assert_eq!(*mutex.lock(), usize::from(mine) + usize::from(theirs));
+    });
+}
+
+#[test]
+fn fair_try_lock_excludes() {
+    model(|| {
+        let mutex = Arc::new(SpinFairMutex::new(0));
+        let other = {
+            let mutex = Arc::clone(&mutex);
+
+            thread::spawn(move || *mutex.lock() += 1)
+        };
+
+        if let Some(mut guard) = mutex.try_lock() {
+            *guard += 1;
+        }
+
+        other.join().unwrap();
+
+        assert!(*mutex.lock() >= 1);
+    });
+}
+
+#[test]
+fn fair_mutex_serves_tickets_in_order() {
+    model(|| {
+        let mutex = Arc::new(SpinFairMutex::new(Vec::new()));
+        let guard = mutex.lock();
+        let waiter = {
+            let mutex = Arc::clone(&mutex);
+
+            thread::spawn(move || mutex.lock().push(1))
+        };
+
+        // wait for the other thread to take its ticket, then take one
+        // after it. it has to be served first
+        while !mutex.is_contended() {
+            thread::yield_now();
+        }
+
+        drop(guard);
+
+        mutex.lock().push(2);
+        waiter.join().unwrap();
+
+        assert_eq!(*mutex.lock(), [1, 2]);
+    });
+}
+
+#[test]
+fn once_cell_get_or_init_publishes() {
+    model(|| {
+        let cell = Arc::new(SpinOnceCell::uninit());
+        let inits = Arc::new(AtomicUsize::new(0));
+        let init = |inits: &AtomicUsize| {
+            inits.fetch_add(1, Ordering::Relaxed);
+
+            vec![1, 2, 3]
+        };
+
+        let other = {
+            let cell = Arc::clone(&cell);
+            let inits = Arc::clone(&inits);
+
+            thread::spawn(move || assert_eq!(cell.get_or_init(|| init(&inits)), &[1, 2, 3]))
+        };
+
+        // whichever thread loses has to wait for the other one to finish
+        assert_eq!(cell.get_or_init(|| init(&inits)), &[1, 2, 3]);
+
+        other.join().unwrap();
+
+        assert_eq!(inits.load(Ordering::Relaxed), 1);
+    });
+}
+
+#[test]
+fn once_cell_get_waits_for_set() {
+    model(|| {
+        let cell = Arc::new(SpinOnceCell::uninit());
+        let setter = {
+            let cell = Arc::clone(&cell);
+
+            thread::spawn(move || cell.set(vec![1, 2, 3]).is_ok())
+        };
+
+        // `get` spins until the other thread has published the value
+        assert_eq!(cell.get(), &[1, 2, 3]);
+        assert!(setter.join().unwrap());
+    });
+}
+
+#[test]
+fn once_cell_set_publishes() {
+    model(|| {
+        let cell = Arc::new(SpinOnceCell::uninit());
+        let setter = {
+            let cell = Arc::clone(&cell);
+
+            thread::spawn(move || cell.set(Box::new(5)).is_ok())
+        };
+
+        let won = cell.set(Box::new(6)).is_ok();
+        let value = **cell.get();
+
+        assert_ne!(won, setter.join().unwrap());
+        assert_eq!(value, if won { 6 } else { 5 });
+    });
+}
+
+#[test]
+fn lazy_initializes_once() {
+    model(|| {
+        let inits = Arc::new(AtomicUsize::new(0));
+        let lazy = {
+            let inits = Arc::clone(&inits);
+
+            Arc::new(SpinLazy::new(move || {
+                inits.fetch_add(1, Ordering::Relaxed);
+
+                vec![4, 5]
+            }))
+        };
+
+        let other = {
+            let lazy = Arc::clone(&lazy);
+
+            thread::spawn(move || assert_eq!(**lazy, [4, 5]))
+        };
+
+        assert_eq!(**lazy, [4, 5]);
+
+        other.join().unwrap();
+
+        assert_eq!(inits.load(Ordering::Relaxed), 1);
+    });
+}
+
+#[test]
+fn semaphore_hands_off_permits() {
+    model(|| {
+        let semaphore = Arc::new(Semaphore::<LoomPark>::new(0));
+        let waiter = {
+            let semaphore = Arc::clone(&semaphore);
+
+            thread::spawn(move || semaphore.acquire())
+        };
+
+        semaphore.release();
+        waiter.join().unwrap();
+
+        // the permit was either handed off or taken from the pool
+        assert_eq!(semaphore.available(), 0);
+    });
+}
+
+#[test]
+fn condvar_doesnt_lose_notifications() {
+    model(|| {
+        let pair = Arc::new((Mutex::<_, LoomPark>::new(false), Condvar::<LoomPark>::new()));
+        let setter = {
+            let pair = Arc::clone(&pair);
+
+            thread::spawn(move || {
+                *pair.0.lock() = true;
+                pair.1.notify_one();
+            })
+        };
+
+        let guard = pair.1.wait_while(pair.0.lock(), |ready| !*ready);
+
+        assert!(*guard);
+
+        drop(guard);
+        setter.join().unwrap();
+    });
+}
+
+#[test]
+fn rcu_grace_period_waits_for_readers() {
+    model(|| {
+        let domain = Arc::new(RcuDomain::<2>::new());
+        let ptr = Arc::new(unsafe { RcuPtr::new(Box::into_raw(Box::new(UnsafeCell::new(1)))) });
+
+        domain.online(0);
+        domain.online(1);
+
+        let reader = {
+            let domain = Arc::clone(&domain);
+            let ptr = Arc::clone(&ptr);
+
+            thread::spawn(move || {
+                let guard = domain.read_lock(1);
+                let value = ptr.read(&guard).unwrap().with(|value| unsafe { *value });
+
+                assert!(value == 1 || value == 2);
+
+                drop(guard);
+                domain.quiescent(1);
+                domain.offline(1);
+            })
+        };
+
+        let old = unsafe { ptr.replace(Box::into_raw(Box::new(UnsafeCell::new(2)))) };
+
+        domain.synchronize(Some(0));
+
+        // if the grace period ended too early, loom sees this racing
+        // with the reader
+        unsafe {
+            (*old).with_mut(|value| *value = 0);
+            drop(Box::from_raw(old));
+        }
+
+        reader.join().unwrap();
+
+        drop(unsafe { Box::from_raw(ptr.replace(std::ptr::null_mut())) });
+    });
+}
+
+#[test]
+fn spsc_ring_hands_off_in_order() {
+    model(|| {
+        let ring = Arc::new(SpscRing::<Box<i32>, 2>::new());
+        let producer = {
+            let ring = Arc::clone(&ring);
+
+            thread::spawn(move || {
+                let mut producer = ring.producer().unwrap();
+
+                for i in 0..3 {
+                    let mut value = Box::new(i);
+
+                    // the ring only fits two, the third has to wait
+                    while let Err(v) = producer.push(value) {
+                        value = v;
+
+                        thread::yield_now();
+                    }
+                }
+            })
+        };
+
+        let mut consumer = ring.consumer().unwrap();
+
+        for i in 0..3 {
+            loop {
+                if let Some(value) = consumer.pop() {
+                    assert_eq!(*value, i);
+
+                    break;
+                }
+
+                thread::yield_now();
+            }
+        }
+
+        producer.join().unwrap();
+    });
+}
+
+#[test]
+fn mpsc_ring_loses_nothing() {
+    model(|| {
+        let ring = Arc::new(MpscRing::<Box<usize>, 2>::new());
+        let producers: Vec<_> = (0..2)
+            .map(|i| {
+                let ring = Arc::clone(&ring);
+
+                thread::spawn(move || ring.push(Box::new(i)).unwrap())
+            })
+            .collect();
+
+        let mut consumer = ring.consumer().unwrap();
+        let mut seen = [false; 2];
+
+        for _ in 0..2 {
+            loop {
+                if let Some(value) = consumer.pop() {
+                    assert!(!seen[*value]);
+
+                    seen[*value] = true;
+
+                    break;
+                }
+
+                thread::yield_now();
+            }
+        }
+
+        for producer in producers {
+            producer.join().unwrap();
+        }
+    });
+}
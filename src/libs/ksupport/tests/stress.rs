@@ -0,0 +1,101 @@
+//======---------------------------------------------------------------======//
+//                                                                           //
+// Copyright 2022-2023 Evan Cox <evanacox00@gmail.com>. All rights reserved. //
+//                                                                           //
+// Use of this source code is governed by a BSD-style license that can be    //
+// found in the LICENSE.txt file at the root of this project, or at the      //
+// following link: https://opensource.org/licenses/BSD-3-Clause              //
+//                                                                           //
+//======---------------------------------------------------------------======//
+
+//! Stress tests for the synchronization primitives, using real threads.
+//!
+//! These can't prove anything the way the loom tests can, but they do run
+//! the real atomics on real hardware.
+
+#![cfg(not(loom))]
+
+use ksupport::sync::{BasicMutex, SpinFairMutex, SpinMutex};
+use ksupport::SpinOnceCell;
+use std::hint;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Barrier};
+use std::thread;
+
+// kept small, the fair lock hands off to waiters that might not be
+// running when the host has fewer CPUs than threads
+const THREADS: usize = 4;
+const ITERATIONS: usize = 1000;
+
+fn hammer<M: BasicMutex<usize> + 'static>(mutex: M) {
+    let mutex = Arc::new(mutex);
+    let barrier = Arc::new(Barrier::new(THREADS));
+    let threads: Vec<_> = (0..THREADS)
+        .map(|_| {
+            let mutex = Arc::clone(&mutex);
+            let barrier = Arc::clone(&barrier);
+
+            thread::spawn(move || {
+                barrier.wait();
+
+                for _ in 0..ITERATIONS {
+                    let mut guard = mutex.lock();
+
+                    // split up so a lost update shows up in the count
+                    let value = *guard;
+
+                    hint::spin_loop();
+
+                    *guard = value + 1;
+                }
+            })
+        })
+        .collect();
+
+    for thread in threads {
+        thread.join().unwrap();
+    }
+
+    assert_eq!(*mutex.lock(), THREADS * ITERATIONS);
+}
+
+#[test]
+fn spin_mutex() {
+    hammer(SpinMutex::new(0));
+}
+
+#[test]
+fn spin_fair_mutex() {
+    hammer(SpinFairMutex::new(0));
+}
+
+#[test]
+fn once_cell_initializes_once() {
+    for _ in 0..100 {
+        let cell = Arc::new(SpinOnceCell::uninit());
+        let inits = Arc::new(AtomicUsize::new(0));
+        let barrier = Arc::new(Barrier::new(THREADS));
+        let threads: Vec<_> = (0..THREADS)
+            .map(|i| {
+                let cell = Arc::clone(&cell);
+                let inits = Arc::clone(&inits);
+                let barrier = Arc::clone(&barrier);
+
+                thread::spawn(move || {
+                    barrier.wait();
+
+                    *cell.get_or_init(|| {
+                        inits.fetch_add(1, Ordering::Relaxed);
+
+                        i
+                    })
+                })
+            })
+            .collect();
+
+        let values: Vec<_> = threads.into_iter().map(|t| t.join().unwrap()).collect();
+
+        assert_eq!(inits.load(Ordering::Relaxed), 1);
+        assert!(values.iter().all(|&value| value == values[0]));
+    }
+}